@@ -1,6 +1,8 @@
 use postgres::{self, GenericConnection};
-use trek::migration_index::MigrationIndex as TrekMigrationIndex;
+use trek::migration_index::{MigrationIndex as TrekMigrationIndex, MigrationReport};
 use trek::migration::Migration as TrekMigration;
+use trek::migration_version::MigrationVersion;
+use trek::squash::{self, SquashedSchema};
 use trek::Result;
 use migrations::migration_20150826001350_create_users_table::CreateUsersTable;
 use migrations::migration_20151008562095_create_companies_table::CreateCompaniesTable;
@@ -18,7 +20,7 @@ impl MigrationIndex {
     }
 
     #[allow(dead_code)]
-    pub fn run(&self, connection: &GenericConnection) -> Result<()> {
+    pub fn run(&self, connection: &GenericConnection) -> Result<MigrationReport> {
         self.migrations.run(connection)
     }
 
@@ -29,9 +31,23 @@ impl MigrationIndex {
 
     #[allow(dead_code)]
     pub fn schema_version(
+        &self,
         connection: &GenericConnection
     ) -> postgres::Result<Option<String>> {
-        TrekMigrationIndex::schema_version(connection)
+        self.migrations.schema_version(connection)
+    }
+
+    #[allow(dead_code)]
+    pub fn latest_applied_version(
+        &self,
+        connection: &GenericConnection
+    ) -> postgres::Result<Option<MigrationVersion>> {
+        self.migrations.latest_applied_version(connection)
+    }
+
+    #[allow(dead_code)]
+    pub fn squash(&self, database_url: &str) -> Result<SquashedSchema> {
+        squash::squash(database_url, &self.migrations)
     }
 }
 
@@ -3,6 +3,8 @@ use std::fmt::{self, Display};
 use postgres;
 
 use trek::migration::Migration;
+use trek::migration_context::MigrationContext;
+use trek::migration_version::MigrationVersion;
 
 #[derive(Debug)]
 pub struct CreateUsersTable {
@@ -16,7 +18,12 @@ impl CreateUsersTable {
     }
 }
 impl Migration for CreateUsersTable {
-    fn up(&self, connection: &postgres::GenericConnection) -> postgres::Result<()> {
+    fn version(&self) -> MigrationVersion {
+        MigrationVersion::new(20150826001350)
+    }
+
+    fn up(&self, context: &MigrationContext) -> postgres::Result<()> {
+        let connection = context.connection();
         try!(connection.execute("CREATE TABLE users (
     id SERIAL PRIMARY KEY,
     username TEXT NOT NULL UNIQUE,
@@ -29,7 +36,8 @@ impl Migration for CreateUsersTable {
         Ok(())
     }
 
-    fn down(&self, connection: &postgres::GenericConnection) -> postgres::Result<()> {
+    fn down(&self, context: &MigrationContext) -> postgres::Result<()> {
+        let connection = context.connection();
         try!(connection.execute("DROP TABLE users;", &[]));
         Ok(())
     }
@@ -3,6 +3,8 @@ use std::fmt::{self, Display};
 use postgres;
 
 use trek::migration::Migration;
+use trek::migration_context::MigrationContext;
+use trek::migration_version::MigrationVersion;
 
 #[derive(Debug)]
 pub struct CreateCompaniesTable {
@@ -16,7 +18,12 @@ impl CreateCompaniesTable {
     }
 }
 impl Migration for CreateCompaniesTable {
-    fn up(&self, connection: &postgres::GenericConnection) -> postgres::Result<()> {
+    fn version(&self) -> MigrationVersion {
+        MigrationVersion::new(20151008562095)
+    }
+
+    fn up(&self, context: &MigrationContext) -> postgres::Result<()> {
+        let connection = context.connection();
         try!(connection.execute("CREATE TABLE companies (
     id SERIAL PRIMARY KEY,
     name TEXT NOT NULL UNIQUE,
@@ -33,7 +40,8 @@ impl Migration for CreateCompaniesTable {
         Ok(())
     }
 
-    fn down(&self, connection: &postgres::GenericConnection) -> postgres::Result<()> {
+    fn down(&self, context: &MigrationContext) -> postgres::Result<()> {
+        let connection = context.connection();
         try!(connection.execute("ALTER TABLE users DROP COLUMN company_id;", &[]));
         try!(connection.execute("DROP TABLE companies;", &[]));
         Ok(())
@@ -0,0 +1,18 @@
+//! `cargo trek`, the `trek` command line tool run as a cargo subcommand, e.g.
+//! `cargo trek new add_index_to_users`. Installing Trek installs it alongside `trek`, and cargo
+//! runs it for `cargo trek` from anywhere in a project. Without a `trek.toml`, migrations are
+//! loaded from the "migrations" directory at the root of the Cargo workspace.
+
+extern crate chrono;
+extern crate docopt;
+extern crate postgres;
+extern crate rustc_serialize;
+extern crate trek;
+
+#[path = "trek.rs"]
+mod trek_cli;
+
+
+fn main() {
+    trek_cli::main();
+}
@@ -0,0 +1,1680 @@
+//! The `trek` command line tool, for managing the schema of a PostgreSQL database with migrations
+//! written as SQL files, so services not written in Rust can use Trek too. Migrations are loaded
+//! from the directory configured in the nearest `trek.toml`, or found by
+//! `trek::config::Config::discover()` if there is none, as described by
+//! `trek::sql_migration::load_directory()`. The database is the one configured for
+//! the environment given with `--env`, or else given by the `TREK_DATABASE_URL` or
+//! `DATABASE_URL` environment variable; command line options override both.
+
+extern crate chrono;
+extern crate docopt;
+extern crate postgres;
+extern crate rustc_serialize;
+extern crate trek;
+
+use std::cmp;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::env;
+use std::fmt::Display;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use chrono::{DateTime, UTC};
+use docopt::Docopt;
+use postgres::{Connection, GenericConnection, TlsMode};
+use rustc_serialize::json::{Json, ToJson};
+use trek::config::Config;
+use trek::database;
+use trek::error::{Error, ResultExt, ValidationError};
+use trek::interactive;
+use trek::migration::Migration;
+use trek::migration_context::RunOptions;
+use trek::migration_index::{MigrationIndex, MigrationReport, VerificationReport};
+use trek::migration_version::MigrationVersion;
+use trek::scaffold::Scaffold;
+use trek::seed::{self, SqlSeed};
+use trek::sql_migration;
+use trek::squash;
+use trek::MigrationTemplate;
+
+
+const USAGE: &'static str = "
+trek - manage a PostgreSQL database's schema with migrations written as SQL files.
+
+Usage:
+  trek migrate [--dry-run | --to=<version> | --watch] [--yes] [--structure-file=<path>]
+               [--lock-timeout=<ms>] [--statement-timeout=<ms>] [options]
+  trek rollback [--to=<version>] [--yes] [--lock-timeout=<ms>] [--statement-timeout=<ms>]
+                [options]
+  trek status [options]
+  trek verify [options]
+  trek baseline [<version>] [options]
+  trek seed [--only=<name>] [options]
+  trek db create [options]
+  trek db (drop | reset) [--yes] [options]
+  trek new <name> [--sql | --rust | --reversible] [--description=<text>] [--author=<name>]
+           [--ticket=<ticket>] [options]
+  trek (-h | --help)
+  trek --version
+
+Commands:
+  migrate    Apply the migrations that haven't been applied yet, each in its own transaction.
+  rollback   Roll back the last applied migration, or with --to, every migration applied
+             after the given one.
+  status     List the migrations, and whether and when each was applied; applied migrations
+             that aren't registered are listed as unknown, exiting with status 4.
+  verify     Check that no applied migration has been modified or is missing or unknown and
+             that none was left dirty, exiting with status 4 if any problems are found, e.g.
+             to check for drift in continuous integration.
+  baseline   Start tracking the versions of an existing database, recording the migration with
+             <version>, or a unique prefix of it, and the ones before it as applied without
+             running them. Defaults to the newest migration.
+  seed       Load the seeds in the seeds directory that apply to the environment and haven't
+             been loaded yet, in a transaction, or with --only, the named seed, even if it has.
+             Seeds in a subdirectory named after a configured environment only apply to it.
+  db         Create the database if it doesn't exist, drop it if it does, or reset it by
+             dropping it, creating it again and applying the migrations, connecting to the
+             server's maintenance database to do so.
+  new        Create a new migration named <name>: <name>.up.sql and <name>.down.sql files, or
+             with --rust or --reversible, a Rust file declared in the directory's mod.rs.
+             Defaults to the template configured in trek.toml, or else SQL.
+
+Options:
+  -h --help                   Show help text.
+  --dry-run                   Print the migrations migrate would apply and their SQL, in a
+                              read-only transaction, instead of applying them.
+  --to=<version>              The version of the migration to migrate up to or roll back to,
+                              which stays applied; a prefix of it is enough if no other
+                              migration's version starts with it.
+  --only=<name>               The name of the seed to load, with or without its version.
+  --sql                       Create the migration as a pair of SQL files.
+  --rust                      Create the migration as a Rust struct implementing Migration.
+  --reversible                Create the migration as a Rust struct implementing
+                              ReversibleMigration, whose changes are undone automatically.
+  --description=<text>        A short description of what the new migration does, recorded
+                              in it.
+  --author=<name>             The author recorded in the new migration.
+  --ticket=<ticket>           The ticket or issue the new migration is for, recorded in it.
+  --structure-file=<path>     After migrating, write the schema to the file, overriding
+                              trek.toml's structure_file, so it can be reviewed and loaded.
+  --watch                     Keep running, applying migrations as they're added to the
+                              migrations directory, until interrupted, e.g. to keep a
+                              development database up to date. Migrations with no
+                              statements yet, like new ones, wait until they're written.
+  --lock-timeout=<ms>         How long a statement may wait for a lock before it's cancelled,
+                              in milliseconds, overriding the environment's lock_timeout.
+  --statement-timeout=<ms>    How long a statement may run before it's cancelled, in
+                              milliseconds, overriding the environment's statement_timeout.
+                              Migrations that set their own timeouts keep them.
+  -y --yes                    Don't ask for confirmation before rolling back, dropping the
+                              database, or applying migrations that drop tables or columns or
+                              delete rows, e.g. in automation.
+  --format=<format>           Print the output as "text", the default, or as a "json"
+                              document, including errors as {"error": "<message>"}.
+  --json                      Short for --format=json.
+  --exit-nothing-to-do        Exit with status 1 if there was nothing to do, e.g. no
+                              migrations to apply or seeds to load.
+  --version                   Show the version of trek.
+  --config=<path>             The configuration file to read, instead of the trek.toml nearest
+                              to the current directory.
+  --env=<environment>         The environment configured in trek.toml to use the database and
+                              settings of.
+  --database-url=<url>        The URL of the database, overriding the environment's. Defaults
+                              to the TREK_DATABASE_URL or DATABASE_URL environment variable.
+  --migrations-dir=<path>     The directory migrations are created in and loaded from,
+                              overriding trek.toml's migrations_dir.
+  --table-name=<name>         The table applied migrations are recorded in, overriding
+                              trek.toml's table_name.
+
+Exit status:
+  0  The command succeeded.
+  1  There was nothing to do, only with --exit-nothing-to-do.
+  2  Migrations are pending, reported by status and migrate --dry-run.
+  3  The command failed, e.g. a migration failed or the database couldn't be reached.
+  4  The database has drifted from the migrations, reported by verify and status, or a
+     command failed because an applied migration isn't registered or has been modified.
+";
+
+/// How often `trek migrate --watch` checks the migrations directory for changes, in
+/// milliseconds.
+const WATCH_INTERVAL_MS: u64 = 1000;
+
+/// The exit status when the command succeeded.
+const EXIT_OK: i32 = 0;
+/// The exit status when there was nothing to do and `--exit-nothing-to-do` was given.
+const EXIT_NOTHING_TO_DO: i32 = 1;
+/// The exit status when `trek status` or `trek migrate --dry-run` found pending migrations.
+const EXIT_PENDING: i32 = 2;
+/// The exit status when the command failed.
+const EXIT_FAILED: i32 = 3;
+/// The exit status when `trek verify` found the database has drifted from the migrations, or a
+/// command failed because of it.
+const EXIT_DRIFTED: i32 = 4;
+
+#[derive(Debug, RustcDecodable)]
+struct Args {
+    cmd_migrate: bool,
+    cmd_rollback: bool,
+    cmd_status: bool,
+    cmd_verify: bool,
+    cmd_baseline: bool,
+    cmd_seed: bool,
+    cmd_db: bool,
+    cmd_create: bool,
+    cmd_drop: bool,
+    cmd_reset: bool,
+    cmd_new: bool,
+    arg_name: String,
+    arg_version: Option<String>,
+    flag_config: Option<String>,
+    flag_env: Option<String>,
+    flag_database_url: Option<String>,
+    flag_migrations_dir: Option<String>,
+    flag_table_name: Option<String>,
+    flag_format: Option<String>,
+    flag_json: bool,
+    flag_exit_nothing_to_do: bool,
+    flag_dry_run: bool,
+    flag_to: Option<String>,
+    flag_watch: bool,
+    flag_only: Option<String>,
+    flag_yes: bool,
+    flag_structure_file: Option<String>,
+    flag_lock_timeout: Option<String>,
+    flag_statement_timeout: Option<String>,
+    flag_sql: bool,
+    flag_rust: bool,
+    flag_reversible: bool,
+    flag_description: Option<String>,
+    flag_author: Option<String>,
+    flag_ticket: Option<String>,
+}
+
+/// What a command found or did, which decides trek's exit status.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Outcome {
+    /// the command did what it was asked to
+    Done,
+    /// there was nothing for the command to do, e.g. no migrations to apply
+    NothingToDo,
+    /// migrations are pending
+    Pending,
+    /// the database has drifted from the migrations
+    Drifted,
+}
+
+impl Outcome {
+    /// Returns the exit status for the outcome, treating nothing to do as success unless
+    /// `--exit-nothing-to-do` was given.
+    fn exit_status(self, args: &Args) -> i32 {
+        match self {
+            Outcome::Done => EXIT_OK,
+            Outcome::NothingToDo if args.flag_exit_nothing_to_do => EXIT_NOTHING_TO_DO,
+            Outcome::NothingToDo => EXIT_OK,
+            Outcome::Pending => EXIT_PENDING,
+            Outcome::Drifted => EXIT_DRIFTED,
+        }
+    }
+}
+
+pub fn main() {
+    let mut argv = env::args().collect::<Vec<_>>();
+    // run as `cargo trek`, cargo passes the subcommand's name before its arguments
+    if is_cargo_subcommand(&argv) {
+        argv.remove(1);
+    }
+    // the arguments are checked for JSON output by hand, to report errors parsing them as JSON
+    let json = wants_json(&argv);
+    let args: Args = Docopt::new(USAGE)
+        .and_then(|docopt| {
+            docopt.argv(argv).version(Some(env!("CARGO_PKG_VERSION").to_owned())).decode()
+        })
+        .unwrap_or_else(|error| {
+            if !error.fatal() {
+                error.exit();
+            }
+            print_error(&error, json);
+            process::exit(EXIT_FAILED);
+        });
+    let json = args.flag_json || args.flag_format.as_ref().map(|format| &**format) == Some("json");
+    match run(&args, json) {
+        Ok(outcome) => process::exit(outcome.exit_status(&args)),
+        Err(error) => {
+            print_error(&error, json);
+            process::exit(error_exit_status(&error));
+        }
+    }
+}
+
+/// Returns the exit status for a command that failed with the error: drifted if it failed
+/// because the database has drifted from the migrations, or else failed.
+fn error_exit_status(error: &Error) -> i32 {
+    if error.is_drift() { EXIT_DRIFTED } else { EXIT_FAILED }
+}
+
+/// Returns true if the arguments ask for JSON output with `--json` or `--format json`.
+fn wants_json(argv: &[String]) -> bool {
+    argv.iter().enumerate().any(|(index, argument)| {
+        argument == "--json" || argument == "--format=json" ||
+            (argument == "--format" && argv.get(index + 1).map(|format| &**format) == Some("json"))
+    })
+}
+
+/// Prints the error to standard error, or as a JSON document like `{"error": "<message>"}` on
+/// standard output.
+fn print_error(error: &Display, json: bool) {
+    if json {
+        let mut document = BTreeMap::new();
+        document.insert("error".to_owned(), Json::String(error.to_string()));
+        print_json(document);
+    } else {
+        eprintln!("{}", error);
+    }
+}
+
+/// Returns true if the program was run by cargo as the `cargo trek` subcommand, i.e. as
+/// `cargo-trek trek`.
+fn is_cargo_subcommand(argv: &[String]) -> bool {
+    let program = argv.first().and_then(|program| Path::new(program).file_stem());
+    program.and_then(|program| program.to_str()) == Some("cargo-trek") &&
+        argv.get(1).map(|argument| &**argument) == Some("trek")
+}
+
+/// Runs the command given by the arguments, printing its output as JSON if `json` is true.
+fn run(args: &Args, json: bool) -> trek::Result<Outcome> {
+    match args.flag_format {
+        Some(ref format) if format != "text" && format != "json" => {
+            return Err(Error::from_message(
+                format!("The format must be text or json, not {}", format)
+            ));
+        },
+        _ => {}
+    }
+    let config = try!(load_config(args));
+    if args.cmd_new {
+        return new_migration(&config, args, json);
+    }
+    if args.cmd_db {
+        return manage_database(&config, args, json);
+    }
+    if args.cmd_migrate && args.flag_watch {
+        return watch(&config, args, json);
+    }
+    let migrations = try!(load_migrations(&config, args));
+    let target = match args.flag_to.as_ref().or(args.arg_version.as_ref()) {
+        Some(target) => Some(try!(resolve_target(&migrations, target))),
+        None => None
+    };
+    let connection = try!(connect(&config, args));
+    if args.cmd_migrate && args.flag_dry_run {
+        print_plan(&migrations, &connection, json)
+    } else if args.cmd_migrate {
+        apply_migrations(&config, args, &migrations, &connection, target, json)
+    } else if args.cmd_rollback {
+        rollback(&migrations, &connection, target, args.flag_yes, json)
+    } else if args.cmd_verify {
+        verify(&migrations, &connection, json)
+    } else if args.cmd_baseline {
+        baseline(&migrations, &connection, target, json)
+    } else if args.cmd_seed {
+        run_seeds(&migrations, &connection, args.flag_only.as_ref().map(|name| &**name), json)
+    } else {
+        status(&migrations, &connection, json)
+    }
+}
+
+/// Reads the configuration file given with `--config`, or else the `trek.toml` nearest to the
+/// current directory, falling back to the default configuration as described by
+/// `Config::discover()`, with the migrations directory given with `--migrations-dir`, if any.
+fn load_config(args: &Args) -> trek::Result<Config> {
+    let config = if let Some(ref path) = args.flag_config {
+        try!(
+            Config::load(Path::new(path))
+                .with_context(|| format!("Error reading the configuration file {}", path))
+        )
+    } else {
+        let current_dir = try!(
+            env::current_dir().context("Error finding the current directory")
+        );
+        try!(Config::discover(&current_dir))
+    };
+    match args.flag_migrations_dir {
+        Some(ref migrations_dir) => Ok(config.with_migrations_dir(Path::new(migrations_dir))),
+        None => Ok(config)
+    }
+}
+
+/// Loads the SQL migrations in the configured migrations directory, and for `trek seed`, the
+/// seeds in the configured seeds directory, into an index using the settings of the environment
+/// given with `--env`, if any, and the timeouts given with `--lock-timeout` and
+/// `--statement-timeout`.
+fn load_migrations(config: &Config, args: &Args) -> trek::Result<MigrationIndex> {
+    let migrations = try!(
+        sql_migration::load_directory(config.migrations_dir()).with_context(|| {
+            format!("Error loading the migrations in {}", config.migrations_dir().display())
+        })
+    );
+    let mut builder = try!(config.index_builder(environment(args)).map_err(Error::Validation))
+        .migrations(
+            migrations.into_iter()
+                .map(|migration| Box::new(migration) as Box<Migration>)
+                .collect()
+        );
+    if let Some(ref table_name) = args.flag_table_name {
+        builder = builder.table_name(table_name);
+    }
+    if args.flag_lock_timeout.is_some() || args.flag_statement_timeout.is_some() {
+        builder = builder.run_options(try!(run_options(config, args)));
+    }
+    if args.cmd_seed {
+        for seed in try!(load_seeds(config)) {
+            builder = builder.seed(seed);
+        }
+    }
+    builder.build().map_err(Error::Validation)
+}
+
+/// Returns the run options of the environment given with `--env`, if any, with the timeouts
+/// given with `--lock-timeout` and `--statement-timeout` in place of the environment's. Trek sets
+/// them while applying or rolling back each migration, local to its transaction if it has one.
+fn run_options(config: &Config, args: &Args) -> trek::Result<RunOptions> {
+    let mut options = environment(args)
+        .and_then(|environment| config.environment(environment))
+        .map(|profile| profile.run_options())
+        .unwrap_or_else(RunOptions::new);
+    if let Some(ref timeout) = args.flag_lock_timeout {
+        options = options.lock_timeout(try!(parse_milliseconds("--lock-timeout", timeout)));
+    }
+    if let Some(ref timeout) = args.flag_statement_timeout {
+        options = options.statement_timeout(
+            try!(parse_milliseconds("--statement-timeout", timeout))
+        );
+    }
+    Ok(options)
+}
+
+/// Parses the value given with the flag as a number of milliseconds.
+fn parse_milliseconds(flag: &str, value: &str) -> trek::Result<Duration> {
+    match value.parse::<u64>() {
+        Ok(milliseconds) => Ok(Duration::from_millis(milliseconds)),
+        Err(_) => {
+            Err(Error::from_message(
+                format!("{} must be a number of milliseconds, not {}", flag, value)
+            ))
+        }
+    }
+}
+
+/// Loads the SQL seeds in the configured seeds directory, if it exists, restricting those in a
+/// subdirectory named after a configured environment to that environment.
+fn load_seeds(config: &Config) -> trek::Result<Vec<SqlSeed>> {
+    let seeds_dir = config.seeds_dir();
+    if !seeds_dir.is_dir() {
+        return Ok(vec![]);
+    }
+    let mut seed_environments = HashMap::new();
+    for environment in config.environment_names() {
+        let environment_dir = seeds_dir.join(environment);
+        if !environment_dir.is_dir() {
+            continue;
+        }
+        let environment_seeds = try!(
+            seed::load_directory(&environment_dir).with_context(|| {
+                format!("Error loading the seeds in {}", environment_dir.display())
+            })
+        );
+        for environment_seed in environment_seeds {
+            seed_environments.insert(environment_seed.to_string(), environment);
+        }
+    }
+    let seeds = try!(
+        seed::load_directory(seeds_dir)
+            .with_context(|| format!("Error loading the seeds in {}", seeds_dir.display()))
+    );
+    Ok(seeds.into_iter()
+        .map(|seed| match seed_environments.get(&seed.to_string()) {
+            Some(environment) => seed.with_environments(&[*environment]),
+            None => seed
+        })
+        .collect())
+}
+
+/// Connects to the database given with `--database-url`, or else as described by
+/// `Config::database_url()`.
+fn connect(config: &Config, args: &Args) -> trek::Result<Connection> {
+    match args.flag_database_url {
+        Some(ref database_url) => {
+            Connection::connect(&**database_url, TlsMode::None)
+                .context("Error connecting to the database")
+        },
+        None => config.connect(environment(args))
+    }
+}
+
+/// Returns the URL of the database given with `--database-url`, or else by
+/// `Config::database_url()`.
+fn database_url(config: &Config, args: &Args) -> trek::Result<String> {
+    let database_url = args.flag_database_url.clone()
+        .or_else(|| config.database_url(environment(args)));
+    match database_url {
+        Some(database_url) => Ok(database_url),
+        None => {
+            Err(Error::Validation(
+                ValidationError::new(
+                    "No database URL was given with --database-url or configured for the \
+                    environment, and neither TREK_DATABASE_URL nor DATABASE_URL is set".to_owned()
+                ).with_hint("Pass --database-url, or set DATABASE_URL")
+            ))
+        }
+    }
+}
+
+/// Returns the environment given with `--env`, if any.
+fn environment(args: &Args) -> Option<&str> {
+    args.flag_env.as_ref().map(|environment| &**environment)
+}
+
+/// Resolves the version given with `--to` to the version of a registered migration, accepting a
+/// prefix of it, e.g. the date of a timestamp version, as long as it's a prefix of no other.
+fn resolve_target(migrations: &MigrationIndex, target: &str) -> trek::Result<MigrationVersion> {
+    if let Err(error) = target.parse::<MigrationVersion>() {
+        return Err(Error::version_parse(format!("The version {} isn't a number", target), error));
+    }
+    let matching = migrations.iter()
+        .filter(|migration| migration.version().to_string().starts_with(target))
+        .collect::<Vec<_>>();
+    if let Some(migration) = matching.iter().find(|migration| {
+        migration.version().to_string() == target
+    }) {
+        return Ok(migration.version());
+    }
+    match matching.len() {
+        0 => Err(Error::from_message(format!("No migration's version starts with {}", target))),
+        1 => Ok(matching[0].version()),
+        _ => {
+            let names = matching.iter()
+                .map(|migration| migration.to_string())
+                .collect::<Vec<_>>();
+            Err(Error::from_message(format!(
+                "The version {} is ambiguous, it could be that of any of {}",
+                target,
+                names.join(", ")
+            )))
+        }
+    }
+}
+
+/// Asks on the terminal whether to go ahead with an action on the database, naming the database
+/// and its server so it's clear which one will be changed, failing if the answer is no. The
+/// question goes to standard error, so it doesn't mix with JSON output.
+fn confirm(connection: &GenericConnection, action: &str) -> trek::Result<()> {
+    let rows = try!(
+        connection.query(
+            "SELECT current_database()::text, \
+                COALESCE(host(inet_server_addr()) || ':' || inet_server_port(), 'local socket');",
+            &[]
+        ).context("Error reading which database is connected to")
+    );
+    let row = rows.get(0);
+    let database: String = row.get(0);
+    let server: String = row.get(1);
+    let question = format!("{} on database {} at {}?", action, database, server);
+    let stdin = io::stdin();
+    let confirmed = try!(
+        interactive::confirm(&mut stdin.lock(), &mut io::stderr(), &question, false)
+            .context("Error asking for confirmation; pass --yes to go ahead without asking")
+    );
+    if confirmed {
+        Ok(())
+    } else {
+        Err(Error::from_message("Cancelled, the database wasn't changed".to_owned()))
+    }
+}
+
+/// Asks for confirmation before applying migrations, up to the one with the target version if
+/// any, that include statements destroying data, listing the statements.
+fn confirm_destructive_migrations(
+    migrations: &MigrationIndex,
+    connection: &Connection,
+    target: Option<MigrationVersion>
+) -> trek::Result<()> {
+    let destructive = try!(destructive_statements(migrations, connection, target));
+    if destructive.is_empty() {
+        return Ok(());
+    }
+    print_destructive_statements(
+        "These statements in the migrations to apply destroy data:",
+        &destructive
+    );
+    confirm(connection, "Apply the migrations")
+}
+
+/// Returns the statements destroying data in the outstanding migrations, up to the one with the
+/// target version if any, along with the names of their migrations.
+fn destructive_statements(
+    migrations: &MigrationIndex,
+    connection: &Connection,
+    target: Option<MigrationVersion>
+) -> trek::Result<Vec<(String, String)>> {
+    let mut destructive = vec![];
+    for migration in try!(migrations.plan(connection)) {
+        let up_sql = migration.up_sql().unwrap_or_default();
+        for statement in sql_migration::destructive_statements(&up_sql) {
+            destructive.push((migration.to_string(), statement));
+        }
+        if Some(migration.version()) == target {
+            break;
+        }
+    }
+    Ok(destructive)
+}
+
+/// Prints the statements destroying data, with the names of their migrations, under the heading
+/// on standard error, so they don't mix with JSON output.
+fn print_destructive_statements(heading: &str, destructive: &[(String, String)]) {
+    eprintln!("{}", heading);
+    for &(ref name, ref statement) in destructive {
+        eprintln!("  {}: {}", name, statement);
+    }
+}
+
+/// Applies the outstanding migrations up to the one with the target version, if any, as
+/// `migrate()` does, asking for confirmation first if they destroy data, unless `--yes` was
+/// given, then writes the schema to the structure file and prints what was done.
+fn apply_migrations(
+    config: &Config,
+    args: &Args,
+    migrations: &MigrationIndex,
+    connection: &Connection,
+    target: Option<MigrationVersion>,
+    json: bool
+) -> trek::Result<Outcome> {
+    if !args.flag_yes {
+        try!(confirm_destructive_migrations(migrations, connection, target));
+    }
+    let report = try!(migrate(migrations, connection, target));
+    let structure_file = try!(write_structure(config, args, migrations));
+    print_migrated(&report, structure_file.as_ref().map(|path| path.as_path()), json);
+    Ok(if report.applied().is_empty() { Outcome::NothingToDo } else { Outcome::Done })
+}
+
+/// Prints what `migrate` did and the file the schema was written to, if any, as JSON if `json`
+/// is true.
+fn print_migrated(report: &MigrationReport, structure_file: Option<&Path>, json: bool) {
+    if json {
+        let mut document = report_json(report);
+        document.insert("structure_file".to_owned(), path_json(structure_file));
+        print_json(document);
+    } else {
+        print_migration_report(report, structure_file);
+    }
+}
+
+/// Watches the migrations directory, checking it for changes every `WATCH_INTERVAL_MS`, and
+/// applies the migrations whenever it changes, until the process is interrupted. Failures are
+/// printed without ending the watch, so a broken migration can be fixed and applied on the next
+/// change, and so can failures to read the directory, e.g. because an editor deleted a temporary
+/// file while it was read, which are tried again on the next check.
+fn watch(config: &Config, args: &Args, json: bool) -> trek::Result<Outcome> {
+    let connection = try!(connect(config, args));
+    let migrations_dir = config.migrations_dir();
+    if !json {
+        println!("Watching {} for new migrations, until interrupted", migrations_dir.display());
+    }
+    let mut snapshot = None;
+    loop {
+        let current = directory_snapshot(migrations_dir).with_context(|| {
+            format!("Error reading the migrations directory {}", migrations_dir.display())
+        });
+        match current {
+            Ok(current) => {
+                if snapshot.as_ref() != Some(&current) {
+                    snapshot = Some(current);
+                    if let Err(error) = apply_written_migrations(config, args, &connection, json) {
+                        print_error(&error, json);
+                    }
+                }
+            },
+            Err(error) => print_error(&error, json)
+        }
+        thread::sleep(Duration::from_millis(WATCH_INTERVAL_MS));
+    }
+}
+
+/// Returns the size and modification time of every file in the directory and its
+/// subdirectories, so changes to them can be noticed.
+fn directory_snapshot(directory: &Path) -> io::Result<BTreeMap<PathBuf, (u64, SystemTime)>> {
+    let mut snapshot = BTreeMap::new();
+    for entry in try!(fs::read_dir(directory)) {
+        let entry = try!(entry);
+        let metadata = try!(entry.metadata());
+        if metadata.is_dir() {
+            snapshot.extend(try!(directory_snapshot(&entry.path())));
+        } else {
+            snapshot.insert(entry.path(), (metadata.len(), try!(metadata.modified())));
+        }
+    }
+    Ok(snapshot)
+}
+
+/// Loads the migrations again and applies the outstanding ones, stopping before the first that
+/// has no statements yet, which is probably still being written. There's no one to ask for
+/// confirmation while watching, so statements destroying data are listed instead, unless
+/// `--yes` was given, and the schema is only written to the structure file if migrations were
+/// applied.
+fn apply_written_migrations(
+    config: &Config,
+    args: &Args,
+    connection: &Connection,
+    json: bool
+) -> trek::Result<()> {
+    let migrations = try!(load_migrations(config, args));
+    let target = {
+        let planned = try!(migrations.plan(connection));
+        let unwritten = planned.iter().position(|migration| {
+            migration.up_sql().map(|sql| sql_migration::split_statements(&sql).is_empty()) ==
+                Some(true)
+        });
+        match unwritten {
+            Some(index) => {
+                if !json {
+                    println!("Waiting for {} to be written", planned[index]);
+                }
+                if index == 0 {
+                    return Ok(());
+                }
+                Some(planned[index - 1].version())
+            },
+            None => None
+        }
+    };
+    if !args.flag_yes {
+        let destructive = try!(destructive_statements(&migrations, connection, target));
+        if !destructive.is_empty() {
+            print_destructive_statements(
+                "Applying these statements in the migrations, which destroy data:",
+                &destructive
+            );
+        }
+    }
+    let report = try!(migrate(&migrations, connection, target));
+    let structure_file = if report.applied().is_empty() {
+        None
+    } else {
+        try!(write_structure(config, args, &migrations))
+    };
+    print_migrated(&report, structure_file.as_ref().map(|path| path.as_path()), json);
+    Ok(())
+}
+
+/// Applies the outstanding migrations, each in its own transaction, up to and including the one
+/// with the target version, if any.
+fn migrate(
+    migrations: &MigrationIndex,
+    connection: &Connection,
+    target: Option<MigrationVersion>
+) -> trek::Result<MigrationReport> {
+    match target {
+        Some(target) => migrations.run_in_transactions_to(connection, target),
+        None => migrations.run_in_transactions(connection)
+    }
+}
+
+/// Prints the migrations `migrate` applied, skipped and deferred, any warnings they left, and the
+/// file the schema was written to, if any.
+fn print_migration_report(report: &MigrationReport, structure_file: Option<&Path>) {
+    if report.applied().is_empty() {
+        println!("The database is up to date, there are no migrations to apply.");
+    }
+    for name in report.applied() {
+        println!("Applied {}", name);
+    }
+    for name in report.skipped() {
+        println!("Skipped {}", name);
+    }
+    for name in report.deferred() {
+        println!("Deferred {} until a later run", name);
+    }
+    for warning in report.warnings() {
+        println!("Warning from {}: {}", warning.migration(), warning.message());
+    }
+    if let Some(path) = structure_file {
+        println!("Wrote the schema to {}", path.display());
+    }
+}
+
+/// Writes the schema to the file given with `--structure-file`, or else configured as
+/// `structure_file` in trek.toml, if any, as described by `trek::squash::dump_structure()`,
+/// returning the file written.
+fn write_structure(
+    config: &Config,
+    args: &Args,
+    migrations: &MigrationIndex
+) -> trek::Result<Option<PathBuf>> {
+    let path = match args.flag_structure_file {
+        Some(ref path) => PathBuf::from(path),
+        None => {
+            match config.structure_file() {
+                Some(path) => path.to_path_buf(),
+                None => return Ok(None)
+            }
+        }
+    };
+    let database_url = try!(database_url(config, args));
+    let structure = try!(squash::dump_structure(&database_url, migrations));
+    try!(
+        File::create(&path)
+            .and_then(|mut file| file.write_all(structure.as_bytes()))
+            .with_context(|| format!("Error writing the schema to {}", path.display()))
+    );
+    Ok(Some(path))
+}
+
+/// Prints the migrations `migrate` would apply and the SQL of each, reading the database in a
+/// read-only transaction, so the plan can be reviewed before it's applied. As JSON, the plan is
+/// like `{"migrations": [{"version": ..., "name": ..., "sql": ...}], "pending": 1}`, with a null
+/// `sql` for migrations that aren't SQL migrations.
+fn print_plan(
+    migrations: &MigrationIndex,
+    connection: &Connection,
+    json: bool
+) -> trek::Result<Outcome> {
+    let transaction = try!(connection.transaction().context("Failed to start transaction"));
+    try!(
+        transaction.batch_execute("SET TRANSACTION READ ONLY;")
+            .context("Failed to make the transaction read-only")
+    );
+    let planned = try!(migrations.plan(&transaction));
+    let outcome = if planned.is_empty() { Outcome::NothingToDo } else { Outcome::Pending };
+    if json {
+        let planned_json = planned.iter().map(|migration| {
+            let mut object = BTreeMap::new();
+            object.insert("version".to_owned(), Json::U64(migration.version().value()));
+            object.insert("name".to_owned(), Json::String(migration.to_string()));
+            object.insert("sql".to_owned(), migration.up_sql().to_json());
+            Json::Object(object)
+        }).collect::<Vec<_>>();
+        let mut document = BTreeMap::new();
+        document.insert("migrations".to_owned(), Json::Array(planned_json));
+        document.insert("pending".to_owned(), Json::U64(planned.len() as u64));
+        print_json(document);
+        return Ok(outcome);
+    }
+    if planned.is_empty() {
+        println!("-- The database is up to date, there are no migrations to apply.");
+        return Ok(outcome);
+    }
+    println!("-- Migrations that would be applied, in order: {}", planned.len());
+    for migration in planned {
+        println!();
+        println!("-- {}", migration);
+        match migration.up_sql() {
+            Some(sql) => println!("{}", sql.trim_end()),
+            None => println!("-- (not a SQL migration, so its statements can't be shown)")
+        }
+    }
+    Ok(outcome)
+}
+
+/// Rolls back the last applied migration, or every migration applied after the one with the
+/// target version, if any, in a transaction, asking for confirmation first unless `yes` is true,
+/// and prints the migrations rolled back and the latest one still applied.
+fn rollback(
+    migrations: &MigrationIndex,
+    connection: &Connection,
+    target: Option<MigrationVersion>,
+    yes: bool,
+    json: bool
+) -> trek::Result<Outcome> {
+    let transaction = try!(connection.transaction().context("Failed to start transaction"));
+    let history = try!(
+        migrations.history(&transaction).context("Error reading the migration history")
+    );
+    let schema_version = try!(
+        migrations.schema_version(&transaction).context("Error reading the schema version")
+    );
+    // there's nothing to confirm if no migrations are applied
+    match schema_version {
+        Some(ref schema_version) if !yes => {
+            let action = match target {
+                Some(target) => {
+                    let name = migrations.get_version(target)
+                        .map(|migration| migration.to_string())
+                        .unwrap_or_else(|| target.to_string());
+                    format!("Roll back every migration applied after {}", name)
+                },
+                None => format!("Roll back migration {}", schema_version)
+            };
+            try!(confirm(&transaction, &action));
+        },
+        _ => {}
+    }
+    match target {
+        Some(target) => try!(migrations.rollback_to(&transaction, target)),
+        None => try!(migrations.rollback(&transaction))
+    }
+    let schema_version = try!(
+        migrations.schema_version(&transaction).context("Error reading the schema version")
+    );
+    let remaining = try!(
+        migrations.history(&transaction).context("Error reading the migration history")
+    ).iter().map(|entry| entry.version()).collect::<HashSet<_>>();
+    try!(transaction.commit().context("Failed to commit the rollback"));
+    let rolled_back = history.iter().rev()
+        .filter(|entry| !remaining.contains(&entry.version()))
+        .map(|entry| entry.name().to_owned())
+        .collect::<Vec<_>>();
+    if json {
+        let mut document = BTreeMap::new();
+        document.insert("rolled_back".to_owned(), names_json(&rolled_back));
+        document.insert("schema_version".to_owned(), schema_version.to_json());
+        print_json(document);
+    } else if rolled_back.is_empty() {
+        println!("There are no migrations to roll back");
+    } else {
+        for name in &rolled_back {
+            println!("Rolled back {}", name);
+        }
+        match schema_version {
+            Some(name) => println!("The latest applied migration is now {}", name),
+            None => println!("No migrations are applied")
+        }
+    }
+    Ok(if rolled_back.is_empty() { Outcome::NothingToDo } else { Outcome::Done })
+}
+
+/// Loads the seeds that haven't been loaded yet, or the seed with the given name even if it has,
+/// in a transaction, and prints the seeds loaded and skipped and any warnings they left.
+fn run_seeds(
+    migrations: &MigrationIndex,
+    connection: &Connection,
+    only: Option<&str>,
+    json: bool
+) -> trek::Result<Outcome> {
+    let transaction = try!(connection.transaction().context("Failed to start transaction"));
+    let report = match only {
+        Some(name) => try!(migrations.run_seed(&transaction, name)),
+        None => try!(migrations.run_pending_seeds(&transaction))
+    };
+    try!(transaction.commit().context("Failed to commit the seeds"));
+    let outcome = if report.applied().is_empty() { Outcome::NothingToDo } else { Outcome::Done };
+    if json {
+        print_json(report_json(&report));
+        return Ok(outcome);
+    }
+    for name in report.applied() {
+        println!("Loaded seed {}", name);
+    }
+    for name in report.skipped() {
+        println!("Skipped seed {}, which doesn't apply to this environment", name);
+    }
+    for warning in report.warnings() {
+        println!("Warning from seed {}: {}", warning.migration(), warning.message());
+    }
+    if report.applied().is_empty() && report.skipped().is_empty() {
+        println!("No seeds to load");
+    }
+    Ok(outcome)
+}
+
+/// Starts tracking the versions of an existing database in a transaction, recording the migration
+/// with the target version, or else the newest migration, and the ones before it as applied, and
+/// prints the migrations recorded.
+fn baseline(
+    migrations: &MigrationIndex,
+    connection: &GenericConnection,
+    target: Option<MigrationVersion>,
+    json: bool
+) -> trek::Result<Outcome> {
+    let newest_version = migrations.iter().last().map(|migration| migration.version());
+    let baseline_version = match target.or(newest_version) {
+        Some(version) => version,
+        None => {
+            return Err(Error::from_message(
+                "There are no migrations to baseline the database at".to_owned()
+            ));
+        }
+    };
+    let transaction = try!(connection.transaction().context("Failed to start transaction"));
+    try!(migrations.baseline(&transaction, baseline_version));
+    let recorded = migrations.iter()
+        .take_while(|migration| migration.version() != baseline_version)
+        .count() + 1;
+    let names = migrations.iter()
+        .take(recorded)
+        .map(|migration| migration.to_string())
+        .collect::<Vec<_>>();
+    let baseline_name = &names[recorded - 1];
+    try!(transaction.commit().context("Failed to commit the baseline"));
+    if json {
+        let mut document = BTreeMap::new();
+        document.insert("recorded".to_owned(), names_json(&names));
+        document.insert("baseline".to_owned(), Json::String(baseline_name.clone()));
+        print_json(document);
+        return Ok(Outcome::Done);
+    }
+    for name in &names {
+        println!("Recorded {} as applied", name);
+    }
+    println!("Baselined the database at {}", baseline_name);
+    Ok(Outcome::Done)
+}
+
+/// The state of a migration listed by `trek status`.
+struct MigrationStatus {
+    version: MigrationVersion,
+    name: String,
+    /// "applied", "pending", or "unknown" for applied migrations that aren't registered
+    state: &'static str,
+    /// when the migration was applied, or None if it's pending or its history was pruned
+    applied_at: Option<DateTime<UTC>>,
+}
+
+impl ToJson for MigrationStatus {
+    fn to_json(&self) -> Json {
+        let mut object = BTreeMap::new();
+        object.insert("version".to_owned(), Json::U64(self.version.value()));
+        object.insert("name".to_owned(), Json::String(self.name.clone()));
+        object.insert("state".to_owned(), Json::String(self.state.to_owned()));
+        object.insert(
+            "applied_at".to_owned(),
+            self.applied_at.map(|applied_at| applied_at.to_rfc3339()).to_json()
+        );
+        Json::Object(object)
+    }
+}
+
+/// Lists the registered migrations and whether and when each was applied, followed by any
+/// applied migrations that aren't registered, as a table or as a JSON document that also counts
+/// the migrations in each state, with an outcome of drifted if any applied migration isn't
+/// registered.
+fn status(
+    migrations: &MigrationIndex,
+    connection: &Connection,
+    json: bool
+) -> trek::Result<Outcome> {
+    let history = try!(
+        migrations.history(connection).context("Error reading the migration history")
+    );
+    let applied = history.iter()
+        .map(|entry| (entry.version(), entry))
+        .collect::<HashMap<_, _>>();
+    // the migrations up to the baseline left by pruning the history count as applied
+    let baseline_version = history.iter()
+        .filter(|entry| entry.is_baseline())
+        .map(|entry| entry.version())
+        .max();
+    let mut statuses = vec![];
+    for migration in migrations.iter() {
+        let entry = applied.get(&migration.version());
+        let pruned = match baseline_version {
+            Some(baseline_version) => migration.version() <= baseline_version,
+            None => false
+        };
+        statuses.push(MigrationStatus {
+            version: migration.version(),
+            name: migration.to_string(),
+            state: if entry.is_some() || pruned { "applied" } else { "pending" },
+            applied_at: entry.map(|entry| entry.applied_at()),
+        });
+    }
+    for entry in &history {
+        if migrations.get_version(entry.version()).is_none() && !entry.is_baseline() {
+            statuses.push(MigrationStatus {
+                version: entry.version(),
+                name: entry.name().to_owned(),
+                state: "unknown",
+                applied_at: Some(entry.applied_at()),
+            });
+        }
+    }
+    if json {
+        print_json(status_json(&statuses));
+    } else {
+        for row in status_table(&statuses) {
+            println!("{}", row);
+        }
+    }
+    Ok(status_outcome(&statuses))
+}
+
+/// Returns the outcome of `trek status` for the statuses: drifted if any applied migration
+/// isn't registered, pending if any migration is pending, or else done.
+fn status_outcome(statuses: &[MigrationStatus]) -> Outcome {
+    if statuses.iter().any(|status| status.state == "unknown") {
+        Outcome::Drifted
+    } else if statuses.iter().any(|status| status.state == "pending") {
+        Outcome::Pending
+    } else {
+        Outcome::Done
+    }
+}
+
+/// Returns the rows of a table of the statuses with a column for each field, headed by the
+/// column names.
+fn status_table(statuses: &[MigrationStatus]) -> Vec<String> {
+    let versions = statuses.iter()
+        .map(|status| status.version.to_string())
+        .collect::<Vec<_>>();
+    let version_width = versions.iter().map(|version| version.len()).max().unwrap_or(0);
+    let version_width = cmp::max(version_width, "VERSION".len());
+    let mut rows = vec![status_row(&["VERSION", "STATE", "APPLIED AT", "NAME"], version_width)];
+    for (status, version) in statuses.iter().zip(&versions) {
+        let applied_at = status.applied_at
+            .map(|applied_at| applied_at.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_default();
+        rows.push(status_row(&[version, status.state, &applied_at, &status.name], version_width));
+    }
+    rows
+}
+
+/// Returns a row of the status table, with the version column the given width.
+fn status_row(columns: &[&str; 4], version_width: usize) -> String {
+    format!(
+        "{:<width$}  {:<7}  {:<19}  {}",
+        columns[0],
+        columns[1],
+        columns[2],
+        columns[3],
+        width = version_width
+    )
+}
+
+/// Returns the statuses as the fields of a JSON document, like
+/// `{"migrations": [...], "applied": 3, "pending": 1, "unknown": 0}`, so that scripts can check
+/// for pending migrations.
+fn status_json(statuses: &[MigrationStatus]) -> BTreeMap<String, Json> {
+    let count = |state: &str| {
+        Json::U64(statuses.iter().filter(|status| status.state == state).count() as u64)
+    };
+    let mut document = BTreeMap::new();
+    document.insert(
+        "migrations".to_owned(),
+        Json::Array(statuses.iter().map(ToJson::to_json).collect())
+    );
+    document.insert("applied".to_owned(), count("applied"));
+    document.insert("pending".to_owned(), count("pending"));
+    document.insert("unknown".to_owned(), count("unknown"));
+    document
+}
+
+/// Checks the database against the migrations without changing it, printing what was found, as
+/// JSON if `json` is true, with an outcome of drifted if any problems were found.
+fn verify(
+    migrations: &MigrationIndex,
+    connection: &Connection,
+    json: bool
+) -> trek::Result<Outcome> {
+    let report = try!(migrations.verify(connection));
+    if json {
+        print_verification_json(&report);
+    } else {
+        print_verification(&report);
+    }
+    Ok(if report.is_ok() { Outcome::Done } else { Outcome::Drifted })
+}
+
+/// Prints the problems `verify` found, one per line, followed by the number of pending
+/// migrations.
+fn print_verification(report: &VerificationReport) {
+    if let Some(name) = report.dirty() {
+        println!("Dirty:    {} failed partway through being applied", name);
+    }
+    for name in report.modified() {
+        println!("Modified: {} has been modified since it was applied", name);
+    }
+    for name in report.missing() {
+        println!("Missing:  {} is applied but no longer exists", name);
+    }
+    for name in report.unknown() {
+        println!("Unknown:  {} is applied but newer than every migration", name);
+    }
+    if report.is_ok() {
+        println!("No problems found.");
+    } else {
+        println!("The database has drifted from the migrations.");
+    }
+    println!("{} migrations are pending.", report.pending().len());
+}
+
+/// Prints what `verify` found as a JSON object, with whether it found no problems as "ok".
+fn print_verification_json(report: &VerificationReport) {
+    let mut document = BTreeMap::new();
+    document.insert("ok".to_owned(), Json::Boolean(report.is_ok()));
+    let dirty = match report.dirty() {
+        Some(name) => Json::String(name.to_owned()),
+        None => Json::Null
+    };
+    document.insert("dirty".to_owned(), dirty);
+    document.insert("modified".to_owned(), names_json(report.modified()));
+    document.insert("missing".to_owned(), names_json(report.missing()));
+    document.insert("unknown".to_owned(), names_json(report.unknown()));
+    document.insert("pending".to_owned(), names_json(report.pending()));
+    print_json(document);
+}
+
+/// Creates, drops or resets the database, as given by `trek db create`, `trek db drop` or
+/// `trek db reset`. Resetting loads the migrations first, so the database isn't dropped if they
+/// can't be loaded.
+fn manage_database(config: &Config, args: &Args, json: bool) -> trek::Result<Outcome> {
+    let database_url = try!(database_url(config, args));
+    let mut document = BTreeMap::new();
+    if args.cmd_create {
+        let created = try!(create_database(&database_url, json));
+        if json {
+            document.insert("created".to_owned(), Json::Boolean(created));
+            print_json(document);
+        }
+        return Ok(if created { Outcome::Done } else { Outcome::NothingToDo });
+    }
+    let migrations = if args.cmd_reset {
+        Some(try!(load_migrations(config, args)))
+    } else {
+        None
+    };
+    let dropped = try!(drop_database(&database_url, args.flag_yes, json));
+    document.insert("dropped".to_owned(), Json::Boolean(dropped));
+    let migrations = match migrations {
+        Some(migrations) => migrations,
+        None => {
+            if json {
+                print_json(document);
+            }
+            return Ok(if dropped { Outcome::Done } else { Outcome::NothingToDo });
+        }
+    };
+    let created = try!(create_database(&database_url, json));
+    let connection = try!(
+        Connection::connect(&*database_url, TlsMode::None)
+            .context("Error connecting to the database")
+    );
+    let report = try!(migrate(&migrations, &connection, None));
+    let structure_file = try!(write_structure(config, args, &migrations));
+    let structure_file = structure_file.as_ref().map(|path| path.as_path());
+    if json {
+        document.insert("created".to_owned(), Json::Boolean(created));
+        document.extend(report_json(&report));
+        document.insert("structure_file".to_owned(), path_json(structure_file));
+        print_json(document);
+    } else {
+        print_migration_report(&report, structure_file);
+    }
+    Ok(Outcome::Done)
+}
+
+/// Creates the database if it doesn't exist, returning true if it was created, and saying so
+/// unless the output is JSON.
+fn create_database(database_url: &str, json: bool) -> trek::Result<bool> {
+    let created = try!(database::create_database(database_url));
+    if !json {
+        if created {
+            println!("Created the database");
+        } else {
+            println!("The database already exists");
+        }
+    }
+    Ok(created)
+}
+
+/// Drops the database if it exists, asking for confirmation first unless `yes` is true, returning
+/// true if it was dropped, and saying so unless the output is JSON.
+fn drop_database(database_url: &str, yes: bool, json: bool) -> trek::Result<bool> {
+    if !yes {
+        // if the database can't be connected to, e.g. because it doesn't exist, dropping it
+        // reports the problem
+        if let Ok(connection) = Connection::connect(database_url, TlsMode::None) {
+            try!(confirm(&connection, "Drop and destroy all the data"));
+        }
+    }
+    let dropped = try!(database::drop_database(database_url));
+    if !json {
+        if dropped {
+            println!("Dropped the database");
+        } else {
+            println!("The database doesn't exist");
+        }
+    }
+    Ok(dropped)
+}
+
+/// Creates a new migration in the configured migrations directory, from the template chosen with
+/// `--sql`, `--rust` or `--reversible`, or else the configured one, defaulting to SQL, recording
+/// the metadata given with `--description`, `--author` and `--ticket`.
+fn new_migration(config: &Config, args: &Args, json: bool) -> trek::Result<Outcome> {
+    let mut generator = config.generator().template(migration_template(config, args));
+    if let Some(ref description) = args.flag_description {
+        generator = generator.description(description);
+    }
+    if let Some(ref author) = args.flag_author {
+        generator = generator.author(author);
+    }
+    if let Some(ref ticket) = args.flag_ticket {
+        generator = generator.ticket(ticket);
+    }
+    let migration = try!(generator.create(&args.arg_name, &Scaffold::Empty));
+    if json {
+        let mut document = BTreeMap::new();
+        document.insert("name".to_owned(), Json::String(migration.name().to_owned()));
+        let paths = migration.paths().iter()
+            .map(|path| path_json(Some(path.as_path())))
+            .collect::<Vec<_>>();
+        document.insert("paths".to_owned(), Json::Array(paths));
+        document.insert("warnings".to_owned(), names_json(migration.warnings()));
+        print_json(document);
+        return Ok(Outcome::Done);
+    }
+    for path in migration.paths() {
+        println!("Created {}", path.display());
+    }
+    for warning in migration.warnings() {
+        println!("Warning: {}", warning);
+    }
+    Ok(Outcome::Done)
+}
+
+/// Returns the template chosen for a new migration with `--sql`, `--rust` or `--reversible`, or
+/// else the configured one, defaulting to SQL.
+fn migration_template(config: &Config, args: &Args) -> MigrationTemplate {
+    if args.flag_rust {
+        MigrationTemplate::Rust
+    } else if args.flag_reversible {
+        MigrationTemplate::Reversible
+    } else if args.flag_sql {
+        MigrationTemplate::Sql
+    } else {
+        config.template_setting().unwrap_or(MigrationTemplate::Sql)
+    }
+}
+
+/// Returns the applied, skipped and deferred migrations or seeds in the report, and the warnings
+/// they left, as the fields of a JSON object.
+fn report_json(report: &MigrationReport) -> BTreeMap<String, Json> {
+    let warnings = report.warnings().iter().map(|warning| {
+        let mut object = BTreeMap::new();
+        object.insert("migration".to_owned(), Json::String(warning.migration().to_owned()));
+        object.insert("message".to_owned(), Json::String(warning.message().to_owned()));
+        Json::Object(object)
+    }).collect::<Vec<_>>();
+    let mut document = BTreeMap::new();
+    document.insert("applied".to_owned(), names_json(report.applied()));
+    document.insert("skipped".to_owned(), names_json(report.skipped()));
+    document.insert("deferred".to_owned(), names_json(report.deferred()));
+    document.insert("warnings".to_owned(), Json::Array(warnings));
+    document
+}
+
+/// Returns the names as a JSON array of strings.
+fn names_json(names: &[String]) -> Json {
+    Json::Array(names.iter().map(ToJson::to_json).collect())
+}
+
+/// Returns the path as a JSON string, or null if there's none.
+fn path_json(path: Option<&Path>) -> Json {
+    match path {
+        Some(path) => Json::String(path.display().to_string()),
+        None => Json::Null
+    }
+}
+
+/// Prints the JSON object as a document on its own.
+fn print_json(document: BTreeMap<String, Json>) {
+    println!("{}", Json::Object(document).pretty());
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::{Read, Write};
+    use std::path::Path;
+    use std::time::Duration;
+
+    use chrono::{TimeZone, UTC};
+    use docopt::Docopt;
+    use postgres::{Connection, TlsMode};
+    use rustc_serialize::json::Json;
+    use trek::MigrationTemplate;
+    use trek::config::Config;
+    use trek::error::Error;
+    use trek::migration::Migration;
+    use trek::migration_context::RunOptions;
+    use trek::migration_index::MigrationIndex;
+    use trek::migration_version::MigrationVersion;
+    use trek::sql_migration::SqlMigration;
+
+    use super::{
+        baseline,
+        directory_snapshot,
+        error_exit_status,
+        migration_template,
+        new_migration,
+        resolve_target,
+        run_options,
+        status_json,
+        status_outcome,
+        status_table,
+        wants_json,
+        Args,
+        MigrationStatus,
+        Outcome,
+        EXIT_DRIFTED,
+        EXIT_FAILED,
+        EXIT_NOTHING_TO_DO,
+        EXIT_OK,
+        EXIT_PENDING,
+        USAGE,
+    };
+
+
+    /// Parses the command line arguments as `main()` does.
+    fn parse_args(argv: &[&str]) -> Args {
+        Docopt::new(USAGE)
+            .and_then(|docopt| docopt.argv(argv.iter().cloned()).decode())
+            .unwrap()
+    }
+
+    /// Returns the arguments as `env::args()` would.
+    fn argv(arguments: &[&str]) -> Vec<String> {
+        arguments.iter().map(|argument| argument.to_string()).collect()
+    }
+
+    /// Connects to the database the integration tests use, given by `TREK_TEST_DB_PARAMS`.
+    fn new_test_connection() -> Connection {
+        let db_params = env::var("TREK_TEST_DB_PARAMS")
+            .expect("TREK_TEST_DB_PARAMS should hold a connection string for a test database");
+        Connection::connect(&*db_params, TlsMode::None).unwrap()
+    }
+
+    /// Returns an irreversible SQL migration with the version.
+    fn sql_migration(version: u64, name: &str) -> SqlMigration {
+        SqlMigration::irreversible(MigrationVersion::new(version), name, "SELECT 1;")
+    }
+
+    /// Returns the status of a migration with the version in the state.
+    fn migration_status(version: u64, state: &'static str) -> MigrationStatus {
+        MigrationStatus {
+            version: MigrationVersion::new(version),
+            name: format!("migration_{}", version),
+            state: state,
+            applied_at: None,
+        }
+    }
+
+    #[test]
+    fn maps_outcomes_to_exit_statuses() {
+        let args = parse_args(&["trek", "status"]);
+        assert_eq!(Outcome::Done.exit_status(&args), EXIT_OK);
+        assert_eq!(Outcome::NothingToDo.exit_status(&args), EXIT_OK);
+        assert_eq!(Outcome::Pending.exit_status(&args), EXIT_PENDING);
+        assert_eq!(Outcome::Drifted.exit_status(&args), EXIT_DRIFTED);
+
+        let args = parse_args(&["trek", "migrate", "--exit-nothing-to-do"]);
+        assert_eq!(Outcome::NothingToDo.exit_status(&args), EXIT_NOTHING_TO_DO);
+        assert_eq!(Outcome::Done.exit_status(&args), EXIT_OK);
+    }
+
+    #[test]
+    fn finds_json_output_in_unparsed_arguments() {
+        assert!(wants_json(&argv(&["trek", "status", "--json"])));
+        assert!(wants_json(&argv(&["trek", "status", "--format=json"])));
+        assert!(wants_json(&argv(&["trek", "status", "--format", "json"])));
+        assert!(!wants_json(&argv(&["trek", "status", "--format", "text"])));
+        assert!(!wants_json(&argv(&["trek", "status", "--format"])));
+        assert!(!wants_json(&argv(&["trek", "new", "json"])));
+    }
+
+    #[test]
+    fn exits_with_the_drift_status_for_drift_errors() {
+        let unknown = Error::UnknownAppliedVersion {
+            message: "Migration 2_newer isn't registered".to_owned(),
+            migration_name: "2_newer".to_owned(),
+        };
+        assert_eq!(error_exit_status(&unknown), EXIT_DRIFTED);
+        let modified = Error::ModifiedMigration {
+            message: "Migration 1_first has been modified since it was applied".to_owned(),
+            migration_name: "1_first".to_owned(),
+        };
+        assert_eq!(error_exit_status(&modified), EXIT_DRIFTED);
+        let wrapped = Error::Context {
+            message: "Error applying the migrations".to_owned(),
+            cause: Box::new(modified),
+        };
+        assert_eq!(error_exit_status(&wrapped), EXIT_DRIFTED);
+        let failed = Error::from_message("The format must be text or json, not xml".to_owned());
+        assert_eq!(error_exit_status(&failed), EXIT_FAILED);
+    }
+
+    #[test]
+    fn reports_unknown_applied_migrations_as_drift_in_status() {
+        let mut statuses = vec![migration_status(1, "applied")];
+        assert_eq!(status_outcome(&statuses), Outcome::Done);
+        statuses.push(migration_status(2, "pending"));
+        assert_eq!(status_outcome(&statuses), Outcome::Pending);
+        statuses.push(migration_status(3, "unknown"));
+        assert_eq!(status_outcome(&statuses), Outcome::Drifted);
+    }
+
+    #[test]
+    fn lists_migration_statuses_as_a_table() {
+        let mut applied = migration_status(20150826001350, "applied");
+        applied.applied_at = Some(UTC.ymd(2015, 8, 26).and_hms(0, 14, 2));
+        let statuses = vec![applied, migration_status(3, "pending")];
+        assert_eq!(
+            status_table(&statuses),
+            vec![
+                "VERSION         STATE    APPLIED AT           NAME",
+                "20150826001350  applied  2015-08-26 00:14:02  migration_20150826001350",
+                "3               pending                       migration_3",
+            ]
+        );
+        assert_eq!(status_table(&[])[0], "VERSION  STATE    APPLIED AT           NAME");
+    }
+
+    #[test]
+    fn counts_migration_statuses_in_json() {
+        let mut applied = migration_status(1, "applied");
+        applied.applied_at = Some(UTC.ymd(2015, 8, 26).and_hms(0, 14, 2));
+        let statuses = vec![
+            applied,
+            migration_status(2, "pending"),
+            migration_status(3, "pending"),
+        ];
+        let document = Json::Object(status_json(&statuses));
+        assert_eq!(document["applied"], Json::U64(1));
+        assert_eq!(document["pending"], Json::U64(2));
+        assert_eq!(document["unknown"], Json::U64(0));
+        assert_eq!(document["migrations"][0]["version"], Json::U64(1));
+        assert_eq!(document["migrations"][0]["name"], Json::String("migration_1".to_owned()));
+        assert_eq!(
+            document["migrations"][0]["applied_at"],
+            Json::String("2015-08-26T00:14:02+00:00".to_owned())
+        );
+        assert_eq!(document["migrations"][1]["state"], Json::String("pending".to_owned()));
+        assert_eq!(document["migrations"][1]["applied_at"], Json::Null);
+    }
+
+    #[test]
+    fn resolves_target_versions_by_prefix() {
+        let migration = |version: u64, name: &str| -> Box<Migration> {
+            Box::new(sql_migration(version, name))
+        };
+        let migrations = MigrationIndex::new(vec![
+            migration(20150826001350, "create_users"),
+            migration(20150827001350, "create_orders"),
+            migration(20150827101350, "create_items"),
+        ]);
+        assert_eq!(
+            resolve_target(&migrations, "20150826").unwrap(),
+            MigrationVersion::new(20150826001350)
+        );
+        assert_eq!(
+            resolve_target(&migrations, "20150827101350").unwrap(),
+            MigrationVersion::new(20150827101350)
+        );
+        match resolve_target(&migrations, "20150827") {
+            Err(Error::Validation(ref error)) => assert!(error.to_string().contains("ambiguous")),
+            result => panic!("expected a validation error, got {:?}", result),
+        }
+        match resolve_target(&migrations, "2016") {
+            Err(Error::Validation(_)) => {},
+            result => panic!("expected a validation error, got {:?}", result),
+        }
+        match resolve_target(&migrations, "latest") {
+            Err(Error::VersionParse { ref message, .. }) => {
+                assert_eq!(message, "The version latest isn't a number");
+            },
+            result => panic!("expected a version parse error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn overrides_the_environment_timeouts_with_the_timeout_flags() {
+        let config = Config::parse(
+            "[environments.production]
+            statement_timeout = 60000
+            lock_timeout = 5000",
+            Path::new("")
+        ).unwrap();
+        let production = RunOptions::new().environment("production");
+
+        let args = parse_args(&["trek", "migrate", "--env=production"]);
+        assert_eq!(
+            run_options(&config, &args).unwrap(),
+            production.clone()
+                .statement_timeout(Duration::from_millis(60000))
+                .lock_timeout(Duration::from_millis(5000))
+        );
+
+        let args = parse_args(
+            &["trek", "migrate", "--env=production", "--lock-timeout=100"]
+        );
+        assert_eq!(
+            run_options(&config, &args).unwrap(),
+            production.clone()
+                .statement_timeout(Duration::from_millis(60000))
+                .lock_timeout(Duration::from_millis(100))
+        );
+
+        let args = parse_args(&["trek", "rollback", "--statement-timeout=2000"]);
+        assert_eq!(
+            run_options(&config, &args).unwrap(),
+            RunOptions::new().statement_timeout(Duration::from_millis(2000))
+        );
+    }
+
+    #[test]
+    fn rejects_timeouts_that_are_not_numbers() {
+        let config = Config::parse("", Path::new("")).unwrap();
+        let args = parse_args(&["trek", "migrate", "--lock-timeout=5s"]);
+        let error = run_options(&config, &args).unwrap_err();
+        assert_eq!(error.message(), "--lock-timeout must be a number of milliseconds, not 5s");
+
+        let args = parse_args(&["trek", "rollback", "--statement-timeout=-1"]);
+        let error = run_options(&config, &args).unwrap_err();
+        assert!(error.message().starts_with("--statement-timeout "));
+    }
+
+    #[test]
+    fn baselines_only_databases_without_history() {
+        let connection = new_test_connection();
+        let transaction = connection.transaction().unwrap();
+        let migrations = MigrationIndex::builder()
+            .migration(sql_migration(1, "first"))
+            .migration(sql_migration(2, "second"))
+            .table_name("cli_baseline_schema_history")
+            .build()
+            .unwrap();
+        assert_eq!(baseline(&migrations, &transaction, None, false).unwrap(), Outcome::Done);
+        let history = migrations.history(&transaction).unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[1].name(), "second");
+
+        // the history already exists, so baselining again would rewrite it
+        match baseline(&migrations, &transaction, Some(MigrationVersion::new(1)), false) {
+            Err(Error::Validation(ref error)) => {
+                assert!(error.to_string().contains("version tracking already exists"));
+            },
+            result => panic!("expected a validation error, got {:?}", result),
+        }
+        assert_eq!(migrations.history(&transaction).unwrap().len(), 2);
+
+        let empty = MigrationIndex::builder()
+            .table_name("cli_baseline_schema_history")
+            .build()
+            .unwrap();
+        assert!(baseline(&empty, &transaction, None, false).is_err());
+    }
+
+    #[test]
+    fn chooses_the_template_of_new_migrations_with_flags() {
+        let unset = Config::parse("", Path::new("")).unwrap();
+        let configured = Config::parse("template = \"reversible\"", Path::new("")).unwrap();
+        let args = parse_args(&["trek", "new", "create_users"]);
+        assert_eq!(migration_template(&unset, &args), MigrationTemplate::Sql);
+        assert_eq!(migration_template(&configured, &args), MigrationTemplate::Reversible);
+
+        let args = parse_args(&["trek", "new", "create_users", "--rust"]);
+        assert_eq!(migration_template(&configured, &args), MigrationTemplate::Rust);
+        let args = parse_args(&["trek", "new", "create_users", "--sql"]);
+        assert_eq!(migration_template(&configured, &args), MigrationTemplate::Sql);
+        let args = parse_args(&["trek", "new", "create_users", "--reversible"]);
+        assert_eq!(migration_template(&unset, &args), MigrationTemplate::Reversible);
+    }
+
+    #[test]
+    fn records_metadata_in_new_migrations() {
+        let project_dir = env::temp_dir().join("trek_test_cli_new_migration");
+        let _ = fs::remove_dir_all(&project_dir);
+        let config = Config::parse("", &project_dir).unwrap();
+        let args = parse_args(&[
+            "trek",
+            "new",
+            "create_users",
+            "--description=Stores the users",
+            "--author=Jane Doe",
+            "--ticket=TREK-12",
+        ]);
+        assert_eq!(new_migration(&config, &args, false).unwrap(), Outcome::Done);
+        let mut created = vec![];
+        for entry in fs::read_dir(config.migrations_dir()).unwrap() {
+            created.push(entry.unwrap().path());
+        }
+        created.sort();
+        let mut up_sql = String::new();
+        File::open(&created[1]).unwrap().read_to_string(&mut up_sql).unwrap();
+        fs::remove_dir_all(&project_dir).unwrap();
+
+        assert_eq!(created.len(), 2);
+        assert!(created[0].to_string_lossy().ends_with("_create_users.down.sql"));
+        assert!(created[1].to_string_lossy().ends_with("_create_users.up.sql"));
+        assert!(
+            up_sql.starts_with(
+                "-- Description: Stores the users\n-- Author: Jane Doe\n-- Ticket: TREK-12\n"
+            )
+        );
+    }
+
+    #[test]
+    fn snapshots_the_migrations_directory_to_notice_changes() {
+        let migrations_dir = env::temp_dir().join("trek_test_watched_migrations");
+        let _ = fs::remove_dir_all(&migrations_dir);
+        fs::create_dir_all(migrations_dir.join("2024")).unwrap();
+        File::create(migrations_dir.join("1_first.up.sql")).unwrap();
+        let snapshot = directory_snapshot(&migrations_dir).unwrap();
+        assert_eq!(snapshot.len(), 1);
+
+        let second = migrations_dir.join("2024").join("2_second.up.sql");
+        File::create(&second).unwrap().write_all(b"SELECT 1;").unwrap();
+        let changed = directory_snapshot(&migrations_dir).unwrap();
+        assert!(changed != snapshot);
+        assert_eq!(changed[&second].0, 9);
+
+        // reading a directory that's gone fails, for the watch to print and try again
+        fs::remove_dir_all(&migrations_dir).unwrap();
+        assert!(directory_snapshot(&migrations_dir).is_err());
+    }
+}
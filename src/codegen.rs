@@ -0,0 +1,182 @@
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use super::generator::migration_struct_name;
+use super::migration_version::MigrationVersion;
+use super::sql_migration;
+
+
+/// The name of the file `generate_migrations()` generates in Cargo's OUT_DIR.
+pub const GENERATED_MIGRATIONS_FILE: &'static str = "trek_migrations.rs";
+
+/// Discovers the Rust migrations in the given directory, as created by `create_migration()` with
+/// `MigrationTemplate::Rust` or `MigrationTemplate::Reversible`, so they don't have to be declared
+/// and registered by hand. Call this from the program's build script, with Trek as a build
+/// dependency, then use `include_migrations!()` in the program, in place of the directory's
+/// `mod.rs`, to declare a module for each migration file and define an `all_migrations()` function
+/// returning a new instance of each migration, in order of version. The tests generated alongside
+/// migrations by `MigrationGenerator::test_skeletons()` are declared for `#[cfg(test)]`. Cargo is
+/// told to rerun the build script whenever the directory changes.
+///
+/// # Failures
+///
+/// Returns an error if it isn't called from a build script, if the directory can't be read or
+/// holds a migration file that isn't named like one `create_migration()` created, if two
+/// subdirectories hold migrations with the same name, or if the generated file can't be written.
+///
+/// # Examples
+///
+/// In build.rs:
+///
+/// ```no_run
+/// extern crate trek;
+///
+/// use std::path::Path;
+///
+/// fn main() {
+///     trek::codegen::generate_migrations(Path::new("src/db/migrations")).unwrap();
+/// }
+/// ```
+///
+/// In the program:
+///
+/// ```ignore
+/// #[macro_use]
+/// extern crate trek;
+///
+/// mod migrations {
+///     include_migrations!();
+/// }
+///
+/// fn main() {
+///     let migrations = MigrationIndex::new(migrations::all_migrations());
+///     // ...
+/// }
+/// ```
+pub fn generate_migrations(migrations_dir: &Path) -> io::Result<()> {
+    let out_dir = match env::var_os("OUT_DIR") {
+        Some(out_dir) => PathBuf::from(out_dir),
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "OUT_DIR isn't set, generate_migrations() must be called from a build script"
+            ));
+        }
+    };
+    println!("cargo:rerun-if-changed={}", migrations_dir.display());
+    write_migrations_module(migrations_dir, &out_dir.join(GENERATED_MIGRATIONS_FILE))
+}
+
+/// Writes Rust source to `out_file` declaring a module for each Rust migration in the given
+/// directory and defining an `all_migrations()` function that returns them. The modules are
+/// declared with their files' absolute paths, so the source can be included from anywhere.
+/// `generate_migrations()` calls this with the file `include_migrations!()` includes.
+pub fn write_migrations_module(migrations_dir: &Path, out_file: &Path) -> io::Result<()> {
+    let migrations = try!(find_rust_migrations(migrations_dir));
+    let mut source = String::new();
+    for migration in &migrations {
+        source.push_str(&format!(
+            "#[path = {:?}]\npub mod {};\n",
+            try!(fs::canonicalize(&migration.path)),
+            migration.module_name
+        ));
+        if let Some(ref test_path) = migration.test_path {
+            source.push_str(&format!(
+                "#[cfg(test)]\n#[path = {:?}]\nmod test_{};\n",
+                try!(fs::canonicalize(test_path)),
+                migration.module_name
+            ));
+        }
+    }
+    source.push_str(
+        "\n/// Returns every Rust migration found by Trek, in order of version.\n\
+        pub fn all_migrations() -> Vec<Box<::trek::migration::Migration>> {\n    vec![\n"
+    );
+    for migration in &migrations {
+        source.push_str(&format!(
+            "        Box::new(self::{}::{}::new()),\n",
+            migration.module_name,
+            migration.struct_name
+        ));
+    }
+    source.push_str("    ]\n}\n");
+
+    let mut file = try!(File::create(out_file));
+    file.write_all(source.as_bytes())
+}
+
+/// A Rust migration file found by `find_rust_migrations()`.
+struct RustMigrationFile {
+    version: MigrationVersion,
+    module_name: String,
+    struct_name: String,
+    path: PathBuf,
+    /// the test generated alongside the migration, if there is one
+    test_path: Option<PathBuf>,
+}
+
+/// Finds the files named "migration_<version>_<name>.rs" in the given directory and its
+/// subdirectories, in order of version, with the tests generated alongside them. Other files, such
+/// as `mod.rs`, are ignored.
+fn find_rust_migrations(migrations_dir: &Path) -> io::Result<Vec<RustMigrationFile>> {
+    let mut migrations: Vec<RustMigrationFile> = vec![];
+    for path in try!(sql_migration::find_files(migrations_dir)) {
+        let file_name = match path.file_name().and_then(|file_name| file_name.to_str()) {
+            Some(file_name) => file_name.to_owned(),
+            None => continue
+        };
+        let module_name = if file_name.starts_with("migration_") && file_name.ends_with(".rs") {
+            file_name[..(file_name.len() - ".rs".len())].to_owned()
+        } else {
+            debug!("Ignoring {}, which isn't a Rust migration", path.display());
+            continue;
+        };
+        let versioned_name = &module_name["migration_".len()..];
+        let version = MigrationVersion::from_name(versioned_name);
+        let name = versioned_name.find('_').map(|underscore| &versioned_name[(underscore + 1)..]);
+        let (version, name) = match (version, name) {
+            (Some(version), Some(name)) if !name.is_empty() => (version, name),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "Migration file {} isn't named like migration_<version>_<name>.rs",
+                        path.display()
+                    )
+                ));
+            }
+        };
+        let struct_name = migration_struct_name(name);
+        if let Some(other) = migrations.iter().find(|other| other.module_name == module_name) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{} and {} define the same migration",
+                    other.path.display(),
+                    path.display()
+                )
+            ));
+        }
+        let test_path = path.with_file_name(format!("test_{}", file_name));
+        migrations.push(RustMigrationFile {
+            version: version,
+            module_name: module_name,
+            struct_name: struct_name,
+            path: path,
+            test_path: if test_path.is_file() { Some(test_path) } else { None },
+        });
+    }
+    migrations.sort_by_key(|migration| migration.version);
+    Ok(migrations)
+}
+
+/// Declares the migration modules and defines the `all_migrations()` function generated by
+/// `codegen::generate_migrations()` in the program's build script.
+#[macro_export]
+macro_rules! include_migrations {
+    () => {
+        include!(concat!(env!("OUT_DIR"), "/trek_migrations.rs"));
+    };
+}
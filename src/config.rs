@@ -0,0 +1,607 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::result;
+use std::time::Duration;
+
+use postgres::{Connection, TlsMode};
+use toml::{self, Value};
+
+use super::error::{Error, ResultExt, ValidationError};
+use super::generator::{MigrationGenerator, MigrationTemplate};
+use super::migration_context::{RetryPolicy, RunOptions};
+use super::migration_index::MigrationIndexBuilder;
+use super::Result;
+
+
+/// The name of the project configuration file `Config::find()` looks for.
+pub const CONFIG_FILE_NAME: &'static str = "trek.toml";
+
+/// The environment variables `Config::database_url()` reads the database's URL from when the
+/// environment doesn't configure it, in order of precedence.
+pub const DATABASE_URL_VARIABLES: &'static [&'static str] = &["TREK_DATABASE_URL", "DATABASE_URL"];
+
+/// A project's Trek configuration, read from a `trek.toml` file, so the migrations directory,
+/// version table and other settings are written down once instead of at every call site.
+///
+/// A configuration file looks like this, with every setting optional:
+///
+/// ```toml
+/// # where migrations are created and loaded from, relative to this file ("migrations" if unset)
+/// migrations_dir = "src/db/migrations"
+/// # where the trek command line tool loads seeds from, relative to this file ("seeds" if unset)
+/// seeds_dir = "src/db/seeds"
+/// # whether new migrations go in year and month subdirectories, like "2024/05" (false if unset)
+/// date_subdirectories = true
+/// # whether a test is generated alongside each Rust migration (false if unset)
+/// test_skeletons = true
+/// # the table applied migrations are recorded in, see MigrationIndexBuilder::table_name()
+/// table_name = "trek_schema_history"
+/// # the kind of file new migrations are created as: "rust" (the default), "sql" or "reversible";
+/// # the trek command line tool creates SQL migrations if unset
+/// template = "sql"
+/// # where the trek command line tool writes the schema after migrating, relative to this file,
+/// # see squash::dump_structure() (not written if unset)
+/// structure_file = "db/structure.sql"
+///
+/// # template files to create new migrations from, see MigrationGenerator::template_file()
+/// [templates]
+/// rust = "templates/migration.rs"
+/// sql = "templates/migration.sql"
+/// reversible = "templates/reversible_migration.rs"
+///
+/// # settings for each environment migrations are applied in, overriding those above
+/// [environments.development]
+/// # the database to migrate, see Config::database_url() (TREK_DATABASE_URL or DATABASE_URL if
+/// # unset)
+/// database_url = "postgresql://localhost/app_development"
+///
+/// [environments.production]
+/// database_url = "postgresql://db.example.com/app"
+/// # timeouts in milliseconds, see RunOptions
+/// statement_timeout = 60000
+/// lock_timeout = 5000
+/// # how long contract migrations wait after their expand migrations, in milliseconds
+/// contract_delay = 86400000
+/// # how many attempts transient database errors are retried within, see RetryPolicy
+/// retry_attempts = 3
+/// ```
+///
+/// # Examples
+///
+/// ```no_run
+/// # use std::env;
+/// # use trek::config::Config;
+/// # use trek::scaffold::Scaffold;
+/// let config = Config::find(&env::current_dir().unwrap()).unwrap();
+/// config.generator().create("create_users_table", &Scaffold::Empty).unwrap();
+/// let migrations = config.index_builder(Some("production")).unwrap()
+///     // .migrations(...)
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+    migrations_dir: PathBuf,
+    seeds_dir: PathBuf,
+    date_subdirectories: bool,
+    test_skeletons: bool,
+    table_name: Option<String>,
+    template: Option<MigrationTemplate>,
+    structure_file: Option<PathBuf>,
+    rust_template_file: Option<PathBuf>,
+    sql_template_file: Option<PathBuf>,
+    reversible_template_file: Option<PathBuf>,
+    environments: BTreeMap<String, EnvironmentProfile>,
+}
+
+impl Config {
+    /// Reads the configuration from the given file. Relative paths in it are relative to the
+    /// file's directory.
+    ///
+    /// # Failures
+    ///
+    /// Returns an error if the file can't be read, or an error of kind `InvalidData`, wrapping a
+    /// `ValidationError`, if it isn't a valid configuration.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut contents = String::new();
+        try!(try!(File::open(path)).read_to_string(&mut contents));
+        let root = path.parent().unwrap_or_else(|| Path::new(""));
+        Config::parse(&contents, root).map_err(|error| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                ValidationError::new(format!("{}: {}", path.display(), error))
+            )
+        })
+    }
+
+    /// Reads the configuration from the `trek.toml` file in the given directory or the nearest
+    /// of its ancestors, as Cargo does with `Cargo.toml`.
+    ///
+    /// # Failures
+    ///
+    /// Returns an error of kind `NotFound` if there is no such file, or fails like `load()`.
+    pub fn find(directory: &Path) -> io::Result<Self> {
+        for ancestor in directory.ancestors() {
+            let path = ancestor.join(CONFIG_FILE_NAME);
+            if path.is_file() {
+                return Config::load(&path);
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("No {} found in {} or its parents", CONFIG_FILE_NAME, directory.display())
+        ))
+    }
+
+    /// Reads the configuration like `find()`, falling back to the default configuration if there
+    /// is no `trek.toml`, with the migrations directory relative to the root of the Cargo
+    /// workspace the given directory is in, as found by `cargo_workspace_root()`, or else to the
+    /// directory itself. This is how `cargo trek` finds a Rust project's migrations from any of
+    /// its directories.
+    ///
+    /// # Failures
+    ///
+    /// Fails like `load()` if there's a `trek.toml` that can't be read.
+    pub fn discover(directory: &Path) -> io::Result<Self> {
+        match Config::find(directory) {
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => {
+                let root = cargo_workspace_root(directory)
+                    .unwrap_or_else(|| directory.to_path_buf());
+                Ok(Config::parse("", &root).expect("the default configuration is valid"))
+            },
+            result => result
+        }
+    }
+
+    /// Parses the contents of a configuration file, resolving relative paths against `root`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::path::Path;
+    /// # use trek::config::Config;
+    /// let config = Config::parse("migrations_dir = \"db/migrations\"", Path::new("app")).unwrap();
+    /// assert_eq!(config.migrations_dir(), Path::new("app/db/migrations"));
+    /// ```
+    ///
+    /// # Failures
+    ///
+    /// Returns an error if the contents aren't valid TOML, or if a setting is unknown or has a
+    /// value of the wrong type.
+    pub fn parse(contents: &str, root: &Path) -> result::Result<Self, ValidationError> {
+        let value = try!(contents.parse::<Value>().map_err(|error| {
+            ValidationError::new(format!("invalid TOML: {}", error))
+        }));
+        let mut settings = match value {
+            Value::Table(table) => Settings::new("", table),
+            _ => return Err(ValidationError::new("expected a table of settings".to_owned()))
+        };
+
+        let migrations_dir = try!(settings.string("migrations_dir"))
+            .unwrap_or_else(|| "migrations".to_owned());
+        let seeds_dir = try!(settings.string("seeds_dir")).unwrap_or_else(|| "seeds".to_owned());
+        let date_subdirectories = try!(settings.boolean("date_subdirectories")).unwrap_or(false);
+        let test_skeletons = try!(settings.boolean("test_skeletons")).unwrap_or(false);
+        let table_name = try!(settings.string("table_name"));
+        let template = match try!(settings.string("template")) {
+            Some(ref template) if template == "rust" => Some(MigrationTemplate::Rust),
+            Some(ref template) if template == "sql" => Some(MigrationTemplate::Sql),
+            Some(ref template) if template == "reversible" => Some(MigrationTemplate::Reversible),
+            Some(template) => {
+                return Err(ValidationError::new(format!(
+                    "template must be \"rust\", \"sql\" or \"reversible\", not {:?}",
+                    template
+                )));
+            },
+            None => None
+        };
+        let structure_file = try!(settings.string("structure_file"));
+        let mut templates = match try!(settings.table("templates")) {
+            Some(templates) => templates,
+            None => Settings::new("templates.", toml::value::Table::new())
+        };
+        let rust_template_file = try!(templates.string("rust"));
+        let sql_template_file = try!(templates.string("sql"));
+        let reversible_template_file = try!(templates.string("reversible"));
+        try!(templates.finish());
+        let mut environments = BTreeMap::new();
+        if let Some(mut profiles) = try!(settings.table("environments")) {
+            for name in profiles.keys() {
+                let mut profile = try!(profiles.table(&name)).expect("The key exists");
+                let environment = EnvironmentProfile {
+                    name: name.clone(),
+                    database_url: try!(profile.string("database_url")),
+                    table_name: try!(profile.string("table_name")),
+                    statement_timeout: try!(profile.milliseconds("statement_timeout")),
+                    lock_timeout: try!(profile.milliseconds("lock_timeout")),
+                    contract_delay: try!(profile.milliseconds("contract_delay")),
+                    retry_attempts: try!(profile.count("retry_attempts")),
+                };
+                try!(profile.finish());
+                environments.insert(name, environment);
+            }
+        }
+        try!(settings.finish());
+
+        Ok(Config {
+            migrations_dir: root.join(migrations_dir),
+            seeds_dir: root.join(seeds_dir),
+            date_subdirectories: date_subdirectories,
+            test_skeletons: test_skeletons,
+            table_name: table_name,
+            template: template,
+            structure_file: structure_file.map(|path| root.join(path)),
+            rust_template_file: rust_template_file.map(|path| root.join(path)),
+            sql_template_file: sql_template_file.map(|path| root.join(path)),
+            reversible_template_file: reversible_template_file.map(|path| root.join(path)),
+            environments: environments,
+        })
+    }
+
+    /// Returns the directory migrations are created in and loaded from.
+    pub fn migrations_dir(&self) -> &Path {
+        &self.migrations_dir
+    }
+
+    /// Sets the directory migrations are created in and loaded from, overriding the configured
+    /// one, e.g. with one given on the command line.
+    pub fn with_migrations_dir(mut self, migrations_dir: &Path) -> Self {
+        self.migrations_dir = migrations_dir.to_path_buf();
+        self
+    }
+
+    /// Returns the directory the trek command line tool loads seeds from. See
+    /// `seed::load_directory()`.
+    pub fn seeds_dir(&self) -> &Path {
+        &self.seeds_dir
+    }
+
+    /// Returns true if new migrations are created in year and month subdirectories of the
+    /// migrations directory. See `MigrationGenerator::date_subdirectories()`.
+    pub fn date_subdirectories(&self) -> bool {
+        self.date_subdirectories
+    }
+
+    /// Returns true if a test is generated alongside each new Rust migration. See
+    /// `MigrationGenerator::test_skeletons()`.
+    pub fn test_skeletons(&self) -> bool {
+        self.test_skeletons
+    }
+
+    /// Returns the name of the table applied migrations are recorded in, if it isn't the default.
+    pub fn table_name(&self) -> Option<&str> {
+        self.table_name.as_ref().map(|table_name| &**table_name)
+    }
+
+    /// Returns the kind of file new migrations are created as, Rust migrations if it isn't set.
+    pub fn template(&self) -> MigrationTemplate {
+        self.template.unwrap_or(MigrationTemplate::Rust)
+    }
+
+    /// Returns the kind of file new migrations are created as if it's set, so tools with a
+    /// different default, like the trek command line tool, can tell it wasn't.
+    pub fn template_setting(&self) -> Option<MigrationTemplate> {
+        self.template
+    }
+
+    /// Returns the file the trek command line tool writes the schema to after migrating, if any.
+    /// See `squash::dump_structure()`.
+    pub fn structure_file(&self) -> Option<&Path> {
+        self.structure_file.as_ref().map(|path| path.as_path())
+    }
+
+    /// Returns the template file new migrations of the given kind are created from, if it isn't
+    /// the built-in template.
+    pub fn template_file(&self, template: MigrationTemplate) -> Option<&Path> {
+        let template_file = match template {
+            MigrationTemplate::Rust => &self.rust_template_file,
+            MigrationTemplate::Sql => &self.sql_template_file,
+            MigrationTemplate::Reversible => &self.reversible_template_file,
+        };
+        template_file.as_ref().map(|path| path.as_path())
+    }
+
+    /// Returns the settings for the environment with the given name, if it's configured.
+    pub fn environment(&self, name: &str) -> Option<&EnvironmentProfile> {
+        self.environments.get(name)
+    }
+
+    /// Returns the names of the configured environments, in alphabetical order.
+    pub fn environment_names(&self) -> Vec<&str> {
+        self.environments.keys().map(|name| &**name).collect()
+    }
+
+    /// Returns the URL of the database to apply migrations to: the one configured for the given
+    /// environment, if any, or else the one in the first of the `TREK_DATABASE_URL` and
+    /// `DATABASE_URL` environment variables that's set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::path::Path;
+    /// # use trek::config::Config;
+    /// let config = Config::parse(
+    ///     "[environments.production]\ndatabase_url = \"postgresql://db.example.com/app\"",
+    ///     Path::new("")
+    /// ).unwrap();
+    /// assert_eq!(
+    ///     config.database_url(Some("production")),
+    ///     Some("postgresql://db.example.com/app".to_owned())
+    /// );
+    /// ```
+    pub fn database_url(&self, environment: Option<&str>) -> Option<String> {
+        let configured_url = environment
+            .and_then(|environment| self.environments.get(environment))
+            .and_then(|profile| profile.database_url());
+        if let Some(database_url) = configured_url {
+            return Some(database_url.to_owned());
+        }
+        DATABASE_URL_VARIABLES.iter().find_map(|variable| env::var(variable).ok())
+    }
+
+    /// Connects to the database given by `database_url()`.
+    ///
+    /// # Failures
+    ///
+    /// Returns a `Validation` error if no database URL is configured or set, or an error if the
+    /// database can't be connected to.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use std::env;
+    /// # use trek::config::Config;
+    /// let config = Config::find(&env::current_dir().unwrap()).unwrap();
+    /// let connection = config.connect(Some("production")).unwrap();
+    /// ```
+    pub fn connect(&self, environment: Option<&str>) -> Result<Connection> {
+        let database_url = match self.database_url(environment) {
+            Some(database_url) => database_url,
+            None => {
+                return Err(Error::Validation(
+                    ValidationError::new(
+                        "No database URL is configured for the environment, and neither \
+                        TREK_DATABASE_URL nor DATABASE_URL is set".to_owned()
+                    ).with_hint("Set DATABASE_URL, or configure the environment's database_url")
+                ));
+            }
+        };
+        Connection::connect(&*database_url, TlsMode::None)
+            .context("Error connecting to the database")
+    }
+
+    /// Returns a generator creating migrations in the configured directory, from the configured
+    /// templates.
+    pub fn generator(&self) -> MigrationGenerator {
+        let mut generator = MigrationGenerator::new(&self.migrations_dir)
+            .template(self.template())
+            .date_subdirectories(self.date_subdirectories)
+            .test_skeletons(self.test_skeletons);
+        let templates = [
+            MigrationTemplate::Rust,
+            MigrationTemplate::Sql,
+            MigrationTemplate::Reversible,
+        ];
+        for &template in &templates {
+            if let Some(path) = self.template_file(template) {
+                generator = generator.template_file(template, path);
+            }
+        }
+        generator
+    }
+
+    /// Returns a builder for a `MigrationIndex` using the configured version table, and applying
+    /// migrations in the given environment, if any, with its table name and timeouts.
+    ///
+    /// # Failures
+    ///
+    /// Returns an error if the environment isn't configured.
+    pub fn index_builder(
+        &self,
+        environment: Option<&str>
+    ) -> result::Result<MigrationIndexBuilder, ValidationError> {
+        let mut builder = MigrationIndexBuilder::new();
+        let mut table_name = self.table_name.as_ref();
+        if let Some(environment) = environment {
+            let profile = match self.environments.get(environment) {
+                Some(profile) => profile,
+                None => {
+                    return Err(ValidationError::new(format!(
+                        "The environment {:?} isn't configured; the configured environments are \
+                        {:?}",
+                        environment,
+                        self.environment_names()
+                    )));
+                }
+            };
+            table_name = profile.table_name.as_ref().or(table_name);
+            builder = builder.run_options(profile.run_options());
+        }
+        if let Some(table_name) = table_name {
+            builder = builder.table_name(table_name);
+        }
+        Ok(builder)
+    }
+}
+
+/// The settings of an environment in a project's configuration.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EnvironmentProfile {
+    name: String,
+    database_url: Option<String>,
+    table_name: Option<String>,
+    statement_timeout: Option<Duration>,
+    lock_timeout: Option<Duration>,
+    contract_delay: Option<Duration>,
+    retry_attempts: Option<u32>,
+}
+
+impl EnvironmentProfile {
+    /// Returns the environment's name, e.g. "production".
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the URL of the environment's database, if it's configured.
+    pub fn database_url(&self) -> Option<&str> {
+        self.database_url.as_ref().map(|database_url| &**database_url)
+    }
+
+    /// Returns the name of the table applied migrations are recorded in, if the environment
+    /// overrides it.
+    pub fn table_name(&self) -> Option<&str> {
+        self.table_name.as_ref().map(|table_name| &**table_name)
+    }
+
+    /// Returns the default statement timeout of migrations applied in the environment, if any.
+    pub fn statement_timeout(&self) -> Option<Duration> {
+        self.statement_timeout
+    }
+
+    /// Returns the default lock timeout of migrations applied in the environment, if any.
+    pub fn lock_timeout(&self) -> Option<Duration> {
+        self.lock_timeout
+    }
+
+    /// Returns how long contract migrations applied in the environment wait after their expand
+    /// migrations, if it's set. See `RunOptions::contract_delay()`.
+    pub fn contract_delay(&self) -> Option<Duration> {
+        self.contract_delay
+    }
+
+    /// Returns the most attempts transient database errors are retried within in the
+    /// environment, if it's set. See `RunOptions::retry_policy()`.
+    pub fn retry_attempts(&self) -> Option<u32> {
+        self.retry_attempts
+    }
+
+    /// Returns the options migrations are applied with in the environment.
+    pub fn run_options(&self) -> RunOptions {
+        let mut options = RunOptions::new().environment(&self.name);
+        if let Some(timeout) = self.statement_timeout {
+            options = options.statement_timeout(timeout);
+        }
+        if let Some(timeout) = self.lock_timeout {
+            options = options.lock_timeout(timeout);
+        }
+        if let Some(delay) = self.contract_delay {
+            options = options.contract_delay(delay);
+        }
+        if let Some(attempts) = self.retry_attempts {
+            options = options.retry_policy(RetryPolicy::new(attempts));
+        }
+        options
+    }
+}
+
+/// Returns the root directory of the Cargo workspace the given directory is in: the nearest
+/// ancestor (or the directory itself) with a `Cargo.toml` that declares a `[workspace]`, or else
+/// the nearest with a `Cargo.toml`, or None if it isn't in a Cargo project. Manifests that can't
+/// be read or parsed are treated as packages rather than workspaces.
+pub fn cargo_workspace_root(directory: &Path) -> Option<PathBuf> {
+    let mut package_root = None;
+    for ancestor in directory.ancestors() {
+        let manifest_path = ancestor.join("Cargo.toml");
+        if !manifest_path.is_file() {
+            continue;
+        }
+        let mut contents = String::new();
+        let declares_workspace = File::open(&manifest_path)
+            .and_then(|mut file| file.read_to_string(&mut contents))
+            .ok()
+            .and_then(|_| contents.parse::<Value>().ok())
+            .map(|manifest| manifest.get("workspace").is_some())
+            .unwrap_or(false);
+        if declares_workspace {
+            return Some(ancestor.to_path_buf());
+        }
+        if package_root.is_none() {
+            package_root = Some(ancestor.to_path_buf());
+        }
+    }
+    package_root
+}
+
+/// A table of settings being read by `Config::parse()`, which takes out each setting as it's read
+/// so that any left over can be reported as unknown.
+struct Settings {
+    /// the table's dotted path in the file, e.g. "environments.production.", or "" at the top
+    path: String,
+    table: toml::value::Table,
+}
+
+impl Settings {
+    fn new(path: &str, table: toml::value::Table) -> Self {
+        Settings {
+            path: path.to_owned(),
+            table: table,
+        }
+    }
+
+    fn keys(&self) -> Vec<String> {
+        self.table.keys().cloned().collect()
+    }
+
+    fn string(&mut self, key: &str) -> result::Result<Option<String>, ValidationError> {
+        match self.table.remove(key) {
+            Some(Value::String(value)) => Ok(Some(value)),
+            Some(_) => Err(self.wrong_type(key, "a string")),
+            None => Ok(None)
+        }
+    }
+
+    fn boolean(&mut self, key: &str) -> result::Result<Option<bool>, ValidationError> {
+        match self.table.remove(key) {
+            Some(Value::Boolean(value)) => Ok(Some(value)),
+            Some(_) => Err(self.wrong_type(key, "true or false")),
+            None => Ok(None)
+        }
+    }
+
+    fn milliseconds(&mut self, key: &str) -> result::Result<Option<Duration>, ValidationError> {
+        match self.table.remove(key) {
+            Some(Value::Integer(value)) if value >= 0 => {
+                Ok(Some(Duration::from_millis(value as u64)))
+            },
+            Some(_) => Err(self.wrong_type(key, "a number of milliseconds")),
+            None => Ok(None)
+        }
+    }
+
+    fn count(&mut self, key: &str) -> result::Result<Option<u32>, ValidationError> {
+        match self.table.remove(key) {
+            Some(Value::Integer(value)) if (0..=i64::from(u32::MAX)).contains(&value) => {
+                Ok(Some(value as u32))
+            },
+            Some(_) => Err(self.wrong_type(key, "a count")),
+            None => Ok(None)
+        }
+    }
+
+    fn table(&mut self, key: &str) -> result::Result<Option<Settings>, ValidationError> {
+        match self.table.remove(key) {
+            Some(Value::Table(table)) => {
+                Ok(Some(Settings::new(&format!("{}{}.", self.path, key), table)))
+            },
+            Some(_) => Err(self.wrong_type(key, "a table")),
+            None => Ok(None)
+        }
+    }
+
+    /// Fails if any settings haven't been read, since they must be misspelt or misplaced.
+    fn finish(self) -> result::Result<(), ValidationError> {
+        match self.table.keys().next() {
+            Some(key) => {
+                Err(ValidationError::new(format!("unknown setting {}{}", self.path, key)))
+            },
+            None => Ok(())
+        }
+    }
+
+    fn wrong_type(&self, key: &str, expected_type: &str) -> ValidationError {
+        ValidationError::new(format!("{}{} must be {}", self.path, key, expected_type))
+    }
+}
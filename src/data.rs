@@ -0,0 +1,130 @@
+use std::thread;
+use std::time::Duration;
+
+use postgres::{self, GenericConnection};
+
+
+/// Options controlling how `batched_update()` splits its work.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BatchOptions {
+    batch_size: i64,
+    pause: Duration,
+}
+
+impl BatchOptions {
+    /// Creates options for batches of 1000 rows with no pause between them.
+    pub fn new() -> Self {
+        BatchOptions {
+            batch_size: 1000,
+            pause: Duration::from_secs(0),
+        }
+    }
+
+    /// Sets the number of rows each batch covers.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `batch_size` is zero.
+    pub fn batch_size(mut self, batch_size: u32) -> Self {
+        assert!(batch_size > 0, "Batch size must be greater than zero");
+        self.batch_size = batch_size as i64;
+        self
+    }
+
+    /// Sets how long to sleep between batches, giving other queries a chance at the table.
+    pub fn pause(mut self, pause: Duration) -> Self {
+        self.pause = pause;
+        self
+    }
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        BatchOptions::new()
+    }
+}
+
+/// Runs an UPDATE or DELETE over a large table in chunks, so that no single statement locks the
+/// table's rows for long. The table's rows are paged through in order of `key_column`, which must
+/// be an integer column with unique values (typically the primary key). For each page, `statement`
+/// is run with `$1` bound to the key the page starts after and `$2` to the last key in the page,
+/// both as BIGINT, so it should restrict itself to rows with keys in that range. Returns the total
+/// number of rows the statement affected.
+///
+/// Row locks are held until the enclosing transaction commits, so for them to be released between
+/// batches, the migration calling this should be non-transactional (see
+/// `Migration::transactional()`).
+///
+/// `table` and `key_column` are interpolated into SQL as they are, so they must be trusted
+/// identifiers, quoted where necessary.
+///
+/// # Examples
+///
+/// ```no_run
+/// # extern crate postgres;
+/// # extern crate trek;
+/// # fn main() {
+/// # use std::time::Duration;
+/// # use postgres::{Connection, TlsMode};
+/// # use trek::data::{self, BatchOptions};
+/// # let connection = Connection::connect("server url", TlsMode::None).unwrap();
+/// let updated = data::batched_update(
+///     &connection,
+///     "users",
+///     "id",
+///     "UPDATE users SET name = email
+///      WHERE name IS NULL AND id > $1::BIGINT AND id <= $2::BIGINT;",
+///     &BatchOptions::new().batch_size(5000).pause(Duration::from_millis(100))
+/// ).unwrap();
+/// println!("Backfilled {} user names", updated);
+/// # }
+/// ```
+///
+/// # Failures
+///
+/// Returns an error if reading the table's keys or running the statement fails. Batches that
+/// already completed outside of a transaction stay applied.
+pub fn batched_update(
+    connection: &GenericConnection,
+    table: &str,
+    key_column: &str,
+    statement: &str,
+    options: &BatchOptions
+) -> postgres::Result<u64> {
+    let next_page_end = format!(
+        "SELECT max({key})::BIGINT FROM (
+            SELECT {key} FROM {table} WHERE $1::BIGINT IS NULL OR {key} > $1::BIGINT
+            ORDER BY {key} LIMIT $2
+        ) AS page;",
+        key=key_column,
+        table=table
+    );
+    let mut affected = 0;
+    let mut page_start: Option<i64> = None;
+    let mut batches = 0;
+    loop {
+        let result = try!(connection.query(&next_page_end, &[&page_start, &options.batch_size]));
+        let page_end: Option<i64> = result.get(0).get(0);
+        let page_end = match page_end {
+            Some(page_end) => page_end,
+            None => break
+        };
+        if batches > 0 && options.pause > Duration::from_secs(0) {
+            thread::sleep(options.pause);
+        }
+        // the first page starts before every key
+        let lower_bound = page_start.unwrap_or(i64::min_value());
+        affected += try!(connection.execute(statement, &[&lower_bound, &page_end]));
+        batches += 1;
+        debug!(
+            "Batch {} of {} covered keys up to {}, {} rows affected so far",
+            batches,
+            table,
+            page_end,
+            affected
+        );
+        page_start = Some(page_end);
+    }
+    info!("Batched statement on {} affected {} rows in {} batches", table, affected, batches);
+    Ok(affected)
+}
@@ -0,0 +1,107 @@
+use postgres::{Connection, TlsMode};
+use postgres::params::{ConnectParams, IntoConnectParams};
+
+use super::error::{Error, ResultExt};
+use super::version_table::quote_identifier;
+use super::Result;
+
+
+/// The database `create_database()` and `drop_database()` connect to, since PostgreSQL can't
+/// create or drop the database a session is connected to. Every PostgreSQL server has it.
+pub const MAINTENANCE_DATABASE: &'static str = "postgres";
+
+/// Creates the database given by the URL, e.g. "postgresql://user@localhost/app_development",
+/// if it doesn't exist yet, owned by the URL's user, so a development database can be set up
+/// before running migrations on it. Returns true if it was created, or false if it already
+/// existed.
+///
+/// # Failures
+///
+/// Returns a `Validation` error if the URL is invalid or doesn't name a database, or an error if
+/// the maintenance database can't be connected to or the database can't be created, e.g.
+/// because the user isn't allowed to create databases.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use trek::database;
+/// if database::create_database("postgresql://user@localhost/app_development").unwrap() {
+///     println!("Created the development database");
+/// }
+/// ```
+pub fn create_database(database_url: &str) -> Result<bool> {
+    let (connection, database) = try!(connect_to_maintenance_database(database_url));
+    if try!(database_exists(&connection, &database)) {
+        return Ok(false);
+    }
+    try!(
+        connection.batch_execute(&format!("CREATE DATABASE {};", quote_identifier(&database)))
+            .with_context(|| format!("Error creating database {}", database))
+    );
+    Ok(true)
+}
+
+/// Drops the database given by the URL, if it exists, destroying all of its data. Returns true
+/// if it was dropped, or false if it didn't exist.
+///
+/// # Failures
+///
+/// Fails like `create_database()`, including if other sessions are connected to the database.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use trek::database;
+/// database::drop_database("postgresql://user@localhost/app_development").unwrap();
+/// assert!(database::create_database("postgresql://user@localhost/app_development").unwrap());
+/// ```
+pub fn drop_database(database_url: &str) -> Result<bool> {
+    let (connection, database) = try!(connect_to_maintenance_database(database_url));
+    if !try!(database_exists(&connection, &database)) {
+        return Ok(false);
+    }
+    try!(
+        connection.batch_execute(&format!("DROP DATABASE {};", quote_identifier(&database)))
+            .with_context(|| format!("Error dropping database {}", database))
+    );
+    Ok(true)
+}
+
+/// Connects to the maintenance database on the server given by the URL, as the URL's user and
+/// with its options, returning the connection and the name of the database the URL gives.
+fn connect_to_maintenance_database(database_url: &str) -> Result<(Connection, String)> {
+    // the URL isn't included in errors, since it may contain a password
+    let params = try!(database_url.into_connect_params().map_err(|error| {
+        Error::from_message(format!("The database URL is invalid: {}", error))
+    }));
+    let database = match params.database() {
+        Some(database) => database.to_owned(),
+        None => {
+            return Err(Error::from_message(
+                "The database URL doesn't name a database".to_owned()
+            ));
+        }
+    };
+    let mut builder = ConnectParams::builder();
+    builder.port(params.port()).database(MAINTENANCE_DATABASE);
+    if let Some(user) = params.user() {
+        builder.user(user.name(), user.password());
+    }
+    for &(ref name, ref value) in params.options() {
+        builder.option(name, value);
+    }
+    let connection = try!(
+        Connection::connect(builder.build(params.host().clone()), TlsMode::None)
+            .context("Error connecting to the maintenance database")
+    );
+    Ok((connection, database))
+}
+
+/// Returns true if a database with the given name exists on the connection's server.
+fn database_exists(connection: &Connection, database: &str) -> Result<bool> {
+    let rows = try!(
+        connection.query("SELECT 1 FROM pg_database WHERE datname = $1;", &[&database])
+            .context("Error checking whether the database exists")
+    );
+    Ok(!rows.is_empty())
+}
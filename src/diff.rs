@@ -0,0 +1,394 @@
+use std::collections::BTreeMap;
+
+use postgres::GenericConnection;
+
+use super::Result;
+use super::error::Error;
+use super::scaffold::{Column, Scaffold};
+use super::sql_migration;
+
+
+/// The schema the target SQL is loaded into while it's compared, inside a transaction that's
+/// rolled back afterwards.
+const TARGET_SCHEMA: &'static str = "trek_diff_target";
+
+/// A table in a declarative target schema described in Rust, for `diff_tables()`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Table {
+    name: String,
+    columns: Vec<Column>,
+}
+
+impl Table {
+    /// Describes a table with the given name and columns.
+    pub fn new(name: &str, columns: Vec<Column>) -> Self {
+        Table {
+            name: name.to_owned(),
+            columns: columns,
+        }
+    }
+
+    /// Returns the SQL creating the table.
+    pub fn sql(&self) -> String {
+        Scaffold::create_table(&self.name, self.columns.clone()).up_sql().unwrap_or_default()
+    }
+}
+
+/// The changes needed to make the current schema match a target schema, as found by `diff_sql()`
+/// or `diff_tables()`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SchemaDiff {
+    statements: Vec<String>,
+    notes: Vec<String>,
+}
+
+impl SchemaDiff {
+    /// Returns the statements converging the current schema on the target, each without a
+    /// terminating semicolon.
+    pub fn statements(&self) -> &[String] {
+        &self.statements
+    }
+
+    /// Returns notes on differences the statements don't resolve, such as tables that aren't in
+    /// the target schema, which are left for you to decide about.
+    pub fn notes(&self) -> &[String] {
+        &self.notes
+    }
+
+    /// Returns true if the current schema already matches the target.
+    pub fn is_empty(&self) -> bool {
+        self.statements.is_empty() && self.notes.is_empty()
+    }
+
+    /// Returns the statements as a script, preceded by the notes as comments.
+    pub fn sql(&self) -> String {
+        let mut sql = String::from(
+            "-- Generated by comparing the schema with its target; review before running.\n"
+        );
+        for note in &self.notes {
+            sql.push_str(&format!("-- {}\n", note));
+        }
+        for statement in &self.statements {
+            sql.push_str(&format!("{};\n", statement));
+        }
+        sql
+    }
+
+    /// Returns a scaffold for a migration running the statements, for
+    /// `create_scaffolded_migration()`. Its down SQL is generated with
+    /// `sql_migration::generate_down()` if all the statements can be inverted, and otherwise left
+    /// for you to write.
+    pub fn scaffold(&self) -> Scaffold {
+        let down = sql_migration::generate_down(&self.sql());
+        let down_sql = if down.is_complete() { Some(down.sql() + "\n") } else { None };
+        Scaffold::sql(&self.sql(), down_sql.as_ref().map(|down_sql| &**down_sql))
+    }
+}
+
+/// Compares the tables in the current schema with the ones `target_sql` creates, and returns the
+/// statements making the current schema match, as a starting point for a migration. Tables the
+/// target SQL creates but the current schema lacks are created with the target's own
+/// `CREATE TABLE` statement, and the columns of tables in both are added, dropped or altered to
+/// match the target's names, types, nullability and defaults. Tables only in the current schema
+/// are noted rather than dropped. Constraints, indexes and other objects aren't compared.
+///
+/// The target SQL is run in a scratch schema, inside a transaction that's rolled back once its
+/// tables have been read, so that PostgreSQL normalizes both schemas' definitions the same way.
+/// It can refer to types and other objects in the current schema.
+///
+/// # Examples
+///
+/// ```no_run
+/// # extern crate postgres;
+/// # extern crate trek;
+/// # fn main() {
+/// # use std::path::Path;
+/// # use postgres::{Connection, TlsMode};
+/// # use trek::{create_scaffolded_migration, MigrationTemplate};
+/// # use trek::diff;
+/// # let connection = Connection::connect("server url", TlsMode::None).unwrap();
+/// let diff = diff::diff_sql(&connection, include_str!("schema.sql")).unwrap();
+/// if !diff.is_empty() {
+///     create_scaffolded_migration(
+///         "converge_schema",
+///         Path::new("migrations"),
+///         MigrationTemplate::Sql,
+///         &diff.scaffold()
+///     ).unwrap();
+/// }
+/// # }
+/// ```
+///
+/// # Failures
+///
+/// Returns an error if the schemas can't be read or the target SQL fails.
+pub fn diff_sql(connection: &GenericConnection, target_sql: &str) -> Result<SchemaDiff> {
+    let transaction = match connection.transaction() {
+        Ok(transaction) => transaction,
+        Err(error) => {
+            return Err(Error::new(
+                "Error starting a transaction to compare schemas".to_owned(),
+                error
+            ));
+        }
+    };
+    let current = match transaction.query("SELECT current_schema();", &[]) {
+        Ok(rows) => rows.get(0).get::<_, Option<String>>(0),
+        Err(error) => return Err(Error::new("Error reading the current schema".to_owned(), error))
+    };
+    let current = match current {
+        Some(current) => current,
+        None => return Err(Error::from_message("No current schema to compare".to_owned()))
+    };
+    let current_tables = try!(read_tables(&transaction, &current));
+
+    let setup = format!(
+        "CREATE SCHEMA {schema};
+        SELECT set_config('search_path', '{schema}, ' || current_setting('search_path'), true);",
+        schema=TARGET_SCHEMA
+    );
+    if let Err(error) = transaction.batch_execute(&setup) {
+        return Err(Error::new("Error creating a schema for the target SQL".to_owned(), error));
+    }
+    if let Err(error) = transaction.batch_execute(target_sql) {
+        return Err(Error::new("Error running the target SQL".to_owned(), error));
+    }
+    let target_tables = try!(read_tables(&transaction, TARGET_SCHEMA));
+    transaction.set_rollback();
+    if let Err(error) = transaction.finish() {
+        return Err(Error::new("Error discarding the target schema".to_owned(), error));
+    }
+
+    let mut diff = SchemaDiff::default();
+    // new tables are created in the order the target SQL creates them, in case they refer to
+    // each other
+    let mut created = vec![];
+    for statement in sql_migration::split_statements(target_sql) {
+        let table = match sql_migration::created_table(&statement) {
+            Some(table) => unquoted_table_name(&table),
+            None => continue
+        };
+        let missing = target_tables.contains_key(&table) && !current_tables.contains_key(&table);
+        if missing && !created.contains(&table) {
+            diff.statements.push(statement);
+            created.push(table);
+        }
+    }
+    for (table, target_columns) in &target_tables {
+        match current_tables.get(table) {
+            Some(current_columns) => {
+                alter_table(&mut diff, table, current_columns, target_columns);
+            },
+            None if !created.contains(table) => {
+                // e.g. created by a DO block, so there's no statement to copy
+                let columns = target_columns.iter()
+                    .map(|column| format!("    {}", column.definition()))
+                    .collect::<Vec<String>>();
+                diff.statements.push(format!(
+                    "CREATE TABLE {} (\n{}\n)",
+                    quote_identifier(table),
+                    columns.join(",\n")
+                ));
+            },
+            None => {}
+        }
+    }
+    for table in current_tables.keys().filter(|table| !target_tables.contains_key(*table)) {
+        diff.notes.push(format!("Table {} isn't in the target schema", quote_identifier(table)));
+    }
+    Ok(diff)
+}
+
+/// Compares the tables in the current schema with the given tables, like `diff_sql()` does with
+/// the tables created by SQL.
+///
+/// # Examples
+///
+/// ```no_run
+/// # extern crate postgres;
+/// # extern crate trek;
+/// # fn main() {
+/// # use postgres::{Connection, TlsMode};
+/// # use trek::diff::{self, Table};
+/// # use trek::scaffold::Column;
+/// # let connection = Connection::connect("server url", TlsMode::None).unwrap();
+/// let diff = diff::diff_tables(&connection, &[
+///     Table::new("users", vec![
+///         Column::new("id", "SERIAL").primary_key(),
+///         Column::new("email", "TEXT").not_null(),
+///     ]),
+/// ]).unwrap();
+/// println!("{}", diff.sql());
+/// # }
+/// ```
+///
+/// # Failures
+///
+/// Fails like `diff_sql()`.
+pub fn diff_tables(connection: &GenericConnection, tables: &[Table]) -> Result<SchemaDiff> {
+    let target_sql = tables.iter().map(|table| table.sql()).collect::<String>();
+    diff_sql(connection, &target_sql)
+}
+
+/// A column as PostgreSQL describes it in its catalog.
+#[derive(Debug, PartialEq)]
+struct ColumnDefinition {
+    name: String,
+    sql_type: String,
+    not_null: bool,
+    default: Option<String>,
+}
+
+impl ColumnDefinition {
+    /// Returns the column's definition, as used in `CREATE TABLE` and `ADD COLUMN`. Columns
+    /// defaulting to the next value of a sequence are defined as serial columns, since the
+    /// sequence won't exist yet.
+    fn definition(&self) -> String {
+        let mut definition = quote_identifier(&self.name);
+        match self.serial_type() {
+            Some(serial_type) => {
+                definition.push(' ');
+                definition.push_str(serial_type);
+            },
+            None => {
+                definition.push(' ');
+                definition.push_str(&self.sql_type);
+                if let Some(ref default) = self.default {
+                    definition.push_str(&format!(" DEFAULT {}", default));
+                }
+            }
+        }
+        if self.not_null {
+            definition.push_str(" NOT NULL");
+        }
+        definition
+    }
+
+    /// Returns the serial type the column was declared with, if it looks like it was.
+    fn serial_type(&self) -> Option<&'static str> {
+        match self.default {
+            Some(ref default) if default.starts_with("nextval(") => {},
+            _ => return None
+        }
+        match &*self.sql_type {
+            "smallint" => Some("SMALLSERIAL"),
+            "integer" => Some("SERIAL"),
+            "bigint" => Some("BIGSERIAL"),
+            _ => None
+        }
+    }
+}
+
+/// Reads the columns of the ordinary and partitioned tables in the given schema, by table name.
+fn read_tables(
+    connection: &GenericConnection,
+    schema: &str
+) -> Result<BTreeMap<String, Vec<ColumnDefinition>>> {
+    let rows = match connection.query(
+        "SELECT class.relname, attribute.attname,
+            format_type(attribute.atttypid, attribute.atttypmod), attribute.attnotnull,
+            pg_get_expr(default_value.adbin, default_value.adrelid)
+        FROM pg_attribute AS attribute
+        JOIN pg_class AS class ON class.oid = attribute.attrelid
+        JOIN pg_namespace AS namespace ON namespace.oid = class.relnamespace
+        LEFT JOIN pg_attrdef AS default_value ON default_value.adrelid = attribute.attrelid
+            AND default_value.adnum = attribute.attnum
+        WHERE namespace.nspname = $1 AND class.relkind IN ('r', 'p')
+            AND attribute.attnum > 0 AND NOT attribute.attisdropped
+        ORDER BY class.relname, attribute.attnum;",
+        &[&schema]
+    ) {
+        Ok(rows) => rows,
+        Err(error) => {
+            return Err(Error::new(format!("Error reading the tables in schema {}", schema), error));
+        }
+    };
+    let mut tables = BTreeMap::new();
+    for row in rows.iter() {
+        tables.entry(row.get(0)).or_insert_with(Vec::new).push(ColumnDefinition {
+            name: row.get(1),
+            sql_type: row.get(2),
+            not_null: row.get(3),
+            default: row.get(4),
+        });
+    }
+    Ok(tables)
+}
+
+/// Adds the statements altering a table's columns to match the target's.
+fn alter_table(
+    diff: &mut SchemaDiff,
+    table: &str,
+    current_columns: &[ColumnDefinition],
+    target_columns: &[ColumnDefinition]
+) {
+    let table = quote_identifier(table);
+    for column in current_columns {
+        if !target_columns.iter().any(|target| target.name == column.name) {
+            diff.statements.push(format!(
+                "ALTER TABLE {} DROP COLUMN {}",
+                table,
+                quote_identifier(&column.name)
+            ));
+        }
+    }
+    for target in target_columns {
+        let current = match current_columns.iter().find(|column| column.name == target.name) {
+            Some(current) => current,
+            None => {
+                diff.statements.push(
+                    format!("ALTER TABLE {} ADD COLUMN {}", table, target.definition())
+                );
+                continue;
+            }
+        };
+        let alter_column = format!(
+            "ALTER TABLE {} ALTER COLUMN {}",
+            table,
+            quote_identifier(&target.name)
+        );
+        if current.sql_type != target.sql_type {
+            diff.statements.push(format!("{} TYPE {}", alter_column, target.sql_type));
+        }
+        // sequences have different names in the scratch schema, so serial columns' defaults are
+        // left alone
+        let serial = current.serial_type().is_some() && target.serial_type().is_some();
+        if current.default != target.default && !serial {
+            match target.default {
+                Some(ref default) => {
+                    diff.statements.push(format!("{} SET DEFAULT {}", alter_column, default));
+                },
+                None => diff.statements.push(format!("{} DROP DEFAULT", alter_column))
+            }
+        }
+        if current.not_null != target.not_null {
+            let action = if target.not_null { "SET" } else { "DROP" };
+            diff.statements.push(format!("{} {} NOT NULL", alter_column, action));
+        }
+    }
+}
+
+/// Returns the name PostgreSQL stores for a table named as in a `CREATE TABLE` statement, e.g.
+/// "users" for `public.Users` or "Users" for `"Users"`.
+fn unquoted_table_name(table: &str) -> String {
+    let name = table.rsplit('.').next().unwrap_or(table);
+    if name.starts_with('"') && name.ends_with('"') && name.len() > 1 {
+        name[1..(name.len() - 1)].replace("\"\"", "\"")
+    } else {
+        name.to_lowercase()
+    }
+}
+
+/// Quotes an identifier for use in SQL, unless it doesn't need to be.
+fn quote_identifier(identifier: &str) -> String {
+    let plain = identifier.starts_with(|character: char| {
+        character.is_ascii_lowercase() || character == '_'
+    }) && identifier.chars().all(|character| {
+        character.is_ascii_lowercase() || character.is_ascii_digit() || character == '_'
+    });
+    if plain {
+        identifier.to_owned()
+    } else {
+        format!("\"{}\"", identifier.replace('"', "\"\""))
+    }
+}
@@ -0,0 +1,115 @@
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use super::sql_migration;
+
+
+/// The name of the file `embed_migrations()` generates in Cargo's OUT_DIR.
+pub const EMBEDDED_MIGRATIONS_FILE: &'static str = "trek_embedded_migrations.rs";
+
+/// Compiles the SQL migrations in the given directory into the program being built, so it needs
+/// no migration files at runtime. Call this from the program's build script, with Trek as a build
+/// dependency, then use `embed_migrations!()` in the program to define an
+/// `embedded_migrations()` function returning a `MigrationIndex` of the migrations. The
+/// migrations are named as described by `sql_migration::load_directory()`. Cargo is told to
+/// rerun the build script whenever the directory changes.
+///
+/// # Failures
+///
+/// Returns an error if it isn't called from a build script, if the migrations can't be found, or
+/// if the generated file can't be written.
+///
+/// # Examples
+///
+/// In build.rs:
+///
+/// ```no_run
+/// extern crate trek;
+///
+/// use std::path::Path;
+///
+/// fn main() {
+///     trek::embed::embed_migrations(Path::new("migrations")).unwrap();
+/// }
+/// ```
+///
+/// In the program:
+///
+/// ```ignore
+/// #[macro_use]
+/// extern crate trek;
+///
+/// embed_migrations!();
+///
+/// fn main() {
+///     let migrations = embedded_migrations();
+///     // ...
+/// }
+/// ```
+pub fn embed_migrations(migrations_dir: &Path) -> io::Result<()> {
+    let out_dir = match env::var_os("OUT_DIR") {
+        Some(out_dir) => PathBuf::from(out_dir),
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "OUT_DIR isn't set, embed_migrations() must be called from a build script"
+            ));
+        }
+    };
+    println!("cargo:rerun-if-changed={}", migrations_dir.display());
+    write_embedded_migrations(migrations_dir, &out_dir.join(EMBEDDED_MIGRATIONS_FILE))
+}
+
+/// Writes Rust source to `out_file` defining an `embedded_migrations()` function that returns a
+/// `MigrationIndex` of the SQL migrations in the given directory, with each migration's SQL
+/// included in the source with `include_str!()`. `embed_migrations()` calls this with the file
+/// `embed_migrations!()` includes.
+pub fn write_embedded_migrations(migrations_dir: &Path, out_file: &Path) -> io::Result<()> {
+    let mut source = String::from(
+        "/// Returns an index of the SQL migrations embedded in this program by Trek.\n\
+        pub fn embedded_migrations() -> ::trek::migration_index::MigrationIndex {\n    \
+            let migrations: Vec<Box<::trek::migration::Migration>> = vec![\n"
+    );
+    for files in try!(sql_migration::find_migration_files(migrations_dir)) {
+        let up_path = try!(fs::canonicalize(&files.up_path));
+        let migration = match files.down_path {
+            Some(down_path) => {
+                format!(
+                    "::trek::sql_migration::SqlMigration::new(\
+                    ::trek::migration_version::MigrationVersion::new({}), {:?}, \
+                    include_str!({:?}), include_str!({:?}))",
+                    files.version,
+                    files.name,
+                    up_path,
+                    try!(fs::canonicalize(&down_path))
+                )
+            },
+            None => {
+                format!(
+                    "::trek::sql_migration::SqlMigration::irreversible(\
+                    ::trek::migration_version::MigrationVersion::new({}), {:?}, \
+                    include_str!({:?}))",
+                    files.version,
+                    files.name,
+                    up_path
+                )
+            }
+        };
+        source.push_str(&format!("        Box::new({}),\n", migration));
+    }
+    source.push_str("    ];\n    ::trek::migration_index::MigrationIndex::new(migrations)\n}\n");
+
+    let mut file = try!(File::create(out_file));
+    file.write_all(source.as_bytes())
+}
+
+/// Defines an `embedded_migrations()` function returning a `MigrationIndex` of the SQL migrations
+/// compiled into the program by `embed::embed_migrations()` in its build script.
+#[macro_export]
+macro_rules! embed_migrations {
+    () => {
+        include!(concat!(env!("OUT_DIR"), "/trek_embedded_migrations.rs"));
+    };
+}
@@ -1,15 +1,101 @@
 use std::fmt::{Display, Formatter, Result};
+use std::io;
+use std::num::ParseIntError;
 use std;
 
 use postgres;
+#[cfg(feature = "serde")]
+use serde::{Serialize, Serializer};
+#[cfg(feature = "serde")]
+use serde::ser::SerializeStruct;
 
-/// An Error type for wrapping database errors in a higher-level message. For example, a database
-/// error may indicate a query failed but it would be more meaningful to provide a higher-level
-/// error message explaining what the query was trying to do.
+/// The errors Trek's operations fail with, each wrapping what caused it, if anything, in a
+/// higher-level message. For example, a database error may indicate a query failed but it would
+/// be more meaningful to provide a higher-level error message explaining what the query was
+/// trying to do. Match on the variants to handle kinds of failure differently. Database and IO
+/// errors convert into it, with a generic message, so `try!` can be used on them in functions
+/// returning `trek::Result`. It's `Send + Sync + 'static`, so it can be sent between threads and
+/// boxed as a `Box<std::error::Error + Send + Sync>`.
+///
+/// # Examples
+///
+/// ```no_run
+/// # extern crate postgres;
+/// # extern crate trek;
+/// # fn main() {
+/// # use postgres::{Connection, TlsMode};
+/// # use trek::error::Error;
+/// # use trek::migration::Migration;
+/// # use trek::migration_index::MigrationIndex;
+/// # let connection = Connection::connect("server url", TlsMode::None).unwrap();
+/// # let migration_list: Vec<Box<Migration>> = vec![];
+/// let migrations = MigrationIndex::new(migration_list);
+/// match migrations.rollback(&connection) {
+///     Ok(()) => println!("Rolled back the last migration."),
+///     Err(Error::Irreversible { ref cause, .. }) => {
+///         println!("{} can't be rolled back, restore a backup instead", cause.migration_name());
+///     },
+///     Err(error) => println!("Error rolling back: {}", error),
+/// }
+/// # }
+/// ```
 #[derive(Debug)]
-pub struct Error {
-    message: String,
-    cause: postgres::error::Error,
+pub enum Error {
+    /// A database error.
+    Db {
+        message: String,
+        cause: postgres::error::Error,
+    },
+    /// A database error running or rolling back migrations, in the given phase of the work and,
+    /// unless it happened while looking up the applied versions, for the named migration. The
+    /// statement that failed is given for migrations running their SQL with
+    /// `MigrationContext::batch_execute()`, as SQL migrations do.
+    Migration {
+        message: String,
+        migration_name: Option<String>,
+        phase: MigrationPhase,
+        statement: Option<String>,
+        cause: postgres::error::Error,
+    },
+    /// An IO error, e.g. reading or writing a migration file or running an external program.
+    Io {
+        message: String,
+        cause: io::Error,
+    },
+    /// A migration version that isn't a number.
+    VersionParse {
+        message: String,
+        cause: ParseIntError,
+    },
+    /// A problem with the migrations, their history or the configuration themselves, e.g. an
+    /// unknown version or a migration that depends on one that isn't registered.
+    Validation(ValidationError),
+    /// A migration that can't be rolled back. See `IrreversibleMigrationError`.
+    Irreversible {
+        message: String,
+        cause: IrreversibleMigrationError,
+    },
+    /// A migration recorded as applied to the database that isn't registered, e.g. one applied by
+    /// a newer release, or one whose code was deleted without squashing it (see
+    /// `squash::squash()`). The database has drifted from the registered migrations, so Trek
+    /// stops rather than guess which of them are outstanding.
+    UnknownAppliedVersion {
+        message: String,
+        migration_name: String,
+    },
+    /// A migration recorded as applied to the database whose checksum no longer matches the one
+    /// recorded when it was applied, so the database has drifted from its code. Changes to an
+    /// applied migration belong in a new migration.
+    ModifiedMigration {
+        message: String,
+        migration_name: String,
+    },
+    /// Another Trek error with a message explaining what was being done when it happened, added
+    /// with `ResultExt::context()`. The accessors look through it to the error it wraps.
+    Context {
+        message: String,
+        cause: Box<Error>,
+    },
 }
 
 impl Error {
@@ -40,13 +126,120 @@ impl Error {
     /// # }
     /// ```
     pub fn new(message: String, cause: postgres::error::Error) -> Self {
-        Error {
+        Error::Db {
+            message: message,
+            cause: cause
+        }
+    }
+
+    /// Wrap a new database error with a message, for an error running or rolling back the named
+    /// migration, or looking up the applied versions beforehand, in the given phase.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # extern crate postgres;
+    /// # extern crate trek;
+    /// # fn main() {
+    /// # use postgres::{Connection, TlsMode};
+    /// # use trek::error::{Error, MigrationPhase};
+    /// # let connection = Connection::connect("server url", TlsMode::None).unwrap();
+    /// let db_error = connection.execute("ANALYZE users;", &[]).unwrap_err();
+    /// let error = Error::migration(
+    ///     "Error analyzing users after migration 20150822094521_create_users_table".to_owned(),
+    ///     MigrationPhase::Hook,
+    ///     Some("20150822094521_create_users_table".to_owned()),
+    ///     db_error
+    /// );
+    /// assert_eq!(error.phase(), Some(MigrationPhase::Hook));
+    /// # }
+    /// ```
+    pub fn migration(
+        message: String,
+        phase: MigrationPhase,
+        migration_name: Option<String>,
+        cause: postgres::error::Error
+    ) -> Self {
+        Error::Migration {
+            message: message,
+            migration_name: migration_name,
+            phase: phase,
+            statement: None,
+            cause: cause
+        }
+    }
+
+    /// Sets the statement that failed, for a `Migration` error. Other errors are returned as they
+    /// are.
+    pub fn with_statement(mut self, failed_statement: Option<String>) -> Self {
+        if let Error::Migration { ref mut statement, .. } = self {
+            *statement = failed_statement;
+        }
+        self
+    }
+
+    /// Wrap a new IO error with a message.
+    pub fn io(message: String, cause: io::Error) -> Self {
+        Error::Io {
+            message: message,
+            cause: cause
+        }
+    }
+
+    /// Wrap the error from parsing a migration version with a message.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use trek::error::Error;
+    /// # use trek::migration_version::MigrationVersion;
+    /// let error = "latest".parse::<MigrationVersion>()
+    ///     .map_err(|error| Error::version_parse("Invalid target version".to_owned(), error))
+    ///     .unwrap_err();
+    /// assert_eq!(
+    ///     error.to_string(),
+    ///     "Invalid target version. The specific error is: invalid digit found in string"
+    /// );
+    /// ```
+    pub fn version_parse(message: String, cause: ParseIntError) -> Self {
+        Error::VersionParse {
             message: message,
             cause: cause
         }
     }
 
-    /// Get the original error.
+    /// Create an error that wasn't caused by another error, e.g. when Trek detects a problem
+    /// with the migrations themselves. It's a `Validation` error.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::error::Error as StdError;
+    /// # use trek::error::Error;
+    /// let error = Error::from_message("Migration history has diverged".to_owned());
+    /// assert!(error.source().is_none());
+    /// ```
+    pub fn from_message(message: String) -> Self {
+        Error::Validation(ValidationError::new(message))
+    }
+
+    /// Returns the message describing what went wrong, without the message of the error that
+    /// caused it.
+    pub fn message(&self) -> &str {
+        match *self {
+            Error::Db { ref message, .. } |
+            Error::Migration { ref message, .. } |
+            Error::Io { ref message, .. } |
+            Error::VersionParse { ref message, .. } |
+            Error::Irreversible { ref message, .. } |
+            Error::UnknownAppliedVersion { ref message, .. } |
+            Error::ModifiedMigration { ref message, .. } |
+            Error::Context { ref message, .. } => message,
+            Error::Validation(ref error) => &error.message
+        }
+    }
+
+    /// Get the original error, if this error was caused by a database error.
     ///
     /// # Examples
     ///
@@ -64,25 +257,648 @@ impl Error {
     /// #     Ok(result) => println!("no op"),
     /// #     Err(db_error) => {
     /// let error = Error::new("Failed to fetch inventory data".to_owned(), db_error);
-    /// println!("Problem communicating with the DB, the low-level error is: {}", error.cause());
+    /// println!(
+    ///     "Problem communicating with the DB, the low-level error is: {}",
+    ///     error.db_error().unwrap()
+    /// );
     /// # }
     /// # }
     /// # }
     /// # }
     /// ```
-    pub fn cause(&self) -> &postgres::error::Error {
-        &self.cause
+    pub fn db_error(&self) -> Option<&postgres::error::Error> {
+        match *self {
+            Error::Db { ref cause, .. } |
+            Error::Migration { ref cause, .. } => Some(cause),
+            Error::Context { ref cause, .. } => cause.db_error(),
+            _ => None
+        }
+    }
+
+    /// Get the original error, if this error was caused by a database error.
+    #[deprecated(note = "use `db_error()`, or `std::error::Error::source()` for any cause")]
+    pub fn cause(&self) -> Option<&postgres::error::Error> {
+        self.db_error()
+    }
+
+    /// Returns the name of the migration that failed, if this error was caused by running or
+    /// rolling back a migration, or of the applied migration that isn't registered or has been
+    /// modified, for an `UnknownAppliedVersion` or `ModifiedMigration` error.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # extern crate postgres;
+    /// # extern crate trek;
+    /// # fn main() {
+    /// # use postgres::{Connection, TlsMode};
+    /// # use trek::error::MigrationPhase;
+    /// # use trek::migration::Migration;
+    /// # use trek::migration_index::MigrationIndex;
+    /// # let connection = Connection::connect("server url", TlsMode::None).unwrap();
+    /// # let migration_list: Vec<Box<Migration>> = vec![];
+    /// let migrations = MigrationIndex::new(migration_list);
+    /// if let Err(error) = migrations.run(&connection) {
+    ///     if error.phase() == Some(MigrationPhase::Up) {
+    ///         println!("The SQL of migration {} failed", error.migration_name().unwrap());
+    ///     }
+    /// }
+    /// # }
+    /// ```
+    pub fn migration_name(&self) -> Option<&str> {
+        match *self {
+            Error::Migration { ref migration_name, .. } => {
+                migration_name.as_ref().map(|name| &**name)
+            },
+            Error::Irreversible { ref cause, .. } => Some(cause.migration_name()),
+            Error::UnknownAppliedVersion { ref migration_name, .. } |
+            Error::ModifiedMigration { ref migration_name, .. } => Some(migration_name),
+            Error::Context { ref cause, .. } => cause.migration_name(),
+            _ => None
+        }
+    }
+
+    /// Returns the phase of running or rolling back migrations this error happened in, if it
+    /// was caused by running or rolling back migrations.
+    pub fn phase(&self) -> Option<MigrationPhase> {
+        match *self {
+            Error::Migration { phase, .. } => Some(phase),
+            Error::Irreversible { .. } => Some(MigrationPhase::Down),
+            Error::UnknownAppliedVersion { .. } |
+            Error::ModifiedMigration { .. } => Some(MigrationPhase::VersionLookup),
+            Error::Context { ref cause, .. } => cause.phase(),
+            _ => None
+        }
+    }
+
+    /// Returns the statement that failed, if this error was caused by a migration whose SQL was run
+    /// with `MigrationContext::batch_execute()`. When the migration ran several statements at once
+    /// and PostgreSQL reported where the error was, it's narrowed down to the statement there.
+    pub fn statement(&self) -> Option<&str> {
+        match *self {
+            Error::Migration { ref statement, .. } => {
+                statement.as_ref().map(|statement| &**statement)
+            },
+            Error::Context { ref cause, .. } => cause.statement(),
+            _ => None
+        }
+    }
+
+    /// Returns the SQLSTATE code PostgreSQL reported, e.g. "23505" for a unique violation, if this
+    /// error was caused by the database rejecting a statement.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # extern crate postgres;
+    /// # extern crate trek;
+    /// # fn main() {
+    /// # use postgres::{Connection, TlsMode};
+    /// # use trek::migration::Migration;
+    /// # use trek::migration_index::MigrationIndex;
+    /// # let connection = Connection::connect("server url", TlsMode::None).unwrap();
+    /// # let migration_list: Vec<Box<Migration>> = vec![];
+    /// let migrations = MigrationIndex::new(migration_list);
+    /// if let Err(error) = migrations.run(&connection) {
+    ///     if error.sqlstate() == Some("23505") {
+    ///         println!("Duplicate rows violate {}", error.constraint().unwrap_or("a constraint"));
+    ///     }
+    /// }
+    /// # }
+    /// ```
+    pub fn sqlstate(&self) -> Option<&str> {
+        self.db_error()
+            .and_then(|error| error.as_db())
+            .map(|db_error| db_error.code.code())
+    }
+
+    /// Returns the name of the constraint the database reported as violated, if this error was
+    /// caused by a statement violating one.
+    pub fn constraint(&self) -> Option<&str> {
+        self.db_error()
+            .and_then(|error| error.as_db())
+            .and_then(|db_error| db_error.constraint.as_ref())
+            .map(|constraint| &**constraint)
+    }
+
+    /// Returns a suggestion of how to fix the problem, for well-known failures: a migration left
+    /// dirty, an applied migration that isn't registered or has been modified, a missing migration
+    /// history table or missing privileges. It's also shown by `Display`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use trek::error::{Error, ValidationError};
+    /// let error = Error::Validation(
+    ///     ValidationError::new("Migration 20150826001350 is dirty".to_owned())
+    ///         .with_hint("Repair the database by hand, then call force_clean()")
+    /// );
+    /// assert_eq!(error.hint(), Some("Repair the database by hand, then call force_clean()"));
+    /// ```
+    pub fn hint(&self) -> Option<&str> {
+        match *self {
+            Error::Validation(ref error) => return error.hint(),
+            Error::UnknownAppliedVersion { .. } => return Some(
+                "Deploy the release that registers the migration, or roll it back with the down \
+                SQL recorded by store_down_sql(); if its code was deleted, squash it instead"
+            ),
+            Error::ModifiedMigration { .. } => return Some(
+                "Revert the changes to the applied migration and make them in a new migration \
+                instead"
+            ),
+            Error::Context { ref cause, .. } => return cause.hint(),
+            _ => {}
+        }
+        match self.sqlstate() {
+            Some(INSUFFICIENT_PRIVILEGE) => Some(
+                "Grant the database user running migrations the privileges it needs, e.g. \
+                GRANT USAGE, CREATE ON SCHEMA <schema> TO <user> to create tables"
+            ),
+            Some(UNDEFINED_TABLE) if self.is_bookkeeping() => Some(
+                "The migration history table doesn't exist: run() creates it, and baseline() \
+                records a database whose schema was created without Trek"
+            ),
+            _ => None
+        }
+    }
+
+    /// Returns true if this error was caused by reading or writing the migration history.
+    fn is_bookkeeping(&self) -> bool {
+        let phase = self.phase();
+        phase == Some(MigrationPhase::VersionLookup) || phase == Some(MigrationPhase::Bookkeeping)
+    }
+
+    /// Returns true if this error was caused by a transient database error, which may not recur
+    /// if the work is tried again: a serialization failure, a deadlock, a lock timeout or the
+    /// connection to the database being lost. `MigrationIndex::run()` retries such failures
+    /// itself when given a `RunOptions::retry_policy()`, except for losing the connection, which
+    /// calls for connecting again.
+    pub fn is_retryable(&self) -> bool {
+        match self.db_error() {
+            Some(error) => is_transient(error),
+            None => false
+        }
+    }
+
+    /// Returns true if this error was caused by the database drifting from the registered
+    /// migrations: an applied migration that isn't registered or has been modified since it was
+    /// applied.
+    pub fn is_drift(&self) -> bool {
+        match *self {
+            Error::UnknownAppliedVersion { .. } | Error::ModifiedMigration { .. } => true,
+            Error::Context { ref cause, .. } => cause.is_drift(),
+            _ => false
+        }
+    }
+
+    /// Returns the error for the migration that can't be undone, if this error was caused by
+    /// trying to roll it back.
+    pub fn irreversible_migration(&self) -> Option<&IrreversibleMigrationError> {
+        match *self {
+            Error::Irreversible { ref cause, .. } => Some(cause),
+            Error::Context { ref cause, .. } => cause.irreversible_migration(),
+            _ => None
+        }
+    }
+
+    /// Returns true if this error was caused by trying to roll back a migration that can't be
+    /// undone. See `IrreversibleMigrationError`.
+    pub fn is_irreversible(&self) -> bool {
+        self.irreversible_migration().is_some()
+    }
+
+    /// Returns the name of the variant, for serializing the error.
+    #[cfg(feature = "serde")]
+    fn kind(&self) -> &'static str {
+        match *self {
+            Error::Db { .. } => "db",
+            Error::Migration { .. } => "migration",
+            Error::Io { .. } => "io",
+            Error::VersionParse { .. } => "version_parse",
+            Error::Validation(_) => "validation",
+            Error::Irreversible { .. } => "irreversible",
+            Error::UnknownAppliedVersion { .. } => "unknown_applied_version",
+            Error::ModifiedMigration { .. } => "modified_migration",
+            Error::Context { .. } => "context",
+        }
+    }
+}
+
+/// Serializes the error as a structure of its kind ("db", "migration", "io", "version_parse",
+/// "validation", "irreversible", "unknown_applied_version", "modified_migration" or "context"),
+/// its message, the details given by its accessors and the message of the error that caused it,
+/// for reporting failures to other tools.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate serde_json;
+/// # extern crate trek;
+/// # fn main() {
+/// # use trek::error::Error;
+/// let error = Error::from_message("Migration history has diverged".to_owned());
+/// let json = serde_json::to_value(&error).unwrap();
+/// assert_eq!(json["kind"], "validation");
+/// assert_eq!(json["message"], "Migration history has diverged");
+/// # }
+/// ```
+#[cfg(feature = "serde")]
+impl Serialize for Error {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        let cause = std::error::Error::source(self).map(|cause| cause.to_string());
+        let mut state = try!(serializer.serialize_struct("Error", 9));
+        try!(state.serialize_field("kind", self.kind()));
+        try!(state.serialize_field("message", self.message()));
+        try!(state.serialize_field("migration_name", &self.migration_name()));
+        try!(state.serialize_field("phase", &self.phase()));
+        try!(state.serialize_field("statement", &self.statement()));
+        try!(state.serialize_field("sqlstate", &self.sqlstate()));
+        try!(state.serialize_field("constraint", &self.constraint()));
+        try!(state.serialize_field("hint", &self.hint()));
+        try!(state.serialize_field("cause", &cause));
+        state.end()
     }
 }
 
 impl std::error::Error for Error {
     fn description(&self) -> &str {
-        &*self.message
+        self.message()
+    }
+
+    /// Returns the error this error wraps, so that error reporters can walk the chain down to
+    /// the underlying database or IO error. `Validation`, `UnknownAppliedVersion` and
+    /// `ModifiedMigration` errors don't wrap one.
+    fn source(&self) -> Option<&(std::error::Error + 'static)> {
+        match *self {
+            Error::Db { ref cause, .. } |
+            Error::Migration { ref cause, .. } => Some(cause),
+            Error::Io { ref cause, .. } => Some(cause),
+            Error::VersionParse { ref cause, .. } => Some(cause),
+            Error::Irreversible { ref cause, .. } => Some(cause),
+            Error::Context { ref cause, .. } => Some(&**cause),
+            Error::Validation(_) |
+            Error::UnknownAppliedVersion { .. } |
+            Error::ModifiedMigration { .. } => None
+        }
     }
 }
 
 impl Display for Error {
     fn fmt(&self, formatter: &mut Formatter) -> Result {
-        write!(formatter, "{}. The specific error is: {}", self.message, self.cause)
+        let cause: &Display = match *self {
+            Error::Db { ref cause, .. } => cause,
+            Error::Migration { ref cause, .. } => cause,
+            Error::Io { ref cause, .. } => cause,
+            Error::VersionParse { ref cause, .. } => cause,
+            Error::Irreversible { ref cause, .. } => cause,
+            Error::Context { ref message, ref cause } => {
+                // the wrapped error shows its own details
+                return write!(formatter, "{}. The specific error is: {}", message, cause);
+            },
+            Error::Validation(_) |
+            Error::UnknownAppliedVersion { .. } |
+            Error::ModifiedMigration { .. } => {
+                try!(write!(formatter, "{}", self.message()));
+                if let Some(hint) = self.hint() {
+                    try!(write!(formatter, "\nHint: {}", hint));
+                }
+                return Ok(());
+            }
+        };
+        try!(write!(formatter, "{}. The specific error is: {}", self.message(), cause));
+        if let Some(sqlstate) = self.sqlstate() {
+            try!(write!(formatter, " (SQLSTATE {}", sqlstate));
+            if let Some(constraint) = self.constraint() {
+                try!(write!(formatter, ", constraint {}", constraint));
+            }
+            try!(write!(formatter, ")"));
+        }
+        if let Some(statement) = self.statement() {
+            try!(write!(formatter, "\nThe statement that failed was: {}", statement));
+        }
+        if let Some(hint) = self.hint() {
+            try!(write!(formatter, "\nHint: {}", hint));
+        }
+        Ok(())
+    }
+}
+
+/// Fails to compile if `Error`, or any error it wraps, stops being `Send + Sync + 'static`.
+#[allow(dead_code)]
+fn assert_error_is_send_sync() {
+    fn is_send_sync<T: Send + Sync + 'static>() {}
+    is_send_sync::<Error>();
+}
+
+impl From<postgres::error::Error> for Error {
+    fn from(error: postgres::error::Error) -> Self {
+        Error::Db {
+            message: "Database error".to_owned(),
+            cause: error
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    /// Converts an IO error into an `Io` error, or, if it wraps a `ValidationError`, as the
+    /// migration generator's errors for invalid names do, into a `Validation` error.
+    fn from(error: io::Error) -> Self {
+        let wraps_validation = match error.get_ref() {
+            Some(inner) => inner.is::<ValidationError>(),
+            None => false
+        };
+        if !wraps_validation {
+            return Error::Io {
+                message: "IO error".to_owned(),
+                cause: error
+            };
+        }
+        match error.into_inner().map(|inner| inner.downcast::<ValidationError>()) {
+            Some(Ok(validation_error)) => Error::Validation(*validation_error),
+            _ => unreachable!("the IO error was checked to wrap a ValidationError")
+        }
+    }
+}
+
+/// Adds a message explaining what was being done to the error of a failed operation, for
+/// functions returning `trek::Result`, in place of matching on the result to wrap its error.
+/// Database and IO errors are wrapped in a `Db` or `Io` error with the message, and Trek errors
+/// in a `Context` error.
+///
+/// # Examples
+///
+/// ```no_run
+/// # extern crate postgres;
+/// # extern crate trek;
+/// # fn main() {
+/// # use postgres::GenericConnection;
+/// # use trek::error::ResultExt;
+/// fn backfill_emails(connection: &GenericConnection, table: &str) -> trek::Result<u64> {
+///     let count = try!(
+///         connection.execute(&format!("UPDATE {} SET email = lower(email);", table), &[])
+///             .with_context(|| format!("Failed to backfill emails in {}", table))
+///     );
+///     try!(connection.execute("ANALYZE;", &[]).context("Failed to analyze after backfilling"));
+///     Ok(count)
+/// }
+/// # }
+/// ```
+pub trait ResultExt<T>: Sized {
+    /// Wraps the error, if there is one, with the message.
+    fn context<M: Into<String>>(self, message: M) -> std::result::Result<T, Error> {
+        self.with_context(|| message.into())
+    }
+
+    /// Wraps the error, if there is one, with the message returned by the function, which is
+    /// only called on failure, sparing formatting the message otherwise.
+    fn with_context<F>(self, message: F) -> std::result::Result<T, Error>
+        where F: FnOnce() -> String;
+}
+
+impl<T> ResultExt<T> for std::result::Result<T, postgres::error::Error> {
+    fn with_context<F>(self, message: F) -> std::result::Result<T, Error>
+        where F: FnOnce() -> String
+    {
+        self.map_err(|error| Error::new(message(), error))
+    }
+}
+
+impl<T> ResultExt<T> for std::result::Result<T, io::Error> {
+    fn with_context<F>(self, message: F) -> std::result::Result<T, Error>
+        where F: FnOnce() -> String
+    {
+        self.map_err(|error| Error::io(message(), error))
+    }
+}
+
+impl<T> ResultExt<T> for std::result::Result<T, Error> {
+    fn with_context<F>(self, message: F) -> std::result::Result<T, Error>
+        where F: FnOnce() -> String
+    {
+        self.map_err(|error| {
+            Error::Context {
+                message: message(),
+                cause: Box::new(error)
+            }
+        })
+    }
+}
+
+/// Wraps database errors running or rolling back migrations as `Migration` errors, like
+/// `ResultExt` wraps other errors, in the phase of the work they happened in.
+pub(crate) trait MigrationResultExt<T>: Sized {
+    /// Wraps the error, if there is one, with the message returned by the function, as an error
+    /// in the given phase that isn't about any one migration.
+    fn with_phase<F>(self, phase: MigrationPhase, message: F) -> std::result::Result<T, Error>
+        where F: FnOnce() -> String;
+
+    /// Wraps the error, if there is one, with the message returned by the function, as an error
+    /// in the given phase of the work on the migration with the given name.
+    fn with_migration<M, F>(
+        self,
+        phase: MigrationPhase,
+        migration_name: &M,
+        message: F
+    ) -> std::result::Result<T, Error>
+        where M: Display + ?Sized, F: FnOnce() -> String;
+}
+
+impl<T> MigrationResultExt<T> for std::result::Result<T, postgres::error::Error> {
+    fn with_phase<F>(self, phase: MigrationPhase, message: F) -> std::result::Result<T, Error>
+        where F: FnOnce() -> String
+    {
+        self.map_err(|error| Error::migration(message(), phase, None, error))
+    }
+
+    fn with_migration<M, F>(
+        self,
+        phase: MigrationPhase,
+        migration_name: &M,
+        message: F
+    ) -> std::result::Result<T, Error>
+        where M: Display + ?Sized, F: FnOnce() -> String
+    {
+        self.map_err(|error| {
+            Error::migration(message(), phase, Some(migration_name.to_string()), error)
+        })
+    }
+}
+
+/// The SQLSTATE of permission denied errors.
+const INSUFFICIENT_PRIVILEGE: &'static str = "42501";
+
+/// The SQLSTATE of errors referring to a table that doesn't exist.
+const UNDEFINED_TABLE: &'static str = "42P01";
+
+/// The SQLSTATE codes of database errors that may not recur when the work is tried again:
+/// serialization failures, deadlocks and lock timeouts.
+const TRANSIENT_SQLSTATES: &'static [&'static str] = &["40001", "40P01", "55P03"];
+
+/// Returns true if the database error is transient, as described by `Error::is_retryable()`.
+pub(crate) fn is_transient(error: &postgres::error::Error) -> bool {
+    if let Some(db_error) = error.as_db() {
+        let code = db_error.code.code();
+        // class 08 is connection exceptions, and 57P01 the server shutting down
+        return TRANSIENT_SQLSTATES.contains(&code) || code.starts_with("08") || code == "57P01";
+    }
+    // the connection failing with an IO error means it was lost, but an irreversible migration
+    // error is passed through postgres as an IO error too
+    error.as_io().is_some() && IrreversibleMigrationError::from_error(error).is_none()
+}
+
+/// Returns true if the database error means the connection to the database was lost, so
+/// retrying on the same connection is futile.
+pub(crate) fn is_connection_lost(error: &postgres::error::Error) -> bool {
+    match error.as_db() {
+        Some(db_error) => {
+            let code = db_error.code.code();
+            code.starts_with("08") || code == "57P01"
+        },
+        None => error.as_io().is_some() && IrreversibleMigrationError::from_error(error).is_none()
+    }
+}
+
+/// The phases of running or rolling back a migration, which `Error::phase()` tells apart.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum MigrationPhase {
+    /// Reading the migration history to find which migrations are applied, before any are run or
+    /// rolled back.
+    VersionLookup,
+    /// Calling the migration's `should_run()`.
+    ShouldRun,
+    /// Applying the migration with its `up()` method.
+    Up,
+    /// Undoing the migration with its `down()` method.
+    Down,
+    /// Calling a `before_each` or `after_each` hook for the migration.
+    Hook,
+    /// Setting or restoring the statement and lock timeouts the migration runs with.
+    Timeouts,
+    /// Recording the migration's progress in the migration history.
+    Bookkeeping,
+}
+
+impl Display for MigrationPhase {
+    fn fmt(&self, formatter: &mut Formatter) -> Result {
+        let description = match *self {
+            MigrationPhase::VersionLookup => "looking up applied versions",
+            MigrationPhase::ShouldRun => "checking whether to run",
+            MigrationPhase::Up => "applying",
+            MigrationPhase::Down => "rolling back",
+            MigrationPhase::Hook => "running a hook",
+            MigrationPhase::Timeouts => "setting timeouts",
+            MigrationPhase::Bookkeeping => "recording in the migration history",
+        };
+        write!(formatter, "{}", description)
+    }
+}
+
+/// An error explaining why a migration index's configuration or list of migrations is invalid.
+#[derive(Debug)]
+pub struct ValidationError {
+    message: String,
+    hint: Option<String>,
+}
+
+impl ValidationError {
+    /// Creates a new validation error with a message describing the problem.
+    pub fn new(message: String) -> Self {
+        ValidationError {
+            message: message,
+            hint: None,
+        }
+    }
+
+    /// Sets a suggestion of how to fix the problem, e.g. "call force_clean()".
+    pub fn with_hint(mut self, hint: &str) -> Self {
+        self.hint = Some(hint.to_owned());
+        self
+    }
+
+    /// Returns the suggestion of how to fix the problem, if there is one.
+    pub fn hint(&self) -> Option<&str> {
+        self.hint.as_ref().map(|hint| &**hint)
+    }
+}
+
+impl std::error::Error for ValidationError {
+    fn description(&self) -> &str {
+        &*self.message
+    }
+}
+
+impl Display for ValidationError {
+    fn fmt(&self, formatter: &mut Formatter) -> Result {
+        write!(formatter, "{}", self.message)
+    }
+}
+
+/// The error returned by the default implementation of `Migration::down()`, for migrations that
+/// can't be undone, e.g. data migrations that discard information. It's passed through
+/// `postgres::error::Error` as an IO error, since that's the error type `down()` returns;
+/// `from_error()` recovers it.
+#[derive(Debug)]
+pub struct IrreversibleMigrationError {
+    migration_name: String,
+}
+
+impl IrreversibleMigrationError {
+    /// Creates an error for the migration with the given name.
+    pub fn new(migration_name: String) -> Self {
+        IrreversibleMigrationError {
+            migration_name: migration_name
+        }
+    }
+
+    /// Returns the name of the migration that can't be undone.
+    pub fn migration_name(&self) -> &str {
+        &self.migration_name
+    }
+
+    /// Returns the irreversible migration error wrapped in the given database error, if it wraps
+    /// one.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate postgres;
+    /// # extern crate trek;
+    /// # fn main() {
+    /// # use trek::error::IrreversibleMigrationError;
+    /// let error: postgres::error::Error =
+    ///     IrreversibleMigrationError::new("drop_legacy_data".to_owned()).into();
+    /// let irreversible = IrreversibleMigrationError::from_error(&error).unwrap();
+    /// assert_eq!(irreversible.migration_name(), "drop_legacy_data");
+    /// # }
+    /// ```
+    pub fn from_error(error: &postgres::error::Error) -> Option<&Self> {
+        error.as_io()
+            .and_then(|io_error| io_error.get_ref())
+            .and_then(|inner| inner.downcast_ref::<IrreversibleMigrationError>())
+    }
+}
+
+impl std::error::Error for IrreversibleMigrationError {
+    fn description(&self) -> &str {
+        "migration is irreversible"
+    }
+}
+
+impl Display for IrreversibleMigrationError {
+    fn fmt(&self, formatter: &mut Formatter) -> Result {
+        write!(
+            formatter,
+            "Migration {} is irreversible and can't be rolled back",
+            self.migration_name
+        )
+    }
+}
+
+impl From<IrreversibleMigrationError> for postgres::error::Error {
+    fn from(error: IrreversibleMigrationError) -> Self {
+        io::Error::new(io::ErrorKind::Other, error).into()
     }
 }
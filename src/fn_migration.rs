@@ -0,0 +1,201 @@
+use std::fmt::{self, Display, Formatter};
+use std::time::Duration;
+
+use postgres;
+
+use super::error::IrreversibleMigrationError;
+use super::migration::Migration;
+use super::migration_context::MigrationContext;
+use super::migration_version::MigrationVersion;
+
+
+/// A closure that applies or undoes a migration. See `FnMigration`.
+pub type MigrationFn = Box<Fn(&MigrationContext) -> postgres::Result<()>>;
+
+/// A migration defined by closures, for migrations such as data transformations that are easier
+/// to express in Rust than in SQL but don't warrant a struct of their own.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate postgres;
+/// # extern crate trek;
+/// # fn main() {
+/// # use trek::fn_migration::FnMigration;
+/// # use trek::migration_context::MigrationContext;
+/// # use trek::migration_index::MigrationIndex;
+/// # use trek::migration_version::MigrationVersion;
+/// let migrations = MigrationIndex::builder()
+///     .migration(FnMigration::new(
+///         MigrationVersion::new(20150826001350),
+///         "20150826001350_normalize_emails",
+///         |context: &MigrationContext| {
+///             let connection = context.connection();
+///             for row in &try!(connection.query("SELECT id, email FROM users;", &[])) {
+///                 let id: i32 = row.get(0);
+///                 let email: String = row.get(1);
+///                 try!(connection.execute(
+///                     "UPDATE users SET email = $2 WHERE id = $1;",
+///                     &[&id, &email.to_lowercase()]
+///                 ));
+///             }
+///             Ok(())
+///         },
+///         |_context: &MigrationContext| Ok(())
+///     ))
+///     .build()
+///     .unwrap();
+/// assert_eq!(migrations.len(), 1);
+/// # }
+/// ```
+pub struct FnMigration {
+    version: MigrationVersion,
+    name: String,
+    up: MigrationFn,
+    down: Option<MigrationFn>,
+    transactional: bool,
+    description: Option<String>,
+    tags: Vec<String>,
+    dependencies: Vec<MigrationVersion>,
+    environments: Vec<String>,
+    statement_timeout: Option<Duration>,
+    lock_timeout: Option<Duration>,
+}
+
+impl FnMigration {
+    /// Creates a migration that's applied by calling `up` and undone by calling `down`.
+    pub fn new<U, D>(version: MigrationVersion, name: &str, up: U, down: D) -> Self
+        where U: Fn(&MigrationContext) -> postgres::Result<()> + 'static,
+              D: Fn(&MigrationContext) -> postgres::Result<()> + 'static
+    {
+        FnMigration {
+            version: version,
+            name: name.to_owned(),
+            up: Box::new(up),
+            down: Some(Box::new(down)),
+            transactional: true,
+            description: None,
+            tags: vec![],
+            dependencies: vec![],
+            environments: vec![],
+            statement_timeout: None,
+            lock_timeout: None,
+        }
+    }
+
+    /// Creates a migration that's applied by calling `up` and can't be undone.
+    pub fn irreversible<U>(version: MigrationVersion, name: &str, up: U) -> Self
+        where U: Fn(&MigrationContext) -> postgres::Result<()> + 'static
+    {
+        FnMigration {
+            version: version,
+            name: name.to_owned(),
+            up: Box::new(up),
+            down: None,
+            transactional: true,
+            description: None,
+            tags: vec![],
+            dependencies: vec![],
+            environments: vec![],
+            statement_timeout: None,
+            lock_timeout: None,
+        }
+    }
+
+    /// Sets the migration's description. See `Migration::description()`.
+    pub fn with_description(mut self, description: &str) -> Self {
+        self.description = Some(description.to_owned());
+        self
+    }
+
+    /// Sets the migration's tags. See `Migration::tags()`.
+    pub fn with_tags(mut self, tags: &[&str]) -> Self {
+        self.tags = tags.iter().map(|tag| tag.to_string()).collect();
+        self
+    }
+
+    /// Sets the versions of the migrations this migration depends on. See
+    /// `Migration::dependencies()`.
+    pub fn with_dependencies(mut self, dependencies: &[MigrationVersion]) -> Self {
+        self.dependencies = dependencies.to_vec();
+        self
+    }
+
+    /// Restricts the migration to the given environments. See `Migration::environments()`.
+    pub fn with_environments(mut self, environments: &[&str]) -> Self {
+        self.environments = environments.iter()
+            .map(|environment| environment.to_string())
+            .collect();
+        self
+    }
+
+    /// Sets the migration's statement timeout. See `Migration::statement_timeout()`.
+    pub fn with_statement_timeout(mut self, timeout: Duration) -> Self {
+        self.statement_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the migration's lock timeout. See `Migration::lock_timeout()`.
+    pub fn with_lock_timeout(mut self, timeout: Duration) -> Self {
+        self.lock_timeout = Some(timeout);
+        self
+    }
+
+    /// Marks the migration as needing to run outside of a transaction. See
+    /// `Migration::transactional()`.
+    pub fn non_transactional(mut self) -> Self {
+        self.transactional = false;
+        self
+    }
+}
+
+impl Migration for FnMigration {
+    fn version(&self) -> MigrationVersion {
+        self.version
+    }
+
+    fn up(&self, context: &MigrationContext) -> postgres::Result<()> {
+        (self.up)(context)
+    }
+
+    fn down(&self, context: &MigrationContext) -> postgres::Result<()> {
+        match self.down {
+            Some(ref down) => down(context),
+            None => Err(IrreversibleMigrationError::new(self.name.clone()).into())
+        }
+    }
+
+    fn transactional(&self) -> bool {
+        self.transactional
+    }
+
+    fn description(&self) -> Option<String> {
+        self.description.clone()
+    }
+
+    fn dependencies(&self) -> Vec<MigrationVersion> {
+        self.dependencies.clone()
+    }
+
+    fn tags(&self) -> Vec<String> {
+        self.tags.clone()
+    }
+
+    fn environments(&self) -> Vec<String> {
+        self.environments.clone()
+    }
+
+    fn statement_timeout(&self) -> Option<Duration> {
+        self.statement_timeout
+    }
+
+    fn lock_timeout(&self) -> Option<Duration> {
+        self.lock_timeout
+    }
+}
+
+impl Display for FnMigration {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        write!(formatter, "{}", self.name)
+    }
+}
@@ -0,0 +1,1467 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::UTC;
+
+use super::Result;
+use super::error::ValidationError;
+use super::migration_version::{MigrationVersion, TimestampVersions, VersionStrategy};
+use super::scaffold::Scaffold;
+use super::sql_migration;
+
+
+/// The kinds of file `create_migration()` can generate a new migration as.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MigrationTemplate {
+    /// A Rust file defining a struct that implements `Migration`, declared as a module in the
+    /// directory's `mod.rs` but registered with a `MigrationIndex` by hand.
+    Rust,
+    /// A pair of ".up.sql" and ".down.sql" files, as loaded by
+    /// `sql_migration::load_directory()` and `embed::embed_migrations()`.
+    Sql,
+    /// A Rust file like `Rust`'s, but whose struct implements `ReversibleMigration`, describing
+    /// the change once so that it's undone without writing the SQL to do so.
+    Reversible,
+}
+
+/// A migration created by `create_migration()`, or a seed created by `create_seed()`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GeneratedMigration {
+    version: MigrationVersion,
+    name: String,
+    template: MigrationTemplate,
+    paths: Vec<PathBuf>,
+    module_name: Option<String>,
+    struct_name: Option<String>,
+    test_module_name: Option<String>,
+    warnings: Vec<String>,
+}
+
+impl GeneratedMigration {
+    /// Returns the new migration's version.
+    pub fn version(&self) -> MigrationVersion {
+        self.version
+    }
+
+    /// Returns the new migration's full name, e.g. "migration_20150822094521_create_users_table"
+    /// for a Rust migration or "20150822094521_create_users_table" for a SQL one.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the template the migration was created from.
+    pub fn template(&self) -> MigrationTemplate {
+        self.template
+    }
+
+    /// Returns the path of the migration's main file: the Rust file, or the ".up.sql" file of a
+    /// SQL migration.
+    pub fn path(&self) -> &Path {
+        &self.paths[0]
+    }
+
+    /// Returns the paths of all the files created for the migration.
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    /// Returns the name of the Rust migration's module, as declared in the migrations
+    /// directory's `mod.rs`. SQL migrations have no module.
+    pub fn module_name(&self) -> Option<&str> {
+        self.module_name.as_ref().map(|module_name| &**module_name)
+    }
+
+    /// Returns the name of the struct implementing the Rust migration, e.g. "CreateUsersTable".
+    /// SQL migrations have no struct.
+    pub fn struct_name(&self) -> Option<&str> {
+        self.struct_name.as_ref().map(|struct_name| &**struct_name)
+    }
+
+    /// Returns the name of the module of the test generated alongside the Rust migration, e.g.
+    /// "test_migration_20150822094521_create_users_table", if
+    /// `MigrationGenerator::test_skeletons()` is set. It's declared in the migrations directory's
+    /// `mod.rs` for `#[cfg(test)]` only.
+    pub fn test_module_name(&self) -> Option<&str> {
+        self.test_module_name.as_ref().map(|test_module_name| &**test_module_name)
+    }
+
+    /// Returns warnings about the new migration, such as its version sorting before that of a
+    /// migration already in the directory or applied to the database (see
+    /// `MigrationGenerator::latest_applied_version()`), which would get it applied out of order.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+}
+
+/// The longest name `create_migration()` accepts for a new migration, in bytes.
+pub const MAX_MIGRATION_NAME_LENGTH: usize = 100;
+
+/// Checks that the given name can be used for a new migration, as `create_migration()` requires.
+/// Names must be snake-cased: lowercase ASCII letters and digits in words separated by single
+/// underscores, starting with a letter, and no longer than `MAX_MIGRATION_NAME_LENGTH`. This
+/// ensures the files, module and struct generated for the migration have valid names.
+///
+/// # Examples
+///
+/// ```
+/// # use trek::validate_migration_name;
+/// assert!(validate_migration_name("create_users_table").is_ok());
+/// assert!(validate_migration_name("drop table; --oops").is_err());
+/// ```
+///
+/// # Failures
+///
+/// Returns an error describing what's wrong with the name if it isn't valid.
+pub fn validate_migration_name(name: &str) -> std::result::Result<(), ValidationError> {
+    let problem = if name.is_empty() {
+        Some("it's empty".to_owned())
+    } else if name.len() > MAX_MIGRATION_NAME_LENGTH {
+        Some(format!("it's longer than {} characters", MAX_MIGRATION_NAME_LENGTH))
+    } else if let Some(character) = name.chars().find(|character| {
+        !(character.is_ascii_lowercase() || character.is_ascii_digit() || *character == '_')
+    }) {
+        Some(format!(
+            "it contains {:?}, but only lowercase letters, digits and underscores are allowed",
+            character
+        ))
+    } else if !name.starts_with(|character: char| character.is_ascii_lowercase()) {
+        Some("it doesn't start with a letter".to_owned())
+    } else if name.ends_with('_') || name.contains("__") {
+        Some("its words aren't separated by single underscores".to_owned())
+    } else if name == "self" {
+        Some("its struct would be named Self, which is a keyword".to_owned())
+    } else {
+        None
+    };
+    match problem {
+        Some(problem) => {
+            Err(ValidationError::new(format!(
+                "{:?} isn't a valid migration name: {}. Migration names must be snake-cased, \
+                like create_users_table",
+                name,
+                problem
+            )))
+        },
+        None => Ok(())
+    }
+}
+
+/// A convenience method that automates creating a new, empty database migration from a name and a
+/// directory where the new migration's files should be created, using the given template. Returns
+/// a description of the new migration, including the paths of the files it created. A new Rust
+/// migration's module is also declared in the directory's `mod.rs`, which is created if it
+/// doesn't exist yet, so the migration compiles into the program without any further edits.
+///
+/// The migration's version is the current time, e.g. 20150822094521, or one more than the latest
+/// version of a migration already in the directory if that's later, so migrations created in the
+/// same second still get distinct versions, in the order they were created. Use a
+/// `MigrationGenerator` to version migrations another way.
+///
+/// # Failures
+///
+/// Returns a `Validation` error if the name isn't valid (see `validate_migration_name()`), or an
+/// `Io` error if a file can't be written.
+///
+/// # Examples:
+///
+/// ```no_run
+/// # use std::path::Path;
+/// # use trek::{create_migration, MigrationTemplate};
+/// let migrations_dir = Path::new("src/db/migrations/");
+/// match create_migration("create_users_table", migrations_dir, MigrationTemplate::Rust) {
+///     Ok(migration) => {
+///         println!(
+///             "Created new migration {} in {}",
+///             migration.struct_name().unwrap(),
+///             migration.path().display()
+///         );
+///     },
+///     Err(error) => println!("Error creating new database migration: {}", error)
+/// }
+/// ```
+pub fn create_migration(
+    name: &str,
+    migrations_dir: &Path,
+    template: MigrationTemplate
+) -> Result<GeneratedMigration> {
+    Ok(try!(create_scaffolded_migration(name, migrations_dir, template, &Scaffold::Empty)))
+}
+
+/// Creates a new, empty SQL migration in the given directory: a pair of files named like
+/// "20150822094521_create_users_table.up.sql" and "20150822094521_create_users_table.down.sql", as
+/// loaded by `sql_migration::load_directory()`. This is `create_migration()` with
+/// `MigrationTemplate::Sql`.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use std::path::Path;
+/// # use trek::create_sql_migration;
+/// let migration = create_sql_migration("create_users_table", Path::new("migrations/")).unwrap();
+/// for path in migration.paths() {
+///     println!("Created {}", path.display());
+/// }
+/// ```
+///
+/// # Failures
+///
+/// Fails like `create_scaffolded_migration()`.
+pub fn create_sql_migration(name: &str, migrations_dir: &Path) -> io::Result<GeneratedMigration> {
+    create_scaffolded_migration(name, migrations_dir, MigrationTemplate::Sql, &Scaffold::Empty)
+}
+
+/// Sets up the given directory for a project's first Rust migrations: creates it, with an empty
+/// `mod.rs` declaring no migrations yet, and a starter `migration_index.rs` next to it, defining a
+/// `MigrationIndex` wrapper whose `Default` impl lists the program's migrations, as described in
+/// the readme. Files that already exist are left as they are. Returns the paths of the files
+/// created. This is `MigrationGenerator::init()` with the default options.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use std::path::Path;
+/// # use trek::init_migrations;
+/// for path in init_migrations(Path::new("src/db/migrations/")).unwrap() {
+///     println!("Created {}", path.display());
+/// }
+/// ```
+///
+/// # Failures
+///
+/// Returns an error if the directory or a file can't be created.
+pub fn init_migrations(migrations_dir: &Path) -> io::Result<Vec<PathBuf>> {
+    MigrationGenerator::new(migrations_dir).init()
+}
+
+/// Creates a new seed in the given directory, like `create_migration()` creates a migration: a
+/// Rust file named like "seed_20150822094521_countries.rs", defining a struct that implements
+/// `Seed` and declared as a module in the directory's `mod.rs`, or a SQL file named like
+/// "20150822094521_countries.sql", as loaded by `seed::load_directory()`. Seeds are versioned
+/// like migrations so that they sort in the order they were created, which is the order they
+/// should be registered and run in.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use std::path::Path;
+/// # use trek::{create_seed, MigrationTemplate};
+/// let seeds_dir = Path::new("src/db/seeds/");
+/// let seed = create_seed("countries", seeds_dir, MigrationTemplate::Sql).unwrap();
+/// println!("Created {}", seed.path().display());
+/// ```
+///
+/// # Failures
+///
+/// Fails like `create_scaffolded_migration()`, or with an error of kind `InvalidInput` if the
+/// template is `MigrationTemplate::Reversible`, since seeds aren't undone.
+pub fn create_seed(
+    name: &str,
+    seeds_dir: &Path,
+    template: MigrationTemplate
+) -> io::Result<GeneratedMigration> {
+    MigrationGenerator::new(seeds_dir).template(template).create_seed(name)
+}
+
+/// Like `create_migration()`, but fills the new migration in with the SQL making the change
+/// described by `scaffold` and undoing it, so it's ready to run.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use std::path::Path;
+/// # use trek::{create_scaffolded_migration, MigrationTemplate};
+/// # use trek::scaffold::{Column, Scaffold};
+/// let migration = create_scaffolded_migration(
+///     "add_email_to_users",
+///     Path::new("src/db/migrations/"),
+///     MigrationTemplate::Sql,
+///     &Scaffold::add_column("users", Column::new("email", "TEXT"))
+/// ).unwrap();
+/// println!("Created {}", migration.name());
+/// ```
+///
+/// # Failures
+///
+/// Returns an error of kind `InvalidInput`, wrapping a `ValidationError`, if the name isn't valid
+/// (see `validate_migration_name()`), or an error if a file can't be written.
+pub fn create_scaffolded_migration(
+    name: &str,
+    migrations_dir: &Path,
+    template: MigrationTemplate,
+    scaffold: &Scaffold
+) -> io::Result<GeneratedMigration> {
+    try!(create_scaffolded_migration_preview(name, migrations_dir, template, scaffold)).write()
+}
+
+/// Returns the files `create_migration()` would create for a migration with the given name and
+/// template, and their contents, without writing anything.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use std::path::Path;
+/// # use trek::{create_migration_preview, MigrationTemplate};
+/// let preview = create_migration_preview(
+///     "create_users_table",
+///     Path::new("src/db/migrations/"),
+///     MigrationTemplate::Rust
+/// ).unwrap();
+/// for (path, contents) in preview.files() {
+///     println!("{}:\n{}", path.display(), contents);
+/// }
+/// // create the files after all
+/// let migration = preview.write().unwrap();
+/// ```
+///
+/// # Failures
+///
+/// Returns an error if the name isn't valid (see `validate_migration_name()`), or if the
+/// migrations directory can't be read to choose the migration's version.
+pub fn create_migration_preview(
+    name: &str,
+    migrations_dir: &Path,
+    template: MigrationTemplate
+) -> io::Result<MigrationPreview> {
+    create_scaffolded_migration_preview(name, migrations_dir, template, &Scaffold::Empty)
+}
+
+/// Returns the files `create_scaffolded_migration()` would create, and their contents, without
+/// writing anything.
+///
+/// # Failures
+///
+/// Fails like `create_migration_preview()`.
+pub fn create_scaffolded_migration_preview(
+    name: &str,
+    migrations_dir: &Path,
+    template: MigrationTemplate,
+    scaffold: &Scaffold
+) -> io::Result<MigrationPreview> {
+    MigrationGenerator::new(migrations_dir).template(template).preview(name, scaffold)
+}
+
+/// Creates migrations in a directory with options beyond those of `create_migration()`, such as
+/// how their versions are chosen.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use std::path::Path;
+/// # use trek::{MigrationGenerator, MigrationTemplate};
+/// # use trek::migration_version::SequentialVersions;
+/// # use trek::scaffold::Scaffold;
+/// let generator = MigrationGenerator::new(Path::new("migrations/"))
+///     .template(MigrationTemplate::Sql)
+///     .version_strategy(SequentialVersions::new());
+/// // creates e.g. 0003_create_users_table.up.sql and 0003_create_users_table.down.sql
+/// let migration = generator.create("create_users_table", &Scaffold::Empty).unwrap();
+/// ```
+pub struct MigrationGenerator {
+    migrations_dir: PathBuf,
+    template: MigrationTemplate,
+    version_strategy: Box<VersionStrategy>,
+    rust_template_file: Option<PathBuf>,
+    sql_template_file: Option<PathBuf>,
+    reversible_template_file: Option<PathBuf>,
+    date_subdirectories: bool,
+    test_skeletons: bool,
+    latest_applied_version: Option<MigrationVersion>,
+    metadata: MigrationMetadata,
+}
+
+impl MigrationGenerator {
+    /// Creates a generator of Rust migrations in the given directory, versioned with
+    /// `TimestampVersions`.
+    pub fn new(migrations_dir: &Path) -> Self {
+        MigrationGenerator {
+            migrations_dir: migrations_dir.to_path_buf(),
+            template: MigrationTemplate::Rust,
+            version_strategy: Box::new(TimestampVersions::new()),
+            rust_template_file: None,
+            sql_template_file: None,
+            reversible_template_file: None,
+            date_subdirectories: false,
+            test_skeletons: false,
+            latest_applied_version: None,
+            metadata: MigrationMetadata::default(),
+        }
+    }
+
+    /// Sets the kind of file migrations are created as.
+    pub fn template(mut self, template: MigrationTemplate) -> Self {
+        self.template = template;
+        self
+    }
+
+    /// Sets how new migrations' versions are chosen and written in their names.
+    pub fn version_strategy<S>(mut self, version_strategy: S) -> Self
+        where S: VersionStrategy + 'static
+    {
+        self.version_strategy = Box::new(version_strategy);
+        self
+    }
+
+    /// Sets whether migrations are created in year and month subdirectories of the directory, e.g.
+    /// "migrations/2024/05/", so it stays manageable as migrations accumulate. The subdirectories
+    /// are created as needed. `sql_migration::load_directory()`, `embed` and `codegen` find
+    /// migrations in subdirectories, and a Rust migration's module is declared in the top-level
+    /// `mod.rs` with a `#[path]` attribute pointing into them.
+    pub fn date_subdirectories(mut self, date_subdirectories: bool) -> Self {
+        self.date_subdirectories = date_subdirectories;
+        self
+    }
+
+    /// Sets whether a test is generated alongside each Rust or reversible migration, in a file
+    /// named like "test_migration_20150822094521_create_users_table.rs" next to the migration's
+    /// and declared in `mod.rs` for `#[cfg(test)]` only. The test applies the migration with
+    /// `testing::apply()`, checks the tables and columns a scaffolded migration creates exist,
+    /// undoes it with `testing::undo()` and checks they're gone again, all in a transaction on
+    /// `testing::test_connection()` that's never committed. For an empty migration, the checks
+    /// are left for you to write. SQL migrations get no test.
+    pub fn test_skeletons(mut self, test_skeletons: bool) -> Self {
+        self.test_skeletons = test_skeletons;
+        self
+    }
+
+    /// Sets the highest version applied to the database, as returned by
+    /// `MigrationIndex::latest_applied_version()`. A new migration whose version isn't after it,
+    /// e.g. because of clock skew between developers' machines, would be applied out of order,
+    /// or skipped if the history was pruned past it, so it's created with a warning (see
+    /// `GeneratedMigration::warnings()`). New migrations are checked against those already in
+    /// the directory either way.
+    pub fn latest_applied_version(mut self, version: MigrationVersion) -> Self {
+        self.latest_applied_version = Some(version);
+        self
+    }
+
+    /// Sets the author recorded in new migrations, e.g. "Jane Doe <jane@example.com>", so it
+    /// travels with the migration rather than only living in version control. It's written in a
+    /// comment at the top of a migration created from a built-in template, and fills in the
+    /// `{{author}}` placeholder of template files.
+    pub fn author(mut self, author: &str) -> Self {
+        self.metadata.author = Some(author.to_owned());
+        self
+    }
+
+    /// Sets the ticket or issue new migrations are for, e.g. its URL, recorded like `author()`
+    /// and filling in the `{{ticket}}` placeholder.
+    pub fn ticket(mut self, ticket: &str) -> Self {
+        self.metadata.ticket = Some(ticket.to_owned());
+        self
+    }
+
+    /// Sets a short description of what new migrations do, recorded like `author()` and filling
+    /// in the `{{description}}` placeholder. A Rust migration created from a built-in template
+    /// returns it from `description()`, and a SQL migration's ".up.sql" file starts with a
+    /// "-- Description:" comment, which `SqlMigration::description()` returns.
+    pub fn description(mut self, description: &str) -> Self {
+        self.metadata.description = Some(description.to_owned());
+        self
+    }
+
+    /// Creates migrations of the given kind from a template file of your own instead of the
+    /// built-in one. Placeholders in the file are replaced with the migration's details:
+    ///
+    /// * `{{name}}`: the name passed to `create()`, e.g. "create_users_table"
+    /// * `{{migration_name}}`: the migration's full name, e.g.
+    ///   "migration_20150822094521_create_users_table"
+    /// * `{{version}}`: the migration's version
+    /// * `{{struct_name}}`: the name of a Rust migration's struct, e.g. "CreateUsersTable"
+    /// * `{{author}}`, `{{ticket}}` and `{{description}}`: the metadata set with `author()`,
+    ///   `ticket()` and `description()`, or nothing if it isn't set
+    /// * `{{contracts}}`: the version of the expand migration a contract migration created by
+    ///   `create_expand_contract()` goes with, or nothing for other migrations
+    /// * `{{up}}` and `{{down}}`, in Rust templates: a statement running the scaffold's SQL, as in
+    ///   the built-in template
+    /// * `{{change}}`, in reversible templates: the statements describing the scaffold's change,
+    ///   as in the built-in template
+    /// * `{{action}}` and `{{sql}}`, in SQL templates, which are used for both the ".up.sql" and
+    ///   the ".down.sql" file: "apply" or "undo", and the scaffold's SQL doing that, if any
+    ///
+    /// The file is read when a migration is created.
+    pub fn template_file(mut self, template: MigrationTemplate, path: &Path) -> Self {
+        match template {
+            MigrationTemplate::Rust => self.rust_template_file = Some(path.to_path_buf()),
+            MigrationTemplate::Sql => self.sql_template_file = Some(path.to_path_buf()),
+            MigrationTemplate::Reversible => {
+                self.reversible_template_file = Some(path.to_path_buf());
+            },
+        }
+        self
+    }
+
+    /// Sets up the generator's directory for a project's first migrations, like
+    /// `init_migrations()`. Only the directory is created for SQL migrations, which need no
+    /// module declarations or index wrapper. Creating a migration creates its directory too, so
+    /// this is only needed for the Rust scaffolding.
+    ///
+    /// # Failures
+    ///
+    /// Fails like `init_migrations()`.
+    pub fn init(&self) -> io::Result<Vec<PathBuf>> {
+        try!(fs::create_dir_all(&self.migrations_dir));
+        let mut files = vec![];
+        if self.template != MigrationTemplate::Sql {
+            let module_name = self.migrations_dir.file_name()
+                .and_then(|module_name| module_name.to_str())
+                .unwrap_or("migrations");
+            let index_path = match self.migrations_dir.parent() {
+                Some(parent) => parent.join("migration_index.rs"),
+                None => PathBuf::from("migration_index.rs")
+            };
+            files.push((self.migrations_dir.join("mod.rs"), String::new()));
+            files.push((index_path, migration_index_template(module_name)));
+        }
+        let mut created = vec![];
+        for (path, contents) in files {
+            if path.exists() {
+                continue;
+            }
+            let mut file = try!(File::create(&path));
+            try!(file.write_all(contents.as_bytes()));
+            created.push(path);
+        }
+        Ok(created)
+    }
+
+    /// Creates a migration with the given name, filled in from the scaffold, like
+    /// `create_scaffolded_migration()`. Pass `Scaffold::Empty` for a migration whose SQL is left
+    /// for you to write.
+    ///
+    /// # Failures
+    ///
+    /// Fails like `create_scaffolded_migration()`.
+    pub fn create(&self, name: &str, scaffold: &Scaffold) -> io::Result<GeneratedMigration> {
+        try!(self.preview(name, scaffold)).write()
+    }
+
+    /// Returns the files `create()` would create, and their contents, without writing anything.
+    ///
+    /// # Failures
+    ///
+    /// Fails like `create_migration_preview()`, or if a template file set with `template_file()`
+    /// can't be read.
+    pub fn preview(&self, name: &str, scaffold: &Scaffold) -> io::Result<MigrationPreview> {
+        self.preview_with_metadata(name, scaffold, &self.metadata)
+    }
+
+    /// Creates an expand migration named "expand_<name>", adding the new structure alongside the
+    /// old, and a contract migration named "contract_<name>", removing the old structure, for a
+    /// schema change made without downtime. Each is filled in from its scaffold like
+    /// `create()` does. The contract migration returns the expand migration's version from
+    /// `Migration::contracts()`, so `MigrationIndex::run()` defers it until the expand migration
+    /// was applied by an earlier release and, if one is set, `RunOptions::contract_delay()` has
+    /// passed. The pair can be committed together: the first deploy applies the expand migration,
+    /// and a later one, once no running release uses the old structure, the contract migration.
+    ///
+    /// A SQL contract migration's ".up.sql" file starts with a "-- Contracts:" comment, which
+    /// `SqlMigration::contracts()` returns, and a Rust or reversible one has a `contracts()`
+    /// method. The version also fills in the `{{contracts}}` placeholder of template files.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use std::path::Path;
+    /// # use trek::{MigrationGenerator, MigrationTemplate};
+    /// # use trek::scaffold::{Column, Scaffold};
+    /// let generator = MigrationGenerator::new(Path::new("migrations"))
+    ///     .template(MigrationTemplate::Sql);
+    /// let (expand, contract) = generator.create_expand_contract(
+    ///     "users_full_name",
+    ///     &Scaffold::add_column("users", Column::new("full_name", "TEXT")),
+    ///     &Scaffold::sql("ALTER TABLE users DROP COLUMN name;\n", None)
+    /// ).unwrap();
+    /// println!("Created {} and {}", expand.name(), contract.name());
+    /// ```
+    ///
+    /// # Failures
+    ///
+    /// Fails like `create()`. If the contract migration can't be created, the expand migration
+    /// is left in place.
+    pub fn create_expand_contract(
+        &self,
+        name: &str,
+        expand: &Scaffold,
+        contract: &Scaffold
+    ) -> io::Result<(GeneratedMigration, GeneratedMigration)> {
+        let expand_name = format!("expand_{}", name);
+        let expand_migration = try!(
+            try!(self.preview_with_metadata(&expand_name, expand, &self.metadata)).write()
+        );
+        let mut metadata = self.metadata.clone();
+        metadata.contracts = Some(expand_migration.version());
+        let contract_name = format!("contract_{}", name);
+        let contract_migration = try!(
+            try!(self.preview_with_metadata(&contract_name, contract, &metadata)).write()
+        );
+        Ok((expand_migration, contract_migration))
+    }
+
+    /// Returns the files `create()` would create with the given metadata, and their contents.
+    fn preview_with_metadata(
+        &self,
+        name: &str,
+        scaffold: &Scaffold,
+        metadata: &MigrationMetadata
+    ) -> io::Result<MigrationPreview> {
+        if let Err(error) = validate_migration_name(name) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, error));
+        }
+        let latest_version = try!(self.latest_version());
+        let version = self.version_strategy.next_version(latest_version);
+        let prefix = self.version_strategy.prefix(version);
+        let (mut migration, files) = match self.template {
+            MigrationTemplate::Rust | MigrationTemplate::Reversible => {
+                let full_name = format!("migration_{}_{}", prefix, name);
+                let template_file = if self.template == MigrationTemplate::Rust {
+                    &self.rust_template_file
+                } else {
+                    &self.reversible_template_file
+                };
+                let contents = match *template_file {
+                    Some(ref path) => {
+                        let mut values = template_values(name, &full_name, version, metadata);
+                        values.push(("up", execute_statement(scaffold.up_sql())));
+                        values.push(("down", execute_statement(scaffold.down_sql())));
+                        values.push(("change", change_statements(scaffold)));
+                        render_template(&try!(read_template(path)), &values)
+                    },
+                    None if self.template == MigrationTemplate::Rust => {
+                        migration_template(name, &full_name, scaffold, metadata)
+                    },
+                    None => {
+                        reversible_migration_template(name, &full_name, scaffold, metadata)
+                    }
+                };
+                let mut files = vec![(full_name.clone() + ".rs", contents)];
+                let test_module_name = if self.test_skeletons {
+                    let test_module_name = format!("test_{}", full_name);
+                    files.push((
+                        test_module_name.clone() + ".rs",
+                        test_template(name, &full_name, scaffold)
+                    ));
+                    Some(test_module_name)
+                } else {
+                    None
+                };
+                let migration = GeneratedMigration {
+                    version: version,
+                    name: full_name.clone(),
+                    template: self.template,
+                    paths: vec![],
+                    module_name: Some(full_name),
+                    struct_name: Some(migration_struct_name(name)),
+                    test_module_name: test_module_name,
+                    warnings: vec![],
+                };
+                (migration, files)
+            },
+            MigrationTemplate::Sql => {
+                let full_name = format!("{}_{}", prefix, name);
+                let sql_file = |action: &str, sql: Option<String>| -> io::Result<String> {
+                    match self.sql_template_file {
+                        Some(ref path) => {
+                            let mut values = template_values(name, &full_name, version, metadata);
+                            values.push(("action", action.to_owned()));
+                            values.push(("sql", sql.unwrap_or_default()));
+                            Ok(render_template(&try!(read_template(path)), &values))
+                        },
+                        None => {
+                            Ok(metadata.sql_comment() +
+                                &sql.unwrap_or_else(|| sql_template(action, name)))
+                        }
+                    }
+                };
+                let files = vec![
+                    (full_name.clone() + ".up.sql", try!(sql_file("apply", scaffold.up_sql()))),
+                    (full_name.clone() + ".down.sql", try!(sql_file("undo", scaffold.down_sql()))),
+                ];
+                let migration = GeneratedMigration {
+                    version: version,
+                    name: full_name,
+                    template: self.template,
+                    paths: vec![],
+                    module_name: None,
+                    struct_name: None,
+                    test_module_name: None,
+                    warnings: vec![],
+                };
+                (migration, files)
+            }
+        };
+        migration.warnings = self.version_warnings(&migration, latest_version);
+        for warning in &migration.warnings {
+            warn!("{}", warning);
+        }
+        Ok(self.preview_files(migration, files))
+    }
+
+    /// Creates a seed with the given name, like `create_seed()`, in the generator's directory and
+    /// versioned by its version strategy. Template files set with `template_file()` apply only
+    /// to migrations, not seeds.
+    ///
+    /// # Failures
+    ///
+    /// Fails like `create_seed()`.
+    pub fn create_seed(&self, name: &str) -> io::Result<GeneratedMigration> {
+        try!(self.preview_seed(name)).write()
+    }
+
+    /// Returns the files `create_seed()` would create, and their contents, without writing
+    /// anything.
+    ///
+    /// # Failures
+    ///
+    /// Fails like `create_seed()`.
+    pub fn preview_seed(&self, name: &str) -> io::Result<MigrationPreview> {
+        if let Err(error) = validate_migration_name(name) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, error));
+        }
+        let version = try!(self.new_version());
+        let prefix = self.version_strategy.prefix(version);
+        let (seed, files) = match self.template {
+            MigrationTemplate::Rust => {
+                let full_name = format!("seed_{}_{}", prefix, name);
+                let files = vec![(full_name.clone() + ".rs", seed_template(name, &full_name))];
+                let seed = GeneratedMigration {
+                    version: version,
+                    name: full_name.clone(),
+                    template: self.template,
+                    paths: vec![],
+                    module_name: Some(full_name),
+                    struct_name: Some(migration_struct_name(name)),
+                    test_module_name: None,
+                    warnings: vec![],
+                };
+                (seed, files)
+            },
+            MigrationTemplate::Sql => {
+                let full_name = format!("{}_{}", prefix, name);
+                let files = vec![(full_name.clone() + ".sql", sql_seed_template(name))];
+                let seed = GeneratedMigration {
+                    version: version,
+                    name: full_name,
+                    template: self.template,
+                    paths: vec![],
+                    module_name: None,
+                    struct_name: None,
+                    test_module_name: None,
+                    warnings: vec![],
+                };
+                (seed, files)
+            },
+            MigrationTemplate::Reversible => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Seeds aren't undone, so they can't be created as reversible migrations"
+                ));
+            }
+        };
+        Ok(self.preview_files(seed, files))
+    }
+
+    /// Returns a preview of the given files, in the generator's directory or this month's
+    /// subdirectory of it, for the given migration or seed.
+    fn preview_files(
+        &self,
+        mut migration: GeneratedMigration,
+        files: Vec<(String, String)>
+    ) -> MigrationPreview {
+        let directory = if self.date_subdirectories {
+            let now = UTC::now();
+            self.migrations_dir
+                .join(now.format("%Y").to_string())
+                .join(now.format("%m").to_string())
+        } else {
+            self.migrations_dir.clone()
+        };
+        let mut contents = vec![];
+        for (file_name, file_contents) in files {
+            migration.paths.push(directory.join(file_name));
+            contents.push(file_contents);
+        }
+        MigrationPreview {
+            migration: migration,
+            contents: contents,
+            migrations_dir: self.migrations_dir.clone(),
+        }
+    }
+
+    /// Returns warnings about the new migration's version not being after the latest version in
+    /// the directory, which only a custom version strategy can cause, or the latest version
+    /// applied to the database, if it's known.
+    fn version_warnings(
+        &self,
+        migration: &GeneratedMigration,
+        latest_version: Option<MigrationVersion>
+    ) -> Vec<String> {
+        let mut warnings = vec![];
+        if let Some(latest_version) = latest_version {
+            if migration.version <= latest_version {
+                warnings.push(format!(
+                    "{}'s version isn't after {}, the latest version in {}, so it would be \
+                    applied out of order",
+                    migration.name,
+                    latest_version,
+                    self.migrations_dir.display()
+                ));
+            }
+        }
+        if let Some(applied_version) = self.latest_applied_version {
+            if migration.version <= applied_version {
+                warnings.push(format!(
+                    "{}'s version isn't after {}, the latest version applied to the database, so \
+                    it would be applied out of order, or skipped if the history was pruned past \
+                    it. Check the system clock, or rename the migration",
+                    migration.name,
+                    applied_version
+                ));
+            }
+        }
+        warnings
+    }
+
+    /// Returns the version for a seed created now: the version strategy's next version after the
+    /// latest version of a file already in the directory or its subdirectories.
+    fn new_version(&self) -> io::Result<MigrationVersion> {
+        Ok(self.version_strategy.next_version(try!(self.latest_version())))
+    }
+
+    /// Returns the latest version of a migration or seed file already in the directory or its
+    /// subdirectories, if there are any.
+    fn latest_version(&self) -> io::Result<Option<MigrationVersion>> {
+        let mut latest_version = None;
+        // a directory that doesn't exist yet is created along with the first migration
+        let paths = match sql_migration::find_files(&self.migrations_dir) {
+            Ok(paths) => paths,
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => vec![],
+            Err(error) => return Err(error)
+        };
+        for path in paths {
+            let file_name = match path.file_name().and_then(|file_name| file_name.to_str()) {
+                Some(file_name) => file_name,
+                None => continue
+            };
+            // Rust migrations' and seeds' files are prefixed with "migration_" and "seed_", SQL
+            // migrations' and seeds' aren't
+            let name = if file_name.starts_with("migration_") {
+                &file_name["migration_".len()..]
+            } else if file_name.starts_with("seed_") {
+                &file_name["seed_".len()..]
+            } else {
+                file_name
+            };
+            let existing_version = self.version_strategy.parse(name);
+            if existing_version > latest_version {
+                latest_version = existing_version;
+            }
+        }
+        Ok(latest_version)
+    }
+}
+
+/// The files a new migration or seed would be created as, returned by
+/// `create_migration_preview()` and `MigrationGenerator::preview_seed()`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MigrationPreview {
+    migration: GeneratedMigration,
+    contents: Vec<String>,
+    migrations_dir: PathBuf,
+}
+
+impl MigrationPreview {
+    /// Returns a description of the migration as it would be created.
+    pub fn migration(&self) -> &GeneratedMigration {
+        &self.migration
+    }
+
+    /// Returns the path and contents of each file that would be created.
+    pub fn files(&self) -> Vec<(&Path, &str)> {
+        self.migration.paths.iter()
+            .zip(self.contents.iter())
+            .map(|(path, contents)| (path.as_path(), contents.as_str()))
+            .collect()
+    }
+
+    /// Creates the previewed files, and declares a Rust migration's or seed's module, and its
+    /// test's, like `create_migration()` does.
+    ///
+    /// # Failures
+    ///
+    /// Returns an error if a directory or file can't be created.
+    pub fn write(self) -> io::Result<GeneratedMigration> {
+        for (path, contents) in self.migration.paths.iter().zip(self.contents.iter()) {
+            if let Some(directory) = path.parent() {
+                try!(fs::create_dir_all(directory));
+            }
+            let mut file = try!(File::create(path));
+            try!(file.write_all(contents.as_bytes()));
+        }
+        if let Some(ref module_name) = self.migration.module_name {
+            try!(self.declare_module(module_name, self.migration.path(), false));
+        }
+        if let Some(ref test_module_name) = self.migration.test_module_name {
+            let test_path = self.migration.paths.last().expect("Tests are created as a file");
+            try!(self.declare_module(test_module_name, test_path, true));
+        }
+        Ok(self.migration)
+    }
+
+    /// Declares the module in the given file in the migrations directory's `mod.rs`.
+    fn declare_module(&self, module_name: &str, path: &Path, test_only: bool) -> io::Result<()> {
+        let relative_path = path.strip_prefix(&self.migrations_dir)
+            .expect("Migrations are created in the migrations directory");
+        // a file in a subdirectory isn't where `mod` looks for the module, so it's given
+        let module_path = match relative_path.parent() {
+            Some(parent) if parent != Path::new("") => Some(relative_path),
+            _ => None
+        };
+        declare_module(&self.migrations_dir, module_name, module_path, test_only)
+    }
+}
+
+
+/// The author, ticket and description recorded in new migrations by `MigrationGenerator`, and
+/// the version of the expand migration a contract migration goes with.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct MigrationMetadata {
+    author: Option<String>,
+    ticket: Option<String>,
+    description: Option<String>,
+    contracts: Option<MigrationVersion>,
+}
+
+impl MigrationMetadata {
+    /// Returns the lines of the comment recording the metadata, without their comment markers,
+    /// or none if no metadata is set.
+    fn lines(&self) -> Vec<String> {
+        let mut lines = vec![];
+        if let Some(ref description) = self.description {
+            lines.push(format!("Description: {}", description));
+        }
+        if let Some(ref author) = self.author {
+            lines.push(format!("Author: {}", author));
+        }
+        if let Some(ref ticket) = self.ticket {
+            lines.push(format!("Ticket: {}", ticket));
+        }
+        if let Some(contracts) = self.contracts {
+            lines.push(format!("Contracts: {}", contracts));
+        }
+        lines
+    }
+
+    /// Returns a doc comment for a Rust migration's struct recording the metadata, or nothing if
+    /// no metadata is set.
+    fn doc_comment(&self) -> String {
+        self.lines().iter()
+            .map(|line| format!("/// {}\n", line.replace('\n', "\n/// ")))
+            .collect()
+    }
+
+    /// Returns a comment for the top of a SQL migration's files recording the metadata, or
+    /// nothing if no metadata is set.
+    fn sql_comment(&self) -> String {
+        self.lines().iter()
+            .map(|line| format!("-- {}\n", line.replace('\n', " ")))
+            .collect()
+    }
+
+    /// Returns the `description()` method of a Rust migration returning the description and its
+    /// `contracts()` method returning the expand migration's version, or nothing for those that
+    /// aren't set.
+    fn methods(&self) -> String {
+        let mut methods = String::new();
+        if let Some(ref description) = self.description {
+            methods.push_str(&format!(
+                "\n    fn description(&self) -> Option<String> {{\n        \
+                Some({:?}.to_owned())\n    }}\n",
+                description
+            ));
+        }
+        if let Some(contracts) = self.contracts {
+            methods.push_str(&format!(
+                "\n    fn contracts(&self) -> Option<MigrationVersion> {{\n        \
+                Some(MigrationVersion::new({}))\n    }}\n",
+                contracts
+            ));
+        }
+        methods
+    }
+}
+
+/// Appends a `pub mod` declaration for the given module to the `mod.rs` file in the given
+/// directory, creating the file if necessary, unless the module is already declared there. The
+/// module's file is at `path`, relative to the directory, if it's given. A test-only module is
+/// declared private and for `#[cfg(test)]` only.
+fn declare_module(
+    directory: &Path,
+    module_name: &str,
+    path: Option<&Path>,
+    test_only: bool
+) -> io::Result<()> {
+    let declaration = if test_only {
+        format!("mod {};", module_name)
+    } else {
+        format!("pub mod {};", module_name)
+    };
+    let mut file = try!(
+        OpenOptions::new().read(true).append(true).create(true).open(directory.join("mod.rs"))
+    );
+    let mut contents = String::new();
+    try!(file.read_to_string(&mut contents));
+    if contents.lines().any(|line| line.trim() == declaration) {
+        return Ok(());
+    }
+    let separator = if contents.is_empty() || contents.ends_with('\n') { "" } else { "\n" };
+    let mut attributes = String::new();
+    if test_only {
+        attributes.push_str("#[cfg(test)]\n");
+    }
+    if let Some(path) = path {
+        attributes.push_str(&format!("#[path = {:?}]\n", path));
+    }
+    file.write_all(format!("{}{}{}\n", separator, attributes, declaration).as_bytes())
+}
+
+/// Reads a template file set with `MigrationGenerator::template_file()`.
+fn read_template(path: &Path) -> io::Result<String> {
+    let mut template = String::new();
+    try!(try!(File::open(path)).read_to_string(&mut template));
+    Ok(template)
+}
+
+/// Returns the values of the placeholders every kind of template file can use.
+fn template_values(
+    name: &str,
+    full_name: &str,
+    version: MigrationVersion,
+    metadata: &MigrationMetadata
+) -> Vec<(&'static str, String)> {
+    vec![
+        ("name", name.to_owned()),
+        ("migration_name", full_name.to_owned()),
+        ("version", version.to_string()),
+        ("struct_name", migration_struct_name(name)),
+        ("author", metadata.author.clone().unwrap_or_default()),
+        ("ticket", metadata.ticket.clone().unwrap_or_default()),
+        ("description", metadata.description.clone().unwrap_or_default()),
+        ("contracts", metadata.contracts.map(|version| version.to_string()).unwrap_or_default()),
+    ]
+}
+
+/// Replaces each "{{key}}" placeholder in the template with its value. Placeholders without a
+/// value are left as they are, and values aren't searched for placeholders themselves.
+fn render_template(template: &str, values: &[(&str, String)]) -> String {
+    let mut rendered = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        rendered.push_str(&rest[..start]);
+        let placeholder = &rest[(start + "{{".len())..];
+        let value = values.iter().find(|&&(key, _)| {
+            placeholder.starts_with(key) && placeholder[key.len()..].starts_with("}}")
+        });
+        match value {
+            Some(&(key, ref value)) => {
+                rendered.push_str(value);
+                rest = &placeholder[(key.len() + "}}".len())..];
+            },
+            None => {
+                rendered.push_str("{{");
+                rest = placeholder;
+            }
+        }
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+/// Returns the placeholder contents of a new SQL migration file, whose SQL will `action` (e.g.
+/// "apply") the migration with the given name.
+fn sql_template(action: &str, name: &str) -> String {
+    format!("-- Your SQL to {} the {} migration here.\n", action, name)
+}
+
+/// Returns the name of the struct generated for a Rust migration with the given name, turning
+/// e.g. "my_migration" into "MyMigration".
+pub(crate) fn migration_struct_name(name: &str) -> String {
+    name.to_owned().split('_').flat_map(|word|
+        word.chars().enumerate().flat_map(|input| {
+            let index = input.0;
+            let character = input.1;
+            if index == 0 {
+                // some exotic Unicode characters have an uppercase form composed of multiple
+                // characters
+                character.to_uppercase().collect()
+            } else {
+                vec!(character)
+            }
+        }).collect::<Vec<char>>()
+    ).collect::<String>()
+}
+
+/// Returns a statement for a Rust migration's `up()` or `down()` method running the given SQL, or
+/// a placeholder for the SQL if there isn't any.
+fn execute_statement(sql: Option<String>) -> String {
+    match sql {
+        Some(ref sql) if !sql.contains("\"#") => {
+            format!("try!(context.connection().batch_execute(r#\"\n{}\"#));", sql)
+        },
+        Some(sql) => format!("try!(context.connection().batch_execute({:?}));", sql),
+        None => "try!(context.connection().execute(\"Your SQL here.\", &[]));".to_owned()
+    }
+}
+
+/// Takes a name (e.g. "create_users_table"), a file name without the extension (e.g.
+/// "migration_20150822094521_create_users_table"), from which the migration's version is parsed,
+/// and the change it makes, and returns a string that can be written into the new migration file
+/// to fill in all the boilerplate code a migration requires
+fn migration_template(
+    name: &str,
+    file_name_without_extension: &str,
+    scaffold: &Scaffold,
+    metadata: &MigrationMetadata
+) -> String {
+    let capitalized_name = migration_struct_name(name);
+
+    format!("\
+use std::fmt::{{self, Display}};
+use postgres;
+use trek::migration::Migration;
+use trek::migration_context::MigrationContext;
+use trek::migration_version::MigrationVersion;
+
+{doc_comment}#[derive(Debug)]
+pub struct {capitalized_name} {{
+    name: String,
+    version: MigrationVersion,
+}}
+impl {capitalized_name} {{
+    pub fn new() -> Self {{
+        let name = \"{file_name_without_extension}\";
+        {capitalized_name} {{
+            name: name.to_owned(),
+            // the version is the number after the name's \"migration_\" prefix
+            version: MigrationVersion::from_name(&name[\"migration_\".len()..])
+                .expect(\"Migration names start with their version\"),
+        }}
+    }}
+}}
+impl Migration for {capitalized_name} {{
+    fn version(&self) -> MigrationVersion {{
+        self.version
+    }}
+
+    fn up(&self, context: &MigrationContext) -> postgres::Result<()> {{
+        {up_statement}
+        Ok(())
+    }}
+
+    fn down(&self, context: &MigrationContext) -> postgres::Result<()> {{
+        {down_statement}
+        Ok(())
+    }}
+{methods}}}
+impl Display for {capitalized_name} {{
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {{
+        write!(formatter, \"{{}}\", self.name)
+    }}
+}}
+",
+        file_name_without_extension=file_name_without_extension,
+        capitalized_name=capitalized_name,
+        up_statement=execute_statement(scaffold.up_sql()),
+        down_statement=execute_statement(scaffold.down_sql()),
+        doc_comment=metadata.doc_comment(),
+        methods=metadata.methods()
+    )
+}
+
+/// Returns a Rust string literal of the given string, raw unless it contains `"#`.
+fn string_literal(string: &str) -> String {
+    if string.contains("\"#") {
+        format!("{:?}", string)
+    } else {
+        format!("r#\"{}\"#", string)
+    }
+}
+
+/// Returns the statements for a reversible migration's `change()` method describing the change
+/// made by the scaffold, or a placeholder if it's empty.
+fn change_statements(scaffold: &Scaffold) -> String {
+    match *scaffold {
+        Scaffold::Empty => {
+            "// e.g. changes.add_column(\"users\", Column::new(\"email\", \"TEXT\"));\n        \
+            changes.sql(\"Your SQL here.\", \"Your SQL undoing it here.\");".to_owned()
+        },
+        Scaffold::CreateTable { ref table, ref columns } => {
+            let columns = columns.iter()
+                .map(|column| format!("            {},\n", column.rust_expression()))
+                .collect::<String>();
+            format!("changes.create_table({:?}, vec![\n{}        ]);", table, columns)
+        },
+        Scaffold::AddColumn { ref table, ref column } => {
+            format!("changes.add_column({:?}, {});", table, column.rust_expression())
+        },
+        Scaffold::AddIndex { ref table, ref columns, unique } => {
+            format!("changes.add_index({:?}, &{:?}, {});", table, columns, unique)
+        },
+        Scaffold::Sql { ref up_sql, ref down_sql } => {
+            let down_sql = down_sql.as_ref().map(|down_sql| &**down_sql)
+                .unwrap_or("Your SQL undoing it here.");
+            format!(
+                "changes.sql(\n            {},\n            {}\n        );",
+                string_literal(up_sql),
+                string_literal(down_sql)
+            )
+        }
+    }
+}
+
+/// Like `migration_template()`, but returns the contents of a reversible migration's file, whose
+/// struct implements `ReversibleMigration`.
+fn reversible_migration_template(
+    name: &str,
+    file_name_without_extension: &str,
+    scaffold: &Scaffold,
+    metadata: &MigrationMetadata
+) -> String {
+    let capitalized_name = migration_struct_name(name);
+    let column_import = match *scaffold {
+        Scaffold::CreateTable { .. } | Scaffold::AddColumn { .. } => {
+            "use trek::scaffold::Column;\n"
+        },
+        _ => ""
+    };
+
+    format!("\
+use std::fmt::{{self, Display}};
+use trek::migration_version::MigrationVersion;
+use trek::reversible_migration::{{Changes, ReversibleMigration}};
+{column_import}
+{doc_comment}#[derive(Debug)]
+pub struct {capitalized_name} {{
+    name: String,
+    version: MigrationVersion,
+}}
+impl {capitalized_name} {{
+    pub fn new() -> Self {{
+        let name = \"{file_name_without_extension}\";
+        {capitalized_name} {{
+            name: name.to_owned(),
+            // the version is the number after the name's \"migration_\" prefix
+            version: MigrationVersion::from_name(&name[\"migration_\".len()..])
+                .expect(\"Migration names start with their version\"),
+        }}
+    }}
+}}
+impl ReversibleMigration for {capitalized_name} {{
+    fn version(&self) -> MigrationVersion {{
+        self.version
+    }}
+
+    fn change(&self, changes: &mut Changes) {{
+        {change_statements}
+    }}
+{methods}}}
+impl Display for {capitalized_name} {{
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {{
+        write!(formatter, \"{{}}\", self.name)
+    }}
+}}
+",
+        file_name_without_extension=file_name_without_extension,
+        capitalized_name=capitalized_name,
+        column_import=column_import,
+        change_statements=change_statements(scaffold),
+        doc_comment=metadata.doc_comment(),
+        methods=metadata.methods()
+    )
+}
+
+/// Returns the contents of the test generated alongside a Rust or reversible migration, which
+/// applies and undoes it, checking the changes made by the scaffold, if any, each time.
+fn test_template(name: &str, module_name: &str, scaffold: &Scaffold) -> String {
+    let (applied_checks, undone_checks) = test_checks(scaffold);
+
+    format!("\
+use trek::testing;
+
+use super::{module_name}::{capitalized_name};
+
+#[test]
+fn {name}_applies_and_rolls_back() {{
+    let connection = testing::test_connection();
+    let transaction = connection.transaction().unwrap();
+    let migration = {capitalized_name}::new();
+
+    testing::apply(&transaction, &migration).unwrap();
+    {applied_checks}
+
+    testing::undo(&transaction, &migration).unwrap();
+    {undone_checks}
+}}
+",
+        name=name,
+        module_name=module_name,
+        capitalized_name=migration_struct_name(name),
+        applied_checks=applied_checks.join("\n    "),
+        undone_checks=undone_checks.join("\n    ")
+    )
+}
+
+/// Returns the statements for a migration's test checking that the change made by the scaffold
+/// was applied, and that it was undone, or placeholders if it's empty or given as SQL.
+fn test_checks(scaffold: &Scaffold) -> (Vec<String>, Vec<String>) {
+    match *scaffold {
+        Scaffold::CreateTable { ref table, ref columns } => {
+            let mut applied_checks = vec![
+                format!("assert!(testing::table_exists(&transaction, {:?}).unwrap());", table),
+            ];
+            for column in columns {
+                applied_checks.push(format!(
+                    "assert!(testing::column_exists(&transaction, {:?}, {:?}).unwrap());",
+                    table,
+                    column.name()
+                ));
+            }
+            let undone_checks = vec![
+                format!("assert!(!testing::table_exists(&transaction, {:?}).unwrap());", table),
+            ];
+            (applied_checks, undone_checks)
+        },
+        Scaffold::AddColumn { ref table, ref column } => {
+            let check = |negation: &str| format!(
+                "assert!({}testing::column_exists(&transaction, {:?}, {:?}).unwrap());",
+                negation,
+                table,
+                column.name()
+            );
+            (vec![check("")], vec![check("!")])
+        },
+        Scaffold::AddIndex { ref table, ref columns, .. } => {
+            // the index is named like `Scaffold::add_index()` names it, in its table's schema
+            let (schema, unqualified_table) = match table.rfind('.') {
+                Some(dot_index) => (&table[..(dot_index + 1)], &table[(dot_index + 1)..]),
+                None => ("", &**table)
+            };
+            let index = format!("{}{}_{}_index", schema, unqualified_table, columns.join("_"));
+            let check = |negation: &str| format!(
+                "assert!({}testing::index_exists(&transaction, {:?}).unwrap());",
+                negation,
+                index
+            );
+            (vec![check("")], vec![check("!")])
+        },
+        Scaffold::Empty | Scaffold::Sql { .. } => {
+            (
+                vec![
+                    "// Check the migration's changes here, e.g.".to_owned(),
+                    "// assert!(testing::table_exists(&transaction, \"users\").unwrap());"
+                        .to_owned(),
+                ],
+                vec![
+                    "// and that they're undone here, e.g.".to_owned(),
+                    "// assert!(!testing::table_exists(&transaction, \"users\").unwrap());"
+                        .to_owned(),
+                ]
+            )
+        }
+    }
+}
+
+/// Returns the contents of a starter `migration_index.rs`, for a program whose migrations are in
+/// the module with the given name.
+fn migration_index_template(module_name: &str) -> String {
+    format!("\
+use postgres::{{self, GenericConnection}};
+use trek::migration_index::{{MigrationIndex as TrekMigrationIndex, MigrationReport}};
+use trek::Result;
+
+/// The program's migrations. Add each new migration to the `Default` impl below, e.g.
+/// `Box::new({module_name}::migration_20150822094521_create_users_table::CreateUsersTable::new())`.
+pub struct MigrationIndex {{
+    migrations: TrekMigrationIndex,
+}}
+
+impl MigrationIndex {{
+    pub fn run(&self, connection: &GenericConnection) -> Result<MigrationReport> {{
+        self.migrations.run(connection)
+    }}
+
+    pub fn rollback(&self, connection: &GenericConnection) -> Result<()> {{
+        self.migrations.rollback(connection)
+    }}
+
+    pub fn schema_version(
+        &self,
+        connection: &GenericConnection
+    ) -> postgres::Result<Option<String>> {{
+        self.migrations.schema_version(connection)
+    }}
+}}
+
+impl Default for MigrationIndex {{
+    fn default() -> MigrationIndex {{
+        MigrationIndex {{
+            migrations: TrekMigrationIndex::new(vec![
+                // record your migrations here
+            ])
+        }}
+    }}
+}}
+",
+        module_name=module_name
+    )
+}
+
+/// Returns the contents of a new SQL seed file.
+fn sql_seed_template(name: &str) -> String {
+    format!(
+        "-- Your SQL to load the {} seed here. Seeds run every time, so it must be idempotent, \
+        e.g. by using INSERT ... ON CONFLICT DO NOTHING.\n",
+        name
+    )
+}
+
+/// Like `migration_template()`, but returns the contents of a new seed's file, whose struct
+/// implements `Seed`.
+fn seed_template(name: &str, file_name_without_extension: &str) -> String {
+    let capitalized_name = migration_struct_name(name);
+
+    format!("\
+use std::fmt::{{self, Display}};
+use postgres;
+use trek::migration_context::MigrationContext;
+use trek::seed::Seed;
+
+#[derive(Debug)]
+pub struct {capitalized_name} {{
+    name: String,
+}}
+impl {capitalized_name} {{
+    pub fn new() -> Self {{
+        {capitalized_name} {{
+            name: \"{file_name_without_extension}\".to_owned(),
+        }}
+    }}
+}}
+impl Seed for {capitalized_name} {{
+    // seeds run every time, so this must be idempotent, e.g. by using
+    // INSERT ... ON CONFLICT DO NOTHING
+    fn run(&self, context: &MigrationContext) -> postgres::Result<()> {{
+        try!(context.connection().execute(\"Your SQL here.\", &[]));
+        Ok(())
+    }}
+}}
+impl Display for {capitalized_name} {{
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {{
+        write!(formatter, \"{{}}\", self.name)
+    }}
+}}
+",
+        file_name_without_extension=file_name_without_extension,
+        capitalized_name=capitalized_name
+    )
+}
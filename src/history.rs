@@ -0,0 +1,340 @@
+#[cfg(feature = "serde")]
+use std::result;
+use std::time::Duration;
+
+use chrono::{DateTime, UTC};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "serde")]
+use serde::ser::{SerializeStruct, Serializer};
+#[cfg(feature = "serde")]
+use serde_json;
+
+use super::migration_version::MigrationVersion;
+#[cfg(feature = "serde")]
+use super::version_table::duration_to_millis;
+
+
+/// The version of the JSON format produced by `MigrationIndex::export_history()`.
+pub const EXPORT_FORMAT_VERSION: u64 = 1;
+
+/// A migration recorded as applied to the database, as returned by `MigrationIndex::history()`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AppliedMigration {
+    pub(crate) version: MigrationVersion,
+    pub(crate) name: String,
+    pub(crate) description: Option<String>,
+    pub(crate) applied_at: DateTime<UTC>,
+    pub(crate) duration: Option<Duration>,
+    pub(crate) checksum: Option<String>,
+    pub(crate) applied_by: Option<String>,
+    pub(crate) application: Option<String>,
+    pub(crate) deploy_id: Option<String>,
+    pub(crate) batch: Option<u64>,
+    pub(crate) dirty: bool,
+    pub(crate) baseline: bool,
+}
+impl AppliedMigration {
+    /// Returns the version of the applied migration.
+    pub fn version(&self) -> MigrationVersion {
+        self.version
+    }
+
+    /// Returns the name of the applied migration.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the description the migration had when it was applied, if it had one. See
+    /// `Migration::description()`.
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_ref().map(|description| &**description)
+    }
+
+    /// Returns when the migration was applied, or when it was recorded as applied if it was
+    /// marked applied or baselined without being run.
+    pub fn applied_at(&self) -> DateTime<UTC> {
+        self.applied_at
+    }
+
+    /// Returns how long the migration took to apply, or None if it was recorded as applied
+    /// without being run.
+    pub fn duration(&self) -> Option<Duration> {
+        self.duration
+    }
+
+    /// Returns the checksum the migration had when it was applied, or None if it had no checksum
+    /// or was applied before Trek recorded checksums.
+    pub fn checksum(&self) -> Option<&str> {
+        self.checksum.as_ref().map(|checksum| &**checksum)
+    }
+
+    /// Returns the database user who applied the migration, or None if it was applied before
+    /// Trek recorded users.
+    pub fn applied_by(&self) -> Option<&str> {
+        self.applied_by.as_ref().map(|applied_by| &**applied_by)
+    }
+
+    /// Returns the name and version of the application that applied the migration, if one was
+    /// configured with `MigrationIndexBuilder::application()`.
+    pub fn application(&self) -> Option<&str> {
+        self.application.as_ref().map(|application| &**application)
+    }
+
+    /// Returns the identifier of the deploy that applied the migration, if one was configured
+    /// with `MigrationIndexBuilder::deploy_id()`.
+    pub fn deploy_id(&self) -> Option<&str> {
+        self.deploy_id.as_ref().map(|deploy_id| &**deploy_id)
+    }
+
+    /// Returns the number of the batch of migrations applied together by one call to
+    /// `MigrationIndex::run()` that the migration was part of, or None if it was recorded as
+    /// applied without being run or was applied before Trek recorded batches.
+    pub fn batch(&self) -> Option<u64> {
+        self.batch
+    }
+
+    /// Returns true if applying the migration failed partway, leaving the database in an
+    /// indeterminate state. See `MigrationIndex::force_clean()`.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Returns true if the history of the migrations before this one was pruned, so that this
+    /// entry stands for every migration up to and including it. See
+    /// `MigrationIndex::prune_history()`.
+    pub fn is_baseline(&self) -> bool {
+        self.baseline
+    }
+}
+
+/// Serializes the entry with the same fields as its entry in `MigrationIndex::export_history()`.
+#[cfg(feature = "serde")]
+impl Serialize for AppliedMigration {
+    fn serialize<S>(&self, serializer: S) -> result::Result<S::Ok, S::Error> where S: Serializer {
+        let mut state = try!(serializer.serialize_struct("AppliedMigration", 12));
+        try!(state.serialize_field("version", &self.version));
+        try!(state.serialize_field("name", &self.name));
+        try!(state.serialize_field("description", &self.description));
+        try!(state.serialize_field("applied_at", &self.applied_at.to_rfc3339()));
+        try!(state.serialize_field("duration_ms", &self.duration.map(duration_to_millis)));
+        try!(state.serialize_field("checksum", &self.checksum));
+        try!(state.serialize_field("applied_by", &self.applied_by));
+        try!(state.serialize_field("application", &self.application));
+        try!(state.serialize_field("deploy_id", &self.deploy_id));
+        try!(state.serialize_field("batch", &self.batch));
+        try!(state.serialize_field("dirty", &self.dirty));
+        try!(state.serialize_field("baseline", &self.baseline));
+        state.end()
+    }
+}
+
+/// A migration that was skipped because its `Migration::should_run()` returned false, as
+/// returned by `MigrationIndex::skipped_migrations()`. It stays outstanding, and is forgotten once
+/// it's applied.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SkippedMigration {
+    pub(crate) version: MigrationVersion,
+    pub(crate) name: String,
+    pub(crate) skipped_at: DateTime<UTC>,
+}
+impl SkippedMigration {
+    /// Returns the version of the skipped migration.
+    pub fn version(&self) -> MigrationVersion {
+        self.version
+    }
+
+    /// Returns the name of the skipped migration.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns when the migration was last skipped.
+    pub fn skipped_at(&self) -> DateTime<UTC> {
+        self.skipped_at
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for SkippedMigration {
+    fn serialize<S>(&self, serializer: S) -> result::Result<S::Ok, S::Error> where S: Serializer {
+        let mut state = try!(serializer.serialize_struct("SkippedMigration", 3));
+        try!(state.serialize_field("version", &self.version));
+        try!(state.serialize_field("name", &self.name));
+        try!(state.serialize_field("skipped_at", &self.skipped_at.to_rfc3339()));
+        state.end()
+    }
+}
+
+/// The last run of a seed, as returned by `MigrationIndex::seed_history()`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SeedRun {
+    pub(crate) name: String,
+    pub(crate) environment: Option<String>,
+    pub(crate) run_at: DateTime<UTC>,
+    pub(crate) duration: Duration,
+}
+impl SeedRun {
+    /// Returns the name of the seed.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the environment the seed last ran in, if one was set with
+    /// `RunOptions::environment()`.
+    pub fn environment(&self) -> Option<&str> {
+        self.environment.as_ref().map(|environment| &**environment)
+    }
+
+    /// Returns when the seed last ran.
+    pub fn run_at(&self) -> DateTime<UTC> {
+        self.run_at
+    }
+
+    /// Returns how long the seed took to run the last time it ran.
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for SeedRun {
+    fn serialize<S>(&self, serializer: S) -> result::Result<S::Ok, S::Error> where S: Serializer {
+        let mut state = try!(serializer.serialize_struct("SeedRun", 4));
+        try!(state.serialize_field("name", &self.name));
+        try!(state.serialize_field("environment", &self.environment));
+        try!(state.serialize_field("run_at", &self.run_at.to_rfc3339()));
+        try!(state.serialize_field("duration_ms", &duration_to_millis(self.duration)));
+        state.end()
+    }
+}
+
+/// Selects which entries `MigrationIndex::prune_history()` removes from the migration history.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PruneCutoff {
+    /// Prune entries for migrations with versions lower than this one.
+    Version(MigrationVersion),
+    /// Prune entries for migrations applied before this time.
+    AppliedBefore(DateTime<UTC>),
+}
+
+impl PruneCutoff {
+    /// Returns true if the given history entry falls before this cutoff.
+    pub fn includes(&self, entry: &AppliedMigration) -> bool {
+        match *self {
+            PruneCutoff::Version(version) => entry.version < version,
+            PruneCutoff::AppliedBefore(time) => entry.applied_at < time,
+        }
+    }
+}
+
+/// The document produced by `export()`.
+#[cfg(feature = "serde")]
+#[derive(Serialize)]
+struct ExportedHistory<'a> {
+    format_version: u64,
+    migrations: &'a [AppliedMigration],
+}
+
+/// The document read by `import()`, whose migrations are only parsed once the format version is
+/// known to be supported.
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct ImportedHistory {
+    format_version: Option<u64>,
+    migrations: Option<serde_json::Value>,
+}
+
+/// An entry of the document read by `import()`, in the format `AppliedMigration` serializes to.
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct ImportedMigration {
+    version: u64,
+    name: String,
+    #[serde(default)]
+    description: Option<String>,
+    applied_at: String,
+    #[serde(default)]
+    duration_ms: Option<u64>,
+    #[serde(default)]
+    checksum: Option<String>,
+    #[serde(default)]
+    applied_by: Option<String>,
+    #[serde(default)]
+    application: Option<String>,
+    #[serde(default)]
+    deploy_id: Option<String>,
+    #[serde(default)]
+    batch: Option<u64>,
+    #[serde(default)]
+    dirty: bool,
+    #[serde(default)]
+    baseline: bool,
+}
+#[cfg(feature = "serde")]
+impl ImportedMigration {
+    /// Converts the entry to the applied migration it records, returning a description of the
+    /// problem if its "applied_at" isn't a valid timestamp.
+    fn into_applied_migration(self) -> result::Result<AppliedMigration, String> {
+        let applied_at = match self.applied_at.parse::<DateTime<UTC>>() {
+            Ok(applied_at) => applied_at,
+            Err(error) => {
+                return Err(format!(
+                    "migration {} has an invalid \"applied_at\": {}",
+                    self.version,
+                    error
+                ));
+            }
+        };
+        Ok(AppliedMigration {
+            version: MigrationVersion::new(self.version),
+            name: self.name,
+            description: self.description,
+            applied_at: applied_at,
+            duration: self.duration_ms.map(Duration::from_millis),
+            checksum: self.checksum,
+            applied_by: self.applied_by,
+            application: self.application,
+            deploy_id: self.deploy_id,
+            batch: self.batch,
+            dirty: self.dirty,
+            baseline: self.baseline,
+        })
+    }
+}
+
+/// Returns the history as a pretty-printed JSON document in the format described by
+/// `MigrationIndex::export_history()`.
+#[cfg(feature = "serde")]
+pub(crate) fn export(history: &[AppliedMigration]) -> String {
+    let document = ExportedHistory { format_version: EXPORT_FORMAT_VERSION, migrations: history };
+    serde_json::to_string_pretty(&document)
+        .expect("the migration history always serializes to JSON")
+}
+
+/// Parses a JSON document produced by `export()`, returning a description of the problem if it's
+/// malformed or in an unsupported format version.
+#[cfg(feature = "serde")]
+pub(crate) fn import(document: &str) -> result::Result<Vec<AppliedMigration>, String> {
+    let document = match serde_json::from_str::<ImportedHistory>(document) {
+        Ok(document) => document,
+        Err(error) => return Err(format!("invalid JSON: {}", error))
+    };
+    match document.format_version {
+        Some(EXPORT_FORMAT_VERSION) => {},
+        Some(format_version) => {
+            return Err(format!("unsupported format version {}", format_version));
+        },
+        None => return Err("missing \"format_version\"".to_owned())
+    }
+    let migrations = match document.migrations {
+        Some(migrations) => migrations,
+        None => return Err("missing \"migrations\" array".to_owned())
+    };
+    match serde_json::from_value::<Vec<ImportedMigration>>(migrations) {
+        Ok(migrations) => {
+            migrations.into_iter().map(ImportedMigration::into_applied_migration).collect()
+        },
+        Err(error) => Err(format!("invalid \"migrations\" array: {}", error))
+    }
+}
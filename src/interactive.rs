@@ -0,0 +1,170 @@
+use std::io::{self, BufRead, Write};
+
+use super::scaffold::{Column, Scaffold};
+use super::sql_migration;
+
+
+/// Asks on `output` for a table to create, its columns and its indexes, reading the answers from
+/// `input`, and returns a scaffold for a migration creating them, undone by dropping them. Pass
+/// it to `create_scaffolded_migration()` to generate a complete migration without knowing the
+/// template's layout.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use std::io;
+/// # use std::path::Path;
+/// # use trek::{create_scaffolded_migration, MigrationTemplate};
+/// # use trek::interactive;
+/// let stdin = io::stdin();
+/// let scaffold = interactive::prompt_create_table(&mut stdin.lock(), &mut io::stdout()).unwrap();
+/// create_scaffolded_migration(
+///     "create_users_table",
+///     Path::new("src/db/migrations/"),
+///     MigrationTemplate::Rust,
+///     &scaffold
+/// ).unwrap();
+/// ```
+///
+/// # Failures
+///
+/// Returns an error if the prompts can't be written or the answers can't be read, including an
+/// error of kind `UnexpectedEof` if the input ends before all the questions are answered.
+pub fn prompt_create_table<R, W>(input: &mut R, output: &mut W) -> io::Result<Scaffold>
+    where R: BufRead, W: Write
+{
+    let mut prompter = Prompter {
+        input: input,
+        output: output,
+    };
+    let mut table = String::new();
+    while table.is_empty() {
+        table = try!(prompter.ask("Table name: "));
+    }
+
+    let mut columns = vec![];
+    loop {
+        let name = try!(prompter.ask("Column name (leave blank when done): "));
+        if name.is_empty() {
+            if columns.is_empty() {
+                try!(prompter.say("The table needs at least one column."));
+                continue;
+            }
+            break;
+        }
+        let mut sql_type = String::new();
+        while sql_type.is_empty() {
+            sql_type = try!(prompter.ask(&format!("Type of {} (e.g. TEXT, INTEGER): ", name)));
+        }
+        let mut column = Column::new(&name, &sql_type);
+        if try!(prompter.confirm(&format!("Is {} the primary key?", name), false)) {
+            column = column.primary_key();
+        } else if !try!(prompter.confirm(&format!("Can {} be null?", name), true)) {
+            column = column.not_null();
+        }
+        let default = try!(prompter.ask(
+            &format!("Default value of {}, as SQL (leave blank for none): ", name)
+        ));
+        if !default.is_empty() {
+            column = column.default(&default);
+        }
+        columns.push(column);
+    }
+
+    let mut up_sql = Scaffold::create_table(&table, columns).up_sql()
+        .expect("Table scaffolds have up SQL");
+    loop {
+        let index_columns = try!(prompter.ask(
+            "Columns of an index, separated by commas (leave blank when done): "
+        ));
+        if index_columns.is_empty() {
+            break;
+        }
+        let index_columns = index_columns.split(',')
+            .map(|column| column.trim())
+            .filter(|column| !column.is_empty())
+            .collect::<Vec<&str>>();
+        let unique = try!(prompter.confirm("Is the index unique?", false));
+        up_sql.push_str(
+            &Scaffold::add_index(&table, &index_columns, unique).up_sql()
+                .expect("Index scaffolds have up SQL")
+        );
+    }
+
+    let down_sql = sql_migration::generate_down(&up_sql).sql() + "\n";
+    Ok(Scaffold::sql(&up_sql, Some(&down_sql)))
+}
+
+/// Asks a yes or no question on `output`, e.g. before doing something destructive, reading the
+/// answer from `input`. It's asked again until it's answered with "y", "yes", "n" or "no", in any
+/// case, or left blank to choose `default`.
+///
+/// # Examples
+///
+/// ```
+/// # use trek::interactive;
+/// let mut input = "yes\n".as_bytes();
+/// let mut output = vec![];
+/// assert!(interactive::confirm(&mut input, &mut output, "Roll back?", false).unwrap());
+/// assert_eq!(output, b"Roll back? [y/N] ");
+/// ```
+///
+/// # Failures
+///
+/// Returns an error if the question can't be written or the answer can't be read, including an
+/// error of kind `UnexpectedEof` if the input ends before it's answered.
+pub fn confirm<R, W>(
+    input: &mut R,
+    output: &mut W,
+    question: &str,
+    default: bool
+) -> io::Result<bool>
+    where R: BufRead, W: Write
+{
+    let mut prompter = Prompter {
+        input: input,
+        output: output,
+    };
+    prompter.confirm(question, default)
+}
+
+/// Asks questions and reads their answers for `prompt_create_table()` and `confirm()`.
+struct Prompter<'a, R: 'a, W: 'a> {
+    input: &'a mut R,
+    output: &'a mut W,
+}
+
+impl<'a, R, W> Prompter<'a, R, W> where R: BufRead, W: Write {
+    /// Writes a line of information.
+    fn say(&mut self, message: &str) -> io::Result<()> {
+        writeln!(self.output, "{}", message)
+    }
+
+    /// Asks a question and returns the answer, trimmed.
+    fn ask(&mut self, question: &str) -> io::Result<String> {
+        try!(write!(self.output, "{}", question));
+        try!(self.output.flush());
+        let mut answer = String::new();
+        if try!(self.input.read_line(&mut answer)) == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "The input ended before all the questions were answered"
+            ));
+        }
+        Ok(answer.trim().to_owned())
+    }
+
+    /// Asks a yes or no question, returning `default` if it's left unanswered.
+    fn confirm(&mut self, question: &str, default: bool) -> io::Result<bool> {
+        let options = if default { "[Y/n]" } else { "[y/N]" };
+        loop {
+            let answer = try!(self.ask(&format!("{} {} ", question, options))).to_lowercase();
+            match &*answer {
+                "" => return Ok(default),
+                "y" | "yes" => return Ok(true),
+                "n" | "no" => return Ok(false),
+                _ => try!(self.say("Please answer y or n."))
+            }
+        }
+    }
+}
@@ -1,13 +1,309 @@
 use std::fmt::Display;
-
-use postgres;
+use std::time::Duration;
 
 use postgres::Result;
 
+use super::error::IrreversibleMigrationError;
+use super::migration_context::MigrationContext;
+use super::migration_version::MigrationVersion;
+
 
 pub trait Migration : Display {
-    /// Applies this migration.
-    fn up(&self, transaction: &postgres::GenericConnection) -> Result<()>;
-    /// Undoes this migration.
-    fn down(&self, transaction: &postgres::GenericConnection) -> Result<()>;
+    /// Returns this migration's version, which determines the order migrations are applied in.
+    fn version(&self) -> MigrationVersion;
+    /// Applies this migration, running its SQL on `context.connection()`.
+    fn up(&self, context: &MigrationContext) -> Result<()>;
+    /// Undoes this migration. Migrations that can't be undone, e.g. data migrations that discard
+    /// information, can leave this unimplemented: by default it fails with an
+    /// `IrreversibleMigrationError`, which `MigrationIndex::rollback()` reports as such (see
+    /// `Error::is_irreversible()`).
+    fn down(&self, _context: &MigrationContext) -> Result<()> {
+        Err(IrreversibleMigrationError::new(self.to_string()).into())
+    }
+
+    /// Returns the names of the environments this migration applies to, e.g. "development" for
+    /// seed-like migrations or "test" for destructive cleanups. When any are given, the migration
+    /// is skipped, as if `should_run()` returned false, unless the environment set with
+    /// `RunOptions::environment()` is one of them. Returns no environments by default, meaning the
+    /// migration applies to every environment.
+    fn environments(&self) -> Vec<String> {
+        vec![]
+    }
+
+    /// Returns whether this migration should be applied, letting it inspect the database first,
+    /// e.g. to check that an extension it needs is installed or that a feature flag is set.
+    /// Migrations returning false are skipped: they're recorded as skipped (see
+    /// `MigrationIndex::skipped_migrations()`) rather than applied, so every later run considers
+    /// them again. Returns true by default.
+    fn should_run(&self, _context: &MigrationContext) -> Result<bool> {
+        Ok(true)
+    }
+
+    /// Returns a short human-readable explanation of what this migration does, e.g. "Adds an index
+    /// on users.email for login lookups", which is recorded in the migration history and shown in
+    /// progress messages. Returns None by default.
+    fn description(&self) -> Option<String> {
+        None
+    }
+
+    /// Returns the versions of the migrations this migration depends on, which are applied before
+    /// it regardless of their versions. This keeps migrations written on long-lived branches in
+    /// a valid order when their timestamps interleave with ones merged in the meantime. Returns
+    /// no dependencies by default.
+    fn dependencies(&self) -> Vec<MigrationVersion> {
+        vec![]
+    }
+
+    /// Returns the tags categorizing this migration, e.g. "schema" or "data", which
+    /// `MigrationIndex::run_tagged()` selects migrations by. Returns no tags by default.
+    fn tags(&self) -> Vec<String> {
+        vec![]
+    }
+
+    /// Returns whether this migration must be applied inside a transaction. Migrations using
+    /// statements that can't run in a transaction, like `CREATE INDEX CONCURRENTLY` or `VACUUM`,
+    /// should return false, and be applied with `MigrationIndex::run_in_transactions()`, which
+    /// runs them directly on the connection. Returns true by default.
+    fn transactional(&self) -> bool {
+        true
+    }
+
+    /// Returns the longest any statement of this migration may run before it's cancelled, which
+    /// Trek sets as PostgreSQL's `statement_timeout` while applying or rolling back the migration.
+    /// Returns None by default, meaning the timeout set with `RunOptions::statement_timeout()`,
+    /// if any, applies.
+    fn statement_timeout(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Returns the longest any statement of this migration may wait for a lock before it's
+    /// cancelled, which Trek sets as PostgreSQL's `lock_timeout` while applying or rolling back
+    /// the migration. This keeps a migration queued behind a long-running query from blocking
+    /// all other traffic to the table. Returns None by default, meaning the timeout set with
+    /// `RunOptions::lock_timeout()`, if any, applies.
+    fn lock_timeout(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Returns a digest of this migration's contents, which is recorded when the migration is
+    /// applied. If the digest of an applied migration later changes, `MigrationIndex::run()`
+    /// refuses to proceed, since the database no longer matches the migration's code. Migrations
+    /// returning None (the default) aren't checked. `content_checksum()` can compute a digest of
+    /// the migration's SQL.
+    fn checksum(&self) -> Option<String> {
+        None
+    }
+
+    /// Returns whether applying this migration can safely be retried after failing with a
+    /// transient database error, e.g. because it only takes locks that may time out or it guards
+    /// its changes with `IF NOT EXISTS`. Transactional migrations are retried from a savepoint, so
+    /// only non-transactional migrations need to tolerate being partly applied before. Only
+    /// retried when a policy is set with `RunOptions::retry_policy()`. Returns false by default.
+    fn idempotent(&self) -> bool {
+        false
+    }
+
+    /// Returns the SQL that applies this migration, for migrations defined as SQL, so that
+    /// `MigrationIndex::plan()` can show what a run would execute. Returns None by default.
+    fn up_sql(&self) -> Option<String> {
+        None
+    }
+
+    /// Returns the SQL that undoes this migration, for migrations defined as SQL. When
+    /// `MigrationIndexBuilder::store_down_sql()` is enabled, it's recorded when the migration is
+    /// applied so that `MigrationIndex::rollback()` can undo the migration even after its code is
+    /// no longer registered. Returns None by default.
+    fn down_sql(&self) -> Option<String> {
+        None
+    }
+
+    /// Returns the version of the last of the migrations this one squashes, for a baseline
+    /// standing in for old migrations whose files were deleted (see `squash::squash()`). On a
+    /// database where that migration was applied, the schema already matches the baseline, so
+    /// `MigrationIndex::run()` records the baseline as applied without running it. Returns None by
+    /// default.
+    fn squashes(&self) -> Option<MigrationVersion> {
+        None
+    }
+
+    /// Returns the version of the expand migration this one is the contract half of, for schema
+    /// changes made without downtime in two steps: an expand migration adds the new structure
+    /// alongside the old, and once every running release of the application uses the new one, a
+    /// contract migration removes the old. See `MigrationGenerator::create_expand_contract()`.
+    /// `MigrationIndex::run()` defers a contract migration, leaving it outstanding, until its
+    /// expand migration was applied by an earlier deploy, or in an earlier run if no deploy id is
+    /// set, and at least `RunOptions::contract_delay()` ago. Returns None by default.
+    fn contracts(&self) -> Option<MigrationVersion> {
+        None
+    }
+}
+
+/// Selects migrations by their tags, for `MigrationIndex::run_tagged()`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TagFilter {
+    /// Selects migrations with at least one of these tags. Untagged migrations aren't selected.
+    Include(Vec<String>),
+    /// Selects migrations with none of these tags, including untagged migrations.
+    Exclude(Vec<String>),
+}
+
+impl TagFilter {
+    /// Creates a filter selecting migrations with at least one of the given tags.
+    pub fn include(tags: &[&str]) -> Self {
+        TagFilter::Include(tags.iter().map(|tag| tag.to_string()).collect())
+    }
+
+    /// Creates a filter selecting migrations with none of the given tags.
+    pub fn exclude(tags: &[&str]) -> Self {
+        TagFilter::Exclude(tags.iter().map(|tag| tag.to_string()).collect())
+    }
+
+    /// Returns true if the filter selects the given migration.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use trek::migration::{Migration, TagFilter};
+    /// # use trek::migration_version::MigrationVersion;
+    /// # use trek::sql_migration::SqlMigration;
+    /// let backfill = SqlMigration::new(
+    ///     MigrationVersion::new(20150826001350),
+    ///     "20150826001350_backfill_user_names",
+    ///     "UPDATE users SET name = email WHERE name IS NULL;",
+    ///     "SELECT 1;"
+    /// ).with_tags(&["data"]);
+    /// assert!(TagFilter::include(&["data"]).matches(&backfill));
+    /// assert!(!TagFilter::exclude(&["data"]).matches(&backfill));
+    /// ```
+    pub fn matches(&self, migration: &Migration) -> bool {
+        let tags = migration.tags();
+        match *self {
+            TagFilter::Include(ref included) => tags.iter().any(|tag| included.contains(tag)),
+            TagFilter::Exclude(ref excluded) => !tags.iter().any(|tag| excluded.contains(tag)),
+        }
+    }
+}
+
+/// Computes a checksum of the given content, e.g. a migration's SQL, suitable for returning from
+/// `Migration::checksum()`. This is a 64-bit FNV-1a hash rendered in hexadecimal: it detects
+/// accidental edits but is not a cryptographic digest.
+///
+/// # Examples
+///
+/// ```
+/// # use trek::migration::content_checksum;
+/// let up_sql = "CREATE TABLE users (id SERIAL PRIMARY KEY);";
+/// assert_eq!(content_checksum(up_sql), content_checksum(up_sql));
+/// assert!(content_checksum(up_sql) != content_checksum("DROP TABLE users;"));
+/// ```
+pub fn content_checksum(content: &str) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in content.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{:016x}", hash)
+}
+
+/// Defines a migration struct from its name and the bodies of its `up()` and `down()` methods,
+/// generating the `new()` constructor, `Display` and `version()` plumbing that the Rust template
+/// otherwise writes out in every migration file. The version is parsed from the digits at the
+/// start of the name, after an optional "migration_" prefix, so the name generated for the
+/// migration's file can be used as it is. `down` can be left out for an irreversible migration.
+///
+/// The method bodies return a `postgres::Result<()>`, so the crate using the macro must have the
+/// `postgres` crate at its root.
+///
+/// # Examples
+///
+/// ```
+/// #[macro_use]
+/// extern crate trek;
+/// extern crate postgres;
+///
+/// # use trek::migration::Migration;
+/// # use trek::migration_version::MigrationVersion;
+/// define_migration! {
+///     pub struct CreateUsersTable = "migration_20150822094521_create_users_table";
+///
+///     fn up(context) {
+///         try!(context.connection().execute("CREATE TABLE users (id SERIAL PRIMARY KEY);", &[]));
+///         Ok(())
+///     }
+///
+///     fn down(context) {
+///         try!(context.connection().execute("DROP TABLE users;", &[]));
+///         Ok(())
+///     }
+/// }
+///
+/// # fn main() {
+/// let migration = CreateUsersTable::new();
+/// assert_eq!(migration.version(), MigrationVersion::new(20150822094521));
+/// assert_eq!(migration.to_string(), "migration_20150822094521_create_users_table");
+/// # }
+/// ```
+///
+/// # Panics
+///
+/// `new()` panics if the name doesn't start with a version.
+#[macro_export]
+macro_rules! define_migration {
+    (
+        $(#[$attribute:meta])*
+        $visibility:vis struct $struct_name:ident = $name:expr;
+
+        fn up($up_context:ident) $up:block
+
+        $(fn down($down_context:ident) $down:block)*
+    ) => {
+        $(#[$attribute])*
+        #[derive(Debug)]
+        $visibility struct $struct_name {
+            name: &'static str,
+            version: $crate::migration_version::MigrationVersion,
+        }
+
+        impl $struct_name {
+            /// Creates the migration.
+            pub fn new() -> Self {
+                let name: &'static str = $name;
+                let unprefixed_name = if name.starts_with("migration_") {
+                    &name["migration_".len()..]
+                } else {
+                    name
+                };
+                $struct_name {
+                    name: name,
+                    version: $crate::migration_version::MigrationVersion::from_name(
+                        unprefixed_name
+                    ).expect("Migration names start with their version"),
+                }
+            }
+        }
+
+        impl $crate::migration::Migration for $struct_name {
+            fn version(&self) -> $crate::migration_version::MigrationVersion {
+                self.version
+            }
+
+            fn up(
+                &self,
+                $up_context: &$crate::migration_context::MigrationContext
+            ) -> ::postgres::Result<()> $up
+
+            $(
+                fn down(
+                    &self,
+                    $down_context: &$crate::migration_context::MigrationContext
+                ) -> ::postgres::Result<()> $down
+            )*
+        }
+
+        impl ::std::fmt::Display for $struct_name {
+            fn fmt(&self, formatter: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                write!(formatter, "{}", self.name)
+            }
+        }
+    };
 }
@@ -0,0 +1,436 @@
+use std::cell::RefCell;
+use std::cmp;
+use std::time::Duration;
+
+use postgres::{self, GenericConnection};
+
+use super::migration::Migration;
+use super::seed::Seed;
+
+
+/// Options affecting how migrations are applied, set with `MigrationIndexBuilder::run_options()`
+/// and available to migrations through their `MigrationContext`.
+///
+/// # Examples
+///
+/// ```
+/// # use trek::migration_context::RunOptions;
+/// # use trek::migration_index::MigrationIndex;
+/// let migrations = MigrationIndex::builder()
+///     .run_options(RunOptions::new().environment("production"))
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RunOptions {
+    environment: Option<String>,
+    statement_timeout: Option<Duration>,
+    lock_timeout: Option<Duration>,
+    contract_delay: Option<Duration>,
+    retry_policy: Option<RetryPolicy>,
+}
+
+impl RunOptions {
+    /// Creates options with no environment, timeouts, contract delay or retry policy set.
+    pub fn new() -> Self {
+        RunOptions {
+            environment: None,
+            statement_timeout: None,
+            lock_timeout: None,
+            contract_delay: None,
+            retry_policy: None,
+        }
+    }
+
+    /// Sets the statement timeout for migrations that don't set their own. See
+    /// `Migration::statement_timeout()`.
+    pub fn statement_timeout(mut self, timeout: Duration) -> Self {
+        self.statement_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the lock timeout for migrations that don't set their own. See
+    /// `Migration::lock_timeout()`.
+    pub fn lock_timeout(mut self, timeout: Duration) -> Self {
+        self.lock_timeout = Some(timeout);
+        self
+    }
+
+    /// Returns the statement timeout the migration runs with: its own, or else the one set with
+    /// `statement_timeout()`, if any.
+    pub fn statement_timeout_for(&self, migration: &Migration) -> Option<Duration> {
+        migration.statement_timeout().or(self.statement_timeout)
+    }
+
+    /// Returns the lock timeout the migration runs with: its own, or else the one set with
+    /// `lock_timeout()`, if any.
+    pub fn lock_timeout_for(&self, migration: &Migration) -> Option<Duration> {
+        migration.lock_timeout().or(self.lock_timeout)
+    }
+
+    /// Sets how long after its expand migration was applied a contract migration is deferred
+    /// for, e.g. long enough for every instance of the previous release to have been replaced.
+    /// See `Migration::contracts()`.
+    pub fn contract_delay(mut self, delay: Duration) -> Self {
+        self.contract_delay = Some(delay);
+        self
+    }
+
+    /// Returns how long after its expand migration the migration is deferred for: the delay set
+    /// with `contract_delay()`, if any, if it's a contract migration.
+    pub fn contract_delay_for(&self, migration: &Migration) -> Option<Duration> {
+        migration.contracts().and(self.contract_delay)
+    }
+
+    /// Sets how work that fails with a transient database error, like a serialization failure or
+    /// a lock timeout, is retried: the bookkeeping of the migration history, and applying
+    /// migrations that are safe to retry (see `Migration::idempotent()`). Without a policy,
+    /// nothing is retried.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// # use trek::migration_context::{RetryPolicy, RunOptions};
+    /// # use trek::migration_index::MigrationIndex;
+    /// let migrations = MigrationIndex::builder()
+    ///     .run_options(RunOptions::new().retry_policy(
+    ///         RetryPolicy::new(5).initial_backoff(Duration::from_millis(500))
+    ///     ))
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = Some(policy);
+        self
+    }
+
+    /// Returns the policy the bookkeeping of the migration history is retried by, if one was set
+    /// with `retry_policy()`.
+    pub fn bookkeeping_retry_policy(&self) -> Option<&RetryPolicy> {
+        self.retry_policy.as_ref()
+    }
+
+    /// Returns the policy applying the migration is retried by: the one set with
+    /// `retry_policy()`, if any, if the migration is idempotent.
+    pub fn retry_policy_for(&self, migration: &Migration) -> Option<&RetryPolicy> {
+        if migration.idempotent() { self.retry_policy.as_ref() } else { None }
+    }
+
+    /// Sets the name of the environment migrations are applied in, e.g. "development" or
+    /// "production".
+    pub fn environment(mut self, environment: &str) -> Self {
+        self.environment = Some(environment.to_owned());
+        self
+    }
+
+    /// Returns the name of the environment migrations are applied in, if one was set.
+    pub fn environment_name(&self) -> Option<&str> {
+        self.environment.as_ref().map(|environment| &**environment)
+    }
+
+    /// Returns true if the migration applies to the environment migrations are applied in: if
+    /// it isn't restricted to particular environments, or if the environment is one of them. See
+    /// `Migration::environments()`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use trek::migration_context::RunOptions;
+    /// # use trek::migration_version::MigrationVersion;
+    /// # use trek::sql_migration::SqlMigration;
+    /// let seed_users = SqlMigration::new(
+    ///     MigrationVersion::new(20150826001350),
+    ///     "20150826001350_seed_users",
+    ///     "INSERT INTO users (username) VALUES ('admin');",
+    ///     "DELETE FROM users WHERE username = 'admin';"
+    /// ).with_environments(&["development"]);
+    /// assert!(RunOptions::new().environment("development").applies_to(&seed_users));
+    /// assert!(!RunOptions::new().environment("production").applies_to(&seed_users));
+    /// assert!(!RunOptions::new().applies_to(&seed_users));
+    /// ```
+    pub fn applies_to(&self, migration: &Migration) -> bool {
+        self.includes_environment(&migration.environments())
+    }
+
+    /// Returns true if the seed applies to the environment migrations are applied in, by the
+    /// same rules as `applies_to()`. See `Seed::environments()`.
+    pub fn applies_to_seed(&self, seed: &Seed) -> bool {
+        self.includes_environment(&seed.environments())
+    }
+
+    /// Returns true if the given environments are empty, meaning every environment, or include
+    /// the environment migrations are applied in.
+    fn includes_environment(&self, environments: &[String]) -> bool {
+        if environments.is_empty() {
+            return true;
+        }
+        match self.environment {
+            Some(ref environment) => environments.contains(environment),
+            None => false
+        }
+    }
+}
+
+/// How work failing with a transient database error is retried, set with
+/// `RunOptions::retry_policy()`. Each retry waits twice as long as the one before, starting from
+/// the initial backoff and up to the maximum backoff.
+///
+/// # Examples
+///
+/// ```
+/// # use std::time::Duration;
+/// # use trek::migration_context::RetryPolicy;
+/// let policy = RetryPolicy::new(4).max_backoff(Duration::from_millis(300));
+/// assert_eq!(policy.max_attempts(), 4);
+/// assert_eq!(policy.backoff(1), Duration::from_millis(100));
+/// assert_eq!(policy.backoff(2), Duration::from_millis(200));
+/// assert_eq!(policy.backoff(3), Duration::from_millis(300));
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// Creates a policy making up to `max_attempts` attempts in all, including the first,
+    /// waiting 100ms before the first retry and at most 10s before any retry.
+    pub fn new(max_attempts: u32) -> Self {
+        RetryPolicy {
+            max_attempts: max_attempts,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+        }
+    }
+
+    /// Sets how long to wait before the first retry.
+    pub fn initial_backoff(mut self, backoff: Duration) -> Self {
+        self.initial_backoff = backoff;
+        self
+    }
+
+    /// Sets the longest to wait before any retry.
+    pub fn max_backoff(mut self, backoff: Duration) -> Self {
+        self.max_backoff = backoff;
+        self
+    }
+
+    /// Returns the most attempts made, including the first.
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// Returns how long to wait after the given failed attempt, counted from 1, before the next.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let mut backoff = self.initial_backoff;
+        for _ in 1..attempt {
+            if backoff >= self.max_backoff {
+                break;
+            }
+            backoff = backoff * 2;
+        }
+        cmp::min(backoff, self.max_backoff)
+    }
+}
+
+/// Writes log messages on behalf of a migration, prefixed with the migration's name so they can be
+/// told apart from Trek's own messages.
+#[derive(Clone, Debug)]
+pub struct MigrationLogger {
+    migration_name: String,
+}
+
+impl MigrationLogger {
+    /// Creates a logger for the migration with the given name.
+    pub fn new(migration_name: &str) -> Self {
+        MigrationLogger {
+            migration_name: migration_name.to_owned(),
+        }
+    }
+
+    /// Logs a message about the migration's progress at the debug level.
+    pub fn debug(&self, message: &str) {
+        debug!("{}: {}", self.migration_name, message);
+    }
+
+    /// Logs a message about the migration's progress at the info level.
+    pub fn info(&self, message: &str) {
+        info!("{}: {}", self.migration_name, message);
+    }
+
+    /// Logs a message about a problem the migration worked around at the warn level.
+    pub fn warn(&self, message: &str) {
+        warn!("{}: {}", self.migration_name, message);
+    }
+}
+
+/// Everything a migration has access to while it's applied or rolled back: the connection to run
+/// its SQL on, the options of the run, a logger, the name of the current environment and a place
+/// to leave warnings. New facilities are added here rather than as new parameters of
+/// `Migration::up()` and `Migration::down()`.
+pub struct MigrationContext<'a> {
+    connection: &'a GenericConnection,
+    options: &'a RunOptions,
+    logger: MigrationLogger,
+    /// the warnings left by the migration, in the order they were left
+    warnings: RefCell<Vec<String>>,
+    /// the SQL last run with batch_execute()
+    last_statement: RefCell<Option<String>>,
+}
+
+impl<'a> MigrationContext<'a> {
+    /// Creates a context for applying or rolling back the migration with the given name. Trek
+    /// creates contexts itself; this is mostly useful for calling a migration's `up()` or `down()`
+    /// directly, e.g. in tests.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # extern crate postgres;
+    /// # extern crate trek;
+    /// # fn main() {
+    /// # use postgres::{Connection, TlsMode};
+    /// # use trek::migration::Migration;
+    /// # use trek::migration_context::{MigrationContext, RunOptions};
+    /// # use trek::migration_version::MigrationVersion;
+    /// # use trek::sql_migration::SqlMigration;
+    /// let connection = Connection::connect("server url", TlsMode::None).unwrap();
+    /// let migration = SqlMigration::new(
+    ///     MigrationVersion::new(20150826001350),
+    ///     "20150826001350_create_users_table",
+    ///     "CREATE TABLE users (id SERIAL PRIMARY KEY);",
+    ///     "DROP TABLE users;"
+    /// );
+    /// let options = RunOptions::new();
+    /// let context = MigrationContext::new(&connection, &options, &migration.to_string());
+    /// migration.up(&context).unwrap();
+    /// # }
+    /// ```
+    pub fn new(
+        connection: &'a GenericConnection,
+        options: &'a RunOptions,
+        migration_name: &str
+    ) -> Self {
+        MigrationContext {
+            connection: connection,
+            options: options,
+            logger: MigrationLogger::new(migration_name),
+            warnings: RefCell::new(vec![]),
+            last_statement: RefCell::new(None),
+        }
+    }
+
+    /// Returns the connection the migration should run its SQL on. This is a transaction unless
+    /// the migration is non-transactional (see `Migration::transactional()`).
+    pub fn connection(&self) -> &GenericConnection {
+        self.connection
+    }
+
+    /// Runs the given SQL, which may hold several statements, on `connection()`, logging it at the
+    /// debug level and remembering it so that if the migration fails, the error names the
+    /// statement that failed (see `Error::statement()`). `SqlMigration` runs its SQL this way.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate postgres;
+    /// # extern crate trek;
+    /// # fn main() {
+    /// # use trek::fn_migration::FnMigration;
+    /// # use trek::migration_context::MigrationContext;
+    /// # use trek::migration_version::MigrationVersion;
+    /// let add_email = FnMigration::new(
+    ///     MigrationVersion::new(20150826001350),
+    ///     "20150826001350_add_email_to_users",
+    ///     |context: &MigrationContext| {
+    ///         context.batch_execute("ALTER TABLE users ADD COLUMN email TEXT;")
+    ///     },
+    ///     |context: &MigrationContext| {
+    ///         context.batch_execute("ALTER TABLE users DROP COLUMN email;")
+    ///     }
+    /// );
+    /// # }
+    /// ```
+    pub fn batch_execute(&self, sql: &str) -> postgres::Result<()> {
+        debug!("Executing SQL: {}", sql);
+        *self.last_statement.borrow_mut() = Some(sql.to_owned());
+        self.connection.batch_execute(sql)
+    }
+
+    /// Returns the SQL last run with `batch_execute()`, if any.
+    pub fn last_statement(&self) -> Option<String> {
+        self.last_statement.borrow().clone()
+    }
+
+    /// Takes on what the migration left in the context of an attempt to apply it: the SQL it
+    /// last ran, and, if the attempt succeeded, its warnings.
+    pub(crate) fn adopt_attempt(&self, attempt: &MigrationContext, succeeded: bool) {
+        *self.last_statement.borrow_mut() = attempt.last_statement();
+        if succeeded {
+            self.warnings.borrow_mut().extend(attempt.warnings());
+        }
+    }
+
+    /// Returns the options of the current run.
+    pub fn options(&self) -> &RunOptions {
+        self.options
+    }
+
+    /// Returns a logger for messages about the migration.
+    pub fn logger(&self) -> &MigrationLogger {
+        &self.logger
+    }
+
+    /// Returns the name of the environment the migration is applied in, if one was set with
+    /// `RunOptions::environment()`.
+    pub fn environment(&self) -> Option<&str> {
+        self.options.environment_name()
+    }
+
+    /// Flags something about the migration that didn't stop it from completing but deserves
+    /// attention, e.g. "3 rows had NULL emails and were skipped". Unlike `logger().warn()`, the
+    /// warning is collected into the `MigrationReport` returned by `MigrationIndex::run()` and
+    /// passed to the progress observer, which logs it by default.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate postgres;
+    /// # extern crate trek;
+    /// # fn main() {
+    /// # use trek::fn_migration::FnMigration;
+    /// # use trek::migration_context::MigrationContext;
+    /// # use trek::migration_version::MigrationVersion;
+    /// let backfill = FnMigration::irreversible(
+    ///     MigrationVersion::new(20150826001350),
+    ///     "20150826001350_backfill_user_names",
+    ///     |context: &MigrationContext| {
+    ///         let connection = context.connection();
+    ///         let rows = try!(connection.query(
+    ///             "SELECT count(*) FROM users WHERE name IS NULL AND email IS NULL;",
+    ///             &[]
+    ///         ));
+    ///         let skipped: i64 = rows.get(0).get(0);
+    ///         try!(connection.execute(
+    ///             "UPDATE users SET name = email WHERE name IS NULL AND email IS NOT NULL;",
+    ///             &[]
+    ///         ));
+    ///         if skipped > 0 {
+    ///             context.warn(&format!("{} rows had NULL emails and were skipped", skipped));
+    ///         }
+    ///         Ok(())
+    ///     }
+    /// );
+    /// # }
+    /// ```
+    pub fn warn(&self, message: &str) {
+        self.warnings.borrow_mut().push(message.to_owned());
+    }
+
+    /// Returns the warnings left with `warn()`, in the order they were left.
+    pub fn warnings(&self) -> Vec<String> {
+        self.warnings.borrow().clone()
+    }
+}
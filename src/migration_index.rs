@@ -1,33 +1,293 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt::{self, Display, Formatter};
+use std::io;
+use std::path::Path;
+use std::result;
+use std::slice;
+use std::thread;
+use std::time::Instant;
 use std::vec::Vec;
 
-use postgres::{self, GenericConnection};
+use chrono::UTC;
+use postgres::{self, Connection, GenericConnection};
+use postgres::error::ErrorPosition;
+#[cfg(feature = "serde")]
+use serde::Serialize;
 
-use super::error::Error;
-use super::migration::Migration;
+use super::error::{
+    is_connection_lost,
+    is_transient,
+    Error,
+    IrreversibleMigrationError,
+    MigrationPhase,
+    MigrationResultExt,
+    ResultExt,
+    ValidationError,
+};
+#[cfg(feature = "serde")]
+use super::history;
+use super::history::{AppliedMigration, PruneCutoff, SeedRun, SkippedMigration};
+use super::migration::{Migration, TagFilter};
+use super::migration_context::{MigrationContext, RetryPolicy, RunOptions};
+use super::migration_version::MigrationVersion;
+use super::progress::{LogProgress, MigrationEvent, ProgressObserver};
+use super::repeatable_migration::RepeatableMigration;
+use super::seed::Seed;
+use super::sql_migration;
+use super::version_table::{self, VersionTable};
 
 use super::Result;
 
 
+/// A callback invoked with the database connection and a migration. See
+/// `MigrationIndexBuilder::before_each()` and `MigrationIndexBuilder::after_each()`.
+pub type MigrationHook = Box<Fn(&GenericConnection, &Migration) -> postgres::Result<()>>;
+
 /// Tracks and manages database migrations for this system.
 pub struct MigrationIndex {
     /// all database migrations, in order from first to last
-    migrations: Vec<Box<Migration>>
+    migrations: Vec<Box<Migration>>,
+    /// migrations applied again whenever they change, in the order they're applied
+    repeatable_migrations: Vec<Box<RepeatableMigration>>,
+    /// reference data loaded by run_seeds(), in the order it's loaded
+    seeds: Vec<Box<Seed>>,
+    /// called just before each migration is applied or rolled back
+    before_each: Option<MigrationHook>,
+    /// called just after each migration is applied or rolled back
+    after_each: Option<MigrationHook>,
+    /// notified as migrations are applied, rolled back, or recorded
+    progress: Box<ProgressObserver>,
+    /// the table applied migrations are recorded in
+    version_table: VersionTable,
+    /// passed to migrations through their context
+    options: RunOptions,
 }
 impl MigrationIndex {
-    /// Wrap the given Migrations list into a new MigrationIndex.
+    /// Wrap the given Migrations list into a new MigrationIndex. The migrations are sorted by
+    /// version, except that each migration comes after the migrations it depends on (see
+    /// `Migration::dependencies()`), so they can be given in any order.
     #[allow(dead_code)]
     pub fn new(mut migrations: Vec<Box<Migration>>) -> Self {
+        // a stable sort, so migrations with the same version stay in registration order until
+        // validate() reports them
+        migrations.sort_by_key(|migration| migration.version());
+        // if the dependencies are invalid, the migrations stay in version order until validate()
+        // reports them
+        if let Ok(order) = dependency_order(&migrations) {
+            let mut unordered = migrations.into_iter().map(Some).collect::<Vec<_>>();
+            migrations = order.into_iter()
+                .map(|position| unordered[position].take().unwrap())
+                .collect();
+        }
         migrations.shrink_to_fit();
         MigrationIndex {
-            migrations: migrations
+            migrations: migrations,
+            repeatable_migrations: vec![],
+            seeds: vec![],
+            before_each: None,
+            after_each: None,
+            progress: Box::new(LogProgress),
+            version_table: VersionTable::default(),
+            options: RunOptions::new(),
         }
     }
 
-    /// Runs all database migrations that haven't yet been applied to the database.
+    /// Creates a MigrationIndex of the SQL migrations in the given directory, read at runtime so
+    /// that migrations can be added without recompiling. See `sql_migration::load_directory()`
+    /// for how the files are named.
     ///
     /// # Failures
     ///
-    /// Returns an error if a problem occurred when communicating with the database.
+    /// Returns an error if the migrations can't be loaded, or if they're invalid, e.g. two share
+    /// a version.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use std::path::Path;
+    /// # use trek::migration_index::MigrationIndex;
+    /// let migrations = MigrationIndex::from_directory(Path::new("migrations/")).unwrap();
+    /// ```
+    pub fn from_directory(directory: &Path) -> io::Result<Self> {
+        let migrations = try!(sql_migration::load_directory(directory)).into_iter()
+            .map(|migration| Box::new(migration) as Box<Migration>)
+            .collect();
+        let index = MigrationIndex::new(migrations);
+        if let Err(error) = index.validate() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, error));
+        }
+        Ok(index)
+    }
+
+    /// Returns a builder for configuring a new MigrationIndex with options such as hooks and the
+    /// name of the version tracking table.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use trek::migration_index::MigrationIndex;
+    /// # use trek::migration::Migration;
+    /// # let migration_list: Vec<Box<Migration>> = vec![];
+    /// let migrations = MigrationIndex::builder()
+    ///     .migrations(migration_list)
+    ///     .table_name("trek_schema_version")
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn builder() -> MigrationIndexBuilder {
+        MigrationIndexBuilder::new()
+    }
+
+    /// Checks that the registered migrations can be told apart and ordered: every migration must
+    /// have a non-empty name, no two migrations may share a name or a version, every dependency
+    /// must be registered without forming a cycle, and no two repeatable migrations or seeds may
+    /// share a name.
+    /// `MigrationIndexBuilder::build()` performs this check automatically.
+    ///
+    /// # Failures
+    ///
+    /// Returns an error identifying the first empty or duplicated name or version found.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use trek::migration_index::MigrationIndex;
+    /// # use trek::migration::Migration;
+    /// # let migration_list: Vec<Box<Migration>> = vec![];
+    /// let migrations = MigrationIndex::new(migration_list);
+    /// if let Err(error) = migrations.validate() {
+    ///     panic!("Invalid migration list: {}", error);
+    /// }
+    /// ```
+    pub fn validate(&self) -> result::Result<(), ValidationError> {
+        let mut names = HashSet::new();
+        for (position, migration) in self.migrations.iter().enumerate() {
+            let name = migration.to_string();
+            if name.is_empty() {
+                return Err(ValidationError::new(format!(
+                    "The migration at position {} has an empty name",
+                    position
+                )));
+            }
+            if !names.insert(name.clone()) {
+                return Err(ValidationError::new(format!(
+                    "More than one migration is named {}",
+                    name
+                )));
+            }
+        }
+        let mut repeatable_names = HashSet::new();
+        for migration in &self.repeatable_migrations {
+            let name = migration.to_string();
+            if name.is_empty() {
+                return Err(ValidationError::new(
+                    "A repeatable migration has an empty name".to_owned()
+                ));
+            }
+            if !repeatable_names.insert(name.clone()) {
+                return Err(ValidationError::new(format!(
+                    "More than one repeatable migration is named {}",
+                    name
+                )));
+            }
+        }
+        let mut seed_names = HashSet::new();
+        for seed in &self.seeds {
+            let name = seed.to_string();
+            if name.is_empty() {
+                return Err(ValidationError::new("A seed has an empty name".to_owned()));
+            }
+            if !seed_names.insert(name.clone()) {
+                return Err(ValidationError::new(format!("More than one seed is named {}", name)));
+            }
+        }
+        let mut versions = HashMap::new();
+        for migration in &self.migrations {
+            if let Some(other) = versions.insert(migration.version(), migration) {
+                return Err(ValidationError::new(format!(
+                    "Migrations {} and {} both have version {}, so their order is ambiguous",
+                    other,
+                    migration,
+                    migration.version()
+                )));
+            }
+        }
+        try!(dependency_order(&self.migrations));
+        Ok(())
+    }
+
+    /// Returns an iterator over the registered migrations, in order from first to last.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use trek::migration_index::MigrationIndex;
+    /// # use trek::migration::Migration;
+    /// # let migration_list: Vec<Box<Migration>> = vec![];
+    /// let migrations = MigrationIndex::new(migration_list);
+    /// for migration in migrations.iter() {
+    ///     println!("{}", migration);
+    /// }
+    /// ```
+    pub fn iter(&self) -> slice::Iter<Box<Migration>> {
+        self.migrations.iter()
+    }
+
+    /// Returns the number of registered migrations.
+    pub fn len(&self) -> usize {
+        self.migrations.len()
+    }
+
+    /// Returns true if no migrations are registered.
+    pub fn is_empty(&self) -> bool {
+        self.migrations.is_empty()
+    }
+
+    /// Returns the registered migration with the given name, if there is one.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use trek::migration_index::MigrationIndex;
+    /// # use trek::migration::Migration;
+    /// # let migration_list: Vec<Box<Migration>> = vec![];
+    /// let migrations = MigrationIndex::new(migration_list);
+    /// if migrations.get("20150826001350_create_users_table").is_none() {
+    ///     println!("The users table migration isn't registered");
+    /// }
+    /// ```
+    pub fn get(&self, name: &str) -> Option<&Migration> {
+        self.current_index(name).map(|index| &*self.migrations[index])
+    }
+
+    /// Returns the registered migration with the given version, if there is one.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use trek::migration_index::MigrationIndex;
+    /// # use trek::migration::Migration;
+    /// # use trek::migration_version::MigrationVersion;
+    /// # let migration_list: Vec<Box<Migration>> = vec![];
+    /// let migrations = MigrationIndex::new(migration_list);
+    /// if let Some(migration) = migrations.get_version(MigrationVersion::new(20150826001350)) {
+    ///     println!("Version 20150826001350 is {}", migration);
+    /// }
+    /// ```
+    pub fn get_version(&self, version: MigrationVersion) -> Option<&Migration> {
+        self.version_index(version).map(|index| &*self.migrations[index])
+    }
+
+    /// Runs all database migrations that haven't yet been applied to the database, followed by
+    /// the repeatable migrations that have changed since they were last applied. The returned
+    /// report lists the applied migrations and any warnings they left.
+    ///
+    /// # Failures
+    ///
+    /// Returns an error if a problem occurred when communicating with the database. Returns an
+    /// `UnknownAppliedVersion` error, without running anything, if a migration recorded as
+    /// applied isn't registered.
     ///
     /// # Examples
     ///
@@ -62,39 +322,172 @@ impl MigrationIndex {
     /// # }
     ///
     /// ```
-    pub fn run(&self, connection: &GenericConnection) -> Result<()> {
-        let mut schema_version = match MigrationIndex::schema_version(connection) {
-            Ok(schema_version_option) => schema_version_option,
-            Err(error) => {
-                return Err(Error::new(
-                    "Error reading current schema version".to_owned(),
-                    error
-                ));
+    pub fn run(&self, connection: &GenericConnection) -> Result<MigrationReport> {
+        self.run_with_table(connection, &self.version_table)
+    }
+
+    /// Returns the migrations `run()` would apply, in the order it would apply them, without
+    /// changing the database, e.g. to review them before a deploy. Migrations that don't apply
+    /// to the configured environment, contract migrations that would be deferred and baselines
+    /// that would be recorded without being run are left out. Migrations whose `should_run()`
+    /// decides they shouldn't run are included, since it isn't called. `Migration::up_sql()`
+    /// gives the SQL of SQL migrations.
+    ///
+    /// # Failures
+    ///
+    /// Returns an error if a problem occurred when communicating with the database, or if `run()`
+    /// would refuse to run, e.g. because an applied migration was modified or isn't registered.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # extern crate postgres;
+    /// # extern crate trek;
+    /// # fn main() {
+    /// # use postgres::{Connection, TlsMode};
+    /// # use trek::migration_index::MigrationIndex;
+    /// # use trek::migration::Migration;
+    /// let connection = Connection::connect("server url", TlsMode::None).unwrap();
+    /// # let migration_list: Vec<Box<Migration>> = vec![];
+    /// let migrations = MigrationIndex::new(migration_list);
+    /// for migration in migrations.plan(&connection).unwrap() {
+    ///     println!("Would apply {}", migration);
+    /// }
+    /// # }
+    /// ```
+    pub fn plan(&self, connection: &GenericConnection) -> Result<Vec<&Migration>> {
+        let version_table = &self.version_table;
+        try!(self.ensure_clean(connection, version_table));
+        let mut applied_versions = try!(self.applied_versions(connection, version_table));
+        // the legacy table would be converted first, recording everything up to its version
+        if let Some(legacy_index) = try!(self.legacy_index(connection, version_table)) {
+            applied_versions.extend(
+                self.migrations[..(legacy_index + 1)].iter().map(|migration| migration.version())
+            );
+        }
+        try!(self.ensure_registered(connection, version_table, &applied_versions));
+        let baselines = try!(self.squashed_baselines(connection, version_table, &applied_versions));
+        for migration in baselines {
+            applied_versions.insert(migration.version());
+        }
+        try!(self.verify_checksums(
+            connection, version_table, &self.applied_migrations(&applied_versions)
+        ));
+        let batch = try!(version_table.next_batch(connection).with_phase(
+            MigrationPhase::VersionLookup,
+            || "Error reading the latest batch of applied migrations".to_owned()
+        ));
+        let mut planned = vec![];
+        for migration in self.outstanding_migrations(&applied_versions) {
+            if !self.options.applies_to(&**migration) {
+                continue;
             }
-        };
-        for migration in self.outstanding_migrations(schema_version.clone()).iter() {
-            if let Err(error) = MigrationIndex::update_schema_version(
-                connection, schema_version, Some(migration.to_string())
-            ) {
-                return Err(Error::new(
-                    "Error updating schema version".to_owned(),
-                    error
-                ));
+            let deferral = try!(
+                self.contract_deferral(connection, version_table, &**migration, batch)
+            );
+            if deferral.is_none() {
+                planned.push(&**migration);
             }
-            if let Err(error) = migration.up(connection) {
-                return Err(Error::new(
-                    format!("Error applying migration {}", migration),
-                    error
-                ));
+        }
+        Ok(planned)
+    }
+
+    /// Checks the database against the registered migrations without changing it, collecting
+    /// every problem that would make `run()` refuse to run instead of stopping at the first, e.g.
+    /// to check for drift in continuous integration: applied migrations that have been modified
+    /// or are no longer registered, and a migration left dirty. Also lists the migrations that
+    /// haven't been applied yet, which isn't a problem.
+    ///
+    /// # Failures
+    ///
+    /// Returns an error if a problem occurred when communicating with the database.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # extern crate postgres;
+    /// # extern crate trek;
+    /// # fn main() {
+    /// # use postgres::{Connection, TlsMode};
+    /// # use trek::migration_index::MigrationIndex;
+    /// # use trek::migration::Migration;
+    /// let connection = Connection::connect("server url", TlsMode::None).unwrap();
+    /// # let migration_list: Vec<Box<Migration>> = vec![];
+    /// let migrations = MigrationIndex::new(migration_list);
+    /// let report = migrations.verify(&connection).unwrap();
+    /// for name in report.modified() {
+    ///     println!("{} was modified after it was applied", name);
+    /// }
+    /// assert!(report.is_ok());
+    /// # }
+    /// ```
+    pub fn verify(&self, connection: &GenericConnection) -> Result<VerificationReport> {
+        let version_table = &self.version_table;
+        let mut report = VerificationReport::new();
+        let dirty_version = try!(
+            version_table.dirty_version(connection)
+                .context("Error checking for partially applied migrations")
+        );
+        report.dirty = dirty_version.map(|version| {
+            self.get_version(version)
+                .map(|migration| migration.to_string())
+                .unwrap_or_else(|| version.to_string())
+        });
+        let mut applied_versions = try!(self.applied_versions(connection, version_table));
+        if let Some(legacy_index) = try!(self.legacy_index(connection, version_table)) {
+            applied_versions.extend(
+                self.migrations[..(legacy_index + 1)].iter().map(|migration| migration.version())
+            );
+        }
+
+        let mut history = try!(
+            version_table.history(connection).context("Error reading the migration history")
+        );
+        history.sort_by_key(|entry| entry.version());
+        let newest_version = self.migrations.iter().map(|migration| migration.version()).max();
+        for entry in history {
+            if entry.is_baseline() || self.is_registered(entry.version()) {
+                continue;
             }
-            schema_version = Some(migration.to_string());
+            // a migration older than the newest registered one was most likely deleted, while a
+            // newer one was most likely applied by a newer release or another branch
+            match newest_version {
+                Some(newest_version) if entry.version() < newest_version => {
+                    report.missing.push(entry.name().to_owned());
+                },
+                _ => report.unknown.push(entry.name().to_owned())
+            }
+        }
 
-            println!("Ran migration {}", migration);
-        };
-        Ok(())
+        let baselines = try!(self.squashed_baselines(connection, version_table, &applied_versions));
+        for migration in baselines {
+            applied_versions.insert(migration.version());
+        }
+        let recorded_checksums = try!(
+            version_table.checksums(connection)
+                .context("Error reading checksums of applied migrations")
+        );
+        for migration in self.applied_migrations(&applied_versions) {
+            if let (Some(checksum), Some(recorded_checksum)) = (
+                migration.checksum(), recorded_checksums.get(&migration.version())
+            ) {
+                if checksum != *recorded_checksum {
+                    report.modified.push(migration.to_string());
+                }
+            }
+        }
+        report.pending = self.outstanding_migrations(&applied_versions).iter()
+            .filter(|migration| self.options.applies_to(&***migration))
+            .map(|migration| migration.to_string())
+            .collect();
+        Ok(report)
     }
 
-    /// Rolls back the last database migration that was successfully applied to the database.
+    /// Runs the database migrations selected by `filter` that haven't yet been applied to the
+    /// database, leaving the others outstanding for a later call to `run()` or `run_tagged()`.
+    /// Repeatable migrations aren't run.
+    /// This allows e.g. applying schema changes during a deploy while deferring long data
+    /// backfills to a maintenance window.
     ///
     /// # Failures
     ///
@@ -107,119 +500,102 @@ impl MigrationIndex {
     /// # extern crate trek;
     /// # fn main() {
     /// # use postgres::{self, Connection, TlsMode};
-    /// # use postgres::transaction::Transaction;
     /// # use trek::migration_index::MigrationIndex;
-    /// # use trek::migration::Migration;
+    /// # use trek::migration::{Migration, TagFilter};
     /// # fn f() -> postgres::Result<()>  {
     /// let connection = Connection::connect("server url", TlsMode::None).unwrap();
     /// let transaction = connection.transaction().unwrap();
     ///
     /// # let migration_list: Vec<Box<Migration>> = vec![];
     /// let migrations = MigrationIndex::new(migration_list);
-    /// match migrations.rollback(&transaction) {
+    /// match migrations.run_tagged(&transaction, &TagFilter::exclude(&["data"])) {
     ///     Ok(_) => {
     ///         try!(transaction.commit());
-    ///         println!("Rollback of latest migration complete.");
+    ///         println!("All outstanding schema migrations have been applied.");
     ///     },
     ///     Err(error) => {
-    ///         // note: no need to manually roll back the transaction, it'll automatically roll
-    ///         // back when the transaction variable goes out of scope
-    ///
-    ///         println!("Error error rolling back last applied migration: {}", error);
+    ///         println!("Error updating database structure: {}", error);
     ///     }
     /// }
     /// # Ok(())
     /// # };
     /// # }
-    ///
     /// ```
-    pub fn rollback(&self, connection: &GenericConnection) -> Result<()> {
-        let old_schema_version = match MigrationIndex::schema_version(connection) {
-            Ok(schema_version_option) => schema_version_option,
-            Err(error) => {
-                return Err(Error::new(
-                    "Failed to get current database schema version".to_owned(),
-                    error
-                ))
-            }
-        };
-        let old_schema_version = match old_schema_version {
-            Some(schema_version) => schema_version,
-            None => {
-                // if there's nothing to roll back, this function call is a no-op
-                return Ok(());
+    pub fn run_tagged(
+        &self,
+        connection: &GenericConnection,
+        filter: &TagFilter
+    ) -> Result<MigrationReport> {
+        let (outstanding_migrations, batch) = try!(
+            self.prepare_run(connection, &self.version_table)
+        );
+        let mut report = MigrationReport::new();
+        for migration in outstanding_migrations {
+            if filter.matches(&**migration) {
+                try!(self.apply_migration(
+                    connection, &self.version_table, &**migration, batch, &mut report
+                ));
             }
-        };
-        let old_migration_index = self.current_index(&old_schema_version).unwrap();
-        let old_migration = self.migrations.get(old_migration_index).unwrap();
-        match old_migration_index {
-            0 => {
-                if let Err(error) = MigrationIndex::update_schema_version(
-                    connection, Some(old_migration.to_string()), None
-                ) {
-                    return Err(Error::new(
-                        format!(
-                            "Failed to update schema version table when rolling back migration {}",
-                            old_migration,
-                        ),
-                        error
-                    ));
-                }
-                if let Err(error) = old_migration.down(connection) {
-                    return Err(Error::new(
-                        format!(
-                            "The down() method of database migration {} failed",
-                            old_migration,
-                        ),
-                        error
-                    ));
-                }
-                println!(
-                    "Rolled back migration {}, database is now empty.",
-                    old_migration
-                );
-                Ok(())
-            },
-            _ => {
-                let new_migration = self.migrations.get(old_migration_index - 1).unwrap();
-                if let Err(error) = MigrationIndex::update_schema_version(
-                    connection, Some(old_migration.to_string()), Some(new_migration.to_string())
-                ) {
-                    return Err(Error::new(
-                        format!(
-                            "Failed to update schema version table when rolling back migration {}",
-                            new_migration,
-                        ),
-                        error
-                    ));
-                }
-                if let Err(error) = old_migration.down(connection) {
-                    return Err(Error::new(
-                        format!(
-                            "The down() method of database migration {} failed",
-                            old_migration,
-                        ),
-                        error
-                    ));
-                }
-                println!(
-                    "Rolled back migration {}, database is now at version {}",
-                    old_migration,
-                    new_migration
-                );
-                Ok(())
+        }
+        Ok(report)
+    }
+
+    /// Runs the database migrations that haven't yet been applied to the database, up to and
+    /// including the migration with the given version, leaving the ones after it outstanding,
+    /// e.g. to apply part of a release's migrations at a time. Repeatable migrations aren't run.
+    ///
+    /// # Failures
+    ///
+    /// Returns a `Validation` error if no registered migration has the version, or an error if a
+    /// problem occurred when communicating with the database.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # extern crate postgres;
+    /// # extern crate trek;
+    /// # fn main() {
+    /// # use postgres::{Connection, TlsMode};
+    /// # use trek::migration_index::MigrationIndex;
+    /// # use trek::migration::Migration;
+    /// # use trek::migration_version::MigrationVersion;
+    /// let connection = Connection::connect("server url", TlsMode::None).unwrap();
+    /// # let migration_list: Vec<Box<Migration>> = vec![];
+    /// let migrations = MigrationIndex::new(migration_list);
+    /// migrations.run_to(&connection, MigrationVersion::new(20150826001350)).unwrap();
+    /// # }
+    /// ```
+    pub fn run_to(
+        &self,
+        connection: &GenericConnection,
+        target: MigrationVersion
+    ) -> Result<MigrationReport> {
+        let target_index = try!(self.target_index(target));
+        let (outstanding_migrations, batch) = try!(
+            self.prepare_run(connection, &self.version_table)
+        );
+        let mut report = MigrationReport::new();
+        for migration in outstanding_migrations {
+            if self.version_index(migration.version()) <= Some(target_index) {
+                try!(self.apply_migration(
+                    connection, &self.version_table, &**migration, batch, &mut report
+                ));
             }
         }
+        Ok(report)
     }
 
-    /// Takes a queryable connection object and returns the current version of the database's
-    /// schema. No changes are made to the database.
+    /// Runs all database migrations that haven't yet been applied to the database, each in its
+    /// own transaction, except for migrations whose `Migration::transactional()` returns false,
+    /// which are run directly on the connection, followed by the repeatable migrations that have
+    /// changed, each in its own transaction. This allows migrations that can't run inside a
+    /// transaction, e.g. ones using `CREATE INDEX CONCURRENTLY` or `VACUUM`, to be mixed with
+    /// ordinary ones. Unlike `run()`, migrations applied before a failure stay applied.
     ///
-    /// # Panics
+    /// # Failures
     ///
-    /// If the schema_version table that Trek uses to save a schema's current version has multiple
-    /// columns then this method will panic. It's expected that the schema_version table has only a
-    /// single column named after the last applied migration.
+    /// Returns an error if a problem occurred when communicating with the database. If a
+    /// non-transactional migration fails partway, it's left dirty (see `force_clean()`).
     ///
     /// # Examples
     ///
@@ -228,115 +604,2757 @@ impl MigrationIndex {
     /// # extern crate trek;
     /// # fn main() {
     /// # use postgres::{self, Connection, TlsMode};
-    /// # use postgres::transaction::Transaction;
     /// # use trek::migration_index::MigrationIndex;
     /// # use trek::migration::Migration;
     /// # fn f() {
     /// let connection = Connection::connect("server url", TlsMode::None).unwrap();
     ///
-    /// match MigrationIndex::schema_version(&connection) {
-    ///     Ok(result_option) => {
-    ///         match result_option {
-    ///             Some(name) => println!("Current database version is: {}", name),
-    ///             None => println!("Database is empty, no migrations applied yet.")
-    ///         };
-    ///     },
-    ///     Err(error) => {
-    ///         println!("Error fetching schema version: {}", error);
-    ///     }
-    /// };
+    /// # let migration_list: Vec<Box<Migration>> = vec![];
+    /// let migrations = MigrationIndex::new(migration_list);
+    /// match migrations.run_in_transactions(&connection) {
+    ///     Ok(_) => println!("All outstanding database migrations have been applied."),
+    ///     Err(error) => println!("Error updating database structure: {}", error)
+    /// }
     /// # };
     /// # }
-    ///
     /// ```
-    pub fn schema_version(
-        connection: &GenericConnection
-    ) -> postgres::Result<Option<String>> {
-        let prepared_stmt = try!(connection.prepare(
-            "SELECT column_name FROM information_schema.columns
-            WHERE table_name=$1 LIMIT 1"
-        ));
-        let result = try!(prepared_stmt.query(&[&"schema_version"]));
-        match result.len() {
-            0 => Ok(None),
-            1 => {
-                let version_string: String = try!(result.get(0).get_opt(0).unwrap());
-                Ok(Some(version_string))
-            },
-            _ => panic!(
-                    "Failed to retrieve current database schema version. The query to get column name \
-                    for version tracking table returned multiple rows."
-            )
-        }
-    }
-
-    /// Takes the current version of the database's schema and returns a slice containing all
-    /// migrations not yet applied to the database, in order from first to last.
-    fn outstanding_migrations(&self, current_version: Option<String>) -> &[Box<Migration>] {
-        match current_version {
-            Some(current_version) => {
-                 match self.current_index(&current_version) {
-                    Some(current_index) => {
-                        &self.migrations[(current_index + 1)..]
-                    }
-                    None => {
-                        &*self.migrations
-                    }
-                }
-            }
-            None => &*self.migrations
-        }
+    pub fn run_in_transactions(&self, connection: &Connection) -> Result<MigrationReport> {
+        self.run_each_in_transaction(connection, None)
     }
 
-    /// Takes the current version of the database's schema and returns the index of the migrations
-    /// field corresponding to the last applied database migration. Returns None if no migrations
-    /// have been applied to the database yet.
-    fn current_index(&self, current_version: &str) -> Option<usize> {
-        self.migrations.iter().position(|ref migration| {
-            migration.to_string() == *current_version
-        })
+    /// Runs the database migrations that haven't yet been applied to the database, up to and
+    /// including the migration with the given version, each in its own transaction, as
+    /// `run_in_transactions()` does, leaving the ones after it outstanding. Repeatable migrations
+    /// aren't run.
+    ///
+    /// # Failures
+    ///
+    /// Returns a `Validation` error if no registered migration has the version, or an error if a
+    /// problem occurred when communicating with the database. Migrations applied before a failure
+    /// stay applied.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # extern crate postgres;
+    /// # extern crate trek;
+    /// # fn main() {
+    /// # use postgres::{Connection, TlsMode};
+    /// # use trek::migration_index::MigrationIndex;
+    /// # use trek::migration::Migration;
+    /// # use trek::migration_version::MigrationVersion;
+    /// let connection = Connection::connect("server url", TlsMode::None).unwrap();
+    /// # let migration_list: Vec<Box<Migration>> = vec![];
+    /// let migrations = MigrationIndex::new(migration_list);
+    /// let target = MigrationVersion::new(20150826001350);
+    /// migrations.run_in_transactions_to(&connection, target).unwrap();
+    /// # }
+    /// ```
+    pub fn run_in_transactions_to(
+        &self,
+        connection: &Connection,
+        target: MigrationVersion
+    ) -> Result<MigrationReport> {
+        let target_index = try!(self.target_index(target));
+        self.run_each_in_transaction(connection, Some(target_index))
+    }
+
+    /// Applies the outstanding migrations as `run_in_transactions()` does, up to and including
+    /// the one at the target index, if there is one, in which case the repeatable migrations
+    /// aren't run.
+    fn run_each_in_transaction(
+        &self,
+        connection: &Connection,
+        target_index: Option<usize>
+    ) -> Result<MigrationReport> {
+        let (outstanding_migrations, batch) = try!(
+            self.prepare_run(connection, &self.version_table)
+        );
+        let mut report = MigrationReport::new();
+        for migration in outstanding_migrations {
+            if target_index.is_some() && self.version_index(migration.version()) > target_index {
+                continue;
+            }
+            if !migration.transactional() {
+                try!(self.apply_migration(
+                    connection, &self.version_table, &**migration, batch, &mut report
+                ));
+                continue;
+            }
+            let transaction = try!(connection.transaction().with_context(|| {
+                format!("Failed to start transaction for migration {}", migration)
+            }));
+            try!(self.apply_migration(
+                &transaction, &self.version_table, &**migration, batch, &mut report
+            ));
+            try!(
+                transaction.commit()
+                    .with_context(|| format!("Failed to commit migration {}", migration))
+            );
+        }
+        if target_index.is_some() {
+            return Ok(report);
+        }
+        for migration in try!(self.changed_repeatable_migrations(connection, &self.version_table)) {
+            let transaction = try!(connection.transaction().with_context(|| {
+                format!("Failed to start transaction for migration {}", migration)
+            }));
+            try!(self.apply_repeatable_migration(&transaction, &self.version_table, &**migration));
+            try!(
+                transaction.commit()
+                    .with_context(|| format!("Failed to commit migration {}", migration))
+            );
+        }
+        Ok(report)
+    }
+
+    /// Runs all outstanding migrations once for each of the given database schemas, e.g. one per
+    /// tenant, with the schema set as the search_path and the schema's version tracked in a table
+    /// inside that schema. Each schema is migrated in its own transaction (or savepoint, if
+    /// `connection` is already a transaction), so a failure in one schema doesn't stop the others
+    /// from being migrated. The returned report lists the outcome for each schema.
+    ///
+    /// # Failures
+    ///
+    /// Returns an error if a problem occurred when communicating with the database outside of
+    /// migrating a specific schema, e.g. while saving or restoring the search_path.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # extern crate postgres;
+    /// # extern crate trek;
+    /// # fn main() {
+    /// # use postgres::{self, Connection, TlsMode};
+    /// # use trek::migration_index::MigrationIndex;
+    /// # use trek::migration::Migration;
+    /// # fn f() {
+    /// let connection = Connection::connect("server url", TlsMode::None).unwrap();
+    ///
+    /// # let migration_list: Vec<Box<Migration>> = vec![];
+    /// let migrations = MigrationIndex::new(migration_list);
+    /// let report = migrations.run_for_schemas(&connection, &["tenant_a", "tenant_b"]).unwrap();
+    /// for &(ref schema, ref error) in report.failures().iter() {
+    ///     println!("Failed to migrate schema {}: {}", schema, error);
+    /// }
+    /// # };
+    /// # }
+    /// ```
+    pub fn run_for_schemas(
+        &self,
+        connection: &GenericConnection,
+        schemas: &[&str]
+    ) -> Result<TenantReport> {
+        let original_search_path = try!(
+            MigrationIndex::search_path(connection)
+                .context("Error reading current search_path")
+        );
+        let mut report = TenantReport { results: vec![] };
+        for schema in schemas.iter() {
+            info!("Migrating schema {}", schema);
+            let result = self.run_in_schema(connection, schema);
+            if let Err(ref error) = result {
+                error!("Failed to migrate schema {}: {}", schema, error);
+            }
+            report.results.push((schema.to_string(), result));
+        }
+        try!(
+            MigrationIndex::set_search_path(connection, &original_search_path)
+                .context("Error restoring original search_path")
+        );
+        Ok(report)
+    }
+
+    /// Like `run_for_schemas()`, but discovers the schemas to migrate by running the given query,
+    /// which must return the schema names in its first column.
+    ///
+    /// # Failures
+    ///
+    /// Returns an error if the discovery query fails, or if a problem occurred when communicating
+    /// with the database outside of migrating a specific schema.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # extern crate postgres;
+    /// # extern crate trek;
+    /// # fn main() {
+    /// # use postgres::{self, Connection, TlsMode};
+    /// # use trek::migration_index::MigrationIndex;
+    /// # use trek::migration::Migration;
+    /// # fn f() {
+    /// let connection = Connection::connect("server url", TlsMode::None).unwrap();
+    ///
+    /// # let migration_list: Vec<Box<Migration>> = vec![];
+    /// let migrations = MigrationIndex::new(migration_list);
+    /// let report = migrations.run_for_discovered_schemas(
+    ///     &connection,
+    ///     "SELECT schema_name FROM information_schema.schemata WHERE schema_name LIKE 'tenant_%'"
+    /// ).unwrap();
+    /// println!("Migrated {} tenant schemas", report.successes().len());
+    /// # };
+    /// # }
+    /// ```
+    pub fn run_for_discovered_schemas(
+        &self,
+        connection: &GenericConnection,
+        query: &str
+    ) -> Result<TenantReport> {
+        let rows = try!(
+            connection.query(query, &[]).context("Error discovering schemas to migrate")
+        );
+        let schemas = rows.iter().map(|row| row.get(0)).collect::<Vec<String>>();
+        let schemas = schemas.iter().map(|schema| &**schema).collect::<Vec<&str>>();
+        self.run_for_schemas(connection, &schemas)
+    }
+
+    /// Applies outstanding migrations to a single schema inside a transaction, committing it only
+    /// if every migration succeeded.
+    fn run_in_schema(&self, connection: &GenericConnection, schema: &str) -> Result<()> {
+        let transaction = try!(
+            connection.transaction()
+                .with_context(|| format!("Failed to start transaction for schema {}", schema))
+        );
+        try!(
+            MigrationIndex::set_search_path(&transaction, &version_table::quote_identifier(schema))
+                .with_context(|| format!("Failed to set search_path to {}", schema))
+        );
+        let tenant_table = self.version_table.in_schema(schema);
+        for warning in try!(self.run_with_table(&transaction, &tenant_table)).warnings() {
+            warn!("Schema {}: {}", schema, warning);
+        }
+        try!(
+            transaction.commit()
+                .with_context(|| format!("Failed to commit migrations for schema {}", schema))
+        );
+        Ok(())
+    }
+
+    /// Returns the connection's current search_path setting.
+    fn search_path(connection: &GenericConnection) -> postgres::Result<String> {
+        let rows = try!(connection.query("SELECT current_setting('search_path');", &[]));
+        Ok(rows.get(0).get(0))
+    }
+
+    /// Sets the search_path for the rest of the current transaction.
+    fn set_search_path(connection: &GenericConnection, search_path: &str) -> postgres::Result<()> {
+        try!(connection.query("SELECT set_config('search_path', $1, true);", &[&search_path]));
+        Ok(())
+    }
+
+    /// Applies all outstanding migrations, tracking the schema version in the given table.
+    fn run_with_table(
+        &self,
+        connection: &GenericConnection,
+        version_table: &VersionTable
+    ) -> Result<MigrationReport> {
+        let (outstanding_migrations, batch) = try!(self.prepare_run(connection, version_table));
+        let mut report = MigrationReport::new();
+        for migration in outstanding_migrations {
+            try!(self.apply_migration(connection, version_table, &**migration, batch, &mut report));
+        }
+        for migration in try!(self.changed_repeatable_migrations(connection, version_table)) {
+            try!(self.apply_repeatable_migration(connection, version_table, &**migration));
+        }
+        Ok(report)
+    }
+
+    /// Returns the repeatable migrations whose checksums differ from the ones recorded in the
+    /// given table when they were last applied, including those never applied, in the order
+    /// they're applied.
+    fn changed_repeatable_migrations(
+        &self,
+        connection: &GenericConnection,
+        version_table: &VersionTable
+    ) -> Result<Vec<&Box<RepeatableMigration>>> {
+        let recorded_checksums = try!(version_table.repeatable_checksums(connection).with_phase(
+            MigrationPhase::VersionLookup,
+            || "Error reading checksums of applied repeatable migrations".to_owned()
+        ));
+        Ok(self.repeatable_migrations.iter()
+            .filter(|migration| {
+                recorded_checksums.get(&migration.to_string()) != Some(&migration.checksum())
+            })
+            .collect())
+    }
+
+    /// Applies the repeatable migration, recording its checksum in the given table.
+    fn apply_repeatable_migration(
+        &self,
+        connection: &GenericConnection,
+        version_table: &VersionTable,
+        migration: &RepeatableMigration
+    ) -> Result<()> {
+        let start_time = Instant::now();
+        if let Err(error) = migration.up(connection) {
+            error!("Repeatable migration {} failed: {}", migration, error);
+            return Err(Error::migration(
+                format!("Error applying repeatable migration {}", migration),
+                MigrationPhase::Up,
+                Some(migration.to_string()),
+                error
+            ));
+        }
+        let duration = start_time.elapsed();
+        try!(version_table.record_repeatable(
+            connection, &migration.to_string(), &migration.checksum(), duration
+        ).with_migration(
+            MigrationPhase::Bookkeeping,
+            migration,
+            || format!("Error recording repeatable migration {} as applied", migration)
+        ));
+        self.progress.on_event(&MigrationEvent::RepeatableApplied(migration, duration));
+        Ok(())
+    }
+
+    /// Checks that migrations can be run with the given table, and returns the outstanding
+    /// migrations, in order from first to last, along with the number of the batch they'll be
+    /// applied in.
+    fn prepare_run(
+        &self,
+        connection: &GenericConnection,
+        version_table: &VersionTable
+    ) -> Result<(Vec<&Box<Migration>>, u64)> {
+        try!(self.upgrade_legacy_table(connection, version_table));
+        try!(self.ensure_clean(connection, version_table));
+        let mut applied_versions = try!(self.applied_versions(connection, version_table));
+        try!(self.ensure_registered(connection, version_table, &applied_versions));
+        try!(self.record_squashed(connection, version_table, &mut applied_versions));
+        try!(self.verify_checksums(
+            connection, version_table, &self.applied_migrations(&applied_versions)
+        ));
+        let batch = self.retry_bookkeeping(connection, |connection| {
+            version_table.next_batch(connection)
+        });
+        let batch = try!(batch.with_phase(
+            MigrationPhase::VersionLookup,
+            || "Error reading the latest batch of applied migrations".to_owned()
+        ));
+        Ok((self.outstanding_migrations(&applied_versions), batch))
+    }
+
+    /// Applies the migration as part of the given batch, recording it in the given table and
+    /// adding it and any warnings it leaves to the report, unless it doesn't apply to the
+    /// configured environment or decides it shouldn't run, in which case it's recorded as
+    /// skipped instead, or it's a contract migration that has to wait, in which case it's left
+    /// outstanding.
+    fn apply_migration(
+        &self,
+        connection: &GenericConnection,
+        version_table: &VersionTable,
+        migration: &Migration,
+        batch: u64,
+        report: &mut MigrationReport
+    ) -> Result<()> {
+        let deferral = try!(self.contract_deferral(connection, version_table, migration, batch));
+        if let Some(reason) = deferral {
+            self.progress.on_event(&MigrationEvent::Deferred(migration, &reason));
+            report.deferred.push(migration.to_string());
+            return Ok(());
+        }
+        let context = MigrationContext::new(connection, &self.options, &migration.to_string());
+        let should_run = if self.options.applies_to(migration) {
+            migration.should_run(&context)
+        } else {
+            Ok(false)
+        };
+        let should_run = match should_run {
+            Ok(should_run) => should_run,
+            Err(error) => {
+                let statement = MigrationIndex::failed_statement(&context, &error);
+                return Err(Error::migration(
+                    format!("Error checking whether migration {} should run", migration),
+                    MigrationPhase::ShouldRun,
+                    Some(migration.to_string()),
+                    error
+                ).with_statement(statement));
+            }
+        };
+        if !should_run {
+            let recorded = self.retry_bookkeeping(connection, |connection| {
+                version_table.record_skipped(connection, migration)
+            });
+            try!(recorded.with_migration(
+                MigrationPhase::Bookkeeping,
+                migration,
+                || format!("Error recording migration {} as skipped", migration)
+            ));
+            self.progress.on_event(&MigrationEvent::Skipped(migration));
+            self.report_warnings(migration, &context, report);
+            report.skipped.push(migration.to_string());
+            return Ok(());
+        }
+        self.progress.on_event(&MigrationEvent::Running(migration));
+        let start_time = Instant::now();
+        try!(MigrationIndex::call_hook(&self.before_each, "before_each", connection, migration));
+        // recorded as dirty until it's finished, in case it fails partway outside of a
+        // transaction
+        let recorded = self.retry_bookkeeping(connection, |connection| {
+            version_table.record_started(connection, migration, batch)
+        });
+        try!(recorded.with_migration(
+            MigrationPhase::Bookkeeping,
+            migration,
+            || format!("Error recording migration {} as started", migration)
+        ));
+        let previous_settings = try!(self.set_timeouts(connection, migration));
+        let result = self.apply_up(connection, migration, &context);
+        let restored = MigrationIndex::restore_settings(connection, migration, previous_settings);
+        self.report_warnings(migration, &context, report);
+        if let Err(error) = result {
+            error!("Migration {} failed: {}", migration, error);
+            let statement = MigrationIndex::failed_statement(&context, &error);
+            return Err(Error::migration(
+                format!("Error applying migration {}", migration),
+                MigrationPhase::Up,
+                Some(migration.to_string()),
+                error
+            ).with_statement(statement));
+        }
+        try!(restored.with_migration(
+            MigrationPhase::Timeouts,
+            migration,
+            || format!("Error restoring timeouts after migration {}", migration)
+        ));
+        try!(MigrationIndex::call_hook(&self.after_each, "after_each", connection, migration));
+        let duration = start_time.elapsed();
+        let recorded = self.retry_bookkeeping(connection, |connection| {
+            version_table.record_finished(connection, migration.version(), duration)
+        });
+        try!(recorded.with_migration(
+            MigrationPhase::Bookkeeping,
+            migration,
+            || format!("Error recording migration {} as applied", migration)
+        ));
+        let cleared = self.retry_bookkeeping(connection, |connection| {
+            version_table.clear_skipped(connection, migration.version())
+        });
+        try!(cleared.with_migration(
+            MigrationPhase::Bookkeeping,
+            migration,
+            || format!("Error clearing the record of migration {} being skipped", migration)
+        ));
+
+        self.progress.on_event(&MigrationEvent::Finished(migration, duration));
+        report.applied.push(migration.to_string());
+        Ok(())
+    }
+
+    /// Applies the migration with `up()`, retrying it by its retry policy, if it has one (see
+    /// `RunOptions::retry_policy_for()`), with a new context for each attempt.
+    fn apply_up(
+        &self,
+        connection: &GenericConnection,
+        migration: &Migration,
+        context: &MigrationContext
+    ) -> postgres::Result<()> {
+        let policy = match self.options.retry_policy_for(migration) {
+            Some(policy) => policy,
+            None => return migration.up(context)
+        };
+        let name = migration.to_string();
+        let transactional = migration.transactional();
+        MigrationIndex::with_retries(connection, policy, transactional, |connection| {
+            let attempt = MigrationContext::new(connection, &self.options, &name);
+            let result = migration.up(&attempt);
+            context.adopt_attempt(&attempt, result.is_ok());
+            result
+        })
+    }
+
+    /// Runs an operation on the migration history, retrying it by the retry policy, if one is
+    /// set (see `RunOptions::bookkeeping_retry_policy()`).
+    fn retry_bookkeeping<T, F>(
+        &self,
+        connection: &GenericConnection,
+        operation: F
+    ) -> postgres::Result<T>
+        where F: Fn(&GenericConnection) -> postgres::Result<T>
+    {
+        match self.options.bookkeeping_retry_policy() {
+            Some(policy) => MigrationIndex::with_retries(connection, policy, true, operation),
+            None => operation(connection)
+        }
+    }
+
+    /// Runs the operation, retrying it by the policy while it fails with a transient database
+    /// error (see `Error::is_retryable()`) other than the connection being lost. If
+    /// `transactional`, each attempt runs in a savepoint, or a transaction if `connection` isn't
+    /// one, so that a failed attempt is rolled back before the next.
+    fn with_retries<T, F>(
+        connection: &GenericConnection,
+        policy: &RetryPolicy,
+        transactional: bool,
+        operation: F
+    ) -> postgres::Result<T>
+        where F: Fn(&GenericConnection) -> postgres::Result<T>
+    {
+        let mut attempt = 1;
+        loop {
+            let result = if transactional {
+                let savepoint = try!(connection.transaction());
+                let result = operation(&savepoint);
+                if result.is_ok() {
+                    try!(savepoint.commit());
+                }
+                result
+            } else {
+                operation(connection)
+            };
+            match result {
+                Err(ref error) if attempt < policy.max_attempts() && is_transient(error) &&
+                    !is_connection_lost(error) =>
+                {
+                    let backoff = policy.backoff(attempt);
+                    warn!(
+                        "Attempt {} failed with a transient error, retrying in {}ms: {}",
+                        attempt,
+                        version_table::duration_to_millis(backoff),
+                        error
+                    );
+                    thread::sleep(backoff);
+                    attempt += 1;
+                },
+                result => return result
+            }
+        }
+    }
+
+    /// Returns the reason a contract migration can't be applied yet, or None if it can, or isn't
+    /// a contract migration. See `Migration::contracts()`. An expand migration covered by the
+    /// baseline left by pruning the history was applied long enough ago.
+    fn contract_deferral(
+        &self,
+        connection: &GenericConnection,
+        version_table: &VersionTable,
+        migration: &Migration,
+        batch: u64
+    ) -> Result<Option<String>> {
+        let expand_version = match migration.contracts() {
+            Some(expand_version) => expand_version,
+            None => return Ok(None)
+        };
+        let result = version_table.history(connection).and_then(|history| {
+            let baseline_version = try!(version_table.baseline_version(connection));
+            Ok((history, baseline_version))
+        });
+        let (history, baseline_version) = try!(result.with_migration(
+            MigrationPhase::VersionLookup,
+            migration,
+            || format!("Error reading the history of the expand migration {}", expand_version)
+        ));
+        let expand = match history.iter().find(|entry| entry.version() == expand_version) {
+            Some(expand) => expand,
+            None => {
+                return Ok(match baseline_version {
+                    Some(baseline_version) if expand_version <= baseline_version => None,
+                    _ => Some(format!("its expand migration {} isn't applied", expand_version))
+                });
+            }
+        };
+        let same_release = match version_table.deploy_id() {
+            Some(deploy_id) => expand.deploy_id() == Some(deploy_id),
+            None => expand.batch() == Some(batch)
+        };
+        if same_release {
+            return Ok(Some(format!(
+                "its expand migration {} was applied by this release",
+                expand.name()
+            )));
+        }
+        if let Some(delay) = self.options.contract_delay_for(migration) {
+            let elapsed = UTC::now() - expand.applied_at();
+            let delay_millis = version_table::duration_to_millis(delay) as i64;
+            if elapsed.num_milliseconds() < delay_millis {
+                return Ok(Some(format!(
+                    "its expand migration {} was applied at {}, less than {}ms ago",
+                    expand.name(),
+                    expand.applied_at(),
+                    delay_millis
+                )));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Passes the warnings the migration left in its context to the progress observer and adds
+    /// them to the report.
+    fn report_warnings(
+        &self,
+        migration: &Migration,
+        context: &MigrationContext,
+        report: &mut MigrationReport
+    ) {
+        for message in context.warnings() {
+            self.progress.on_event(&MigrationEvent::Warning(migration, &message));
+            report.warnings.push(MigrationWarning {
+                migration: migration.to_string(),
+                message: message,
+            });
+        }
+    }
+
+    /// Rolls back the last database migration that was successfully applied to the database. If
+    /// that migration is no longer registered, the down SQL recorded when it was applied is run
+    /// instead (see `MigrationIndexBuilder::store_down_sql()`).
+    ///
+    /// # Failures
+    ///
+    /// Returns an error if a problem occurred when communicating with the database, or if the
+    /// last applied migration isn't registered and its down SQL wasn't recorded.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # extern crate postgres;
+    /// # extern crate trek;
+    /// # fn main() {
+    /// # use postgres::{self, Connection, TlsMode};
+    /// # use postgres::transaction::Transaction;
+    /// # use trek::migration_index::MigrationIndex;
+    /// # use trek::migration::Migration;
+    /// # fn f() -> postgres::Result<()>  {
+    /// let connection = Connection::connect("server url", TlsMode::None).unwrap();
+    /// let transaction = connection.transaction().unwrap();
+    ///
+    /// # let migration_list: Vec<Box<Migration>> = vec![];
+    /// let migrations = MigrationIndex::new(migration_list);
+    /// match migrations.rollback(&transaction) {
+    ///     Ok(_) => {
+    ///         try!(transaction.commit());
+    ///         println!("Rollback of latest migration complete.");
+    ///     },
+    ///     Err(error) => {
+    ///         // note: no need to manually roll back the transaction, it'll automatically roll
+    ///         // back when the transaction variable goes out of scope
+    ///
+    ///         println!("Error error rolling back last applied migration: {}", error);
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # };
+    /// # }
+    ///
+    /// ```
+    pub fn rollback(&self, connection: &GenericConnection) -> Result<()> {
+        try!(self.upgrade_legacy_table(connection, &self.version_table));
+        try!(self.ensure_clean(connection, &self.version_table));
+        let head = try!(self.version_table.head(connection).with_phase(
+            MigrationPhase::VersionLookup,
+            || "Failed to get current database schema version".to_owned()
+        ));
+        let (head_version, head_name) = match head {
+            Some(head) => head,
+            None => {
+                // if there's nothing to roll back, this function call is a no-op
+                warn!("No migrations have been applied, there is nothing to roll back");
+                return Ok(());
+            }
+        };
+        if self.get_version(head_version).is_none() {
+            return self.roll_back_migration(connection, head_version, &head_name);
+        }
+        // a migration's dependencies can have higher versions than it, so the registered
+        // migration applied last in dependency order is rolled back, not necessarily the head
+        let applied_versions = try!(self.version_table.applied_versions(connection).with_phase(
+            MigrationPhase::VersionLookup,
+            || "Error reading the list of applied migrations".to_owned()
+        ));
+        let last_applied = self.migrations.iter().rev()
+            .find(|migration| applied_versions.contains(&migration.version()))
+            .unwrap();
+        self.roll_back_migration(connection, last_applied.version(), &last_applied.to_string())
+    }
+
+    /// Rolls back every migration applied after the migration with the given version, from the
+    /// last to the first, leaving it and the ones before it applied. Applied migrations that are
+    /// no longer registered and have later versions are rolled back first, with their recorded
+    /// down SQL, as with `rollback()`.
+    ///
+    /// # Failures
+    ///
+    /// Returns a `Validation` error if no registered migration has the version, or an error if a
+    /// problem occurred when communicating with the database or a migration after it can't be
+    /// rolled back. Migrations rolled back before the failure stay rolled back unless
+    /// `connection` is a transaction that's then dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # extern crate postgres;
+    /// # extern crate trek;
+    /// # fn main() {
+    /// # use postgres::{Connection, TlsMode};
+    /// # use trek::migration_index::MigrationIndex;
+    /// # use trek::migration::Migration;
+    /// # use trek::migration_version::MigrationVersion;
+    /// let connection = Connection::connect("server url", TlsMode::None).unwrap();
+    /// let transaction = connection.transaction().unwrap();
+    /// # let migration_list: Vec<Box<Migration>> = vec![];
+    /// let migrations = MigrationIndex::new(migration_list);
+    /// migrations.rollback_to(&transaction, MigrationVersion::new(20150826001350)).unwrap();
+    /// transaction.commit().unwrap();
+    /// # }
+    /// ```
+    pub fn rollback_to(
+        &self,
+        connection: &GenericConnection,
+        target: MigrationVersion
+    ) -> Result<()> {
+        let target_index = try!(self.target_index(target));
+        try!(self.upgrade_legacy_table(connection, &self.version_table));
+        try!(self.ensure_clean(connection, &self.version_table));
+        let history = try!(self.version_table.history(connection).with_phase(
+            MigrationPhase::VersionLookup,
+            || "Error reading the migration history".to_owned()
+        ));
+        let mut unregistered = vec![];
+        let mut registered = vec![];
+        for entry in &history {
+            match self.version_index(entry.version()) {
+                Some(index) if index > target_index => registered.push((index, entry)),
+                None if entry.version() > target => unregistered.push(entry),
+                _ => {}
+            }
+        }
+        if unregistered.is_empty() && registered.is_empty() {
+            warn!("No migrations after {} are applied, there is nothing to roll back", target);
+            return Ok(());
+        }
+        // migrations that are no longer registered were most likely applied by a newer release,
+        // so they're rolled back before the registered ones, dependents before dependencies
+        unregistered.sort_by_key(|entry| Reverse(entry.version()));
+        registered.sort_by_key(|&(index, _)| Reverse(index));
+        let registered = registered.into_iter().map(|(_, entry)| entry);
+        for entry in unregistered.into_iter().chain(registered) {
+            try!(self.roll_back_migration(connection, entry.version(), entry.name()));
+        }
+        Ok(())
+    }
+
+    /// Rolls back every migration applied by the latest call to `run()` that applied any, from
+    /// the last to the first, so that a release's migrations can be undone together. Migrations
+    /// recorded as applied by `mark_applied()` or `baseline()` aren't part of any batch. If a
+    /// migration that's no longer registered needs to be rolled back, its recorded down SQL is
+    /// run instead, as with `rollback()`.
+    ///
+    /// # Failures
+    ///
+    /// Returns an error if a problem occurred when communicating with the database, or if a
+    /// migration in the batch can't be rolled back. Migrations rolled back before the failure
+    /// stay rolled back unless `connection` is a transaction that's then dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # extern crate postgres;
+    /// # extern crate trek;
+    /// # fn main() {
+    /// # use postgres::{self, Connection, TlsMode};
+    /// # use trek::migration_index::MigrationIndex;
+    /// # use trek::migration::Migration;
+    /// # fn f() -> postgres::Result<()>  {
+    /// let connection = Connection::connect("server url", TlsMode::None).unwrap();
+    /// let transaction = connection.transaction().unwrap();
+    ///
+    /// # let migration_list: Vec<Box<Migration>> = vec![];
+    /// let migrations = MigrationIndex::new(migration_list);
+    /// match migrations.rollback_last_batch(&transaction) {
+    ///     Ok(_) => {
+    ///         try!(transaction.commit());
+    ///         println!("Rollback of the latest release's migrations complete.");
+    ///     },
+    ///     Err(error) => {
+    ///         println!("Error rolling back the latest batch of migrations: {}", error);
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # };
+    /// # }
+    /// ```
+    pub fn rollback_last_batch(&self, connection: &GenericConnection) -> Result<()> {
+        try!(self.upgrade_legacy_table(connection, &self.version_table));
+        try!(self.ensure_clean(connection, &self.version_table));
+        let batch = try!(self.version_table.last_batch(connection).with_phase(
+            MigrationPhase::VersionLookup,
+            || "Error reading the latest batch of applied migrations".to_owned()
+        ));
+        if batch.is_empty() {
+            warn!("No batch of migrations has been applied, there is nothing to roll back");
+            return Ok(());
+        }
+        // dependents are rolled back before the migrations they depend on, followed by any
+        // migrations that are no longer registered
+        let mut batch = batch;
+        batch.sort_by_key(|&(version, _)| Reverse(self.version_index(version)));
+        for (version, name) in batch {
+            try!(self.roll_back_migration(connection, version, &name));
+        }
+        Ok(())
+    }
+
+    /// Rolls back the applied migration with the given version and recorded name, using its
+    /// recorded down SQL if it's no longer registered.
+    fn roll_back_migration(
+        &self,
+        connection: &GenericConnection,
+        old_version: MigrationVersion,
+        old_name: &str
+    ) -> Result<()> {
+        let baseline_version = try!(self.version_table.baseline_version(connection).with_phase(
+            MigrationPhase::VersionLookup,
+            || "Error reading the baseline of the migration history".to_owned()
+        ));
+        if baseline_version == Some(old_version) {
+            return Err(Error::from_message(format!(
+                "Can't roll back migration {}: the history of the migrations before it was \
+                pruned, so the schema version after rolling it back is unknown",
+                old_name
+            )));
+        }
+        let recorded_migration;
+        let old_migration = match self.get_version(old_version) {
+            Some(migration) => migration,
+            None => {
+                // the migration's code is gone, but it can still be undone if its down SQL was
+                // recorded when it was applied
+                let recorded = try!(
+                    self.version_table.recorded_migration(connection, old_version).with_migration(
+                        MigrationPhase::VersionLookup,
+                        old_name,
+                        || format!("Error reading recorded down SQL of {}", old_name)
+                    )
+                );
+                match recorded {
+                    Some(migration) => {
+                        recorded_migration = migration;
+                        &recorded_migration as &Migration
+                    },
+                    None => {
+                        return Err(Error::from_message(format!(
+                            "Can't roll back migration {}: it isn't registered with this \
+                            migration index and its down SQL wasn't recorded",
+                            old_name
+                        )));
+                    }
+                }
+            }
+        };
+        self.progress.on_event(&MigrationEvent::RollingBack(old_migration));
+        let start_time = Instant::now();
+        try!(MigrationIndex::call_hook(
+            &self.before_each, "before_each", connection, old_migration
+        ));
+        let context = MigrationContext::new(connection, &self.options, &old_migration.to_string());
+        let previous_settings = try!(self.set_timeouts(connection, old_migration));
+        let result = old_migration.down(&context);
+        let restored = MigrationIndex::restore_settings(
+            connection, old_migration, previous_settings
+        );
+        for message in &context.warnings() {
+            self.progress.on_event(&MigrationEvent::Warning(old_migration, message));
+        }
+        if let Err(error) = result {
+            if let Some(irreversible) = IrreversibleMigrationError::from_error(&error) {
+                error!("Migration {} is irreversible", old_migration);
+                return Err(Error::Irreversible {
+                    message: format!(
+                        "Can't roll back migration {}: it's irreversible",
+                        old_migration
+                    ),
+                    cause: IrreversibleMigrationError::new(
+                        irreversible.migration_name().to_owned()
+                    ),
+                });
+            }
+            error!("Rolling back migration {} failed: {}", old_migration, error);
+            let statement = MigrationIndex::failed_statement(&context, &error);
+            return Err(Error::migration(
+                format!(
+                    "The down() method of database migration {} failed",
+                    old_migration,
+                ),
+                MigrationPhase::Down,
+                Some(old_migration.to_string()),
+                error
+            ).with_statement(statement));
+        }
+        try!(restored.with_migration(
+            MigrationPhase::Timeouts,
+            old_migration,
+            || format!("Error restoring timeouts after rolling back migration {}", old_migration)
+        ));
+        try!(MigrationIndex::call_hook(&self.after_each, "after_each", connection, old_migration));
+        try!(self.version_table.remove_applied(connection, old_version).with_migration(
+            MigrationPhase::Bookkeeping,
+            old_migration,
+            || format!(
+                "Failed to update the migration history when rolling back migration {}",
+                old_migration,
+            )
+        ));
+        let new_head = try!(self.version_table.head(connection).with_migration(
+            MigrationPhase::Bookkeeping,
+            old_migration,
+            || "Failed to get the database schema version after rolling back".to_owned()
+        ));
+        let new_migration = new_head.and_then(|(version, _)| self.get_version(version));
+        self.progress.on_event(&MigrationEvent::RolledBack(
+            old_migration, new_migration, start_time.elapsed()
+        ));
+        Ok(())
+    }
+
+    /// Records every migration up to and including the one with the given version as applied,
+    /// without running their `up()` methods. This is useful when adopting Trek on a database whose
+    /// schema already contains the changes made by those migrations. Migrations that have already
+    /// been applied are left untouched.
+    ///
+    /// # Failures
+    ///
+    /// Returns a `Validation` error if no registered migration has the version, or an error if a
+    /// problem occurred when communicating with the database.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # extern crate postgres;
+    /// # extern crate trek;
+    /// # fn main() {
+    /// # use postgres::{self, Connection, TlsMode};
+    /// # use trek::migration_index::MigrationIndex;
+    /// # use trek::migration::Migration;
+    /// # use trek::migration_version::MigrationVersion;
+    /// # fn f() -> postgres::Result<()>  {
+    /// let connection = Connection::connect("server url", TlsMode::None).unwrap();
+    /// let transaction = connection.transaction().unwrap();
+    ///
+    /// # let migration_list: Vec<Box<Migration>> = vec![];
+    /// let migrations = MigrationIndex::new(migration_list);
+    /// match migrations.mark_applied(&transaction, MigrationVersion::new(20150826001350)) {
+    ///     Ok(_) => {
+    ///         try!(transaction.commit());
+    ///         println!("Existing schema recorded as applied.");
+    ///     },
+    ///     Err(error) => {
+    ///         println!("Error recording migrations as applied: {}", error);
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # };
+    /// # }
+    ///
+    /// ```
+    pub fn mark_applied(
+        &self,
+        connection: &GenericConnection,
+        version: MigrationVersion
+    ) -> Result<()> {
+        let target_index = match self.version_index(version) {
+            Some(index) => index,
+            None => {
+                return Err(Error::Validation(ValidationError::new(format!(
+                    "Can't mark unknown migration version {} as applied",
+                    version
+                ))));
+            }
+        };
+        try!(self.upgrade_legacy_table(connection, &self.version_table));
+        try!(self.ensure_clean(connection, &self.version_table));
+        let applied_versions = try!(self.applied_versions(connection, &self.version_table));
+        let unapplied_migrations = self.migrations[..(target_index + 1)].iter()
+            .filter(|migration| !applied_versions.contains(&migration.version()))
+            .collect::<Vec<&Box<Migration>>>();
+        if unapplied_migrations.is_empty() {
+            // the target migration and everything before it is already applied, so there's
+            // nothing to record
+            warn!(
+                "Migration {} is already applied, not marking it again",
+                self.migrations[target_index]
+            );
+            return Ok(());
+        }
+        for migration in unapplied_migrations {
+            try!(MigrationIndex::record_applied(connection, &self.version_table, &**migration));
+        }
+
+        let target_migration = self.migrations.get(target_index).unwrap();
+        self.progress.on_event(&MigrationEvent::MarkedApplied(&**target_migration));
+        Ok(())
+    }
+
+    /// Initializes version tracking for a database that predates Trek, recording the migration
+    /// with the given version, and the ones before it, as applied. Subsequent calls to `run()` will
+    /// only apply the migrations that come after the baseline.
+    ///
+    /// # Failures
+    ///
+    /// Returns a `Validation` error if no registered migration has the version, or if the
+    /// database already has a schema version recorded, or an error if a problem occurred when
+    /// communicating with the database.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # extern crate postgres;
+    /// # extern crate trek;
+    /// # fn main() {
+    /// # use postgres::{self, Connection, TlsMode};
+    /// # use trek::migration_index::MigrationIndex;
+    /// # use trek::migration::Migration;
+    /// # use trek::migration_version::MigrationVersion;
+    /// # fn f() -> postgres::Result<()>  {
+    /// let connection = Connection::connect("server url", TlsMode::None).unwrap();
+    /// let transaction = connection.transaction().unwrap();
+    ///
+    /// # let migration_list: Vec<Box<Migration>> = vec![];
+    /// let migrations = MigrationIndex::new(migration_list);
+    /// match migrations.baseline(&transaction, MigrationVersion::new(20151008562095)) {
+    ///     Ok(_) => {
+    ///         try!(transaction.commit());
+    ///         println!("Version tracking initialized.");
+    ///     },
+    ///     Err(error) => {
+    ///         println!("Error initializing version tracking: {}", error);
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # };
+    /// # }
+    ///
+    /// ```
+    pub fn baseline(
+        &self,
+        connection: &GenericConnection,
+        version: MigrationVersion
+    ) -> Result<()> {
+        let baseline_index = match self.version_index(version) {
+            Some(index) => index,
+            None => {
+                return Err(Error::Validation(ValidationError::new(format!(
+                    "Can't baseline database at unknown migration version {}",
+                    version
+                ))));
+            }
+        };
+        try!(self.upgrade_legacy_table(connection, &self.version_table));
+        let schema_version = try!(
+            self.schema_version(connection).context("Error reading current schema version")
+        );
+        if let Some(schema_version) = schema_version {
+            return Err(Error::Validation(ValidationError::new(format!(
+                "Can't baseline database at migration {}: version tracking already exists and is \
+                at migration {}",
+                self.migrations[baseline_index],
+                schema_version
+            ))));
+        }
+        for migration in self.migrations[..(baseline_index + 1)].iter() {
+            try!(MigrationIndex::record_applied(connection, &self.version_table, &**migration));
+        }
+
+        let baseline_migration = self.migrations.get(baseline_index).unwrap();
+        self.progress.on_event(&MigrationEvent::Baselined(&**baseline_migration));
+        Ok(())
+    }
+
+    /// Clears the dirty state left by a migration that failed partway through being applied outside
+    /// of a transaction, once the database has been repaired manually. `version` is the version
+    /// the database is at after the repair: if the dirty migration's version is at most
+    /// `version`, it's recorded as cleanly applied, otherwise it's recorded as not applied so the
+    /// next call to `run()` applies it again. While a migration is dirty, `run()`, `rollback()`
+    /// and `mark_applied()` refuse to make changes.
+    ///
+    /// # Failures
+    ///
+    /// Returns an error if a problem occurred when communicating with the database.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # extern crate postgres;
+    /// # extern crate trek;
+    /// # fn main() {
+    /// # use postgres::{self, Connection, TlsMode};
+    /// # use trek::migration_index::MigrationIndex;
+    /// # use trek::migration::Migration;
+    /// # use trek::migration_version::MigrationVersion;
+    /// # fn f() {
+    /// let connection = Connection::connect("server url", TlsMode::None).unwrap();
+    ///
+    /// # let migration_list: Vec<Box<Migration>> = vec![];
+    /// let migrations = MigrationIndex::new(migration_list);
+    /// // the failed migration's changes were completed by hand
+    /// migrations.force_clean(&connection, MigrationVersion::new(20151008562095)).unwrap();
+    /// # };
+    /// # }
+    /// ```
+    pub fn force_clean(
+        &self,
+        connection: &GenericConnection,
+        version: MigrationVersion
+    ) -> Result<()> {
+        let dirty_version = try!(self.version_table.dirty_version(connection).with_phase(
+            MigrationPhase::VersionLookup,
+            || "Error checking for partially applied migrations".to_owned()
+        ));
+        let dirty_version = match dirty_version {
+            Some(dirty_version) => dirty_version,
+            None => {
+                warn!("No migration is dirty, there is nothing to clean");
+                return Ok(());
+            }
+        };
+        let result = if dirty_version <= version {
+            info!("Recording dirty migration {} as applied", dirty_version);
+            self.version_table.mark_clean(connection, dirty_version)
+        } else {
+            info!("Recording dirty migration {} as not applied", dirty_version);
+            self.version_table.remove_applied(connection, dirty_version)
+        };
+        try!(result.with_context(|| {
+            format!("Error clearing the dirty state of migration {}", dirty_version)
+        }));
+        Ok(())
+    }
+
+    /// Takes a queryable connection object and returns the current version of the database's
+    /// schema, i.e. the name of the applied migration with the highest version. The registered
+    /// migration's current name is returned if there is one, otherwise the name recorded when it
+    /// was applied. No changes are made to the database: if the version is still recorded in the
+    /// format used by earlier versions of Trek, it's read from there.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # extern crate postgres;
+    /// # extern crate trek;
+    /// # fn main() {
+    /// # use postgres::{self, Connection, TlsMode};
+    /// # use postgres::transaction::Transaction;
+    /// # use trek::migration_index::MigrationIndex;
+    /// # use trek::migration::Migration;
+    /// # fn f() {
+    /// let connection = Connection::connect("server url", TlsMode::None).unwrap();
+    ///
+    /// # let migration_list: Vec<Box<Migration>> = vec![];
+    /// let migrations = MigrationIndex::new(migration_list);
+    /// match migrations.schema_version(&connection) {
+    ///     Ok(result_option) => {
+    ///         match result_option {
+    ///             Some(name) => println!("Current database version is: {}", name),
+    ///             None => println!("Database is empty, no migrations applied yet.")
+    ///         };
+    ///     },
+    ///     Err(error) => {
+    ///         println!("Error fetching schema version: {}", error);
+    ///     }
+    /// };
+    /// # };
+    /// # }
+    ///
+    /// ```
+    pub fn schema_version(
+        &self,
+        connection: &GenericConnection
+    ) -> postgres::Result<Option<String>> {
+        match try!(self.version_table.head(connection)) {
+            Some((version, recorded_name)) => {
+                Ok(Some(
+                    self.get_version(version)
+                        .map(|migration| migration.to_string())
+                        .unwrap_or(recorded_name)
+                ))
+            },
+            None => self.version_table.legacy_version(connection)
+        }
+    }
+
+    /// Returns the highest version recorded as applied to the database, if any, whether or not
+    /// it's a registered migration. Migrations with lower versions that haven't been applied yet
+    /// are applied out of order, or skipped if the history was pruned past them, so
+    /// `MigrationGenerator::latest_applied_version()` uses this to warn about new migrations
+    /// that would sort before it.
+    ///
+    /// # Failures
+    ///
+    /// Returns an error if a problem occurred when communicating with the database.
+    pub fn latest_applied_version(
+        &self,
+        connection: &GenericConnection
+    ) -> postgres::Result<Option<MigrationVersion>> {
+        Ok(try!(self.version_table.head(connection)).map(|(version, _)| version))
+    }
+
+    /// Returns the table applied migrations are recorded in.
+    pub(crate) fn version_table(&self) -> &VersionTable {
+        &self.version_table
+    }
+
+    /// Returns every migration applied to the database, in the order they were applied, along
+    /// with when and by whom each was applied, how long it took, and its checksum. No changes are
+    /// made to the database.
+    ///
+    /// # Failures
+    ///
+    /// Returns an error if a problem occurred when communicating with the database.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # extern crate postgres;
+    /// # extern crate trek;
+    /// # fn main() {
+    /// # use postgres::{self, Connection, TlsMode};
+    /// # use trek::migration_index::MigrationIndex;
+    /// # use trek::migration::Migration;
+    /// # fn f() {
+    /// let connection = Connection::connect("server url", TlsMode::None).unwrap();
+    ///
+    /// # let migration_list: Vec<Box<Migration>> = vec![];
+    /// let migrations = MigrationIndex::new(migration_list);
+    /// for applied_migration in migrations.history(&connection).unwrap() {
+    ///     match applied_migration.duration() {
+    ///         Some(duration) => println!(
+    ///             "{} applied at {} in {}s",
+    ///             applied_migration.name(),
+    ///             applied_migration.applied_at(),
+    ///             duration.as_secs()
+    ///         ),
+    ///         None => println!(
+    ///             "{} recorded as applied at {}",
+    ///             applied_migration.name(),
+    ///             applied_migration.applied_at()
+    ///         )
+    ///     }
+    /// }
+    /// # };
+    /// # }
+    /// ```
+    pub fn history(
+        &self,
+        connection: &GenericConnection
+    ) -> postgres::Result<Vec<AppliedMigration>> {
+        self.version_table.history(connection)
+    }
+
+    /// Runs every registered seed that applies to the configured environment (see
+    /// `Seed::environments()`), in the order they were registered, recording when each ran. Seeds
+    /// are tracked separately from migrations, so this can be called as often as needed, e.g.
+    /// after every `run()`; since seeds run every time, they must be idempotent. The returned
+    /// report lists the seeds that ran or were skipped, and any warnings they left.
+    ///
+    /// # Failures
+    ///
+    /// Returns an error if a problem occurred when communicating with the database.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # extern crate postgres;
+    /// # extern crate trek;
+    /// # fn main() {
+    /// # use postgres::{self, Connection, TlsMode};
+    /// # use trek::migration_context::RunOptions;
+    /// # use trek::migration_index::MigrationIndex;
+    /// # use trek::seed::SqlSeed;
+    /// # fn f() -> postgres::Result<()>  {
+    /// let connection = Connection::connect("server url", TlsMode::None).unwrap();
+    /// let transaction = connection.transaction().unwrap();
+    ///
+    /// let migrations = MigrationIndex::builder()
+    ///     .seed(SqlSeed::new(
+    ///         "demo_users",
+    ///         "INSERT INTO users (username) VALUES ('demo') ON CONFLICT DO NOTHING;"
+    ///     ).with_environments(&["development"]))
+    ///     .run_options(RunOptions::new().environment("development"))
+    ///     .build()
+    ///     .unwrap();
+    /// match migrations.run_seeds(&transaction) {
+    ///     Ok(report) => {
+    ///         try!(transaction.commit());
+    ///         println!("Loaded {} seeds.", report.applied().len());
+    ///     },
+    ///     Err(error) => {
+    ///         println!("Error loading seed data: {}", error);
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # };
+    /// # }
+    /// ```
+    pub fn run_seeds(&self, connection: &GenericConnection) -> Result<MigrationReport> {
+        let mut report = MigrationReport::new();
+        for seed in &self.seeds {
+            try!(self.run_seed_into_report(connection, &**seed, &mut report));
+        }
+        Ok(report)
+    }
+
+    /// Runs the registered seeds that apply to the configured environment and have never run on
+    /// the database, like `run_seeds()`, so seeds only load their data once unless they're run
+    /// again with `run_seed()`. Seeds that have run are left out of the report.
+    ///
+    /// # Failures
+    ///
+    /// Returns an error if a problem occurred when communicating with the database.
+    pub fn run_pending_seeds(&self, connection: &GenericConnection) -> Result<MigrationReport> {
+        let seed_runs = try!(
+            self.version_table.seed_runs(connection).context("Error reading when seeds ran")
+        );
+        let mut report = MigrationReport::new();
+        for seed in &self.seeds {
+            let name = seed.to_string();
+            if seed_runs.iter().all(|seed_run| seed_run.name() != name) {
+                try!(self.run_seed_into_report(connection, &**seed, &mut report));
+            }
+        }
+        Ok(report)
+    }
+
+    /// Runs the registered seed with the given name, or whose name is the given one prefixed by
+    /// its version, like "20150822094521_countries" for "countries", whether or not it's run
+    /// before, reporting it as skipped if it doesn't apply to the configured environment.
+    ///
+    /// # Failures
+    ///
+    /// Returns a `Validation` error if no registered seed, or more than one, has the name, or an
+    /// error if a problem occurred when communicating with the database.
+    pub fn run_seed(&self, connection: &GenericConnection, name: &str) -> Result<MigrationReport> {
+        let seed = match self.seeds.iter().find(|seed| seed.to_string() == name) {
+            Some(seed) => seed,
+            None => {
+                let suffix = format!("_{}", name);
+                let matching = self.seeds.iter()
+                    .filter(|seed| seed.to_string().ends_with(&suffix))
+                    .collect::<Vec<_>>();
+                if matching.len() != 1 {
+                    let names = matching.iter().map(|seed| seed.to_string()).collect::<Vec<_>>();
+                    return Err(Error::from_message(format!(
+                        "Expected one seed named {}, found {}",
+                        name,
+                        if names.is_empty() { "none".to_owned() } else { names.join(", ") }
+                    )));
+                }
+                matching[0]
+            }
+        };
+        let mut report = MigrationReport::new();
+        try!(self.run_seed_into_report(connection, &**seed, &mut report));
+        Ok(report)
+    }
+
+    /// Runs the seed if it applies to the configured environment, recording when it ran, and adds
+    /// it to the report as run or skipped along with any warnings it left.
+    fn run_seed_into_report(
+        &self,
+        connection: &GenericConnection,
+        seed: &Seed,
+        report: &mut MigrationReport
+    ) -> Result<()> {
+        if !self.options.applies_to_seed(seed) {
+            debug!("Skipping seed {}, which doesn't apply to this environment", seed);
+            report.skipped.push(seed.to_string());
+            return Ok(());
+        }
+        let start_time = Instant::now();
+        let context = MigrationContext::new(connection, &self.options, &seed.to_string());
+        let result = seed.run(&context);
+        for message in context.warnings() {
+            warn!("Seed {} warned: {}", seed, message);
+            report.warnings.push(MigrationWarning {
+                migration: seed.to_string(),
+                message: message,
+            });
+        }
+        if let Err(error) = result {
+            error!("Seed {} failed: {}", seed, error);
+            return Err(Error::new(format!("Error running seed {}", seed), error));
+        }
+        let duration = start_time.elapsed();
+        try!(
+            self.version_table.record_seed(
+                connection, &seed.to_string(), self.options.environment_name(), duration
+            ).with_context(|| format!("Error recording seed {} as run", seed))
+        );
+        self.progress.on_event(&MigrationEvent::SeedRun(seed, duration));
+        report.applied.push(seed.to_string());
+        Ok(())
+    }
+
+    /// Returns when each seed last ran, in order of name. No changes are made to the database.
+    ///
+    /// # Failures
+    ///
+    /// Returns an error if a problem occurred when communicating with the database.
+    pub fn seed_history(&self, connection: &GenericConnection) -> postgres::Result<Vec<SeedRun>> {
+        self.version_table.seed_runs(connection)
+    }
+
+    /// Returns the migrations that were skipped because their `Migration::should_run()` returned
+    /// false and haven't been applied since, in order of version. No changes are made to the
+    /// database.
+    ///
+    /// # Failures
+    ///
+    /// Returns an error if a problem occurred when communicating with the database.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # extern crate postgres;
+    /// # extern crate trek;
+    /// # fn main() {
+    /// # use postgres::{self, Connection, TlsMode};
+    /// # use trek::migration_index::MigrationIndex;
+    /// # use trek::migration::Migration;
+    /// # fn f() {
+    /// let connection = Connection::connect("server url", TlsMode::None).unwrap();
+    ///
+    /// # let migration_list: Vec<Box<Migration>> = vec![];
+    /// let migrations = MigrationIndex::new(migration_list);
+    /// for skipped in migrations.skipped_migrations(&connection).unwrap() {
+    ///     println!("{} was last skipped at {}", skipped.name(), skipped.skipped_at());
+    /// }
+    /// # };
+    /// # }
+    /// ```
+    pub fn skipped_migrations(
+        &self,
+        connection: &GenericConnection
+    ) -> postgres::Result<Vec<SkippedMigration>> {
+        self.version_table.skipped(connection)
+    }
+
+    /// Returns the migration history as a JSON document, e.g. to attach to an incident report or
+    /// to seed another database's history with `import_history()`. No changes are made to the
+    /// database.
+    ///
+    /// The document is an object with two keys: "format_version", currently 1 (see
+    /// `history::EXPORT_FORMAT_VERSION`), and "migrations", an array holding an object for each
+    /// applied migration in the order they were applied. Each migration object has the keys
+    /// "version" (a number), "name" (a string), "applied_at" (an RFC 3339 timestamp),
+    /// "duration_ms" (a number or null), "description", "checksum", "applied_by", "application"
+    /// and "deploy_id" (strings or null), "batch" (a number or null), and "dirty" and "baseline"
+    /// (booleans).
+    ///
+    /// Only available with the `serde` feature enabled.
+    ///
+    /// # Failures
+    ///
+    /// Returns an error if a problem occurred when communicating with the database.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # extern crate postgres;
+    /// # extern crate trek;
+    /// # fn main() {
+    /// # use std::fs::File;
+    /// # use std::io::Write;
+    /// # use postgres::{self, Connection, TlsMode};
+    /// # use trek::migration_index::MigrationIndex;
+    /// # use trek::migration::Migration;
+    /// # fn f() {
+    /// let connection = Connection::connect("server url", TlsMode::None).unwrap();
+    ///
+    /// # let migration_list: Vec<Box<Migration>> = vec![];
+    /// let migrations = MigrationIndex::new(migration_list);
+    /// let history = migrations.export_history(&connection).unwrap();
+    /// let mut file = File::create("schema_history.json").unwrap();
+    /// file.write_all(history.as_bytes()).unwrap();
+    /// # };
+    /// # }
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn export_history(&self, connection: &GenericConnection) -> Result<String> {
+        let applied_migrations = try!(
+            self.version_table.history(connection).context("Error reading the migration history")
+        );
+        Ok(history::export(&applied_migrations))
+    }
+
+    /// Records the migrations in a JSON document produced by `export_history()` as applied,
+    /// keeping the times and details recorded when they were originally applied, without running
+    /// them. This is useful for seeding a database restored from another's schema, e.g. staging
+    /// from production. Migrations already recorded as applied are left untouched. The import
+    /// happens in its own transaction (or savepoint, if `connection` is already a transaction),
+    /// so either every migration is recorded or none are. Returns the number of migrations
+    /// recorded.
+    ///
+    /// Only available with the `serde` feature enabled.
+    ///
+    /// # Failures
+    ///
+    /// Returns an error if the document is malformed or in an unsupported format version, or if a
+    /// problem occurred when communicating with the database.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # extern crate postgres;
+    /// # extern crate trek;
+    /// # fn main() {
+    /// # use std::fs::File;
+    /// # use std::io::Read;
+    /// # use postgres::{self, Connection, TlsMode};
+    /// # use trek::migration_index::MigrationIndex;
+    /// # use trek::migration::Migration;
+    /// # fn f() {
+    /// let connection = Connection::connect("server url", TlsMode::None).unwrap();
+    ///
+    /// # let migration_list: Vec<Box<Migration>> = vec![];
+    /// let migrations = MigrationIndex::new(migration_list);
+    /// let mut history = String::new();
+    /// File::open("schema_history.json").unwrap().read_to_string(&mut history).unwrap();
+    /// let imported = migrations.import_history(&connection, &history).unwrap();
+    /// println!("Recorded {} migrations as applied.", imported);
+    /// # };
+    /// # }
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn import_history(&self, connection: &GenericConnection, document: &str) -> Result<usize> {
+        let applied_migrations = match history::import(document) {
+            Ok(applied_migrations) => applied_migrations,
+            Err(message) => {
+                return Err(Error::from_message(format!(
+                    "Can't import the migration history: {}",
+                    message
+                )));
+            }
+        };
+        let transaction = try!(
+            connection.transaction()
+                .context("Failed to start transaction for importing the migration history")
+        );
+        let mut imported = 0;
+        for applied_migration in &applied_migrations {
+            let imported_now = try!(
+                self.version_table.import(&transaction, applied_migration).with_context(|| {
+                    format!("Error importing migration {}", applied_migration.name())
+                })
+            );
+            if imported_now {
+                imported += 1;
+            } else {
+                info!(
+                    "Migration {} is already recorded as applied, not importing it",
+                    applied_migration.name()
+                );
+            }
+        }
+        try!(transaction.commit().context("Failed to commit the imported migration history"));
+        Ok(imported)
+    }
+
+    /// Removes the entries that fall before `cutoff` from the migration history, so that it
+    /// doesn't grow without bound. The latest remaining entry before the cutoff becomes the
+    /// baseline, standing in for every migration up to and including it, so the pruned migrations
+    /// still count as applied. The entry of the latest applied migration is always kept, and
+    /// entries are only pruned up to the first registered migration that isn't applied, so that
+    /// it's still applied by `run()`. Returns the pruned entries, e.g. to archive them as JSON with
+    /// the `serde` feature enabled before committing `connection`'s transaction.
+    ///
+    /// Once the history has been pruned, the baseline migration can't be rolled back.
+    ///
+    /// # Failures
+    ///
+    /// Returns an error if a problem occurred when communicating with the database, or if a
+    /// migration is dirty.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # extern crate postgres;
+    /// # extern crate trek;
+    /// # fn main() {
+    /// # use postgres::{self, Connection, TlsMode};
+    /// # use trek::history::PruneCutoff;
+    /// # use trek::migration_index::MigrationIndex;
+    /// # use trek::migration::Migration;
+    /// # use trek::migration_version::MigrationVersion;
+    /// # fn f() -> postgres::Result<()>  {
+    /// let connection = Connection::connect("server url", TlsMode::None).unwrap();
+    /// let transaction = connection.transaction().unwrap();
+    ///
+    /// # let migration_list: Vec<Box<Migration>> = vec![];
+    /// let migrations = MigrationIndex::new(migration_list);
+    /// let cutoff = PruneCutoff::Version(MigrationVersion::new(20150826001350));
+    /// match migrations.prune_history(&transaction, cutoff) {
+    ///     Ok(pruned) => {
+    ///         try!(transaction.commit());
+    ///         println!("Pruned {} history entries.", pruned.len());
+    ///     },
+    ///     Err(error) => {
+    ///         println!("Error pruning the migration history: {}", error);
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # };
+    /// # }
+    /// ```
+    pub fn prune_history(
+        &self,
+        connection: &GenericConnection,
+        cutoff: PruneCutoff
+    ) -> Result<Vec<AppliedMigration>> {
+        try!(self.ensure_clean(connection, &self.version_table));
+        let applied_versions = try!(self.applied_versions(connection, &self.version_table));
+        let mut history = try!(
+            self.version_table.history(connection)
+                .context("Error reading the migration history")
+        );
+        history.sort_by_key(|entry| entry.version());
+        // the baseline must be preceded only by applied migrations, since everything up to it
+        // counts as applied once the entries before it are gone
+        let first_unapplied_version = self.outstanding_migrations(&applied_versions).first()
+            .map(|migration| migration.version());
+        let head_index = history.len().saturating_sub(1);
+        let baseline_index = history[..head_index].iter()
+            .take_while(|entry| {
+                cutoff.includes(entry) &&
+                    first_unapplied_version.map_or(true, |version| entry.version() < version)
+            })
+            .count()
+            .saturating_sub(1);
+        let pruned = history.drain(..baseline_index).collect::<Vec<_>>();
+        if pruned.is_empty() {
+            info!("No migration history falls before the cutoff, there is nothing to prune");
+            return Ok(pruned);
+        }
+        let baseline_version = history[0].version();
+        try!(
+            self.version_table.prune(connection, baseline_version)
+                .context("Error pruning the migration history")
+        );
+        info!(
+            "Pruned {} migration history entries, keeping migration {} as the baseline",
+            pruned.len(),
+            baseline_version
+        );
+        Ok(pruned)
+    }
+
+    /// Returns an error if a migration was left dirty by a failed attempt to apply it outside of a
+    /// transaction.
+    fn ensure_clean(
+        &self,
+        connection: &GenericConnection,
+        version_table: &VersionTable
+    ) -> Result<()> {
+        match version_table.dirty_version(connection) {
+            Ok(None) => Ok(()),
+            Ok(Some(version)) => {
+                let name = self.get_version(version)
+                    .map(|migration| migration.to_string())
+                    .unwrap_or_else(|| version.to_string());
+                error!("Migration {} is dirty", name);
+                let error = ValidationError::new(format!(
+                    "Migration {} failed partway through being applied, leaving the database in an \
+                    indeterminate state",
+                    name
+                ));
+                Err(Error::Validation(error.with_hint(
+                    "Repair the database manually, then call force_clean() with the version the \
+                    database is at"
+                )))
+            },
+            Err(error) => {
+                Err(Error::migration(
+                    "Error checking for partially applied migrations".to_owned(),
+                    MigrationPhase::VersionLookup,
+                    None,
+                    error
+                ))
+            }
+        }
+    }
+
+    /// Returns the versions of the migrations recorded as applied in the given table, along with
+    /// those of the registered migrations covered by the baseline left by pruning the history.
+    fn applied_versions(
+        &self,
+        connection: &GenericConnection,
+        version_table: &VersionTable
+    ) -> Result<HashSet<MigrationVersion>> {
+        let result = version_table.applied_versions(connection).and_then(|applied_versions| {
+            let baseline_version = try!(version_table.baseline_version(connection));
+            Ok((applied_versions, baseline_version))
+        });
+        let (mut applied_versions, baseline_version) = try!(result.with_phase(
+            MigrationPhase::VersionLookup,
+            || "Error reading the list of applied migrations".to_owned()
+        ));
+        if let Some(baseline_version) = baseline_version {
+            applied_versions.extend(
+                self.migrations.iter()
+                    .map(|migration| migration.version())
+                    .filter(|version| *version <= baseline_version)
+            );
+        }
+        Ok(applied_versions)
+    }
+
+    /// Checks that every migration recorded as applied in the given table is registered, squashed
+    /// by a registered migration (see `Migration::squashes()`) or the baseline left by pruning
+    /// the history, returning an `UnknownAppliedVersion` error for the first one that isn't.
+    fn ensure_registered(
+        &self,
+        connection: &GenericConnection,
+        version_table: &VersionTable,
+        applied_versions: &HashSet<MigrationVersion>
+    ) -> Result<()> {
+        if applied_versions.iter().all(|version| self.is_registered(*version)) {
+            return Ok(());
+        }
+        let mut history = try!(version_table.history(connection).with_phase(
+            MigrationPhase::VersionLookup,
+            || "Error reading the migration history".to_owned()
+        ));
+        history.sort_by_key(|entry| entry.version());
+        let unknown = history.iter()
+            .find(|entry| !entry.is_baseline() && !self.is_registered(entry.version()));
+        match unknown {
+            Some(entry) => {
+                error!("Applied migration {} isn't registered", entry.name());
+                Err(Error::UnknownAppliedVersion {
+                    message: format!(
+                        "Migration {} is recorded as applied to the database but isn't \
+                        registered, so the database has drifted from the registered migrations",
+                        entry.name()
+                    ),
+                    migration_name: entry.name().to_owned(),
+                })
+            },
+            None => Ok(())
+        }
+    }
+
+    /// Returns true if a migration with the given version is registered or squashed by a
+    /// registered migration (see `Migration::squashes()`).
+    fn is_registered(&self, version: MigrationVersion) -> bool {
+        let squashed_version = self.migrations.iter()
+            .filter_map(|migration| migration.squashes())
+            .max();
+        let squashed = match squashed_version {
+            Some(squashed_version) => version <= squashed_version,
+            None => false
+        };
+        squashed || self.get_version(version).is_some()
+    }
+
+    /// Records the outstanding baselines squashing migrations that were applied (see
+    /// `Migration::squashes()`) as applied, without running them, and adds them to the given
+    /// applied versions. The squashed migration counts as applied if it's in the history or
+    /// covered by the baseline left by pruning it, even once it's no longer registered.
+    fn record_squashed(
+        &self,
+        connection: &GenericConnection,
+        version_table: &VersionTable,
+        applied_versions: &mut HashSet<MigrationVersion>
+    ) -> Result<()> {
+        let baselines = try!(self.squashed_baselines(connection, version_table, applied_versions));
+        for migration in baselines {
+            info!(
+                "Migration {} squashes migrations already applied, recording it as applied",
+                migration
+            );
+            try!(MigrationIndex::record_applied(connection, version_table, &**migration));
+            applied_versions.insert(migration.version());
+            self.progress.on_event(&MigrationEvent::MarkedApplied(&**migration));
+        }
+        Ok(())
+    }
+
+    /// Returns the outstanding baselines squashing migrations that were applied, which are
+    /// recorded as applied without being run.
+    fn squashed_baselines(
+        &self,
+        connection: &GenericConnection,
+        version_table: &VersionTable,
+        applied_versions: &HashSet<MigrationVersion>
+    ) -> Result<Vec<&Box<Migration>>> {
+        let baseline_version = try!(version_table.baseline_version(connection).with_phase(
+            MigrationPhase::VersionLookup,
+            || "Error reading the baseline of the migration history".to_owned()
+        ));
+        let mut baselines = vec![];
+        for migration in self.outstanding_migrations(applied_versions) {
+            if let Some(version) = migration.squashes() {
+                let covered = match baseline_version {
+                    Some(baseline_version) => version <= baseline_version,
+                    None => false
+                };
+                if covered || applied_versions.contains(&version) {
+                    baselines.push(migration);
+                }
+            }
+        }
+        Ok(baselines)
+    }
+
+    /// Converts the single-column version table used by earlier versions of Trek, if there is one,
+    /// into the migration history, recording every migration up to and including the legacy
+    /// table's version as applied. The conversion happens in its own transaction (or savepoint,
+    /// if `connection` is already a transaction), so it either completes or leaves the legacy
+    /// table untouched.
+    fn upgrade_legacy_table(
+        &self,
+        connection: &GenericConnection,
+        version_table: &VersionTable
+    ) -> Result<()> {
+        let legacy_index = match try!(self.legacy_index(connection, version_table)) {
+            Some(legacy_index) => legacy_index,
+            None => return Ok(())
+        };
+        info!(
+            "Converting legacy schema version table at migration {} into the migration history",
+            self.migrations[legacy_index]
+        );
+        let transaction = try!(connection.transaction().context(
+            "Failed to start transaction for converting the legacy schema version table"
+        ));
+        for migration in self.migrations[..(legacy_index + 1)].iter() {
+            try!(MigrationIndex::record_applied(&transaction, version_table, &**migration));
+        }
+        try!(
+            version_table.drop_legacy(&transaction)
+                .context("Error dropping the legacy schema version table")
+        );
+        try!(
+            transaction.commit()
+                .context("Failed to commit the conversion of the legacy schema version table")
+        );
+        Ok(())
+    }
+
+    /// Returns the index in the migrations field of the version recorded in the single-column
+    /// version table used by earlier versions of Trek, or None if there is no such table.
+    fn legacy_index(
+        &self,
+        connection: &GenericConnection,
+        version_table: &VersionTable
+    ) -> Result<Option<usize>> {
+        let legacy_version = try!(
+            version_table.legacy_version(connection)
+                .context("Error checking for a legacy schema version table")
+        );
+        let legacy_version = match legacy_version {
+            Some(legacy_version) => legacy_version,
+            None => return Ok(None),
+        };
+        // earlier versions of Trek recorded the migration's name, which starts with its version
+        // unless the migration's name was chosen by hand
+        let legacy_index = MigrationVersion::from_name(&legacy_version)
+            .and_then(|version| self.version_index(version))
+            .or_else(|| self.current_index(&legacy_version));
+        match legacy_index {
+            Some(index) => Ok(Some(index)),
+            None => {
+                Err(Error::UnknownAppliedVersion {
+                    message: format!(
+                        "Can't convert the legacy schema version table: its version {} isn't a \
+                        registered migration",
+                        legacy_version
+                    ),
+                    migration_name: legacy_version,
+                })
+            }
+        }
+    }
+
+    /// Takes the versions of the migrations recorded as applied and returns the registered
+    /// migrations among them, in order from first to last.
+    fn applied_migrations(
+        &self,
+        applied_versions: &HashSet<MigrationVersion>
+    ) -> Vec<&Box<Migration>> {
+        self.migrations.iter()
+            .filter(|migration| applied_versions.contains(&migration.version()))
+            .collect()
+    }
+
+    /// Takes the versions of the migrations recorded as applied and returns all migrations not
+    /// yet applied to the database, in order from first to last.
+    fn outstanding_migrations(
+        &self,
+        applied_versions: &HashSet<MigrationVersion>
+    ) -> Vec<&Box<Migration>> {
+        self.migrations.iter()
+            .filter(|migration| !applied_versions.contains(&migration.version()))
+            .collect()
+    }
+
+    /// Returns the index in the migrations field of the migration with the given version, for
+    /// running or rolling back to it, or a `Validation` error if no registered migration has
+    /// that version.
+    fn target_index(&self, target: MigrationVersion) -> Result<usize> {
+        match self.version_index(target) {
+            Some(index) => Ok(index),
+            None => {
+                Err(Error::from_message(format!(
+                    "No registered migration has version {}",
+                    target
+                )))
+            }
+        }
+    }
+
+    /// Returns the index in the migrations field of the migration with the given version, or
+    /// None if no registered migration has that version.
+    fn version_index(&self, version: MigrationVersion) -> Option<usize> {
+        self.migrations.iter().position(|migration| migration.version() == version)
+    }
+
+    /// Takes the name of a migration and returns its index in the migrations field, or None if no
+    /// registered migration has that name.
+    fn current_index(&self, current_version: &str) -> Option<usize> {
+        self.migrations.iter().position(|ref migration| {
+            migration.to_string() == *current_version
+        })
+    }
+
+    /// Checks that none of the given applied migrations have been modified since they were
+    /// applied, by comparing their checksums against the ones recorded when they were applied.
+    fn verify_checksums(
+        &self,
+        connection: &GenericConnection,
+        version_table: &VersionTable,
+        applied_migrations: &[&Box<Migration>]
+    ) -> Result<()> {
+        let recorded_checksums = try!(version_table.checksums(connection).with_phase(
+            MigrationPhase::VersionLookup,
+            || "Error reading checksums of applied migrations".to_owned()
+        ));
+        for migration in applied_migrations.iter() {
+            let name = migration.to_string();
+            if let (Some(checksum), Some(recorded_checksum)) = (
+                migration.checksum(), recorded_checksums.get(&migration.version())
+            ) {
+                if checksum != *recorded_checksum {
+                    error!("Applied migration {} has been modified", name);
+                    return Err(Error::ModifiedMigration {
+                        message: format!(
+                            "Migration {} has been modified since it was applied: its checksum \
+                            was {} when applied but is now {}",
+                            name,
+                            recorded_checksum,
+                            checksum
+                        ),
+                        migration_name: name,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Records the migration as applied without having been run.
+    fn record_applied(
+        connection: &GenericConnection,
+        version_table: &VersionTable,
+        migration: &Migration
+    ) -> Result<()> {
+        try!(version_table.record_applied(connection, migration, None).with_migration(
+            MigrationPhase::Bookkeeping,
+            migration,
+            || format!("Error recording migration {} as applied", migration)
+        ));
+        Ok(())
+    }
+
+    /// Sets the statement and lock timeouts the migration runs with, if it has any (see
+    /// `RunOptions::statement_timeout_for()`). For transactional migrations they're set within the
+    /// transaction, otherwise for the session. Returns the settings they replaced, so that
+    /// `restore_settings()` can put them back before the next migration runs.
+    fn set_timeouts(
+        &self,
+        connection: &GenericConnection,
+        migration: &Migration
+    ) -> Result<Vec<(&'static str, String)>> {
+        let timeouts = [
+            ("statement_timeout", self.options.statement_timeout_for(migration)),
+            ("lock_timeout", self.options.lock_timeout_for(migration)),
+        ];
+        let local = migration.transactional();
+        let mut previous_settings = vec![];
+        for &(setting, timeout) in &timeouts {
+            let timeout = match timeout {
+                Some(timeout) => timeout,
+                None => continue
+            };
+            let rows = try!(
+                connection.query("SELECT current_setting($1);", &[&setting]).with_migration(
+                    MigrationPhase::Timeouts,
+                    migration,
+                    || format!("Error reading {}", setting)
+                )
+            );
+            previous_settings.push((setting, rows.get(0).get(0)));
+            let value = format!("{}ms", version_table::duration_to_millis(timeout));
+            debug!("Setting {} to {} for migration {}", setting, value, migration);
+            try!(connection.query(
+                "SELECT set_config($1, $2, $3);", &[&setting, &value, &local]
+            ).with_migration(
+                MigrationPhase::Timeouts,
+                migration,
+                || format!("Error setting {} for migration {}", setting, migration)
+            ));
+        }
+        Ok(previous_settings)
     }
 
-    /// Takes a queryable connection object and uses it to record a new schema version in the
-    /// database's version table.
-    fn update_schema_version(
+    /// Puts back the settings replaced by `set_timeouts()`.
+    fn restore_settings(
         connection: &GenericConnection,
-        old_version: Option<String>,
-        new_version: Option<String>
+        migration: &Migration,
+        previous_settings: Vec<(&'static str, String)>
     ) -> postgres::Result<()> {
-        match (old_version, new_version) {
-            (Some(old_version), Some(new_version)) => {
-                try!(connection.execute(
-                    &format!(
-                        "ALTER TABLE schema_version RENAME COLUMN \"{}\" TO \"{}\";",
-                        &old_version, &new_version
-                    ),
-                    &[]
-                ));
+        let local = migration.transactional();
+        for (setting, value) in previous_settings {
+            try!(connection.query("SELECT set_config($1, $2, $3);", &[&setting, &value, &local]));
+        }
+        Ok(())
+    }
+
+    /// Returns the statement that failed with the given error: the SQL the migration last ran with
+    /// `MigrationContext::batch_execute()`, narrowed down to the statement at the position of the
+    /// error if PostgreSQL reported one.
+    fn failed_statement(context: &MigrationContext, error: &postgres::Error) -> Option<String> {
+        context.last_statement().map(|sql| {
+            let position = error.as_db().and_then(|db_error| db_error.position.as_ref());
+            match position {
+                Some(&ErrorPosition::Original(position)) => {
+                    sql_migration::statement_at(&sql, position as usize).unwrap_or(sql)
+                },
+                _ => sql
+            }
+        })
+    }
+
+    /// Runs the given hook, if one is registered, wrapping any failure in an error naming the hook
+    /// and the migration it was called for.
+    fn call_hook(
+        hook: &Option<MigrationHook>,
+        hook_name: &str,
+        connection: &GenericConnection,
+        migration: &Migration
+    ) -> Result<()> {
+        if let Some(ref hook) = *hook {
+            try!(hook(connection, migration).with_migration(
+                MigrationPhase::Hook,
+                migration,
+                || format!("The {} hook failed for migration {}", hook_name, migration)
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl<'a> IntoIterator for &'a MigrationIndex {
+    type Item = &'a Box<Migration>;
+    type IntoIter = slice::Iter<'a, Box<Migration>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.migrations.iter()
+    }
+}
+
+/// Configures and creates a MigrationIndex. Obtained from `MigrationIndex::builder()`.
+pub struct MigrationIndexBuilder {
+    /// all database migrations, in order from first to last
+    migrations: Vec<Box<Migration>>,
+    /// migrations applied again whenever they change, in the order they're applied
+    repeatable_migrations: Vec<Box<RepeatableMigration>>,
+    /// reference data loaded by run_seeds(), in the order it's loaded
+    seeds: Vec<Box<Seed>>,
+    before_each: Option<MigrationHook>,
+    after_each: Option<MigrationHook>,
+    progress: Box<ProgressObserver>,
+    /// the schema containing the version tracking table, if not the connection's default
+    table_schema: Option<String>,
+    /// the name of the version tracking table, if not the default
+    table_name: Option<String>,
+    /// the migration group whose version table should be used
+    group: Option<String>,
+    /// the application whose version table should be used
+    namespace: Option<String>,
+    /// the application name and version to record with each applied migration
+    application: Option<String>,
+    /// the deploy identifier to record with each applied migration
+    deploy_id: Option<String>,
+    /// whether to record each applied migration's down SQL
+    store_down_sql: bool,
+    options: RunOptions,
+}
+impl MigrationIndexBuilder {
+    /// Creates a builder with no migrations and all options at their defaults.
+    pub fn new() -> Self {
+        MigrationIndexBuilder {
+            migrations: vec![],
+            repeatable_migrations: vec![],
+            seeds: vec![],
+            before_each: None,
+            after_each: None,
+            progress: Box::new(LogProgress),
+            table_schema: None,
+            table_name: None,
+            group: None,
+            namespace: None,
+            application: None,
+            deploy_id: None,
+            store_down_sql: false,
+            options: RunOptions::new(),
+        }
+    }
+
+    /// Adds the given migrations, in order from first to last, after any already added.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use trek::migration_index::MigrationIndex;
+    /// # use trek::migration::Migration;
+    /// # let migration_list: Vec<Box<Migration>> = vec![];
+    /// let migrations = MigrationIndex::builder()
+    ///     .migrations(migration_list)
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn migrations(mut self, migrations: Vec<Box<Migration>>) -> Self {
+        self.migrations.extend(migrations);
+        self
+    }
+
+    /// Adds a single migration after any already added.
+    pub fn migration<M>(mut self, migration: M) -> Self where M: Migration + 'static {
+        self.migrations.push(Box::new(migration));
+        self
+    }
+
+    /// Adds a repeatable migration, which `MigrationIndex::run()` applies after all versioned
+    /// migrations whenever it has changed, after any repeatable migrations already added. See
+    /// `RepeatableMigration`.
+    pub fn repeatable_migration<M>(mut self, migration: M) -> Self
+        where M: RepeatableMigration + 'static
+    {
+        self.repeatable_migrations.push(Box::new(migration));
+        self
+    }
+
+    /// Adds a seed, which `MigrationIndex::run_seeds()` runs after any seeds already added. See
+    /// `Seed`.
+    pub fn seed<S>(mut self, seed: S) -> Self where S: Seed + 'static {
+        self.seeds.push(Box::new(seed));
+        self
+    }
+
+    /// Sets the name of the table used to record applied migrations, which defaults to
+    /// "trek_schema_history". Useful when another tool already uses a table with the default name.
+    /// The name can be schema-qualified, e.g. "trek.schema_history", to keep the table in a
+    /// dedicated schema, which is created if it doesn't exist; this is equivalent to also calling
+    /// `table_schema()`. Can't be combined with `group()` or `namespace()`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use trek::migration_index::MigrationIndex;
+    /// # use trek::migration::Migration;
+    /// # let migration_list: Vec<Box<Migration>> = vec![];
+    /// let migrations = MigrationIndex::builder()
+    ///     .migrations(migration_list)
+    ///     .table_name("trek_schema_version")
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn table_name(mut self, name: &str) -> Self {
+        self.table_name = Some(name.to_owned());
+        self
+    }
+
+    /// Places this index's migrations in a named group with its own version tracking, so that
+    /// several independent indexes (e.g. one for an application's core schema and one for a
+    /// plugin) can manage their migrations in the same database without interfering with each
+    /// other. The group's applied migrations are recorded in a table named
+    /// "trek_schema_history_<group>". Can't be combined with `table_name()` or `namespace()`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use trek::migration_index::MigrationIndex;
+    /// # use trek::migration::Migration;
+    /// # let core_migration_list: Vec<Box<Migration>> = vec![];
+    /// # let plugin_migration_list: Vec<Box<Migration>> = vec![];
+    /// let core_migrations = MigrationIndex::new(core_migration_list);
+    /// let plugin_migrations = MigrationIndex::builder()
+    ///     .migrations(plugin_migration_list)
+    ///     .group("plugin")
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn group(mut self, group: &str) -> Self {
+        self.group = Some(group.to_owned());
+        self
+    }
+
+    /// Scopes version tracking to the named application, recording its applied migrations in a
+    /// table named "trek_history_<namespace>". Several services sharing one database can each
+    /// track their own migrations this way without choosing table names by hand. Can't be
+    /// combined with `table_name()` or `group()`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use trek::migration_index::MigrationIndex;
+    /// # use trek::migration::Migration;
+    /// # let migration_list: Vec<Box<Migration>> = vec![];
+    /// let migrations = MigrationIndex::builder()
+    ///     .migrations(migration_list)
+    ///     .namespace("billing")
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn namespace(mut self, namespace: &str) -> Self {
+        self.namespace = Some(namespace.to_owned());
+        self
+    }
+
+    /// Sets the database schema containing the version tracking table, which is created if it
+    /// doesn't exist. By default the table is created in and looked up in the connection's
+    /// current schema, i.e. the first existing schema on its search_path; tables with the same
+    /// name in other schemas are ignored.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use trek::migration_index::MigrationIndex;
+    /// # use trek::migration::Migration;
+    /// # let migration_list: Vec<Box<Migration>> = vec![];
+    /// let migrations = MigrationIndex::builder()
+    ///     .migrations(migration_list)
+    ///     .table_schema("admin")
+    ///     .table_name("trek_schema_version")
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn table_schema(mut self, schema: &str) -> Self {
+        self.table_schema = Some(schema.to_owned());
+        self
+    }
+
+    /// Sets the application name and version recorded in the migration history alongside each
+    /// migration this index applies, so the history shows which program changed the schema.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use trek::migration_index::MigrationIndex;
+    /// # use trek::migration::Migration;
+    /// # let migration_list: Vec<Box<Migration>> = vec![];
+    /// let migrations = MigrationIndex::builder()
+    ///     .migrations(migration_list)
+    ///     .application(concat!(env!("CARGO_PKG_NAME"), " ", env!("CARGO_PKG_VERSION")))
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn application(mut self, application: &str) -> Self {
+        self.application = Some(application.to_owned());
+        self
+    }
+
+    /// Sets an identifier for the current deploy, e.g. a release tag or CI build number, recorded
+    /// in the migration history alongside each migration this index applies.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use std::env;
+    /// # use trek::migration_index::MigrationIndex;
+    /// # use trek::migration::Migration;
+    /// # let migration_list: Vec<Box<Migration>> = vec![];
+    /// let mut builder = MigrationIndex::builder().migrations(migration_list);
+    /// if let Ok(build_number) = env::var("BUILD_NUMBER") {
+    ///     builder = builder.deploy_id(&build_number);
+    /// }
+    /// let migrations = builder.build().unwrap();
+    /// ```
+    pub fn deploy_id(mut self, deploy_id: &str) -> Self {
+        self.deploy_id = Some(deploy_id.to_owned());
+        self
+    }
+
+    /// Sets whether the down SQL of each migration is recorded in the migration history when the
+    /// migration is applied, for migrations that provide it through `Migration::down_sql()`. With
+    /// the down SQL recorded, `rollback()` can undo a migration even after its code has been
+    /// removed from the index, e.g. when rolling back a deploy on a server that only has the
+    /// previous release's binary. Defaults to false.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use trek::migration_index::MigrationIndex;
+    /// # use trek::migration::Migration;
+    /// # let migration_list: Vec<Box<Migration>> = vec![];
+    /// let migrations = MigrationIndex::builder()
+    ///     .migrations(migration_list)
+    ///     .store_down_sql(true)
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn store_down_sql(mut self, store_down_sql: bool) -> Self {
+        self.store_down_sql = store_down_sql;
+        self
+    }
+
+    /// Sets the options passed to migrations through their `MigrationContext` as they're applied
+    /// or rolled back.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use trek::migration_index::MigrationIndex;
+    /// # use trek::migration::Migration;
+    /// # use trek::migration_context::RunOptions;
+    /// # let migration_list: Vec<Box<Migration>> = vec![];
+    /// let migrations = MigrationIndex::builder()
+    ///     .migrations(migration_list)
+    ///     .run_options(RunOptions::new().environment("staging"))
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn run_options(mut self, options: RunOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Registers an observer to be notified as migrations are applied, rolled back, or recorded,
+    /// replacing the default observer which logs progress through the `log` crate.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use trek::migration_index::MigrationIndex;
+    /// # use trek::migration::Migration;
+    /// # use trek::progress::MigrationEvent;
+    /// # let migration_list: Vec<Box<Migration>> = vec![];
+    /// let migrations = MigrationIndex::builder()
+    ///     .migrations(migration_list)
+    ///     .progress_observer(|event: &MigrationEvent| {
+    ///         // forward the event to a status panel instead of stdout
+    ///         let _status_line = event.to_string();
+    ///     })
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn progress_observer<O>(mut self, observer: O) -> Self
+        where O: ProgressObserver + 'static
+    {
+        self.progress = Box::new(observer);
+        self
+    }
+
+    /// Registers a callback to run just before each migration is applied or rolled back, on the
+    /// same connection the migration will use. If the callback returns an error, the migration
+    /// is not run and the error is returned from `run()` or `rollback()`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use trek::migration_index::MigrationIndex;
+    /// # use trek::migration::Migration;
+    /// # let migration_list: Vec<Box<Migration>> = vec![];
+    /// let migrations = MigrationIndex::builder()
+    ///     .migrations(migration_list)
+    ///     .before_each(|connection, _migration| {
+    ///         try!(connection.execute("SET lock_timeout = '5s';", &[]));
+    ///         Ok(())
+    ///     })
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn before_each<F>(mut self, hook: F) -> Self
+        where F: Fn(&GenericConnection, &Migration) -> postgres::Result<()> + 'static
+    {
+        self.before_each = Some(Box::new(hook));
+        self
+    }
+
+    /// Registers a callback to run just after each migration is successfully applied or rolled
+    /// back, on the same connection the migration used. If the callback returns an error, that
+    /// error is returned from `run()` or `rollback()`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use trek::migration_index::MigrationIndex;
+    /// # use trek::migration::Migration;
+    /// # let migration_list: Vec<Box<Migration>> = vec![];
+    /// let migrations = MigrationIndex::builder()
+    ///     .migrations(migration_list)
+    ///     .after_each(|connection, migration| {
+    ///         try!(connection.execute(
+    ///             "INSERT INTO audit_log (event) VALUES ($1);",
+    ///             &[&format!("migrated {}", migration)]
+    ///         ));
+    ///         Ok(())
+    ///     })
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn after_each<F>(mut self, hook: F) -> Self
+        where F: Fn(&GenericConnection, &Migration) -> postgres::Result<()> + 'static
+    {
+        self.after_each = Some(Box::new(hook));
+        self
+    }
+
+    /// Validates the configuration and creates the MigrationIndex.
+    ///
+    /// # Failures
+    ///
+    /// Returns an error if both `table_name()` and `group()` were set, if the table name, schema,
+    /// or group isn't usable as part of a PostgreSQL identifier, or if the migrations fail
+    /// `MigrationIndex::validate()`.
+    pub fn build(self) -> result::Result<MigrationIndex, ValidationError> {
+        let mut table_schema = self.table_schema;
+        let table_name = match (self.table_name, self.group, self.namespace) {
+            (Some(table_name), None, None) => {
+                match table_name.find('.') {
+                    Some(dot_index) => {
+                        if table_schema.is_some() {
+                            return Err(ValidationError::new(format!(
+                                "The version table name \"{}\" is schema-qualified, so a \
+                                separate table schema can't also be set",
+                                table_name
+                            )));
+                        }
+                        table_schema = Some(table_name[..dot_index].to_owned());
+                        table_name[(dot_index + 1)..].to_owned()
+                    },
+                    None => table_name
+                }
             },
-            (None, Some(new_version)) => {
-                try!(connection.execute(
-                    &format!(
-                        "CREATE TABLE schema_version (
-                             \"{}\" INT NOT NULL
-                        );",
-                        &new_version
-                    ),
-                    &[]
-                ));
+            (None, Some(group), None) => {
+                try!(validate_identifier("group", &group));
+                format!("{}_{}", version_table::DEFAULT_TABLE_NAME, group)
             },
-            (Some(_old_version), None) => {
-                try!(connection.execute("DROP TABLE schema_version;", &[]));
+            (None, None, Some(namespace)) => {
+                try!(validate_identifier("namespace", &namespace));
+                format!("{}_{}", version_table::NAMESPACED_TABLE_PREFIX, namespace)
             },
-            (None, None) => {
-                // technically going from no database schema to no database schema is a no-op, but
-                // it probably indicates a bug so panic on this questionable input
-                panic!(
-                    "Can't update schema version from None to None: at least one of old_version \
-                    and new_version parameters must be Some"
-                );
+            (None, None, None) => version_table::DEFAULT_TABLE_NAME.to_owned(),
+            _ => {
+                return Err(ValidationError::new(
+                    "A migration index can only have one of a custom table name, a group, and a \
+                    namespace".to_owned()
+                ));
             }
+        };
+        try!(validate_identifier("version table name", &table_name));
+        if let Some(ref table_schema) = table_schema {
+            try!(validate_identifier("version table schema", table_schema));
         }
-        Ok(())
+
+        let mut index = MigrationIndex::new(self.migrations);
+        index.repeatable_migrations = self.repeatable_migrations;
+        index.seeds = self.seeds;
+        index.before_each = self.before_each;
+        index.after_each = self.after_each;
+        index.progress = self.progress;
+        index.version_table = VersionTable::new(table_schema, table_name);
+        index.version_table.set_audit_info(self.application, self.deploy_id);
+        index.version_table.set_store_down_sql(self.store_down_sql);
+        index.options = self.options;
+        try!(index.validate());
+        Ok(index)
+    }
+}
+
+impl Default for MigrationIndexBuilder {
+    fn default() -> Self {
+        MigrationIndexBuilder::new()
+    }
+}
+
+/// Orders the given migrations so that each comes after the migrations it depends on, and
+/// otherwise in order of version, returning their positions in that order.
+///
+/// # Failures
+///
+/// Returns an error if a migration depends on a version that isn't registered, or if the
+/// dependencies form a cycle.
+fn dependency_order(
+    migrations: &[Box<Migration>]
+) -> result::Result<Vec<usize>, ValidationError> {
+    let positions = migrations.iter().enumerate()
+        .map(|(position, migration)| (migration.version(), position))
+        .collect::<HashMap<_, _>>();
+    let mut dependents = vec![vec![]; migrations.len()];
+    let mut unmet_dependencies = vec![0; migrations.len()];
+    for (position, migration) in migrations.iter().enumerate() {
+        for dependency in migration.dependencies() {
+            match positions.get(&dependency) {
+                Some(&dependency_position) => {
+                    dependents[dependency_position].push(position);
+                    unmet_dependencies[position] += 1;
+                },
+                None => {
+                    return Err(ValidationError::new(format!(
+                        "Migration {} depends on version {}, which isn't registered",
+                        migration,
+                        dependency
+                    )));
+                }
+            }
+        }
+    }
+
+    // migrations whose dependencies have all been ordered, lowest version first
+    let mut ready = migrations.iter().enumerate()
+        .filter(|&(position, _)| unmet_dependencies[position] == 0)
+        .map(|(position, migration)| (migration.version(), position))
+        .collect::<BTreeSet<_>>();
+    let mut order = Vec::with_capacity(migrations.len());
+    loop {
+        let next = match ready.iter().next() {
+            Some(&next) => next,
+            None => break
+        };
+        ready.remove(&next);
+        let (_, position) = next;
+        order.push(position);
+        for &dependent in &dependents[position] {
+            unmet_dependencies[dependent] -= 1;
+            if unmet_dependencies[dependent] == 0 {
+                ready.insert((migrations[dependent].version(), dependent));
+            }
+        }
+    }
+    if order.len() < migrations.len() {
+        let unordered = migrations.iter().enumerate()
+            .filter(|&(position, _)| unmet_dependencies[position] > 0)
+            .map(|(_, migration)| migration.to_string())
+            .collect::<Vec<_>>();
+        return Err(ValidationError::new(format!(
+            "The dependencies of migrations {} form a cycle",
+            unordered.join(", ")
+        )));
+    }
+    Ok(order)
+}
+
+/// The longest identifier PostgreSQL accepts without truncating it, in bytes.
+const MAX_IDENTIFIER_LENGTH: usize = 63;
+
+/// Checks that a configured name can be used as a PostgreSQL identifier. `description` names the
+/// option being checked, for the error message.
+fn validate_identifier(
+    description: &str,
+    identifier: &str
+) -> result::Result<(), ValidationError> {
+    if identifier.is_empty() {
+        return Err(ValidationError::new(format!("The {} can't be empty", description)));
+    }
+    if identifier.len() > MAX_IDENTIFIER_LENGTH {
+        return Err(ValidationError::new(format!(
+            "The {} \"{}\" is longer than PostgreSQL's limit of {} bytes",
+            description,
+            identifier,
+            MAX_IDENTIFIER_LENGTH
+        )));
+    }
+    if identifier.contains('\0') {
+        return Err(ValidationError::new(format!(
+            "The {} can't contain NUL characters",
+            description
+        )));
+    }
+    Ok(())
+}
+
+/// The outcome of a successful call to `MigrationIndex::run()`: the migrations that were applied
+/// or skipped and the warnings they left. `MigrationIndex::run_seeds()` reports the seeds it ran
+/// or skipped in the same way.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct MigrationReport {
+    /// the names of the applied migrations, in the order they were applied
+    applied: Vec<String>,
+    /// the names of the migrations skipped because they decided not to run, in the order they
+    /// were considered
+    skipped: Vec<String>,
+    /// the names of the contract migrations deferred because it's too soon after their expand
+    /// migrations, in the order they were considered
+    deferred: Vec<String>,
+    /// the warnings left by the applied migrations, in the order they were left
+    warnings: Vec<MigrationWarning>,
+}
+impl MigrationReport {
+    fn new() -> Self {
+        MigrationReport {
+            applied: vec![],
+            skipped: vec![],
+            deferred: vec![],
+            warnings: vec![],
+        }
+    }
+
+    /// Returns the names of the migrations that were applied, in the order they were applied.
+    pub fn applied(&self) -> &[String] {
+        &self.applied
+    }
+
+    /// Returns the names of the migrations that were skipped, in the order they were considered.
+    /// See `Migration::should_run()`.
+    pub fn skipped(&self) -> &[String] {
+        &self.skipped
+    }
+
+    /// Returns the names of the contract migrations that were left outstanding because it's too
+    /// soon after their expand migrations, in the order they were considered. See
+    /// `Migration::contracts()`.
+    pub fn deferred(&self) -> &[String] {
+        &self.deferred
+    }
+
+    /// Returns the warnings left by the applied migrations. See `MigrationContext::warn()`.
+    pub fn warnings(&self) -> &[MigrationWarning] {
+        &self.warnings
+    }
+
+    /// Returns true if any applied migration left a warning.
+    pub fn has_warnings(&self) -> bool {
+        !self.warnings.is_empty()
+    }
+}
+
+/// A warning left by a migration with `MigrationContext::warn()`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct MigrationWarning {
+    migration: String,
+    message: String,
+}
+impl MigrationWarning {
+    /// Returns the name of the migration that left the warning.
+    pub fn migration(&self) -> &str {
+        &self.migration
+    }
+
+    /// Returns the warning's message.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl Display for MigrationWarning {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        write!(formatter, "{}: {}", self.migration, self.message)
+    }
+}
+
+/// The outcome of migrating several database schemas with `MigrationIndex::run_for_schemas()`.
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct TenantReport {
+    /// each schema's name paired with the result of migrating it, in the order they were migrated
+    results: Vec<(String, Result<()>)>,
+}
+impl TenantReport {
+    /// Returns each schema's name paired with the result of migrating it.
+    pub fn results(&self) -> &[(String, Result<()>)] {
+        &self.results
+    }
+
+    /// Returns the names of the schemas that were migrated successfully.
+    pub fn successes(&self) -> Vec<&str> {
+        self.results.iter()
+            .filter(|&&(_, ref result)| result.is_ok())
+            .map(|&(ref schema, _)| &**schema)
+            .collect()
+    }
+
+    /// Returns the names of the schemas that failed to migrate, paired with the error for each.
+    pub fn failures(&self) -> Vec<(&str, &Error)> {
+        self.results.iter()
+            .filter_map(|&(ref schema, ref result)| match *result {
+                Ok(_) => None,
+                Err(ref error) => Some((&**schema, error))
+            })
+            .collect()
+    }
+
+    /// Returns true if every schema was migrated successfully.
+    pub fn is_success(&self) -> bool {
+        self.results.iter().all(|&(_, ref result)| result.is_ok())
+    }
+}
+
+/// The outcome of checking a database against the registered migrations with
+/// `MigrationIndex::verify()`.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct VerificationReport {
+    /// the name of the migration that failed partway through being applied, if any
+    dirty: Option<String>,
+    /// the names of the applied migrations whose checksums changed since they were applied
+    modified: Vec<String>,
+    /// the names of the applied migrations that aren't registered and are newer than every
+    /// registered migration
+    unknown: Vec<String>,
+    /// the names of the applied migrations that aren't registered but are older than a
+    /// registered migration
+    missing: Vec<String>,
+    /// the names of the migrations that haven't been applied yet, in order
+    pending: Vec<String>,
+}
+impl VerificationReport {
+    fn new() -> Self {
+        VerificationReport {
+            dirty: None,
+            modified: vec![],
+            unknown: vec![],
+            missing: vec![],
+            pending: vec![],
+        }
+    }
+
+    /// Returns the name of the migration that failed partway through being applied, leaving the
+    /// database dirty, if any. See `MigrationIndex::force_clean()`.
+    pub fn dirty(&self) -> Option<&str> {
+        self.dirty.as_ref().map(|name| &**name)
+    }
+
+    /// Returns the names of the applied migrations that have been modified since they were
+    /// applied, according to their checksums. See `Migration::checksum()`.
+    pub fn modified(&self) -> &[String] {
+        &self.modified
+    }
+
+    /// Returns the names of the applied migrations that aren't registered and are newer than
+    /// every registered migration, most likely applied by a newer release or another branch.
+    pub fn unknown(&self) -> &[String] {
+        &self.unknown
+    }
+
+    /// Returns the names of the applied migrations that aren't registered but are older than a
+    /// registered migration, most likely because they were deleted, e.g. their SQL files.
+    pub fn missing(&self) -> &[String] {
+        &self.missing
+    }
+
+    /// Returns the names of the migrations that haven't been applied yet, in the order they'd be
+    /// applied.
+    pub fn pending(&self) -> &[String] {
+        &self.pending
+    }
+
+    /// Returns true if no problems were found: no migration is dirty, and every applied migration
+    /// is registered and unmodified. Pending migrations aren't a problem.
+    pub fn is_ok(&self) -> bool {
+        self.dirty.is_none() &&
+            self.modified.is_empty() &&
+            self.unknown.is_empty() &&
+            self.missing.is_empty()
     }
 }
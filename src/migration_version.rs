@@ -0,0 +1,218 @@
+use std::fmt::{self, Display, Formatter};
+use std::num::ParseIntError;
+use std::str::FromStr;
+
+use chrono::UTC;
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+
+/// Identifies a migration's position in the migration history. Versions are usually the
+/// timestamp prefix of a migration's name, e.g. 20150826001350 for a migration named
+/// "20150826001350_create_users_table", and migrations are applied in order of increasing version.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct MigrationVersion(u64);
+
+impl MigrationVersion {
+    /// Creates a version from its numeric value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use trek::migration_version::MigrationVersion;
+    /// let version = MigrationVersion::new(20150826001350);
+    /// assert_eq!(version.to_string(), "20150826001350");
+    /// ```
+    pub fn new(version: u64) -> Self {
+        MigrationVersion(version)
+    }
+
+    /// Extracts the version from the numeric prefix of a migration or file name. Returns None if
+    /// the name doesn't start with a number.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use trek::migration_version::MigrationVersion;
+    /// assert_eq!(
+    ///     MigrationVersion::from_name("20150826001350_create_users_table"),
+    ///     Some(MigrationVersion::new(20150826001350))
+    /// );
+    /// assert_eq!(MigrationVersion::from_name("create_users_table"), None);
+    /// ```
+    pub fn from_name(name: &str) -> Option<Self> {
+        let digits = name.chars()
+            .take_while(|character| character.is_digit(10))
+            .collect::<String>();
+        digits.parse().ok().map(MigrationVersion)
+    }
+
+    /// Returns the numeric value of this version.
+    pub fn value(&self) -> u64 {
+        self.0
+    }
+}
+
+impl Display for MigrationVersion {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        write!(formatter, "{}", self.0)
+    }
+}
+
+impl FromStr for MigrationVersion {
+    type Err = ParseIntError;
+
+    fn from_str(version: &str) -> Result<Self, Self::Err> {
+        version.parse().map(MigrationVersion)
+    }
+}
+
+/// Chooses the versions of new migrations, and how they're written as the prefix of migration
+/// names. `MigrationGenerator` uses a `TimestampVersions` strategy unless given another one.
+///
+/// Whatever the strategy, versions are read back from names by `parse()`, which defaults to
+/// `MigrationVersion::from_name()`, as used by `sql_migration::load_directory()` and the Rust
+/// migration template, so names written by a strategy must start with their version's digits.
+pub trait VersionStrategy {
+    /// Returns the version for a new migration, given the latest version of the migrations that
+    /// already exist, if there are any. It must be greater than the latest version.
+    fn next_version(&self, latest_version: Option<MigrationVersion>) -> MigrationVersion;
+
+    /// Returns the prefix of a new migration's name for the given version.
+    fn prefix(&self, version: MigrationVersion) -> String {
+        version.to_string()
+    }
+
+    /// Extracts the version from a migration or file name, or returns None if it doesn't have
+    /// one.
+    fn parse(&self, name: &str) -> Option<MigrationVersion> {
+        MigrationVersion::from_name(name)
+    }
+}
+
+/// The format of `TimestampVersions` versions unless another is given, with a resolution of a
+/// second.
+pub const DEFAULT_TIMESTAMP_FORMAT: &'static str = "%Y%m%d%H%M%S";
+
+/// Versions new migrations with the current UTC time, e.g. 20150822094521, or one more than the
+/// latest existing version if that's later, so migrations created in the same second (or day,
+/// with a date-only format) still get distinct versions, in the order they were created.
+///
+/// # Examples
+///
+/// ```
+/// # use trek::migration_version::{MigrationVersion, TimestampVersions, VersionStrategy};
+/// let strategy = TimestampVersions::with_format("%Y%m%d");
+/// let version = strategy.next_version(Some(MigrationVersion::new(99990101)));
+/// assert_eq!(version, MigrationVersion::new(99990102));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TimestampVersions {
+    format: String,
+}
+
+impl TimestampVersions {
+    /// Creates a strategy versioning migrations with timestamps in `DEFAULT_TIMESTAMP_FORMAT`.
+    pub fn new() -> Self {
+        TimestampVersions::with_format(DEFAULT_TIMESTAMP_FORMAT)
+    }
+
+    /// Creates a strategy versioning migrations with timestamps in the given `strftime`-like
+    /// format, e.g. "%Y%m%d" for dates without a time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the format doesn't produce a number, e.g. because it has separators.
+    pub fn with_format(format: &str) -> Self {
+        let strategy = TimestampVersions {
+            format: format.to_owned(),
+        };
+        strategy.now();
+        strategy
+    }
+
+    /// Returns the current time as a version in this strategy's format.
+    fn now(&self) -> MigrationVersion {
+        let timestamp = UTC::now().format(&self.format).to_string();
+        match timestamp.parse() {
+            Ok(version) => version,
+            Err(_) => {
+                panic!(
+                    "The timestamp format {:?} produced {:?}, which isn't a number",
+                    self.format,
+                    timestamp
+                );
+            }
+        }
+    }
+}
+
+impl Default for TimestampVersions {
+    fn default() -> Self {
+        TimestampVersions::new()
+    }
+}
+
+impl VersionStrategy for TimestampVersions {
+    fn next_version(&self, latest_version: Option<MigrationVersion>) -> MigrationVersion {
+        let version = self.now();
+        match latest_version {
+            Some(latest_version) if latest_version >= version => {
+                MigrationVersion::new(latest_version.value() + 1)
+            },
+            _ => version
+        }
+    }
+}
+
+/// Versions new migrations with consecutive integers, starting from 1, written as zero-padded
+/// prefixes so that file names sort in order of version, e.g. "0001_create_users_table".
+///
+/// # Examples
+///
+/// ```
+/// # use trek::migration_version::{MigrationVersion, SequentialVersions, VersionStrategy};
+/// let strategy = SequentialVersions::new();
+/// let version = strategy.next_version(Some(MigrationVersion::new(41)));
+/// assert_eq!(strategy.prefix(version), "0042");
+/// assert_eq!(strategy.parse("0042_create_users_table"), Some(version));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SequentialVersions {
+    width: usize,
+}
+
+impl SequentialVersions {
+    /// Creates a strategy whose prefixes are padded to 4 digits.
+    pub fn new() -> Self {
+        SequentialVersions::with_width(4)
+    }
+
+    /// Creates a strategy whose prefixes are padded with zeros to the given number of digits.
+    /// Versions with more digits than that are written in full.
+    pub fn with_width(width: usize) -> Self {
+        SequentialVersions {
+            width: width,
+        }
+    }
+}
+
+impl Default for SequentialVersions {
+    fn default() -> Self {
+        SequentialVersions::new()
+    }
+}
+
+impl VersionStrategy for SequentialVersions {
+    fn next_version(&self, latest_version: Option<MigrationVersion>) -> MigrationVersion {
+        match latest_version {
+            Some(latest_version) => MigrationVersion::new(latest_version.value() + 1),
+            None => MigrationVersion::new(1)
+        }
+    }
+
+    fn prefix(&self, version: MigrationVersion) -> String {
+        format!("{:0width$}", version.value(), width = self.width)
+    }
+}
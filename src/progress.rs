@@ -0,0 +1,157 @@
+use std::fmt::{self, Display, Formatter};
+use std::time::Duration;
+
+use super::migration::Migration;
+use super::repeatable_migration::RepeatableMigration;
+use super::seed::Seed;
+
+
+/// Something that happened while a `MigrationIndex` was changing the database schema. Observers
+/// registered with `MigrationIndex::progress_observer()` receive these as they happen.
+pub enum MigrationEvent<'a> {
+    /// The migration is about to be applied.
+    Running(&'a Migration),
+    /// The migration was applied successfully, taking the given amount of time.
+    Finished(&'a Migration, Duration),
+    /// The migration is about to be rolled back.
+    RollingBack(&'a Migration),
+    /// The first migration was rolled back, taking the given amount of time. The second field is
+    /// the migration the database is now at, or None if no migrations remain applied.
+    RolledBack(&'a Migration, Option<&'a Migration>, Duration),
+    /// All migrations up to and including this one were recorded as applied without being run.
+    MarkedApplied(&'a Migration),
+    /// Version tracking was initialized with this migration as the current version.
+    Baselined(&'a Migration),
+    /// The repeatable migration was applied because it changed, taking the given amount of time.
+    RepeatableApplied(&'a RepeatableMigration, Duration),
+    /// The seed was run, taking the given amount of time.
+    SeedRun(&'a Seed, Duration),
+    /// The migration was skipped because its `Migration::should_run()` returned false.
+    Skipped(&'a Migration),
+    /// The contract migration was left outstanding for the given reason, because it's too soon
+    /// after its expand migration. See `Migration::contracts()`.
+    Deferred(&'a Migration, &'a str),
+    /// The migration left a warning while it was applied or rolled back. See
+    /// `MigrationContext::warn()`.
+    Warning(&'a Migration, &'a str),
+}
+
+impl<'a> Display for MigrationEvent<'a> {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        match *self {
+            MigrationEvent::Running(migration) => {
+                write!(formatter, "Running migration {}", describe(migration))
+            },
+            MigrationEvent::Finished(migration, duration) => {
+                write!(
+                    formatter,
+                    "Ran migration {} in {}",
+                    describe(migration),
+                    format_duration(duration)
+                )
+            },
+            MigrationEvent::RollingBack(migration) => {
+                write!(formatter, "Rolling back migration {}", migration)
+            },
+            MigrationEvent::RolledBack(migration, None, duration) => {
+                write!(
+                    formatter,
+                    "Rolled back migration {} in {}, database is now empty.",
+                    migration,
+                    format_duration(duration)
+                )
+            },
+            MigrationEvent::RolledBack(migration, Some(new_migration), duration) => {
+                write!(
+                    formatter,
+                    "Rolled back migration {} in {}, database is now at version {}",
+                    migration,
+                    format_duration(duration),
+                    new_migration
+                )
+            },
+            MigrationEvent::MarkedApplied(migration) => {
+                write!(formatter, "Marked migrations up to {} as applied", migration)
+            },
+            MigrationEvent::Baselined(migration) => {
+                write!(formatter, "Baselined database at migration {}", migration)
+            },
+            MigrationEvent::RepeatableApplied(migration, duration) => {
+                write!(
+                    formatter,
+                    "Applied repeatable migration {} in {}",
+                    migration,
+                    format_duration(duration)
+                )
+            },
+            MigrationEvent::SeedRun(seed, duration) => {
+                write!(formatter, "Ran seed {} in {}", seed, format_duration(duration))
+            },
+            MigrationEvent::Skipped(migration) => {
+                write!(formatter, "Skipped migration {}", describe(migration))
+            },
+            MigrationEvent::Deferred(migration, reason) => {
+                write!(formatter, "Deferred contract migration {}: {}", migration, reason)
+            },
+            MigrationEvent::Warning(migration, message) => {
+                write!(formatter, "Migration {} warned: {}", migration, message)
+            },
+        }
+    }
+}
+
+/// Receives progress events from a `MigrationIndex`. Any closure taking a `&MigrationEvent` can
+/// be used as an observer.
+pub trait ProgressObserver {
+    /// Called each time something happens during a migration run or rollback.
+    fn on_event(&self, event: &MigrationEvent);
+}
+
+impl<F> ProgressObserver for F where F: Fn(&MigrationEvent) {
+    fn on_event(&self, event: &MigrationEvent) {
+        self(event)
+    }
+}
+
+/// The default observer, which logs each event through the `log` crate: finished operations at
+/// info level, operations about to start at debug level and warnings at warn level.
+#[derive(Debug, Default)]
+pub struct LogProgress;
+
+impl ProgressObserver for LogProgress {
+    fn on_event(&self, event: &MigrationEvent) {
+        match *event {
+            MigrationEvent::Running(_) | MigrationEvent::RollingBack(_) => debug!("{}", event),
+            MigrationEvent::Warning(..) => warn!("{}", event),
+            _ => info!("{}", event)
+        }
+    }
+}
+
+/// An observer which prints a line to stdout each time a migration finishes being applied,
+/// rolled back, or recorded. Useful for command line tools that don't configure a logger.
+#[derive(Debug, Default)]
+pub struct StdoutProgress;
+
+impl ProgressObserver for StdoutProgress {
+    fn on_event(&self, event: &MigrationEvent) {
+        match *event {
+            MigrationEvent::Running(_) | MigrationEvent::RollingBack(_) => {},
+            _ => println!("{}", event)
+        }
+    }
+}
+
+/// Formats a migration's name followed by its description, if it has one, e.g.
+/// "20150826001350_index_emails (Adds an index on users.email)".
+fn describe(migration: &Migration) -> String {
+    match migration.description() {
+        Some(description) => format!("{} ({})", migration, description),
+        None => migration.to_string()
+    }
+}
+
+/// Formats a duration as seconds with millisecond precision, e.g. "2.300s".
+fn format_duration(duration: Duration) -> String {
+    format!("{}.{:03}s", duration.as_secs(), duration.subsec_nanos() / 1_000_000)
+}
@@ -0,0 +1,186 @@
+use postgres::GenericConnection;
+
+use super::Result;
+use super::error::Error;
+use super::scaffold::Scaffold;
+use super::sql_migration;
+use super::version_table::quote_identifier;
+
+
+/// The name of the table recorded DDL is kept in, and of the function and event trigger recording
+/// it, all in the schema that was current when recording started.
+pub const RECORDING_NAME: &'static str = "trek_recorded_ddl";
+
+/// Experimental: starts recording the DDL run against the database, e.g. by a developer trying
+/// out schema changes in psql, so that `stop_recording()` can turn it into a migration. The
+/// statements are captured by an event trigger, which requires superuser privileges and sees
+/// every session's DDL, so only record against a scratch database.
+///
+/// # Examples
+///
+/// ```no_run
+/// # extern crate postgres;
+/// # extern crate trek;
+/// # fn main() {
+/// # use std::path::Path;
+/// # use postgres::{Connection, TlsMode};
+/// # use trek::{create_scaffolded_migration, MigrationTemplate};
+/// # use trek::record;
+/// let connection = Connection::connect("scratch database url", TlsMode::None).unwrap();
+/// record::start_recording(&connection).unwrap();
+/// // ... run CREATE TABLE, ALTER TABLE and so on in psql ...
+/// let recorded = record::stop_recording(&connection).unwrap();
+/// if !recorded.is_empty() {
+///     create_scaffolded_migration(
+///         "create_prototyped_tables",
+///         Path::new("migrations"),
+///         MigrationTemplate::Sql,
+///         &recorded.scaffold()
+///     ).unwrap();
+/// }
+/// # }
+/// ```
+///
+/// # Failures
+///
+/// Returns an error if DDL is already being recorded, or if the table, function or event
+/// trigger recording it can't be created.
+pub fn start_recording(connection: &GenericConnection) -> Result<()> {
+    if try!(is_recording(connection)) {
+        return Err(Error::from_message(
+            "DDL is already being recorded; call stop_recording() first".to_owned()
+        ));
+    }
+    let schema = match connection.query("SELECT current_schema();", &[]) {
+        Ok(rows) => rows.get(0).get::<_, Option<String>>(0),
+        Err(error) => return Err(Error::new("Error reading the current schema".to_owned(), error))
+    };
+    let schema = match schema {
+        Some(schema) => schema,
+        None => return Err(Error::from_message("No current schema to record into".to_owned()))
+    };
+    // the table is qualified in the function, so DDL is recorded whatever the search_path of the
+    // session running it
+    let setup = format!(
+        "CREATE TABLE {name} (
+            id BIGSERIAL PRIMARY KEY,
+            command_tag TEXT NOT NULL,
+            query TEXT NOT NULL
+        );
+        CREATE FUNCTION {name}() RETURNS event_trigger AS $$
+        BEGIN
+            INSERT INTO {schema}.{name} (command_tag, query) VALUES (tg_tag, current_query());
+        END;
+        $$ LANGUAGE plpgsql;
+        CREATE EVENT TRIGGER {name} ON ddl_command_end EXECUTE PROCEDURE {name}();",
+        name=RECORDING_NAME,
+        schema=quote_identifier(&schema)
+    );
+    debug!("Executing SQL: {}", setup);
+    if let Err(error) = connection.batch_execute(&setup) {
+        return Err(Error::new(
+            "Error installing the event trigger recording DDL".to_owned(),
+            error
+        ));
+    }
+    Ok(())
+}
+
+/// Experimental: stops recording DDL, removing the table, function and event trigger
+/// `start_recording()` installed, and returns the statements recorded since it was called. It
+/// must be called with the same current schema as `start_recording()`.
+///
+/// # Failures
+///
+/// Returns an error if DDL isn't being recorded, or if the recorded statements can't be read or
+/// the recording can't be removed.
+pub fn stop_recording(connection: &GenericConnection) -> Result<RecordedDdl> {
+    if !try!(is_recording(connection)) {
+        return Err(Error::from_message(
+            "DDL isn't being recorded; call start_recording() first".to_owned()
+        ));
+    }
+    let query = format!("SELECT query FROM {} ORDER BY id;", RECORDING_NAME);
+    debug!("Executing SQL: {}", query);
+    let rows = match connection.query(&query, &[]) {
+        Ok(rows) => rows,
+        Err(error) => return Err(Error::new("Error reading the recorded DDL".to_owned(), error))
+    };
+    let mut recorded = RecordedDdl::default();
+    let mut previous_query = None;
+    for row in rows.iter() {
+        let query: String = row.get(0);
+        // a query running several DDL statements is recorded once for each of them
+        if previous_query.as_ref() == Some(&query) {
+            continue;
+        }
+        recorded.statements.extend(sql_migration::split_statements(&query));
+        previous_query = Some(query);
+    }
+
+    let teardown = format!(
+        "DROP EVENT TRIGGER {name};
+        DROP FUNCTION {name}();
+        DROP TABLE {name};",
+        name=RECORDING_NAME
+    );
+    debug!("Executing SQL: {}", teardown);
+    if let Err(error) = connection.batch_execute(&teardown) {
+        return Err(Error::new(
+            "Error removing the event trigger recording DDL".to_owned(),
+            error
+        ));
+    }
+    Ok(recorded)
+}
+
+/// Returns true if `start_recording()` has installed its event trigger.
+pub fn is_recording(connection: &GenericConnection) -> Result<bool> {
+    let query = "SELECT EXISTS (SELECT 1 FROM pg_event_trigger WHERE evtname = $1);";
+    debug!("Executing SQL: {}", query);
+    match connection.query(query, &[&RECORDING_NAME]) {
+        Ok(rows) => Ok(rows.get(0).get(0)),
+        Err(error) => Err(Error::new("Error checking for the DDL recording".to_owned(), error))
+    }
+}
+
+/// The DDL recorded between `start_recording()` and `stop_recording()`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RecordedDdl {
+    statements: Vec<String>,
+}
+
+impl RecordedDdl {
+    /// Returns the recorded statements, in the order they were run, each without a terminating
+    /// semicolon. Other statements sent to the database in the same query as a DDL statement are
+    /// included too.
+    pub fn statements(&self) -> &[String] {
+        &self.statements
+    }
+
+    /// Returns true if no DDL was recorded.
+    pub fn is_empty(&self) -> bool {
+        self.statements.is_empty()
+    }
+
+    /// Returns the statements as a script.
+    pub fn sql(&self) -> String {
+        let mut sql = String::from(
+            "-- Recorded from DDL run against the database; review before running.\n"
+        );
+        for statement in &self.statements {
+            sql.push_str(&format!("{};\n", statement));
+        }
+        sql
+    }
+
+    /// Returns a scaffold for a migration running the statements, for
+    /// `create_scaffolded_migration()`. Its down SQL is generated with
+    /// `sql_migration::generate_down()` if all the statements can be inverted, and otherwise left
+    /// for you to write.
+    pub fn scaffold(&self) -> Scaffold {
+        let down = sql_migration::generate_down(&self.sql());
+        let down_sql = if down.is_complete() { Some(down.sql() + "\n") } else { None };
+        Scaffold::sql(&self.sql(), down_sql.as_ref().map(|down_sql| &**down_sql))
+    }
+}
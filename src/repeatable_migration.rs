@@ -0,0 +1,68 @@
+use std::fmt::{self, Display, Formatter};
+
+use postgres::{self, GenericConnection};
+
+use super::migration;
+
+
+/// A migration that isn't versioned, but is applied again whenever its checksum changes, for
+/// objects that are redefined wholesale rather than altered step by step, such as views,
+/// functions and grants. Repeatable migrations registered with
+/// `MigrationIndexBuilder::repeatable_migration()` are applied by `MigrationIndex::run()` after
+/// all versioned migrations, in the order they were registered.
+pub trait RepeatableMigration : Display {
+    /// Applies this migration. Since it's applied again whenever it changes, it must replace any
+    /// earlier definition of what it creates, e.g. with `CREATE OR REPLACE VIEW`.
+    fn up(&self, transaction: &GenericConnection) -> postgres::Result<()>;
+
+    /// Returns a digest of this migration's contents. The migration is applied again whenever
+    /// this differs from the digest recorded when it was last applied.
+    fn checksum(&self) -> String;
+}
+
+/// A repeatable migration defined by SQL, whose checksum is computed from the SQL.
+///
+/// # Examples
+///
+/// ```
+/// # use trek::migration_index::MigrationIndex;
+/// # use trek::repeatable_migration::RepeatableSqlMigration;
+/// let migrations = MigrationIndex::builder()
+///     .repeatable_migration(RepeatableSqlMigration::new(
+///         "active_users_view",
+///         "CREATE OR REPLACE VIEW active_users AS SELECT * FROM users WHERE active;"
+///     ))
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct RepeatableSqlMigration {
+    name: String,
+    sql: String,
+}
+
+impl RepeatableSqlMigration {
+    /// Creates a repeatable migration that's applied by running `sql`.
+    pub fn new(name: &str, sql: &str) -> Self {
+        RepeatableSqlMigration {
+            name: name.to_owned(),
+            sql: sql.to_owned(),
+        }
+    }
+}
+
+impl RepeatableMigration for RepeatableSqlMigration {
+    fn up(&self, transaction: &GenericConnection) -> postgres::Result<()> {
+        transaction.batch_execute(&self.sql)
+    }
+
+    fn checksum(&self) -> String {
+        migration::content_checksum(&self.sql)
+    }
+}
+
+impl Display for RepeatableSqlMigration {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        write!(formatter, "{}", self.name)
+    }
+}
@@ -0,0 +1,194 @@
+use std::fmt::Display;
+
+use postgres;
+
+use super::migration::Migration;
+use super::migration_context::MigrationContext;
+use super::migration_version::MigrationVersion;
+use super::scaffold::{Column, Scaffold};
+
+
+/// A migration described once, as a list of schema changes, from which both the SQL applying it
+/// and the SQL undoing it are derived, like ActiveRecord's reversible migrations. Every
+/// `ReversibleMigration` is a `Migration`, so it can be registered with a `MigrationIndex` like
+/// any other.
+///
+/// `create_migration()` with `MigrationTemplate::Reversible` generates a skeleton implementing
+/// this trait.
+///
+/// # Examples
+///
+/// ```
+/// # use std::fmt::{self, Display};
+/// # use trek::migration_version::MigrationVersion;
+/// # use trek::reversible_migration::{Changes, ReversibleMigration};
+/// # use trek::scaffold::Column;
+/// struct CreateUsersTable;
+///
+/// impl ReversibleMigration for CreateUsersTable {
+///     fn version(&self) -> MigrationVersion {
+///         MigrationVersion::new(20150822094521)
+///     }
+///
+///     fn change(&self, changes: &mut Changes) {
+///         changes
+///             .create_table("users", vec![Column::new("id", "SERIAL").primary_key()])
+///             .add_index("users", &["id"], true);
+///     }
+/// }
+/// # impl Display for CreateUsersTable {
+/// #     fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+/// #         write!(formatter, "create_users_table")
+/// #     }
+/// # }
+///
+/// let mut changes = Changes::new();
+/// CreateUsersTable.change(&mut changes);
+/// assert_eq!(changes.down_sql(), "DROP INDEX users_id_index;\nDROP TABLE users;\n");
+/// ```
+pub trait ReversibleMigration : Display {
+    /// Returns this migration's version, which determines the order migrations are applied in.
+    fn version(&self) -> MigrationVersion;
+
+    /// Describes the change this migration makes by adding it to `changes`, in the order it's
+    /// applied. It's undone in the reverse order.
+    fn change(&self, changes: &mut Changes);
+
+    /// Returns a short human-readable explanation of what this migration does. See
+    /// `Migration::description()`. Returns None by default.
+    fn description(&self) -> Option<String> {
+        None
+    }
+
+    /// Returns the version of the expand migration this one is the contract half of. See
+    /// `Migration::contracts()`. Returns None by default.
+    fn contracts(&self) -> Option<MigrationVersion> {
+        None
+    }
+}
+
+impl<T> Migration for T where T: ReversibleMigration {
+    fn version(&self) -> MigrationVersion {
+        ReversibleMigration::version(self)
+    }
+
+    fn up(&self, context: &MigrationContext) -> postgres::Result<()> {
+        let mut changes = Changes::new();
+        self.change(&mut changes);
+        context.batch_execute(&changes.up_sql())
+    }
+
+    fn down(&self, context: &MigrationContext) -> postgres::Result<()> {
+        let mut changes = Changes::new();
+        self.change(&mut changes);
+        context.batch_execute(&changes.down_sql())
+    }
+
+    fn up_sql(&self) -> Option<String> {
+        let mut changes = Changes::new();
+        self.change(&mut changes);
+        Some(changes.up_sql())
+    }
+
+    fn down_sql(&self) -> Option<String> {
+        let mut changes = Changes::new();
+        self.change(&mut changes);
+        Some(changes.down_sql())
+    }
+
+    fn description(&self) -> Option<String> {
+        ReversibleMigration::description(self)
+    }
+
+    fn contracts(&self) -> Option<MigrationVersion> {
+        ReversibleMigration::contracts(self)
+    }
+}
+
+/// The schema changes a `ReversibleMigration` makes, each recorded with the SQL applying it and
+/// the SQL undoing it. Table, column and index names are interpolated into the SQL as they are,
+/// as with `Scaffold`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Changes {
+    /// the SQL applying and undoing each change, in the order they're applied
+    statements: Vec<(String, String)>,
+}
+
+impl Changes {
+    /// Creates an empty list of changes.
+    pub fn new() -> Self {
+        Changes {
+            statements: vec![],
+        }
+    }
+
+    /// Creates a table with the given columns, undone by dropping it.
+    pub fn create_table(&mut self, table: &str, columns: Vec<Column>) -> &mut Self {
+        self.scaffold(Scaffold::create_table(table, columns))
+    }
+
+    /// Renames a table, undone by renaming it back. The new name is in the table's schema, so
+    /// it's given without one.
+    pub fn rename_table(&mut self, table: &str, new_name: &str) -> &mut Self {
+        let (schema, old_name) = match table.rfind('.') {
+            Some(dot_index) => (&table[..(dot_index + 1)], &table[(dot_index + 1)..]),
+            None => ("", table)
+        };
+        self.sql(
+            &format!("ALTER TABLE {} RENAME TO {};\n", table, new_name),
+            &format!("ALTER TABLE {}{} RENAME TO {};\n", schema, new_name, old_name)
+        )
+    }
+
+    /// Adds a column to a table, undone by dropping it.
+    pub fn add_column(&mut self, table: &str, column: Column) -> &mut Self {
+        self.scaffold(Scaffold::add_column(table, column))
+    }
+
+    /// Drops a column from a table, undone by adding it back with the given definition. The
+    /// column's data isn't restored.
+    pub fn remove_column(&mut self, table: &str, column: Column) -> &mut Self {
+        self.sql(
+            &format!("ALTER TABLE {} DROP COLUMN {};\n", table, column.name()),
+            &format!("ALTER TABLE {} ADD COLUMN {};\n", table, column.definition())
+        )
+    }
+
+    /// Renames a table's column, undone by renaming it back.
+    pub fn rename_column(&mut self, table: &str, column: &str, new_name: &str) -> &mut Self {
+        self.sql(
+            &format!("ALTER TABLE {} RENAME COLUMN {} TO {};\n", table, column, new_name),
+            &format!("ALTER TABLE {} RENAME COLUMN {} TO {};\n", table, new_name, column)
+        )
+    }
+
+    /// Creates an index on the given columns of a table, or a unique index if `unique` is set,
+    /// named like `Scaffold::add_index()` names it and undone by dropping it.
+    pub fn add_index(&mut self, table: &str, columns: &[&str], unique: bool) -> &mut Self {
+        self.scaffold(Scaffold::add_index(table, columns, unique))
+    }
+
+    /// Runs the given SQL, undone by running `down_sql`, for changes the other methods don't
+    /// cover.
+    pub fn sql(&mut self, up_sql: &str, down_sql: &str) -> &mut Self {
+        self.statements.push((up_sql.to_owned(), down_sql.to_owned()));
+        self
+    }
+
+    /// Returns the SQL applying all the changes, in order.
+    pub fn up_sql(&self) -> String {
+        self.statements.iter().map(|&(ref up_sql, _)| &**up_sql).collect()
+    }
+
+    /// Returns the SQL undoing all the changes, in reverse order.
+    pub fn down_sql(&self) -> String {
+        self.statements.iter().rev().map(|&(_, ref down_sql)| &**down_sql).collect()
+    }
+
+    /// Records a change whose SQL is generated by a scaffold.
+    fn scaffold(&mut self, scaffold: Scaffold) -> &mut Self {
+        let up_sql = scaffold.up_sql().expect("Structural scaffolds have up SQL");
+        let down_sql = scaffold.down_sql().expect("Structural scaffolds have down SQL");
+        self.sql(&up_sql, &down_sql)
+    }
+}
@@ -0,0 +1,209 @@
+use super::sql_migration;
+
+
+/// A column of a table created or altered by a `Scaffold`.
+///
+/// # Examples
+///
+/// ```
+/// # use trek::scaffold::Column;
+/// let column = Column::new("created_at", "TIMESTAMPTZ").not_null().default("now()");
+/// assert_eq!(column.definition(), "created_at TIMESTAMPTZ NOT NULL DEFAULT now()");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Column {
+    name: String,
+    sql_type: String,
+    not_null: bool,
+    primary_key: bool,
+    default: Option<String>,
+}
+
+impl Column {
+    /// Creates a nullable column with the given name and SQL type, e.g. "TEXT" or "NUMERIC(10, 2)".
+    pub fn new(name: &str, sql_type: &str) -> Self {
+        Column {
+            name: name.to_owned(),
+            sql_type: sql_type.to_owned(),
+            not_null: false,
+            primary_key: false,
+            default: None,
+        }
+    }
+
+    /// Makes the column NOT NULL.
+    pub fn not_null(mut self) -> Self {
+        self.not_null = true;
+        self
+    }
+
+    /// Makes the column the table's primary key.
+    pub fn primary_key(mut self) -> Self {
+        self.primary_key = true;
+        self
+    }
+
+    /// Gives the column a default value, as an SQL expression.
+    pub fn default(mut self, expression: &str) -> Self {
+        self.default = Some(expression.to_owned());
+        self
+    }
+
+    /// Returns the column's name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the column's definition, as used in `CREATE TABLE` and `ALTER TABLE ... ADD COLUMN`
+    /// statements.
+    pub fn definition(&self) -> String {
+        let mut definition = format!("{} {}", self.name, self.sql_type);
+        if self.primary_key {
+            definition.push_str(" PRIMARY KEY");
+        }
+        if self.not_null {
+            definition.push_str(" NOT NULL");
+        }
+        if let Some(ref default) = self.default {
+            definition.push_str(&format!(" DEFAULT {}", default));
+        }
+        definition
+    }
+
+    /// Returns the Rust expression creating this column, as written into generated migrations.
+    pub(crate) fn rust_expression(&self) -> String {
+        let mut expression = format!("Column::new({:?}, {:?})", self.name, self.sql_type);
+        if self.primary_key {
+            expression.push_str(".primary_key()");
+        }
+        if self.not_null {
+            expression.push_str(".not_null()");
+        }
+        if let Some(ref default) = self.default {
+            expression.push_str(&format!(".default({:?})", default));
+        }
+        expression
+    }
+}
+
+/// The change a migration created by `create_scaffolded_migration()` makes, for the common cases
+/// whose SQL can be written for you. Table, column and index names are interpolated into the SQL
+/// as they are, so they must be valid identifiers, quoted where necessary.
+///
+/// # Examples
+///
+/// ```
+/// # use trek::scaffold::{Column, Scaffold};
+/// let scaffold = Scaffold::create_table("users", vec![
+///     Column::new("id", "SERIAL").primary_key(),
+///     Column::new("email", "TEXT").not_null(),
+/// ]);
+/// assert_eq!(
+///     scaffold.up_sql().unwrap(),
+///     "CREATE TABLE users (\n    id SERIAL PRIMARY KEY,\n    email TEXT NOT NULL\n);\n"
+/// );
+/// assert_eq!(scaffold.down_sql().unwrap(), "DROP TABLE users;\n");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Scaffold {
+    /// No change: the migration's SQL is left for you to write.
+    Empty,
+    /// Creates a table with the given columns.
+    CreateTable {
+        table: String,
+        columns: Vec<Column>,
+    },
+    /// Adds a column to an existing table.
+    AddColumn {
+        table: String,
+        column: Column,
+    },
+    /// Creates an index on the given columns of a table, named after them.
+    AddIndex {
+        table: String,
+        columns: Vec<String>,
+        unique: bool,
+    },
+    /// A change given as SQL, such as one generated by `diff::diff_sql()`. Without down SQL,
+    /// undoing the change is left for you to write.
+    Sql {
+        up_sql: String,
+        down_sql: Option<String>,
+    },
+}
+
+impl Scaffold {
+    /// Creates a scaffold for a migration creating a table.
+    pub fn create_table(table: &str, columns: Vec<Column>) -> Self {
+        Scaffold::CreateTable {
+            table: table.to_owned(),
+            columns: columns,
+        }
+    }
+
+    /// Creates a scaffold for a migration adding a column to a table.
+    pub fn add_column(table: &str, column: Column) -> Self {
+        Scaffold::AddColumn {
+            table: table.to_owned(),
+            column: column,
+        }
+    }
+
+    /// Creates a scaffold for a migration adding an index to a table, or a unique index if
+    /// `unique` is set.
+    pub fn add_index(table: &str, columns: &[&str], unique: bool) -> Self {
+        Scaffold::AddIndex {
+            table: table.to_owned(),
+            columns: columns.iter().map(|column| column.to_string()).collect(),
+            unique: unique,
+        }
+    }
+
+    /// Creates a scaffold for a migration running the given SQL, and undone by `down_sql` if
+    /// there is any.
+    pub fn sql(up_sql: &str, down_sql: Option<&str>) -> Self {
+        Scaffold::Sql {
+            up_sql: up_sql.to_owned(),
+            down_sql: down_sql.map(|down_sql| down_sql.to_owned()),
+        }
+    }
+
+    /// Returns the SQL applying the change, or None for an empty scaffold.
+    pub fn up_sql(&self) -> Option<String> {
+        match *self {
+            Scaffold::Empty => None,
+            Scaffold::CreateTable { ref table, ref columns } => {
+                let columns = columns.iter()
+                    .map(|column| format!("    {}", column.definition()))
+                    .collect::<Vec<String>>();
+                Some(format!("CREATE TABLE {} (\n{}\n);\n", table, columns.join(",\n")))
+            },
+            Scaffold::AddColumn { ref table, ref column } => {
+                Some(format!("ALTER TABLE {} ADD COLUMN {};\n", table, column.definition()))
+            },
+            Scaffold::AddIndex { ref table, ref columns, unique } => {
+                // the index is created in its table's schema, so it's named without it
+                let unqualified_table = table.rsplit('.').next().unwrap_or(table);
+                Some(format!(
+                    "CREATE {}INDEX {}_{}_index ON {} ({});\n",
+                    if unique { "UNIQUE " } else { "" },
+                    unqualified_table,
+                    columns.join("_"),
+                    table,
+                    columns.join(", ")
+                ))
+            },
+            Scaffold::Sql { ref up_sql, .. } => Some(up_sql.clone())
+        }
+    }
+
+    /// Returns the SQL undoing the change, or None for an empty scaffold or SQL given without
+    /// any. Other than for SQL scaffolds, it's derived from the up SQL with
+    /// `sql_migration::generate_down()`.
+    pub fn down_sql(&self) -> Option<String> {
+        match *self {
+            Scaffold::Sql { ref down_sql, .. } => down_sql.clone(),
+            _ => self.up_sql().map(|up_sql| sql_migration::generate_down(&up_sql).sql() + "\n")
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use std::fmt::{self, Display, Formatter};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use postgres;
+
+use super::migration_context::MigrationContext;
+use super::migration_version::MigrationVersion;
+use super::sql_migration;
+
+
+/// Reference data loaded into the database by `MigrationIndex::run_seeds()`, e.g. lookup tables
+/// or development fixtures. Unlike migrations, seeds aren't versioned: every call to
+/// `run_seeds()` runs all seeds that apply to the current environment, and records when each last
+/// ran separately from the schema history (see `MigrationIndex::seed_history()`). Seeds must
+/// therefore be idempotent, e.g. by using `INSERT ... ON CONFLICT DO NOTHING`.
+pub trait Seed : Display {
+    /// Loads this seed's data, running its SQL on `context.connection()`.
+    fn run(&self, context: &MigrationContext) -> postgres::Result<()>;
+
+    /// Returns the names of the environments this seed applies to, with the same meaning as
+    /// `Migration::environments()`. Returns no environments by default, meaning the seed applies
+    /// to every environment.
+    fn environments(&self) -> Vec<String> {
+        vec![]
+    }
+}
+
+/// A seed defined by SQL.
+///
+/// # Examples
+///
+/// ```
+/// # use trek::migration_index::MigrationIndex;
+/// # use trek::seed::SqlSeed;
+/// let migrations = MigrationIndex::builder()
+///     .seed(SqlSeed::new(
+///         "countries",
+///         "INSERT INTO countries (code, name) VALUES ('NZ', 'New Zealand')
+///          ON CONFLICT (code) DO UPDATE SET name = excluded.name;"
+///     ))
+///     .seed(SqlSeed::new(
+///         "demo_users",
+///         "INSERT INTO users (username) VALUES ('demo') ON CONFLICT DO NOTHING;"
+///     ).with_environments(&["development"]))
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct SqlSeed {
+    name: String,
+    sql: String,
+    environments: Vec<String>,
+}
+
+impl SqlSeed {
+    /// Creates a seed that's loaded by running `sql`.
+    pub fn new(name: &str, sql: &str) -> Self {
+        SqlSeed {
+            name: name.to_owned(),
+            sql: sql.to_owned(),
+            environments: vec![],
+        }
+    }
+
+    /// Restricts the seed to the given environments. See `Seed::environments()`.
+    pub fn with_environments(mut self, environments: &[&str]) -> Self {
+        self.environments = environments.iter()
+            .map(|environment| environment.to_string())
+            .collect();
+        self
+    }
+}
+
+impl Seed for SqlSeed {
+    fn run(&self, context: &MigrationContext) -> postgres::Result<()> {
+        context.batch_execute(&self.sql)
+    }
+
+    fn environments(&self) -> Vec<String> {
+        self.environments.clone()
+    }
+}
+
+impl Display for SqlSeed {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        write!(formatter, "{}", self.name)
+    }
+}
+
+/// Loads the SQL seeds in the given directory, from files named like
+/// "20150822094521_countries.sql" as created by `create_seed()`, in order of version, which is
+/// the order they should be registered with `MigrationIndexBuilder::seed()` in. Each seed is
+/// named after its file, without the extension. Other files are ignored. Seeds in
+/// subdirectories are loaded too.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use std::path::Path;
+/// # use trek::migration_index::MigrationIndex;
+/// # use trek::seed;
+/// let mut builder = MigrationIndex::builder();
+/// for seed in seed::load_directory(Path::new("src/db/seeds/")).unwrap() {
+///     builder = builder.seed(seed);
+/// }
+/// let migrations = builder.build().unwrap();
+/// ```
+///
+/// # Failures
+///
+/// Returns an error if the directory or a seed file can't be read, or if a seed's name doesn't
+/// start with its version.
+pub fn load_directory(directory: &Path) -> io::Result<Vec<SqlSeed>> {
+    let mut seed_files = vec![];
+    for path in try!(sql_migration::find_files(directory)) {
+        let file_name = match path.file_name().and_then(|file_name| file_name.to_str()) {
+            Some(file_name) => file_name.to_owned(),
+            None => continue
+        };
+        if !file_name.ends_with(".sql") {
+            debug!("Ignoring {}, which isn't a SQL seed", path.display());
+            continue;
+        }
+        let name = file_name[..(file_name.len() - ".sql".len())].to_owned();
+        let version = match MigrationVersion::from_name(&name) {
+            Some(version) => version,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("The name of seed {} doesn't start with its version", name)
+                ));
+            }
+        };
+        seed_files.push((version, name, path));
+    }
+    seed_files.sort();
+
+    let mut seeds = vec![];
+    for (_, name, path) in seed_files {
+        let mut sql = String::new();
+        try!(try!(File::open(&path)).read_to_string(&mut sql));
+        seeds.push(SqlSeed::new(&name, &sql));
+    }
+    Ok(seeds)
+}
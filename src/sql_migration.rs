@@ -0,0 +1,1044 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Display, Formatter};
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use postgres;
+
+use super::error::IrreversibleMigrationError;
+use super::migration::{self, Migration};
+use super::migration_context::MigrationContext;
+use super::migration_version::MigrationVersion;
+
+
+/// A migration defined entirely by SQL, for migrations that don't need any Rust code.
+///
+/// # Examples
+///
+/// ```
+/// # use trek::migration_index::MigrationIndex;
+/// # use trek::migration_version::MigrationVersion;
+/// # use trek::sql_migration::SqlMigration;
+/// let migrations = MigrationIndex::builder()
+///     .migration(SqlMigration::new(
+///         MigrationVersion::new(20150826001350),
+///         "20150826001350_create_users_table",
+///         "CREATE TABLE users (id SERIAL PRIMARY KEY);",
+///         "DROP TABLE users;"
+///     ))
+///     .build()
+///     .unwrap();
+/// assert_eq!(migrations.len(), 1);
+/// ```
+#[derive(Clone, Debug)]
+pub struct SqlMigration {
+    version: MigrationVersion,
+    name: String,
+    up_sql: String,
+    down_sql: Option<String>,
+    transactional: bool,
+    idempotent: bool,
+    description: Option<String>,
+    tags: Vec<String>,
+    dependencies: Vec<MigrationVersion>,
+    environments: Vec<String>,
+    statement_timeout: Option<Duration>,
+    lock_timeout: Option<Duration>,
+    squashes: Option<MigrationVersion>,
+    contracts: Option<MigrationVersion>,
+}
+
+impl SqlMigration {
+    /// Creates a migration that's applied by running `up_sql` and undone by running `down_sql`.
+    pub fn new(version: MigrationVersion, name: &str, up_sql: &str, down_sql: &str) -> Self {
+        SqlMigration {
+            version: version,
+            name: name.to_owned(),
+            up_sql: up_sql.to_owned(),
+            down_sql: Some(down_sql.to_owned()),
+            transactional: true,
+            idempotent: false,
+            description: None,
+            tags: vec![],
+            dependencies: vec![],
+            environments: vec![],
+            statement_timeout: None,
+            lock_timeout: None,
+            squashes: None,
+            contracts: None,
+        }
+    }
+
+    /// Creates a migration that's applied by running `up_sql` and can't be undone.
+    pub fn irreversible(version: MigrationVersion, name: &str, up_sql: &str) -> Self {
+        SqlMigration {
+            version: version,
+            name: name.to_owned(),
+            up_sql: up_sql.to_owned(),
+            down_sql: None,
+            transactional: true,
+            idempotent: false,
+            description: None,
+            tags: vec![],
+            dependencies: vec![],
+            environments: vec![],
+            statement_timeout: None,
+            lock_timeout: None,
+            squashes: None,
+            contracts: None,
+        }
+    }
+
+    /// Creates a migration that's applied by running `up_sql` and undone by SQL derived from it
+    /// with `generate_down()`. If any of its statements can't be inverted, the migration is
+    /// irreversible instead, rather than being undone only partly.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use trek::migration::Migration;
+    /// # use trek::migration_version::MigrationVersion;
+    /// # use trek::sql_migration::SqlMigration;
+    /// let migration = SqlMigration::with_generated_down(
+    ///     MigrationVersion::new(20150826001350),
+    ///     "20150826001350_create_users_table",
+    ///     "CREATE TABLE users (id SERIAL PRIMARY KEY);
+    ///      CREATE INDEX users_id ON users (id);"
+    /// );
+    /// assert_eq!(
+    ///     migration.down_sql(),
+    ///     Some("DROP INDEX users_id;\nDROP TABLE users;".to_owned())
+    /// );
+    /// ```
+    pub fn with_generated_down(version: MigrationVersion, name: &str, up_sql: &str) -> Self {
+        let down = generate_down(up_sql);
+        if down.is_complete() {
+            return SqlMigration::new(version, name, up_sql, &down.sql());
+        }
+        for statement in down.uninvertible() {
+            warn!("Can't generate down SQL for migration {} from {}", name, statement);
+        }
+        SqlMigration::irreversible(version, name, up_sql)
+    }
+
+    /// Sets the migration's description. See `Migration::description()`. Without one, the
+    /// description is read from a "-- Description: ..." line in the comments at the start of the
+    /// up SQL, as written by `MigrationGenerator::description()`, if there is one.
+    pub fn with_description(mut self, description: &str) -> Self {
+        self.description = Some(description.to_owned());
+        self
+    }
+
+    /// Sets the migration's tags. See `Migration::tags()`.
+    pub fn with_tags(mut self, tags: &[&str]) -> Self {
+        self.tags = tags.iter().map(|tag| tag.to_string()).collect();
+        self
+    }
+
+    /// Sets the versions of the migrations this migration depends on. See
+    /// `Migration::dependencies()`.
+    pub fn with_dependencies(mut self, dependencies: &[MigrationVersion]) -> Self {
+        self.dependencies = dependencies.to_vec();
+        self
+    }
+
+    /// Restricts the migration to the given environments. See `Migration::environments()`.
+    pub fn with_environments(mut self, environments: &[&str]) -> Self {
+        self.environments = environments.iter()
+            .map(|environment| environment.to_string())
+            .collect();
+        self
+    }
+
+    /// Sets the migration's statement timeout. See `Migration::statement_timeout()`.
+    pub fn with_statement_timeout(mut self, timeout: Duration) -> Self {
+        self.statement_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the migration's lock timeout. See `Migration::lock_timeout()`.
+    pub fn with_lock_timeout(mut self, timeout: Duration) -> Self {
+        self.lock_timeout = Some(timeout);
+        self
+    }
+
+    /// Marks the migration as a baseline squashing the migrations up to and including the one with
+    /// the given version. See `Migration::squashes()`. Without it, the version is read from a
+    /// "-- Squashes: ..." line in the comments at the start of the up SQL, as written by
+    /// `squash::squash()`, if there is one.
+    pub fn with_squashes(mut self, version: MigrationVersion) -> Self {
+        self.squashes = Some(version);
+        self
+    }
+
+    /// Marks the migration as the contract half of the expand migration with the given version.
+    /// See `Migration::contracts()`. Without it, the version is read from a "-- Contracts: ..."
+    /// line in the comments at the start of the up SQL, as written by
+    /// `MigrationGenerator::create_expand_contract()`, if there is one.
+    pub fn with_contracts(mut self, version: MigrationVersion) -> Self {
+        self.contracts = Some(version);
+        self
+    }
+
+    /// Marks the migration as needing to run outside of a transaction. See
+    /// `Migration::transactional()`. Its SQL is then split into statements with
+    /// `split_statements()` and each is run on its own, since PostgreSQL runs a multi-statement
+    /// query as a single transaction.
+    pub fn non_transactional(mut self) -> Self {
+        self.transactional = false;
+        self
+    }
+
+    /// Marks the migration as safe to retry after a transient database error. See
+    /// `Migration::idempotent()`.
+    pub fn as_idempotent(mut self) -> Self {
+        self.idempotent = true;
+        self
+    }
+
+    /// Returns the value of the first "-- <key>: <value>" line in the comments at the start of the
+    /// up SQL, if there is one.
+    fn header_value(&self, key: &str) -> Option<String> {
+        self.up_sql.lines()
+            .map(|line| line.trim())
+            .take_while(|line| line.starts_with("--"))
+            .filter_map(|line| {
+                let comment = line["--".len()..].trim();
+                if comment.starts_with(key) && comment[key.len()..].starts_with(':') {
+                    Some(comment[(key.len() + 1)..].trim().to_owned())
+                } else {
+                    None
+                }
+            })
+            .next()
+    }
+
+    /// Runs the given SQL, one statement at a time if the migration is non-transactional.
+    fn execute(&self, context: &MigrationContext, sql: &str) -> postgres::Result<()> {
+        if self.transactional {
+            return context.batch_execute(sql);
+        }
+        for statement in split_statements(sql) {
+            try!(context.batch_execute(&statement));
+        }
+        Ok(())
+    }
+}
+
+impl Migration for SqlMigration {
+    fn version(&self) -> MigrationVersion {
+        self.version
+    }
+
+    fn up(&self, context: &MigrationContext) -> postgres::Result<()> {
+        self.execute(context, &self.up_sql)
+    }
+
+    fn down(&self, context: &MigrationContext) -> postgres::Result<()> {
+        match self.down_sql {
+            Some(ref down_sql) => self.execute(context, down_sql),
+            None => Err(IrreversibleMigrationError::new(self.name.clone()).into())
+        }
+    }
+
+    fn transactional(&self) -> bool {
+        self.transactional
+    }
+
+    fn idempotent(&self) -> bool {
+        self.idempotent
+    }
+
+    fn description(&self) -> Option<String> {
+        self.description.clone().or_else(|| self.header_value("Description"))
+    }
+
+    fn dependencies(&self) -> Vec<MigrationVersion> {
+        self.dependencies.clone()
+    }
+
+    fn tags(&self) -> Vec<String> {
+        self.tags.clone()
+    }
+
+    fn environments(&self) -> Vec<String> {
+        self.environments.clone()
+    }
+
+    fn statement_timeout(&self) -> Option<Duration> {
+        self.statement_timeout
+    }
+
+    fn lock_timeout(&self) -> Option<Duration> {
+        self.lock_timeout
+    }
+
+    fn checksum(&self) -> Option<String> {
+        Some(migration::content_checksum(&self.up_sql))
+    }
+
+    fn up_sql(&self) -> Option<String> {
+        Some(self.up_sql.clone())
+    }
+
+    fn down_sql(&self) -> Option<String> {
+        self.down_sql.clone()
+    }
+
+    fn squashes(&self) -> Option<MigrationVersion> {
+        self.squashes.or_else(|| {
+            self.header_value("Squashes").and_then(|version| version.parse().ok())
+        })
+    }
+
+    fn contracts(&self) -> Option<MigrationVersion> {
+        self.contracts.or_else(|| {
+            self.header_value("Contracts").and_then(|version| version.parse().ok())
+        })
+    }
+}
+
+impl Display for SqlMigration {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        write!(formatter, "{}", self.name)
+    }
+}
+
+/// Declares a `SqlMigration` inline, from its name and its up and optionally down SQL, for small
+/// migrations that don't merit files of their own. The version is parsed from the digits at the
+/// start of the name; without `down`, the migration is irreversible.
+///
+/// # Examples
+///
+/// ```
+/// #[macro_use]
+/// extern crate trek;
+///
+/// # use trek::migration_index::MigrationIndex;
+/// # fn main() {
+/// let migrations = MigrationIndex::builder()
+///     .migration(migration!(
+///         "20240101120000_create_users",
+///         up: "CREATE TABLE users (id SERIAL PRIMARY KEY);",
+///         down: "DROP TABLE users;"
+///     ))
+///     .migration(migration!(
+///         "20240102090000_backfill_users",
+///         up: "INSERT INTO users DEFAULT VALUES;"
+///     ))
+///     .build()
+///     .unwrap();
+/// assert_eq!(migrations.len(), 2);
+/// # }
+/// ```
+///
+/// # Panics
+///
+/// Panics when evaluated if the name doesn't start with a version.
+#[macro_export]
+macro_rules! migration {
+    ($name:expr, up: $up_sql:expr, down: $down_sql:expr $(,)*) => {
+        $crate::sql_migration::SqlMigration::new(
+            $crate::migration_version::MigrationVersion::from_name($name)
+                .expect("Migration names start with their version"),
+            $name,
+            $up_sql,
+            $down_sql
+        )
+    };
+    ($name:expr, up: $up_sql:expr $(,)*) => {
+        $crate::sql_migration::SqlMigration::irreversible(
+            $crate::migration_version::MigrationVersion::from_name($name)
+                .expect("Migration names start with their version"),
+            $name,
+            $up_sql
+        )
+    };
+}
+
+/// Loads the SQL migrations in the given directory. Each migration is a file named
+/// "<version>_<name>.up.sql" holding the SQL that applies it, e.g.
+/// "20150826001350_create_users_table.up.sql", and optionally a file with the same name ending in
+/// ".down.sql" holding the SQL that undoes it; migrations without one are irreversible. The
+/// migration's name is its file name without the extension. Other files are ignored. Migrations
+/// in subdirectories, such as the year and month directories `MigrationGenerator` can create,
+/// are loaded too. The migrations are returned in order of version.
+///
+/// # Failures
+///
+/// Returns an error if the directory or a migration file can't be read, if a migration's name
+/// doesn't start with its version, if a down migration has no matching up migration, or if two
+/// subdirectories hold migrations with the same name.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use std::path::{Path, PathBuf};
+/// # use trek::migration::Migration;
+/// # use trek::migration_index::MigrationIndex;
+/// # use trek::sql_migration;
+/// let migrations = sql_migration::load_directory(Path::new("migrations/")).unwrap();
+/// let migrations = MigrationIndex::builder()
+///     .migrations(migrations.into_iter().map(|migration| Box::new(migration) as Box<Migration>)
+///         .collect())
+///     .table_name("app_schema_history")
+///     .build()
+///     .unwrap();
+/// ```
+pub fn load_directory(directory: &Path) -> io::Result<Vec<SqlMigration>> {
+    let mut migrations = vec![];
+    for files in try!(find_migration_files(directory)) {
+        let up_sql = try!(read_file(&files.up_path));
+        let migration = match files.down_path {
+            Some(down_path) => {
+                SqlMigration::new(files.version, &files.name, &up_sql, &try!(read_file(&down_path)))
+            },
+            None => SqlMigration::irreversible(files.version, &files.name, &up_sql)
+        };
+        migrations.push(migration);
+    }
+    Ok(migrations)
+}
+
+/// The files making up one SQL migration in a migrations directory.
+pub(crate) struct MigrationFiles {
+    pub(crate) version: MigrationVersion,
+    pub(crate) name: String,
+    pub(crate) up_path: PathBuf,
+    pub(crate) down_path: Option<PathBuf>,
+}
+
+/// Finds the SQL migrations in the given directory, named as described by `load_directory()`,
+/// in order of version.
+pub(crate) fn find_migration_files(directory: &Path) -> io::Result<Vec<MigrationFiles>> {
+    let mut up_paths = BTreeMap::new();
+    let mut down_paths = HashMap::new();
+    for path in try!(find_files(directory)) {
+        let file_name = match path.file_name().and_then(|file_name| file_name.to_str()) {
+            Some(file_name) => file_name.to_owned(),
+            None => continue
+        };
+        let previous_path = if file_name.ends_with(".up.sql") {
+            let name = file_name[..(file_name.len() - ".up.sql".len())].to_owned();
+            up_paths.insert(name, path.clone())
+        } else if file_name.ends_with(".down.sql") {
+            let name = file_name[..(file_name.len() - ".down.sql".len())].to_owned();
+            down_paths.insert(name, path.clone())
+        } else {
+            debug!("Ignoring {}, which isn't an up or down migration", path.display());
+            continue;
+        };
+        if let Some(previous_path) = previous_path {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{} and {} define the same migration",
+                    previous_path.display(),
+                    path.display()
+                )
+            ));
+        }
+    }
+    if let Some(name) = down_paths.keys().find(|name| !up_paths.contains_key(*name)) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Migration {} has a down migration but no up migration", name)
+        ));
+    }
+
+    let mut migration_files = vec![];
+    for (name, up_path) in up_paths {
+        let version = match MigrationVersion::from_name(&name) {
+            Some(version) => version,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("The name of migration {} doesn't start with its version", name)
+                ));
+            }
+        };
+        migration_files.push(MigrationFiles {
+            version: version,
+            down_path: down_paths.remove(&name),
+            name: name,
+            up_path: up_path,
+        });
+    }
+    migration_files.sort_by_key(|files| files.version);
+    Ok(migration_files)
+}
+
+/// Returns the paths of the files in the given directory and, recursively, its subdirectories,
+/// in no particular order.
+pub(crate) fn find_files(directory: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    for entry in try!(fs::read_dir(directory)) {
+        let entry = try!(entry);
+        if try!(entry.file_type()).is_dir() {
+            files.extend(try!(find_files(&entry.path())));
+        } else {
+            files.push(entry.path());
+        }
+    }
+    Ok(files)
+}
+
+/// Splits a SQL script into its statements, at the semicolons that end them. Semicolons inside
+/// string literals (including escape strings like `E'\';'`), quoted identifiers, dollar-quoted
+/// strings such as function bodies and `DO` blocks, and comments don't end a statement. Each
+/// statement is returned without its terminating semicolon or surrounding whitespace, and parts
+/// of the script holding only whitespace and comments are dropped.
+///
+/// # Examples
+///
+/// ```
+/// # use trek::sql_migration::split_statements;
+/// let statements = split_statements("
+///     CREATE TABLE users (id SERIAL PRIMARY KEY, name TEXT);
+///     -- keep names tidy; trailing spaces are never wanted
+///     CREATE FUNCTION trim_name() RETURNS trigger AS $$
+///     BEGIN
+///         NEW.name := trim(NEW.name);
+///         RETURN NEW;
+///     END;
+///     $$ LANGUAGE plpgsql;
+/// ");
+/// assert_eq!(statements.len(), 2);
+/// assert!(statements[1].ends_with("$$ LANGUAGE plpgsql"));
+/// ```
+pub fn split_statements(sql: &str) -> Vec<String> {
+    let bytes = sql.as_bytes();
+    let mut statements = vec![];
+    let mut start = 0;
+    // whether anything other than whitespace and comments was seen since the last statement
+    let mut has_code = false;
+    let mut position = 0;
+    while position < bytes.len() {
+        let byte = bytes[position];
+        let next = bytes.get(position + 1).cloned();
+        position = match byte {
+            b';' => {
+                if has_code {
+                    statements.push(sql[start..position].trim().to_owned());
+                }
+                start = position + 1;
+                has_code = false;
+                position + 1
+            },
+            b'-' if next == Some(b'-') => {
+                match bytes[position..].iter().position(|&byte| byte == b'\n') {
+                    Some(offset) => position + offset + 1,
+                    None => bytes.len()
+                }
+            },
+            b'/' if next == Some(b'*') => skip_block_comment(bytes, position),
+            _ if byte.is_ascii_whitespace() => position + 1,
+            b'\'' => {
+                has_code = true;
+                let escapes = position > 0 && (bytes[position - 1] == b'E' ||
+                    bytes[position - 1] == b'e') &&
+                    (position < 2 || !is_identifier_byte(bytes[position - 2]));
+                skip_quoted(bytes, position, b'\'', escapes)
+            },
+            b'"' => {
+                has_code = true;
+                skip_quoted(bytes, position, b'"', false)
+            },
+            b'$' if position == 0 || !is_identifier_byte(bytes[position - 1]) => {
+                has_code = true;
+                match dollar_quote_tag(bytes, position) {
+                    Some(tag) => {
+                        let body_start = position + tag.len();
+                        match find(&bytes[body_start..], tag) {
+                            Some(offset) => body_start + offset + tag.len(),
+                            None => bytes.len()
+                        }
+                    },
+                    None => position + 1
+                }
+            },
+            _ => {
+                has_code = true;
+                position + 1
+            }
+        };
+    }
+    if has_code {
+        statements.push(sql[start..].trim().to_owned());
+    }
+    statements
+}
+
+/// Returns the statement of the script containing the character at the given position, counted
+/// from 1 like the positions PostgreSQL reports errors at, or None if the position is past the
+/// end of the script's statements.
+pub(crate) fn statement_at(sql: &str, position: usize) -> Option<String> {
+    let (byte_position, _) = sql.char_indices().nth(position.saturating_sub(1))?;
+    let mut offset = 0;
+    for statement in split_statements(sql) {
+        let start = match sql[offset..].find(&*statement) {
+            Some(start) => offset + start,
+            None => break
+        };
+        // the position of the statement's terminating semicolon counts as part of it
+        let end = start + statement.len();
+        if byte_position <= end {
+            return Some(statement);
+        }
+        offset = end;
+    }
+    None
+}
+
+/// SQL undoing a migration, derived from the SQL applying it by `generate_down()`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GeneratedDown {
+    statements: Vec<String>,
+    uninvertible: Vec<String>,
+}
+
+impl GeneratedDown {
+    /// Returns the statements undoing the up SQL's invertible statements, in the order they
+    /// should run, each without a terminating semicolon.
+    pub fn statements(&self) -> &[String] {
+        &self.statements
+    }
+
+    /// Returns the up SQL's statements that couldn't be inverted, in the order they appeared.
+    pub fn uninvertible(&self) -> &[String] {
+        &self.uninvertible
+    }
+
+    /// Returns true if every statement in the up SQL was inverted.
+    pub fn is_complete(&self) -> bool {
+        self.uninvertible.is_empty()
+    }
+
+    /// Returns the down statements as a script, one statement per line.
+    pub fn sql(&self) -> String {
+        self.statements.iter()
+            .map(|statement| format!("{};", statement))
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+/// Derives SQL undoing `up_sql`, for migrations that only make simple structural changes. Each of
+/// the following statements is inverted, in reverse order:
+///
+/// * `CREATE TABLE t ...` becomes `DROP TABLE t`.
+/// * `CREATE INDEX i ON t ...` becomes `DROP INDEX i`, or `DROP INDEX CONCURRENTLY i` if the index
+///   was created concurrently.
+/// * `ALTER TABLE t ADD COLUMN c ...` becomes `ALTER TABLE t DROP COLUMN c`. Several columns may
+///   be added in one statement, but other kinds of `ALTER TABLE` actions aren't inverted.
+///
+/// Statements creating objects only if they don't exist are inverted as if they created them.
+/// Any other statement, or an index without a name, is flagged as uninvertible, and the result is
+/// only a best effort: it's up to the caller to check `GeneratedDown::is_complete()`.
+///
+/// # Examples
+///
+/// ```
+/// # use trek::sql_migration::generate_down;
+/// let down = generate_down("
+///     CREATE TABLE users (id SERIAL PRIMARY KEY);
+///     ALTER TABLE accounts ADD COLUMN owner_id INT, ADD COLUMN note TEXT;
+///     UPDATE accounts SET note = 'migrated';
+/// ");
+/// assert_eq!(down.statements(), &[
+///     "ALTER TABLE accounts DROP COLUMN note, DROP COLUMN owner_id".to_owned(),
+///     "DROP TABLE users".to_owned(),
+/// ]);
+/// assert_eq!(down.uninvertible(), &["UPDATE accounts SET note = 'migrated'".to_owned()]);
+/// ```
+pub fn generate_down(up_sql: &str) -> GeneratedDown {
+    let mut down = GeneratedDown::default();
+    for statement in split_statements(up_sql) {
+        match invert_statement(&statement) {
+            Some(inverse) => down.statements.push(inverse),
+            None => down.uninvertible.push(statement)
+        }
+    }
+    down.statements.reverse();
+    down
+}
+
+/// Returns the statements in `sql` that destroy data: ones dropping tables, schemas, databases or
+/// columns, and `TRUNCATE` and `DELETE` statements. The trek command line tool asks for
+/// confirmation before applying migrations containing any. Statements that destroy data less
+/// directly, e.g. by changing a column's type, aren't detected.
+///
+/// # Examples
+///
+/// ```
+/// # use trek::sql_migration::destructive_statements;
+/// let statements = destructive_statements("
+///     ALTER TABLE users ADD COLUMN email TEXT, DROP COLUMN legacy_email;
+///     ALTER TABLE users ALTER COLUMN name DROP NOT NULL;
+///     DROP INDEX users_name_index;
+///     TRUNCATE sessions;
+/// ");
+/// assert_eq!(statements, vec![
+///     "ALTER TABLE users ADD COLUMN email TEXT, DROP COLUMN legacy_email".to_owned(),
+///     "TRUNCATE sessions".to_owned(),
+/// ]);
+/// ```
+pub fn destructive_statements(sql: &str) -> Vec<String> {
+    split_statements(sql).into_iter().filter(|statement| is_destructive(statement)).collect()
+}
+
+/// Returns true if the statement is one `destructive_statements()` detects.
+fn is_destructive(statement: &str) -> bool {
+    let tokens = tokenize(statement);
+    let mut tokens = Tokens { tokens: &tokens, position: 0 };
+    if tokens.keyword("TRUNCATE") || tokens.keyword("DELETE") {
+        return true;
+    }
+    if tokens.keyword("DROP") {
+        return ["TABLE", "SCHEMA", "DATABASE"].iter().any(|keyword| tokens.peek_keyword(keyword));
+    }
+    if !tokens.keywords(&["ALTER", "TABLE"]) {
+        return false;
+    }
+    tokens.keywords(&["IF", "EXISTS"]);
+    tokens.keyword("ONLY");
+    if tokens.name().is_none() {
+        return false;
+    }
+    loop {
+        // DROP CONSTRAINT only loses a rule, while DROP [COLUMN] loses the column's data
+        if tokens.keyword("DROP") && !tokens.peek_keyword("CONSTRAINT") {
+            return true;
+        }
+        if !tokens.skip_past_action() {
+            return false;
+        }
+    }
+}
+
+/// Returns the statement undoing the given one, if it's one `generate_down()` can invert.
+fn invert_statement(statement: &str) -> Option<String> {
+    if let Some(table) = created_table(statement) {
+        return Some(format!("DROP TABLE {}", table));
+    }
+    let tokens = tokenize(statement);
+    let mut tokens = Tokens { tokens: &tokens, position: 0 };
+    if tokens.keyword("CREATE") {
+        if tokens.keyword("UNIQUE") || tokens.keyword("INDEX") {
+            return invert_create_index(tokens);
+        }
+        return None;
+    }
+    if tokens.keyword("ALTER") && tokens.keyword("TABLE") {
+        return invert_add_columns(tokens);
+    }
+    None
+}
+
+/// Returns the name of the table the statement creates, as written in it, if it's a
+/// `CREATE TABLE` statement.
+pub(crate) fn created_table(statement: &str) -> Option<String> {
+    let tokens = tokenize(statement);
+    let mut tokens = Tokens { tokens: &tokens, position: 0 };
+    if !tokens.keyword("CREATE") {
+        return None;
+    }
+    for modifier in &["UNLOGGED", "TEMPORARY", "TEMP"] {
+        tokens.keyword(modifier);
+    }
+    if !tokens.keyword("TABLE") {
+        return None;
+    }
+    tokens.keywords(&["IF", "NOT", "EXISTS"]);
+    tokens.name().map(|table| table.to_owned())
+}
+
+/// Inverts a `CREATE INDEX` statement whose tokens have been consumed up to the `INDEX` keyword,
+/// or up to `UNIQUE` for unique indexes.
+fn invert_create_index(mut tokens: Tokens) -> Option<String> {
+    tokens.keyword("INDEX");
+    let concurrently = tokens.keyword("CONCURRENTLY");
+    tokens.keywords(&["IF", "NOT", "EXISTS"]);
+    let index = match tokens.name() {
+        Some(index) if !index.eq_ignore_ascii_case("ON") => index,
+        _ => return None
+    };
+    if !tokens.keyword("ON") {
+        return None;
+    }
+    tokens.keyword("ONLY");
+    let table = tokens.name()?;
+    // indexes are created in their table's schema
+    let index = match table.rfind('.') {
+        Some(dot) if !index.contains('.') => format!("{}.{}", &table[..dot], index),
+        _ => index.to_owned()
+    };
+    let concurrently = if concurrently { "CONCURRENTLY " } else { "" };
+    Some(format!("DROP INDEX {}{}", concurrently, index))
+}
+
+/// Inverts an `ALTER TABLE` statement whose tokens have been consumed up to the `TABLE` keyword,
+/// if all of its actions add columns.
+fn invert_add_columns(mut tokens: Tokens) -> Option<String> {
+    tokens.keywords(&["IF", "EXISTS"]);
+    tokens.keyword("ONLY");
+    let table = tokens.name()?;
+    let mut columns = vec![];
+    loop {
+        if !tokens.keyword("ADD") {
+            return None;
+        }
+        let is_constraint = ["CONSTRAINT", "PRIMARY", "UNIQUE", "CHECK", "FOREIGN", "EXCLUDE"]
+            .iter()
+            .any(|keyword| tokens.peek_keyword(keyword));
+        if is_constraint {
+            return None;
+        }
+        tokens.keyword("COLUMN");
+        tokens.keywords(&["IF", "NOT", "EXISTS"]);
+        columns.push(tokens.name()?);
+        if !tokens.skip_past_action() {
+            break;
+        }
+    }
+    let drops = columns.iter()
+        .rev()
+        .map(|column| format!("DROP COLUMN {}", column))
+        .collect::<Vec<String>>();
+    Some(format!("ALTER TABLE {} {}", table, drops.join(", ")))
+}
+
+/// A cursor over the tokens of a statement, for matching the statements `generate_down()`
+/// understands.
+struct Tokens<'a> {
+    tokens: &'a [&'a str],
+    position: usize,
+}
+
+impl<'a> Tokens<'a> {
+    /// Returns true if the token `offset` tokens ahead is the given keyword, ignoring case.
+    fn keyword_at(&self, offset: usize, keyword: &str) -> bool {
+        match self.tokens.get(self.position + offset) {
+            Some(token) => token.eq_ignore_ascii_case(keyword),
+            None => false
+        }
+    }
+
+    /// Returns true if the next token is the given keyword.
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        self.keyword_at(0, keyword)
+    }
+
+    /// Consumes the next token and returns true if it's the given keyword.
+    fn keyword(&mut self, keyword: &str) -> bool {
+        let matches = self.peek_keyword(keyword);
+        if matches {
+            self.position += 1;
+        }
+        matches
+    }
+
+    /// Consumes the next tokens and returns true if they're the given keywords, in order.
+    /// Otherwise consumes nothing.
+    fn keywords(&mut self, keywords: &[&str]) -> bool {
+        let matches = keywords.iter()
+            .enumerate()
+            .all(|(offset, keyword)| self.keyword_at(offset, keyword));
+        if matches {
+            self.position += keywords.len();
+        }
+        matches
+    }
+
+    /// Consumes the next token and returns it if it's a name, possibly qualified or quoted.
+    fn name(&mut self) -> Option<&'a str> {
+        let token = match self.tokens.get(self.position) {
+            Some(token) => *token,
+            None => return None
+        };
+        let first = token.as_bytes()[0];
+        let is_name = first == b'"' ||
+            (is_identifier_byte(first) && first != b'$' && !first.is_ascii_digit());
+        if !is_name {
+            return None;
+        }
+        self.position += 1;
+        Some(token)
+    }
+
+    /// Consumes the tokens up to and including the comma ending the current `ALTER TABLE` action.
+    /// Returns false if the statement ended instead.
+    fn skip_past_action(&mut self) -> bool {
+        let mut depth = 0;
+        while let Some(token) = self.tokens.get(self.position) {
+            self.position += 1;
+            match *token {
+                "(" => depth += 1,
+                ")" => depth -= 1,
+                "," if depth == 0 => return true,
+                _ => {}
+            }
+        }
+        false
+    }
+}
+
+/// Splits a statement into tokens, skipping whitespace and comments. Names, including qualified
+/// and quoted ones like `public."Users"`, string literals and dollar-quoted strings are single
+/// tokens, as is each other character that isn't part of a name.
+fn tokenize(statement: &str) -> Vec<&str> {
+    let bytes = statement.as_bytes();
+    let mut tokens = vec![];
+    let mut position = 0;
+    while position < bytes.len() {
+        let byte = bytes[position];
+        let next = bytes.get(position + 1).cloned();
+        let end = match byte {
+            b'-' if next == Some(b'-') => {
+                position = match bytes[position..].iter().position(|&byte| byte == b'\n') {
+                    Some(offset) => position + offset + 1,
+                    None => bytes.len()
+                };
+                continue;
+            },
+            b'/' if next == Some(b'*') => {
+                position = skip_block_comment(bytes, position);
+                continue;
+            },
+            _ if byte.is_ascii_whitespace() => {
+                position += 1;
+                continue;
+            },
+            b'\'' => {
+                let escapes = position > 0 && (bytes[position - 1] == b'E' ||
+                    bytes[position - 1] == b'e') &&
+                    (position < 2 || !is_identifier_byte(bytes[position - 2]));
+                skip_quoted(bytes, position, b'\'', escapes)
+            },
+            b'$' => {
+                match dollar_quote_tag(bytes, position) {
+                    Some(tag) => {
+                        let body_start = position + tag.len();
+                        match find(&bytes[body_start..], tag) {
+                            Some(offset) => body_start + offset + tag.len(),
+                            None => bytes.len()
+                        }
+                    },
+                    None => position + 1
+                }
+            },
+            _ if byte == b'"' || is_identifier_byte(byte) => skip_name(bytes, position),
+            // multi-byte characters outside names are kept whole
+            _ => {
+                (position + 1..bytes.len() + 1)
+                    .find(|&end| statement.is_char_boundary(end))
+                    .unwrap_or(bytes.len())
+            }
+        };
+        tokens.push(&statement[position..end]);
+        position = end;
+    }
+    tokens
+}
+
+/// Returns the position just past the possibly qualified name starting at `start`.
+fn skip_name(bytes: &[u8], start: usize) -> usize {
+    let mut position = start;
+    loop {
+        if bytes.get(position) == Some(&b'"') {
+            position = skip_quoted(bytes, position, b'"', false);
+        } else {
+            while position < bytes.len() && is_identifier_byte(bytes[position]) {
+                position += 1;
+            }
+        }
+        let continues = bytes.get(position) == Some(&b'.') && match bytes.get(position + 1) {
+            Some(&byte) => byte == b'"' || is_identifier_byte(byte),
+            None => false
+        };
+        if !continues {
+            return position;
+        }
+        position += 1;
+    }
+}
+
+/// Returns true if the byte can be part of an unquoted identifier, or is a non-ASCII byte of one.
+fn is_identifier_byte(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'_' || byte == b'$' || byte >= 0x80
+}
+
+/// Returns the position just past the string or identifier quoted with `quote` that starts at
+/// `start`, where a doubled quote stands for itself and, if `escapes` is set, a backslash escapes
+/// the character after it.
+fn skip_quoted(bytes: &[u8], start: usize, quote: u8, escapes: bool) -> usize {
+    let mut position = start + 1;
+    while position < bytes.len() {
+        if escapes && bytes[position] == b'\\' {
+            position += 2;
+        } else if bytes[position] == quote {
+            if bytes.get(position + 1) == Some(&quote) {
+                position += 2;
+            } else {
+                return position + 1;
+            }
+        } else {
+            position += 1;
+        }
+    }
+    bytes.len()
+}
+
+/// Returns the position just past the block comment starting at `start`. Block comments nest.
+fn skip_block_comment(bytes: &[u8], start: usize) -> usize {
+    let mut depth = 0;
+    let mut position = start;
+    while position < bytes.len() {
+        if bytes[position..].starts_with(b"/*") {
+            depth += 1;
+            position += 2;
+        } else if bytes[position..].starts_with(b"*/") {
+            depth -= 1;
+            position += 2;
+            if depth == 0 {
+                return position;
+            }
+        } else {
+            position += 1;
+        }
+    }
+    bytes.len()
+}
+
+/// Returns the dollar quote tag, e.g. `$$` or `$body$`, starting at `start`, if there is one.
+/// Positional parameters like `$1` aren't tags, since tags can't start with a digit.
+fn dollar_quote_tag(bytes: &[u8], start: usize) -> Option<&[u8]> {
+    let mut position = start + 1;
+    while position < bytes.len() {
+        let byte = bytes[position];
+        if byte == b'$' {
+            return Some(&bytes[start..(position + 1)]);
+        }
+        let valid = byte.is_ascii_alphabetic() || byte == b'_' || byte >= 0x80 ||
+            (position > start + 1 && byte.is_ascii_digit());
+        if !valid {
+            return None;
+        }
+        position += 1;
+    }
+    None
+}
+
+/// Returns the position of the first occurrence of `needle` in `haystack`.
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+/// Reads the whole contents of the file at the given path.
+fn read_file(path: &Path) -> io::Result<String> {
+    let mut contents = String::new();
+    try!(try!(File::open(path)).read_to_string(&mut contents));
+    Ok(contents)
+}
@@ -0,0 +1,234 @@
+use std::process::Command;
+
+use postgres::{Connection, TlsMode};
+
+use super::Result;
+use super::error::{Error, ResultExt};
+use super::migration_index::MigrationIndex;
+use super::migration_version::MigrationVersion;
+use super::scaffold::Scaffold;
+
+
+/// The program `squash()` dumps the schema with.
+pub const PG_DUMP: &'static str = "pg_dump";
+
+/// Dumps the schema of the database at `database_url`, which has had every migration in
+/// `migrations` applied, with `pg_dump` and returns it as the SQL of a single baseline migration
+/// squashing them all. Bootstrapping a fresh database then runs the baseline instead of every
+/// old migration; on databases where the squashed migrations were applied, `MigrationIndex::run()`
+/// records the baseline as applied without running it (see `Migration::squashes()`). Once the
+/// baseline has been deployed everywhere, the old migrations' files can be deleted.
+///
+/// Trek's own tables are left out of the dump, as are ownership and privileges. Data isn't
+/// dumped: reference data belongs in seeds (see `seed::Seed`).
+///
+/// # Examples
+///
+/// ```no_run
+/// # use std::path::Path;
+/// # use trek::{create_scaffolded_migration, MigrationTemplate};
+/// # use trek::migration::Migration;
+/// # use trek::migration_index::MigrationIndex;
+/// # use trek::squash;
+/// # let migration_list: Vec<Box<Migration>> = vec![];
+/// let migrations = MigrationIndex::new(migration_list);
+/// let squashed = squash::squash("postgresql://localhost/app_development", &migrations).unwrap();
+/// create_scaffolded_migration(
+///     "squashed_baseline",
+///     Path::new("migrations"),
+///     MigrationTemplate::Sql,
+///     &squashed.scaffold()
+/// ).unwrap();
+/// ```
+///
+/// # Failures
+///
+/// Returns an error if the database can't be connected to, if it has no migrations applied or
+/// later migrations in `migrations` left to apply, or if `pg_dump` can't be run or fails.
+pub fn squash(database_url: &str, migrations: &MigrationIndex) -> Result<SquashedSchema> {
+    let connection = match Connection::connect(database_url, TlsMode::None) {
+        Ok(connection) => connection,
+        Err(error) => {
+            return Err(Error::new(
+                "Error connecting to the database to squash".to_owned(),
+                error
+            ));
+        }
+    };
+    let squashed_version = match migrations.latest_applied_version(&connection) {
+        Ok(Some(version)) => version,
+        Ok(None) => {
+            return Err(Error::from_message(
+                "No migrations have been applied to the database, so there's nothing to squash"
+                    .to_owned()
+            ));
+        },
+        Err(error) => {
+            return Err(Error::new("Error reading the latest applied migration".to_owned(), error));
+        }
+    };
+    let outstanding = migrations.iter()
+        .filter(|migration| migration.version() > squashed_version)
+        .map(|migration| migration.to_string())
+        .collect::<Vec<String>>();
+    if !outstanding.is_empty() {
+        return Err(Error::from_message(format!(
+            "Migrations {} haven't been applied to the database; apply them before squashing",
+            outstanding.join(", ")
+        )));
+    }
+
+    Ok(SquashedSchema {
+        squashed_version: squashed_version,
+        schema_sql: try!(dump_schema(database_url, migrations)),
+    })
+}
+
+/// Dumps the schema of the database at `database_url` with `pg_dump`, for a file like
+/// `structure.sql` that's committed alongside the migrations, so the schema they build can be
+/// reviewed in pull requests, and loaded into fresh test databases faster than running every
+/// migration. It's headed by a comment naming the latest migration applied; after loading it,
+/// record the migrations up to that one as applied, e.g. with `MigrationIndex::baseline()`.
+///
+/// Like `squash()`'s, the dump only has the statements creating the schema, without Trek's own
+/// tables, ownership or privileges. `pg_dump` orders them by the kind of object, then name, so
+/// the file only changes where the schema does.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use std::fs::File;
+/// # use std::io::Write;
+/// # use trek::migration::Migration;
+/// # use trek::migration_index::MigrationIndex;
+/// # use trek::squash;
+/// # let migration_list: Vec<Box<Migration>> = vec![];
+/// let migrations = MigrationIndex::new(migration_list);
+/// let database_url = "postgresql://localhost/app_development";
+/// let structure = squash::dump_structure(database_url, &migrations).unwrap();
+/// File::create("db/structure.sql").unwrap().write_all(structure.as_bytes()).unwrap();
+/// ```
+///
+/// # Failures
+///
+/// Returns an error if the database can't be connected to, or if `pg_dump` can't be run or
+/// fails.
+pub fn dump_structure(database_url: &str, migrations: &MigrationIndex) -> Result<String> {
+    let connection = try!(
+        Connection::connect(database_url, TlsMode::None)
+            .context("Error connecting to the database to dump its schema")
+    );
+    let schema_version = try!(
+        migrations.schema_version(&connection).context("Error reading the schema version")
+    );
+    let header = match schema_version {
+        Some(name) => {
+            format!(
+                "-- The schema after applying migration {}, dumped by Trek. After loading it,\n\
+                -- record the migrations up to it as applied, e.g. with `trek baseline`.\n",
+                name
+            )
+        },
+        None => "-- The schema before any migrations were applied, dumped by Trek.\n".to_owned()
+    };
+    let schema_sql = try!(dump_schema(database_url, migrations));
+    Ok(format!("{}\n{}", header, schema_sql))
+}
+
+/// Dumps the statements creating the schema of the database at `database_url` with `pg_dump`,
+/// leaving out the tables of the migrations' version table.
+fn dump_schema(database_url: &str, migrations: &MigrationIndex) -> Result<String> {
+    // the pattern also matches the tables named after the version table, recording repeatable
+    // migrations, seeds and skipped migrations
+    let excluded_tables = migrations.version_table().qualified_name() + "*";
+    debug!("Running {} on the database to dump its schema", PG_DUMP);
+    let output = Command::new(PG_DUMP)
+        .args(&["--schema-only", "--no-owner", "--no-privileges"])
+        .arg(format!("--exclude-table={}", excluded_tables))
+        .arg(format!("--dbname={}", database_url))
+        .output();
+    let output = match output {
+        Ok(output) => output,
+        Err(error) => {
+            return Err(Error::io(format!("Error running {}", PG_DUMP), error));
+        }
+    };
+    if !output.status.success() {
+        return Err(Error::from_message(format!(
+            "{} failed ({}): {}",
+            PG_DUMP,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(clean_dump(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Removes the comments, session settings and client commands from `pg_dump` output, leaving
+/// the statements creating the schema. `pg_dump` empties the search path, so the statements are
+/// schema-qualified.
+fn clean_dump(dump: &str) -> String {
+    let mut sql = String::new();
+    let mut blank = false;
+    for line in dump.lines() {
+        if line.starts_with("--") || line.starts_with("SET ") || line.starts_with('\\') ||
+            line.starts_with("SELECT pg_catalog.set_config(")
+        {
+            continue;
+        }
+        if line.trim().is_empty() {
+            // runs of blank lines left by the removed comments are collapsed into one
+            blank = true;
+            continue;
+        }
+        if blank && !sql.is_empty() {
+            sql.push('\n');
+        }
+        sql.push_str(line);
+        sql.push('\n');
+        blank = false;
+    }
+    sql
+}
+
+/// The schema of a database, dumped by `squash()` to stand in for the migrations applied to it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SquashedSchema {
+    squashed_version: MigrationVersion,
+    schema_sql: String,
+}
+
+impl SquashedSchema {
+    /// Returns the version of the last migration squashed, the latest one applied to the
+    /// database.
+    pub fn squashed_version(&self) -> MigrationVersion {
+        self.squashed_version
+    }
+
+    /// Returns the statements creating the schema.
+    pub fn schema_sql(&self) -> &str {
+        &self.schema_sql
+    }
+
+    /// Returns the up SQL of the baseline migration: the statements creating the schema, headed
+    /// by the "-- Squashes: ..." comment `SqlMigration::squashes()` reads.
+    pub fn sql(&self) -> String {
+        format!(
+            "-- Squashes: {version}\n\
+            -- Description: Creates the schema built by the migrations up to {version}\n\
+            \n\
+            {schema_sql}",
+            version=self.squashed_version,
+            schema_sql=self.schema_sql
+        )
+    }
+
+    /// Returns a scaffold for the baseline migration, for `create_scaffolded_migration()`. Pass
+    /// `MigrationTemplate::Sql`, since the "-- Squashes: ..." comment only marks SQL migrations
+    /// as baselines; a Rust migration must return the version from `squashes()` itself. The
+    /// baseline has no down SQL, so delete the down file generated for it to make it
+    /// irreversible.
+    pub fn scaffold(&self) -> Scaffold {
+        Scaffold::sql(&self.sql(), None)
+    }
+}
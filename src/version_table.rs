@@ -0,0 +1,930 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display, Formatter};
+use std::time::Duration;
+
+use chrono::{TimeZone, Timelike, UTC};
+use postgres::{self, GenericConnection};
+
+use super::history::{AppliedMigration, SeedRun, SkippedMigration};
+use super::migration::Migration;
+use super::migration_context::MigrationContext;
+use super::migration_version::MigrationVersion;
+
+
+/// The name of the table Trek records applied migrations in when no other name is configured.
+pub const DEFAULT_TABLE_NAME: &'static str = "trek_schema_history";
+
+/// The prefix of the table names used for namespaced migration histories, followed by an
+/// underscore and the namespace.
+pub const NAMESPACED_TABLE_PREFIX: &'static str = "trek_history";
+
+/// The suffix appended to the table's name to name the table recording applied repeatable
+/// migrations.
+const REPEATABLE_TABLE_SUFFIX: &'static str = "_repeatable";
+
+/// The suffix appended to the table's name to name the table recording skipped migrations.
+const SKIPPED_TABLE_SUFFIX: &'static str = "_skipped";
+
+/// The suffix appended to the table's name to name the table recording seed runs.
+const SEEDS_TABLE_SUFFIX: &'static str = "_seeds";
+
+/// The name of the table earlier versions of Trek recorded the schema version in, as the name of
+/// the table's only column.
+const LEGACY_TABLE_NAME: &'static str = "schema_version";
+
+/// The database table Trek records applied migrations in, and the queries for reading and
+/// updating it.
+///
+/// The table has one row per applied migration, holding the migration's version, its name and
+/// description, when it was applied, how long it took to apply, its checksum, the database user
+/// who applied it, the application and deploy that applied it, the batch it was applied in,
+/// optionally its down SQL, whether it's dirty, i.e. failed partway through being applied, and
+/// whether it's the baseline left by pruning the history. The table is dropped once the last
+/// migration has been rolled back.
+#[derive(Clone, Debug)]
+pub struct VersionTable {
+    /// the schema the table lives in, or None to use the connection's current schema
+    schema: Option<String>,
+    /// the unqualified name of the table
+    name: String,
+    /// the application name and version recorded with each applied migration
+    application: Option<String>,
+    /// the deploy identifier recorded with each applied migration
+    deploy_id: Option<String>,
+    /// whether to record the down SQL of each applied migration that provides it
+    store_down_sql: bool,
+}
+impl VersionTable {
+    pub fn new(schema: Option<String>, name: String) -> Self {
+        VersionTable {
+            schema: schema,
+            name: name,
+            application: None,
+            deploy_id: None,
+            store_down_sql: false,
+        }
+    }
+
+    /// Sets the application identity and deploy identifier recorded with each migration applied
+    /// from now on.
+    pub fn set_audit_info(&mut self, application: Option<String>, deploy_id: Option<String>) {
+        self.application = application;
+        self.deploy_id = deploy_id;
+    }
+
+    /// Returns the deploy identifier recorded with each migration applied from now on, if one is
+    /// set.
+    pub fn deploy_id(&self) -> Option<&str> {
+        self.deploy_id.as_ref().map(|deploy_id| &**deploy_id)
+    }
+
+    /// Sets whether the down SQL of each migration applied from now on is recorded, for
+    /// migrations that provide it.
+    pub fn set_store_down_sql(&mut self, store_down_sql: bool) {
+        self.store_down_sql = store_down_sql;
+    }
+
+    /// Returns a copy of this table located in the given schema instead.
+    pub fn in_schema(&self, schema: &str) -> Self {
+        VersionTable {
+            schema: Some(schema.to_owned()),
+            ..self.clone()
+        }
+    }
+
+    /// Returns the schema the table lives in, if one was configured.
+    pub fn schema(&self) -> Option<&str> {
+        self.schema.as_ref().map(|schema| &**schema)
+    }
+
+    /// Returns the unqualified name of the table.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the table's name, schema-qualified if a schema was configured, quoted for use in
+    /// SQL statements.
+    pub fn qualified_name(&self) -> String {
+        self.qualify(&self.name)
+    }
+
+    /// Returns true if the table has been created.
+    pub fn exists(&self, connection: &GenericConnection) -> postgres::Result<bool> {
+        Ok(try!(self.table_oid(connection, &self.name)).is_some())
+    }
+
+    /// Returns the versions of all applied migrations.
+    pub fn applied_versions(
+        &self,
+        connection: &GenericConnection
+    ) -> postgres::Result<HashSet<MigrationVersion>> {
+        if !try!(self.exists(connection)) {
+            return Ok(HashSet::new());
+        }
+        let query = format!("SELECT version FROM {};", self.qualified_name());
+        debug!("Executing SQL: {}", query);
+        let rows = try!(connection.query(&query, &[]));
+        Ok(rows.iter().map(|row| {
+            let version: i64 = row.get(0);
+            MigrationVersion::new(version as u64)
+        }).collect())
+    }
+
+    /// Returns the version and recorded name of the applied migration with the highest version,
+    /// or None if no migrations have been applied.
+    pub fn head(
+        &self,
+        connection: &GenericConnection
+    ) -> postgres::Result<Option<(MigrationVersion, String)>> {
+        if !try!(self.exists(connection)) {
+            return Ok(None);
+        }
+        let query = format!(
+            "SELECT version, name FROM {} ORDER BY version DESC LIMIT 1;",
+            self.qualified_name()
+        );
+        debug!("Executing SQL: {}", query);
+        let rows = try!(connection.query(&query, &[]));
+        if rows.is_empty() {
+            return Ok(None);
+        }
+        let version: i64 = rows.get(0).get(0);
+        Ok(Some((MigrationVersion::new(version as u64), rows.get(0).get(1))))
+    }
+
+    /// Returns every applied migration, in the order they were applied.
+    pub fn history(
+        &self,
+        connection: &GenericConnection
+    ) -> postgres::Result<Vec<AppliedMigration>> {
+        if !try!(self.exists(connection)) {
+            return Ok(vec![]);
+        }
+        // chrono 0.2 can't be read from the database directly, so the timestamp is read as
+        // seconds since the epoch. Columns missing from tables created by earlier versions of
+        // Trek read as NULL until the next migration is recorded. Ties between migrations applied
+        // by earlier versions of Trek, which recorded the transaction's start time, are broken by
+        // version.
+        let query = format!(
+            "SELECT version, name, EXTRACT(EPOCH FROM applied_at)::float8, duration_ms, {}, {}, {},
+                {}, {}, {}, {}, {}
+            FROM {} ORDER BY applied_at, version;",
+            try!(self.column_or(connection, "checksum", "NULL::text")),
+            try!(self.column_or(connection, "applied_by", "NULL::text")),
+            try!(self.column_or(connection, "application", "NULL::text")),
+            try!(self.column_or(connection, "deploy_id", "NULL::text")),
+            try!(self.column_or(connection, "dirty", "false")),
+            try!(self.column_or(connection, "baseline", "false")),
+            try!(self.column_or(connection, "batch", "NULL::bigint")),
+            try!(self.column_or(connection, "description", "NULL::text")),
+            self.qualified_name()
+        );
+        debug!("Executing SQL: {}", query);
+        let rows = try!(connection.query(&query, &[]));
+        Ok(rows.iter().map(|row| {
+            let version: i64 = row.get(0);
+            let applied_at: f64 = row.get(2);
+            let duration_ms: Option<i64> = row.get(3);
+            let batch: Option<i64> = row.get(10);
+            AppliedMigration {
+                version: MigrationVersion::new(version as u64),
+                name: row.get(1),
+                description: row.get(11),
+                applied_at: UTC.timestamp(
+                    applied_at.floor() as i64,
+                    (applied_at.fract() * 1_000_000_000.0) as u32
+                ),
+                duration: duration_ms.map(|duration_ms| Duration::from_millis(duration_ms as u64)),
+                checksum: row.get(4),
+                applied_by: row.get(5),
+                application: row.get(6),
+                deploy_id: row.get(7),
+                dirty: row.get(8),
+                baseline: row.get(9),
+                batch: batch.map(|batch| batch as u64),
+            }
+        }).collect())
+    }
+
+    /// Records the migration as applied, along with its checksum if it has one, the current
+    /// database user, application, and deploy, and its down SQL if configured, creating the table
+    /// if it doesn't exist yet. The duration is how long the migration took to apply, or None if
+    /// it wasn't run.
+    pub fn record_applied(
+        &self,
+        connection: &GenericConnection,
+        migration: &Migration,
+        duration: Option<Duration>
+    ) -> postgres::Result<()> {
+        self.insert(connection, migration, duration, None, false)
+    }
+
+    /// Records the migration as dirty, i.e. about to be applied as part of the given batch, so
+    /// that if applying it fails partway outside of a transaction the database is known to be in
+    /// an indeterminate state. `record_finished()` marks it as cleanly applied.
+    pub fn record_started(
+        &self,
+        connection: &GenericConnection,
+        migration: &Migration,
+        batch: u64
+    ) -> postgres::Result<()> {
+        self.insert(connection, migration, None, Some(batch), true)
+    }
+
+    /// Returns the number of the next batch of migrations to be applied, one more than the latest
+    /// recorded batch.
+    pub fn next_batch(&self, connection: &GenericConnection) -> postgres::Result<u64> {
+        if !try!(self.has_column(connection, "batch")) {
+            return Ok(1);
+        }
+        let query = format!(
+            "SELECT COALESCE(max(batch), 0) + 1 FROM {};",
+            self.qualified_name()
+        );
+        debug!("Executing SQL: {}", query);
+        let rows = try!(connection.query(&query, &[]));
+        let batch: i64 = rows.get(0).get(0);
+        Ok(batch as u64)
+    }
+
+    /// Returns the versions and recorded names of the migrations applied in the latest batch,
+    /// from the highest version to the lowest, or an empty list if no migrations have been
+    /// applied in a batch.
+    pub fn last_batch(
+        &self,
+        connection: &GenericConnection
+    ) -> postgres::Result<Vec<(MigrationVersion, String)>> {
+        if !try!(self.has_column(connection, "batch")) {
+            return Ok(vec![]);
+        }
+        let query = format!(
+            "SELECT version, name FROM {0}
+            WHERE batch = (SELECT max(batch) FROM {0})
+            ORDER BY version DESC;",
+            self.qualified_name()
+        );
+        debug!("Executing SQL: {}", query);
+        let rows = try!(connection.query(&query, &[]));
+        Ok(rows.iter().map(|row| {
+            let version: i64 = row.get(0);
+            (MigrationVersion::new(version as u64), row.get(1))
+        }).collect())
+    }
+
+    /// Records that the dirty migration with the given version finished applying, taking the
+    /// given amount of time.
+    pub fn record_finished(
+        &self,
+        connection: &GenericConnection,
+        version: MigrationVersion,
+        duration: Duration
+    ) -> postgres::Result<()> {
+        let statement = format!(
+            "UPDATE {} SET dirty = false, applied_at = clock_timestamp(), duration_ms = $2
+            WHERE version = $1;",
+            self.qualified_name()
+        );
+        debug!("Executing SQL: {}", statement);
+        try!(connection.execute(
+            &statement,
+            &[&(version.value() as i64), &(duration_to_millis(duration) as i64)]
+        ));
+        Ok(())
+    }
+
+    /// Returns the version of the migration left dirty by a failed attempt to apply it, if any.
+    pub fn dirty_version(
+        &self,
+        connection: &GenericConnection
+    ) -> postgres::Result<Option<MigrationVersion>> {
+        if !try!(self.has_column(connection, "dirty")) {
+            return Ok(None);
+        }
+        let query = format!(
+            "SELECT version FROM {} WHERE dirty ORDER BY version LIMIT 1;",
+            self.qualified_name()
+        );
+        debug!("Executing SQL: {}", query);
+        let rows = try!(connection.query(&query, &[]));
+        if rows.is_empty() {
+            return Ok(None);
+        }
+        let version: i64 = rows.get(0).get(0);
+        Ok(Some(MigrationVersion::new(version as u64)))
+    }
+
+    /// Clears the dirty flag of the migration with the given version, leaving it recorded as
+    /// applied.
+    pub fn mark_clean(
+        &self,
+        connection: &GenericConnection,
+        version: MigrationVersion
+    ) -> postgres::Result<()> {
+        let statement = format!(
+            "UPDATE {} SET dirty = false WHERE version = $1;",
+            self.qualified_name()
+        );
+        debug!("Executing SQL: {}", statement);
+        try!(connection.execute(&statement, &[&(version.value() as i64)]));
+        Ok(())
+    }
+
+    /// Returns the version of the baseline left by pruning the history, if it has been pruned.
+    /// Every migration with a version up to and including the baseline's counts as applied.
+    pub fn baseline_version(
+        &self,
+        connection: &GenericConnection
+    ) -> postgres::Result<Option<MigrationVersion>> {
+        if !try!(self.has_column(connection, "baseline")) {
+            return Ok(None);
+        }
+        let query = format!(
+            "SELECT max(version) FROM {} WHERE baseline;",
+            self.qualified_name()
+        );
+        debug!("Executing SQL: {}", query);
+        let rows = try!(connection.query(&query, &[]));
+        let version: Option<i64> = rows.get(0).get(0);
+        Ok(version.map(|version| MigrationVersion::new(version as u64)))
+    }
+
+    /// Deletes the rows of the migrations with versions lower than the given one and makes the
+    /// row with the given version the baseline, standing in for all of them.
+    pub fn prune(
+        &self,
+        connection: &GenericConnection,
+        baseline_version: MigrationVersion
+    ) -> postgres::Result<()> {
+        try!(self.create(connection));
+        let version = baseline_version.value() as i64;
+        let delete_statement = format!("DELETE FROM {} WHERE version < $1;", self.qualified_name());
+        debug!("Executing SQL: {}", delete_statement);
+        try!(connection.execute(&delete_statement, &[&version]));
+        let update_statement = format!(
+            "UPDATE {} SET baseline = true WHERE version = $1;",
+            self.qualified_name()
+        );
+        debug!("Executing SQL: {}", update_statement);
+        try!(connection.execute(&update_statement, &[&version]));
+        Ok(())
+    }
+
+    /// Inserts a row for the migration, creating the table if it doesn't exist yet.
+    fn insert(
+        &self,
+        connection: &GenericConnection,
+        migration: &Migration,
+        duration: Option<Duration>,
+        batch: Option<u64>,
+        dirty: bool
+    ) -> postgres::Result<()> {
+        try!(self.create(connection));
+        let insert_statement = format!(
+            "INSERT INTO {} (
+                 version, name, applied_at, duration_ms, checksum, applied_by, application,
+                 deploy_id, batch, down_sql, dirty, description
+            ) VALUES ($1, $2, clock_timestamp(), $3, $4, current_user, $5, $6, $7, $8, $9, $10);",
+            self.qualified_name()
+        );
+        debug!("Executing SQL: {}", insert_statement);
+        let duration_ms = duration.map(|duration| duration_to_millis(duration) as i64);
+        let batch = batch.map(|batch| batch as i64);
+        let down_sql = if self.store_down_sql { migration.down_sql() } else { None };
+        try!(connection.execute(
+            &insert_statement,
+            &[
+                &(migration.version().value() as i64),
+                &migration.to_string(),
+                &duration_ms,
+                &migration.checksum(),
+                &self.application,
+                &self.deploy_id,
+                &batch,
+                &down_sql,
+                &dirty,
+                &migration.description()
+            ]
+        ));
+        Ok(())
+    }
+
+    /// Records a history entry read from an export, keeping its recorded time and details, unless
+    /// a migration with its version is already recorded. Returns true if the entry was recorded.
+    pub fn import(
+        &self,
+        connection: &GenericConnection,
+        entry: &AppliedMigration
+    ) -> postgres::Result<bool> {
+        try!(self.create(connection));
+        let insert_statement = format!(
+            "INSERT INTO {} (
+                 version, name, applied_at, duration_ms, checksum, applied_by, application,
+                 deploy_id, batch, dirty, baseline, description
+            ) VALUES ($1, $2, to_timestamp($3), $4, $5, $6, $7, $8, $9, $10, $11, $12)
+            ON CONFLICT (version) DO NOTHING;",
+            self.qualified_name()
+        );
+        debug!("Executing SQL: {}", insert_statement);
+        let applied_at = entry.applied_at.timestamp() as f64 +
+            entry.applied_at.nanosecond() as f64 / 1_000_000_000.0;
+        let inserted = try!(connection.execute(
+            &insert_statement,
+            &[
+                &(entry.version.value() as i64),
+                &entry.name,
+                &applied_at,
+                &entry.duration.map(|duration| duration_to_millis(duration) as i64),
+                &entry.checksum,
+                &entry.applied_by,
+                &entry.application,
+                &entry.deploy_id,
+                &entry.batch.map(|batch| batch as i64),
+                &entry.dirty,
+                &entry.baseline,
+                &entry.description
+            ]
+        ));
+        Ok(inserted == 1)
+    }
+
+    /// Records the migration with the given version as no longer applied. Once no applied
+    /// migrations remain, the table is dropped.
+    pub fn remove_applied(
+        &self,
+        connection: &GenericConnection,
+        version: MigrationVersion
+    ) -> postgres::Result<()> {
+        let delete_statement = format!("DELETE FROM {} WHERE version = $1;", self.qualified_name());
+        debug!("Executing SQL: {}", delete_statement);
+        try!(connection.execute(&delete_statement, &[&(version.value() as i64)]));
+        if try!(self.head(connection)).is_none() {
+            let drop_statement = format!("DROP TABLE {};", self.qualified_name());
+            debug!("Executing SQL: {}", drop_statement);
+            try!(connection.execute(&drop_statement, &[]));
+        }
+        Ok(())
+    }
+
+    /// Returns the name of the last applied migration as recorded in the single-column version
+    /// table used by earlier versions of Trek, or None if there's no legacy table to convert.
+    /// Only the default table is converted, and only if it hasn't been created yet.
+    pub fn legacy_version(
+        &self,
+        connection: &GenericConnection
+    ) -> postgres::Result<Option<String>> {
+        if self.name != DEFAULT_TABLE_NAME || try!(self.exists(connection)) {
+            return Ok(None);
+        }
+        let legacy_oid = match try!(self.table_oid(connection, LEGACY_TABLE_NAME)) {
+            Some(oid) => oid,
+            None => return Ok(None)
+        };
+        let query = "SELECT attname::text FROM pg_attribute
+            WHERE attrelid = $1 AND attnum > 0 AND NOT attisdropped;";
+        debug!("Executing SQL: {}", query);
+        let rows = try!(connection.query(query, &[&legacy_oid]));
+        if rows.len() == 1 {
+            Ok(Some(rows.get(0).get(0)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Drops the single-column version table used by earlier versions of Trek.
+    pub fn drop_legacy(&self, connection: &GenericConnection) -> postgres::Result<()> {
+        let statement = format!("DROP TABLE {};", self.qualify(LEGACY_TABLE_NAME));
+        debug!("Executing SQL: {}", statement);
+        try!(connection.execute(&statement, &[]));
+        Ok(())
+    }
+
+    /// Returns the applied migration with the given version as recorded in the table, if it was
+    /// applied with its down SQL recorded, so it can be rolled back without its code.
+    pub fn recorded_migration(
+        &self,
+        connection: &GenericConnection,
+        version: MigrationVersion
+    ) -> postgres::Result<Option<RecordedMigration>> {
+        if !try!(self.has_column(connection, "down_sql")) {
+            return Ok(None);
+        }
+        let query = format!(
+            "SELECT name, down_sql FROM {} WHERE version = $1 AND down_sql IS NOT NULL;",
+            self.qualified_name()
+        );
+        debug!("Executing SQL: {}", query);
+        let rows = try!(connection.query(&query, &[&(version.value() as i64)]));
+        if rows.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(RecordedMigration {
+            name: rows.get(0).get(0),
+            version: version,
+            down_sql: rows.get(0).get(1),
+        }))
+    }
+
+    /// Returns the recorded checksum of each applied migration, keyed by migration version.
+    /// Migrations applied without a checksum, including those applied before checksums were
+    /// recorded, are absent from the map.
+    pub fn checksums(
+        &self,
+        connection: &GenericConnection
+    ) -> postgres::Result<HashMap<MigrationVersion, String>> {
+        if !try!(self.has_column(connection, "checksum")) {
+            return Ok(HashMap::new());
+        }
+        let query = format!(
+            "SELECT version, checksum FROM {} WHERE checksum IS NOT NULL;",
+            self.qualified_name()
+        );
+        debug!("Executing SQL: {}", query);
+        let rows = try!(connection.query(&query, &[]));
+        Ok(rows.iter().map(|row| {
+            let version: i64 = row.get(0);
+            (MigrationVersion::new(version as u64), row.get(1))
+        }).collect())
+    }
+
+    /// Returns the checksum each repeatable migration had when it was last applied, keyed by
+    /// migration name.
+    pub fn repeatable_checksums(
+        &self,
+        connection: &GenericConnection
+    ) -> postgres::Result<HashMap<String, String>> {
+        let table_name = self.repeatable_table_name();
+        if try!(self.table_oid(connection, &table_name)).is_none() {
+            return Ok(HashMap::new());
+        }
+        let query = format!("SELECT name, checksum FROM {};", self.qualify(&table_name));
+        debug!("Executing SQL: {}", query);
+        let rows = try!(connection.query(&query, &[]));
+        Ok(rows.iter().map(|row| (row.get(0), row.get(1))).collect())
+    }
+
+    /// Records that the repeatable migration with the given name and checksum was applied, taking
+    /// the given amount of time, creating the repeatable migrations table if it doesn't exist
+    /// yet.
+    pub fn record_repeatable(
+        &self,
+        connection: &GenericConnection,
+        name: &str,
+        checksum: &str,
+        duration: Duration
+    ) -> postgres::Result<()> {
+        try!(self.create_schema(connection));
+        let table_name = self.qualify(&self.repeatable_table_name());
+        let create_statement = format!(
+            "CREATE TABLE IF NOT EXISTS {} (
+                 name TEXT PRIMARY KEY,
+                 checksum TEXT NOT NULL,
+                 applied_at TIMESTAMP WITH TIME ZONE NOT NULL,
+                 duration_ms BIGINT NOT NULL,
+                 applied_by TEXT NOT NULL
+            );",
+            table_name
+        );
+        debug!("Executing SQL: {}", create_statement);
+        try!(connection.batch_execute(&create_statement));
+        let upsert_statement = format!(
+            "INSERT INTO {} (name, checksum, applied_at, duration_ms, applied_by)
+            VALUES ($1, $2, clock_timestamp(), $3, current_user)
+            ON CONFLICT (name) DO UPDATE SET
+                checksum = excluded.checksum,
+                applied_at = excluded.applied_at,
+                duration_ms = excluded.duration_ms,
+                applied_by = excluded.applied_by;",
+            table_name
+        );
+        debug!("Executing SQL: {}", upsert_statement);
+        try!(connection.execute(
+            &upsert_statement,
+            &[&name, &checksum, &(duration_to_millis(duration) as i64)]
+        ));
+        Ok(())
+    }
+
+    /// Returns the migrations recorded as skipped and not applied since, in order of version.
+    pub fn skipped(
+        &self,
+        connection: &GenericConnection
+    ) -> postgres::Result<Vec<SkippedMigration>> {
+        let table_name = self.skipped_table_name();
+        if try!(self.table_oid(connection, &table_name)).is_none() {
+            return Ok(vec![]);
+        }
+        // read as seconds since the epoch, as in history()
+        let query = format!(
+            "SELECT version, name, EXTRACT(EPOCH FROM skipped_at)::float8 FROM {}
+            ORDER BY version;",
+            self.qualify(&table_name)
+        );
+        debug!("Executing SQL: {}", query);
+        let rows = try!(connection.query(&query, &[]));
+        Ok(rows.iter().map(|row| {
+            let version: i64 = row.get(0);
+            let skipped_at: f64 = row.get(2);
+            SkippedMigration {
+                version: MigrationVersion::new(version as u64),
+                name: row.get(1),
+                skipped_at: UTC.timestamp(
+                    skipped_at.floor() as i64,
+                    (skipped_at.fract() * 1_000_000_000.0) as u32
+                ),
+            }
+        }).collect())
+    }
+
+    /// Records that the migration was skipped, creating the skipped migrations table if it
+    /// doesn't exist yet.
+    pub fn record_skipped(
+        &self,
+        connection: &GenericConnection,
+        migration: &Migration
+    ) -> postgres::Result<()> {
+        try!(self.create_schema(connection));
+        let table_name = self.qualify(&self.skipped_table_name());
+        let create_statement = format!(
+            "CREATE TABLE IF NOT EXISTS {} (
+                 version BIGINT PRIMARY KEY,
+                 name TEXT NOT NULL,
+                 skipped_at TIMESTAMP WITH TIME ZONE NOT NULL
+            );",
+            table_name
+        );
+        debug!("Executing SQL: {}", create_statement);
+        try!(connection.batch_execute(&create_statement));
+        let upsert_statement = format!(
+            "INSERT INTO {} (version, name, skipped_at) VALUES ($1, $2, clock_timestamp())
+            ON CONFLICT (version) DO UPDATE SET
+                name = excluded.name,
+                skipped_at = excluded.skipped_at;",
+            table_name
+        );
+        debug!("Executing SQL: {}", upsert_statement);
+        try!(connection.execute(
+            &upsert_statement,
+            &[&(migration.version().value() as i64), &migration.to_string()]
+        ));
+        Ok(())
+    }
+
+    /// Forgets that the migration with the given version was skipped, if it was.
+    pub fn clear_skipped(
+        &self,
+        connection: &GenericConnection,
+        version: MigrationVersion
+    ) -> postgres::Result<()> {
+        let table_name = self.skipped_table_name();
+        if try!(self.table_oid(connection, &table_name)).is_none() {
+            return Ok(());
+        }
+        let delete_statement = format!(
+            "DELETE FROM {} WHERE version = $1;",
+            self.qualify(&table_name)
+        );
+        debug!("Executing SQL: {}", delete_statement);
+        try!(connection.execute(&delete_statement, &[&(version.value() as i64)]));
+        Ok(())
+    }
+
+    /// Returns the last run of each seed, in order of name.
+    pub fn seed_runs(&self, connection: &GenericConnection) -> postgres::Result<Vec<SeedRun>> {
+        let table_name = self.seeds_table_name();
+        if try!(self.table_oid(connection, &table_name)).is_none() {
+            return Ok(vec![]);
+        }
+        // read as seconds since the epoch, as in history()
+        let query = format!(
+            "SELECT name, environment, EXTRACT(EPOCH FROM run_at)::float8, duration_ms FROM {}
+            ORDER BY name;",
+            self.qualify(&table_name)
+        );
+        debug!("Executing SQL: {}", query);
+        let rows = try!(connection.query(&query, &[]));
+        Ok(rows.iter().map(|row| {
+            let run_at: f64 = row.get(2);
+            let duration_ms: i64 = row.get(3);
+            SeedRun {
+                name: row.get(0),
+                environment: row.get(1),
+                run_at: UTC.timestamp(
+                    run_at.floor() as i64,
+                    (run_at.fract() * 1_000_000_000.0) as u32
+                ),
+                duration: Duration::from_millis(duration_ms as u64),
+            }
+        }).collect())
+    }
+
+    /// Records that the seed with the given name ran in the given environment, taking the given
+    /// amount of time, creating the seeds table if it doesn't exist yet.
+    pub fn record_seed(
+        &self,
+        connection: &GenericConnection,
+        name: &str,
+        environment: Option<&str>,
+        duration: Duration
+    ) -> postgres::Result<()> {
+        try!(self.create_schema(connection));
+        let table_name = self.qualify(&self.seeds_table_name());
+        let create_statement = format!(
+            "CREATE TABLE IF NOT EXISTS {} (
+                 name TEXT PRIMARY KEY,
+                 environment TEXT,
+                 run_at TIMESTAMP WITH TIME ZONE NOT NULL,
+                 duration_ms BIGINT NOT NULL,
+                 run_by TEXT NOT NULL
+            );",
+            table_name
+        );
+        debug!("Executing SQL: {}", create_statement);
+        try!(connection.batch_execute(&create_statement));
+        let upsert_statement = format!(
+            "INSERT INTO {} (name, environment, run_at, duration_ms, run_by)
+            VALUES ($1, $2, clock_timestamp(), $3, current_user)
+            ON CONFLICT (name) DO UPDATE SET
+                environment = excluded.environment,
+                run_at = excluded.run_at,
+                duration_ms = excluded.duration_ms,
+                run_by = excluded.run_by;",
+            table_name
+        );
+        debug!("Executing SQL: {}", upsert_statement);
+        try!(connection.execute(
+            &upsert_statement,
+            &[&name, &environment, &(duration_to_millis(duration) as i64)]
+        ));
+        Ok(())
+    }
+
+    /// Returns the unqualified name of the table recording applied repeatable migrations.
+    fn repeatable_table_name(&self) -> String {
+        format!("{}{}", self.name, REPEATABLE_TABLE_SUFFIX)
+    }
+
+    /// Returns the unqualified name of the table recording seed runs.
+    fn seeds_table_name(&self) -> String {
+        format!("{}{}", self.name, SEEDS_TABLE_SUFFIX)
+    }
+
+    /// Returns the unqualified name of the table recording skipped migrations.
+    fn skipped_table_name(&self) -> String {
+        format!("{}{}", self.name, SKIPPED_TABLE_SUFFIX)
+    }
+
+    /// Creates the configured schema, if there is one and it doesn't exist yet.
+    fn create_schema(&self, connection: &GenericConnection) -> postgres::Result<()> {
+        if let Some(ref schema) = self.schema {
+            let schema_statement = format!(
+                "CREATE SCHEMA IF NOT EXISTS {};",
+                quote_identifier(schema)
+            );
+            debug!("Executing SQL: {}", schema_statement);
+            try!(connection.batch_execute(&schema_statement));
+        }
+        Ok(())
+    }
+
+    /// Creates the table if it doesn't exist yet, along with its schema if one was configured, and
+    /// adds any columns missing from a table created by an earlier version of Trek.
+    fn create(&self, connection: &GenericConnection) -> postgres::Result<()> {
+        try!(self.create_schema(connection));
+        let create_statement = format!(
+            "CREATE TABLE IF NOT EXISTS {0} (
+                 version BIGINT PRIMARY KEY,
+                 name TEXT NOT NULL,
+                 description TEXT,
+                 applied_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                 duration_ms BIGINT,
+                 checksum TEXT,
+                 applied_by TEXT,
+                 application TEXT,
+                 deploy_id TEXT,
+                 batch BIGINT,
+                 down_sql TEXT,
+                 dirty BOOLEAN NOT NULL DEFAULT false,
+                 baseline BOOLEAN NOT NULL DEFAULT false
+            );
+            ALTER TABLE {0}
+                ADD COLUMN IF NOT EXISTS description TEXT,
+                ADD COLUMN IF NOT EXISTS checksum TEXT,
+                ADD COLUMN IF NOT EXISTS applied_by TEXT,
+                ADD COLUMN IF NOT EXISTS application TEXT,
+                ADD COLUMN IF NOT EXISTS deploy_id TEXT,
+                ADD COLUMN IF NOT EXISTS batch BIGINT,
+                ADD COLUMN IF NOT EXISTS down_sql TEXT,
+                ADD COLUMN IF NOT EXISTS dirty BOOLEAN NOT NULL DEFAULT false,
+                ADD COLUMN IF NOT EXISTS baseline BOOLEAN NOT NULL DEFAULT false;",
+            self.qualified_name()
+        );
+        debug!("Executing SQL: {}", create_statement);
+        connection.batch_execute(&create_statement)
+    }
+
+    /// Returns true if the table exists and has a column with the given name.
+    fn has_column(&self, connection: &GenericConnection, column: &str) -> postgres::Result<bool> {
+        let oid = match try!(self.table_oid(connection, &self.name)) {
+            Some(oid) => oid,
+            None => return Ok(false)
+        };
+        let query = "SELECT EXISTS (
+                SELECT 1 FROM pg_attribute
+                WHERE attrelid = $1 AND attname = $2 AND NOT attisdropped
+            );";
+        debug!("Executing SQL: {}", query);
+        let rows = try!(connection.query(query, &[&oid, &column]));
+        Ok(rows.get(0).get(0))
+    }
+
+    /// Returns the OID of the table with the given unqualified name in this table's schema, or in
+    /// the connection's current schema if no schema was configured. Only the current schema is
+    /// checked rather than the whole search_path, which is also where an unqualified table is
+    /// created, so a table of the same name further along the search_path, e.g. one belonging to
+    /// another application, isn't mistaken for this one.
+    fn table_oid(
+        &self,
+        connection: &GenericConnection,
+        name: &str
+    ) -> postgres::Result<Option<u32>> {
+        let query = "SELECT c.oid FROM pg_class c
+            JOIN pg_namespace n ON n.oid = c.relnamespace
+            WHERE n.nspname = COALESCE($1, current_schema()) AND c.relname = $2;";
+        debug!("Executing SQL: {}", query);
+        let rows = try!(connection.query(query, &[&self.schema, &name]));
+        if rows.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(rows.get(0).get(0)))
+        }
+    }
+
+    /// Returns the quoted name of the column if the table has it, or the given SQL expression
+    /// otherwise, for selecting columns that tables created by earlier versions of Trek lack.
+    fn column_or(
+        &self,
+        connection: &GenericConnection,
+        column: &str,
+        default: &str
+    ) -> postgres::Result<String> {
+        if try!(self.has_column(connection, column)) {
+            Ok(quote_identifier(column))
+        } else {
+            Ok(default.to_owned())
+        }
+    }
+
+    /// Returns the given table name qualified with this table's schema, if one was configured,
+    /// quoted for use in SQL statements.
+    fn qualify(&self, name: &str) -> String {
+        match self.schema {
+            Some(ref schema) => format!("{}.{}", quote_identifier(schema), quote_identifier(name)),
+            None => quote_identifier(name)
+        }
+    }
+}
+
+impl Default for VersionTable {
+    fn default() -> Self {
+        VersionTable::new(None, DEFAULT_TABLE_NAME.to_owned())
+    }
+}
+
+/// An applied migration that's no longer registered, reconstructed from the down SQL recorded when
+/// it was applied. It can only be rolled back.
+pub struct RecordedMigration {
+    name: String,
+    version: MigrationVersion,
+    down_sql: String,
+}
+
+impl Migration for RecordedMigration {
+    fn version(&self) -> MigrationVersion {
+        self.version
+    }
+
+    fn up(&self, _context: &MigrationContext) -> postgres::Result<()> {
+        unreachable!("Recorded migration {} can only be rolled back", self.name)
+    }
+
+    fn down(&self, context: &MigrationContext) -> postgres::Result<()> {
+        context.batch_execute(&self.down_sql)
+    }
+}
+
+impl Display for RecordedMigration {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        write!(formatter, "{}", self.name)
+    }
+}
+
+/// Converts a duration to whole milliseconds, rounding down, e.g. for storing in a duration_ms
+/// column.
+pub(crate) fn duration_to_millis(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + u64::from(duration.subsec_nanos() / 1_000_000)
+}
+
+/// Quotes an identifier such as a table or column name so it can be safely interpolated into SQL.
+pub fn quote_identifier(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
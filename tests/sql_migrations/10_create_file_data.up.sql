@@ -0,0 +1 @@
+CREATE TABLE file_data (id INT);
@@ -0,0 +1 @@
+INSERT INTO file_data VALUES (1);
@@ -1,17 +1,60 @@
 extern crate chrono;
+extern crate log;
 extern crate postgres;
+#[cfg(feature = "serde")]
+extern crate serde_json;
+#[macro_use]
 extern crate trek;
 
+use std::cell::{Cell, RefCell};
 use std::env;
+use std::error::Error as StdError;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::rc::Rc;
+use std::thread;
+use std::time::Duration;
 
+use chrono::UTC;
 use postgres::{Connection, TlsMode};
 
+use trek::MigrationGenerator;
+use trek::codegen;
+use trek::config::{self, Config};
+use trek::data::{self, BatchOptions};
+use trek::database;
+use trek::diff::{self, Table};
+use trek::embed;
+use trek::error::{MigrationPhase, ResultExt};
+use trek::fn_migration::FnMigration;
+use trek::history::PruneCutoff;
+use trek::migration::{Migration, TagFilter};
+use trek::migration_context::{MigrationContext, RetryPolicy, RunOptions};
 use trek::migration_index::MigrationIndex;
+use trek::migration_version::{MigrationVersion, SequentialVersions};
+use trek::progress::MigrationEvent;
+use trek::record;
+use trek::repeatable_migration::RepeatableSqlMigration;
+use trek::reversible_migration::Changes;
+use trek::scaffold::{Column, Scaffold};
+use trek::seed::{self, SqlSeed};
+use trek::sql_migration;
+use trek::testing;
 
 use self::types::good_migration_1::GoodMigration1;
 use self::types::good_migration_2::GoodMigration2;
 use self::types::good_migration_up_bad_migration_down::GoodMigrationUpBadMigrationDown;
 use self::types::bad_migration_1::BadMigration1;
+use self::types::checksummed_migration::ChecksummedMigration;
+use self::types::conditional_migration::ConditionalMigration;
+use self::types::defined_migration::DefinedMigration;
+use self::types::irreversible_migration::IrreversibleMigration;
+use self::types::migration_25_create_template_data::CreateTemplateData;
+use self::types::migration_26_create_reversible_data::CreateReversibleData;
+use self::types::non_transactional_migration::NonTransactionalMigration;
+use self::types::partially_failing_migration::PartiallyFailingMigration;
+use self::types::sql_migration::SqlMigration;
 
 mod types;
 
@@ -30,6 +73,22 @@ fn new_test_connection() -> Connection {
     Connection::connect(&*db_params, TlsMode::None).unwrap()
 }
 
+/// Returns the URL in the TREK_TEST_DB_PARAMS environment variable with its database replaced by
+/// the given one.
+fn test_database_url(database: &str) -> String {
+    let db_params = env::var("TREK_TEST_DB_PARAMS").unwrap();
+    let (url, query) = match db_params.find('?') {
+        Some(position) => db_params.split_at(position),
+        None => (&*db_params, "")
+    };
+    let authority_start = url.find("://").map(|position| position + 3).unwrap_or(0);
+    let server = match url[authority_start..].find('/') {
+        Some(position) => &url[..(authority_start + position)],
+        None => url
+    };
+    format!("{}/{}{}", server, database, query)
+}
+
 #[test]
 fn can_run_migration() {
     let connection = new_test_connection();
@@ -48,7 +107,7 @@ fn can_run_migration() {
     assert!(migration_ran);
 
     // check schema version is correct
-    let schema_version = MigrationIndex::schema_version(&transaction).unwrap();
+    let schema_version = migration_index.schema_version(&transaction).unwrap();
     assert!(schema_version.is_some());
     assert_eq!(
         schema_version.unwrap(),
@@ -77,7 +136,7 @@ fn can_rollback_migration() {
     assert_eq!(result.len(), 0);
 
     // check schema version is correct
-    assert!(MigrationIndex::schema_version(&transaction).unwrap().is_none());
+    assert!(migration_index.schema_version(&transaction).unwrap().is_none());
 }
 
 #[test]
@@ -101,7 +160,7 @@ fn can_apply_migrations_sequentially() {
     assert!(migration_ran);
 
     // check schema version is correct
-    let schema_version = MigrationIndex::schema_version(&transaction).unwrap();
+    let schema_version = migration_index.schema_version(&transaction).unwrap();
     assert!(schema_version.is_some());
     assert_eq!(
         schema_version.unwrap(),
@@ -135,7 +194,7 @@ fn can_rollback_migrations_sequentially() {
 
     let migration_ran: String = result.get(0).get(0);
     assert_eq!(migration_ran, "good_migration_1_ran");
-    let schema_version = MigrationIndex::schema_version(&transaction).unwrap();
+    let schema_version = migration_index.schema_version(&transaction).unwrap();
     assert!(schema_version.is_some());
     assert_eq!(
         schema_version.unwrap(),
@@ -150,29 +209,2901 @@ fn can_rollback_migrations_sequentially() {
         .unwrap();
     let result = prepared_statement.query(&[&schema_name]).unwrap();
     assert_eq!(result.len(), 0);
-    assert!(MigrationIndex::schema_version(&transaction).unwrap().is_none());
+    assert!(migration_index.schema_version(&transaction).unwrap().is_none());
 }
 
 #[test]
-fn fails_gracefully_on_migration_run_error() {
+fn records_each_applied_migration_in_history_table() {
     let connection = new_test_connection();
     let transaction = connection.transaction().unwrap();
     let migration_index = MigrationIndex::new(
-        vec![Box::new(BadMigration1::new())]
+        vec![
+            Box::new(GoodMigration1::new()),
+            Box::new(GoodMigration2::new()),
+        ]
+    );
+    migration_index.run(&transaction).unwrap();
+
+    // check that there's one row per applied migration
+    let prepared_statement = transaction.prepare(
+            "SELECT version, name FROM trek_schema_history ORDER BY version;"
+        ).unwrap();
+    let result = prepared_statement.query(&[]).unwrap();
+    assert_eq!(result.len(), 2);
+    let version: i64 = result.get(0).get(0);
+    let name: String = result.get(0).get(1);
+    assert_eq!((version, &*name), (1, "GoodMigration1"));
+    let version: i64 = result.get(1).get(0);
+    let name: String = result.get(1).get(1);
+    assert_eq!((version, &*name), (2, "GoodMigration2"));
+
+    // rolling back removes only the last migration's row
+    migration_index.rollback(&transaction).unwrap();
+    let result = prepared_statement.query(&[]).unwrap();
+    assert_eq!(result.len(), 1);
+    let name: String = result.get(0).get(1);
+    assert_eq!(name, "GoodMigration1");
+}
+
+#[test]
+fn history_lists_applied_migrations_with_timings() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let migration_index = MigrationIndex::new(
+        vec![
+            Box::new(GoodMigration1::new()),
+            Box::new(GoodMigration2::new()),
+        ]
+    );
+    assert!(migration_index.history(&transaction).unwrap().is_empty());
+    migration_index.mark_applied(&transaction, MigrationVersion::new(1)).unwrap();
+    migration_index.run(&transaction).unwrap();
+
+    let history = migration_index.history(&transaction).unwrap();
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[0].name(), "GoodMigration1");
+    assert_eq!(history[0].version().value(), 1);
+    // migrations recorded without being run have no duration
+    assert!(history[0].duration().is_none());
+    assert_eq!(history[1].name(), "GoodMigration2");
+    assert_eq!(history[1].version().value(), 2);
+    assert!(history[1].duration().is_some());
+    assert!(history[1].applied_at() <= UTC::now());
+}
+
+#[test]
+fn history_records_who_applied_each_migration() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let migration_index = MigrationIndex::builder()
+        .migration(GoodMigration1::new())
+        .application("trek-tests 1.0")
+        .deploy_id("deploy-42")
+        .build()
+        .unwrap();
+    migration_index.run(&transaction).unwrap();
+
+    let current_user: String = transaction.query("SELECT current_user::text;", &[]).unwrap()
+        .get(0).get(0);
+    let history = migration_index.history(&transaction).unwrap();
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].applied_by(), Some(&*current_user));
+    assert_eq!(history[0].application(), Some("trek-tests 1.0"));
+    assert_eq!(history[0].deploy_id(), Some("deploy-42"));
+}
+
+#[test]
+fn history_records_migration_descriptions() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let migration_index = MigrationIndex::builder()
+        .migration(GoodMigration1::new())
+        .migration(
+            sql_migration::SqlMigration::new(
+                MigrationVersion::new(9),
+                "create_built_in_sql_data",
+                "CREATE TABLE built_in_sql_data (id INT);",
+                "DROP TABLE built_in_sql_data;"
+            ).with_description("Adds a table for built-in SQL migration data")
+        )
+        .build()
+        .unwrap();
+    migration_index.run(&transaction).unwrap();
+
+    let history = migration_index.history(&transaction).unwrap();
+    assert_eq!(history[0].description(), None);
+    assert_eq!(
+        history[1].description(),
+        Some("Adds a table for built-in SQL migration data")
+    );
+}
+
+#[test]
+fn converts_legacy_schema_version_table() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    // the state earlier versions of Trek left the database in after applying GoodMigration1
+    transaction.batch_execute(
+        "CREATE TABLE data (good_migration_1_ran BOOLEAN);
+        INSERT INTO data (good_migration_1_ran) VALUES (true);
+        CREATE TABLE schema_version (\"GoodMigration1\" INT NOT NULL);"
+    ).unwrap();
+    let migration_index = MigrationIndex::new(
+        vec![
+            Box::new(GoodMigration1::new()),
+            Box::new(GoodMigration2::new()),
+        ]
+    );
+    assert_eq!(
+        migration_index.schema_version(&transaction).unwrap().unwrap(),
+        "GoodMigration1"
+    );
+    migration_index.run(&transaction).unwrap();
+
+    // only the migration after the legacy version was run
+    let history = migration_index.history(&transaction).unwrap();
+    assert_eq!(history.len(), 2);
+    assert!(history[0].duration().is_none());
+    assert!(history[1].duration().is_some());
+    assert_eq!(
+        migration_index.schema_version(&transaction).unwrap().unwrap(),
+        "GoodMigration2"
+    );
+
+    // the legacy table is gone
+    let prepared_statement = transaction.prepare(
+            "SELECT table_name FROM information_schema.tables WHERE table_name=$1;"
+        ).unwrap();
+    let result = prepared_statement.query(&[&"schema_version"]).unwrap();
+    assert_eq!(result.len(), 0);
+}
+
+#[test]
+fn can_roll_back_unregistered_migration_with_stored_down_sql() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let old_index = MigrationIndex::builder()
+        .migration(SqlMigration::new())
+        .store_down_sql(true)
+        .build()
+        .unwrap();
+    old_index.run(&transaction).unwrap();
+
+    // a newer release no longer has the migration's code
+    let new_index = MigrationIndex::new(vec![]);
+    new_index.rollback(&transaction).unwrap();
+
+    let prepared_statement = transaction.prepare(
+            "SELECT table_name FROM information_schema.tables WHERE table_name=$1;"
+        ).unwrap();
+    let result = prepared_statement.query(&[&"sql_data"]).unwrap();
+    assert_eq!(result.len(), 0);
+    assert!(new_index.schema_version(&transaction).unwrap().is_none());
+}
+
+#[test]
+fn fails_to_roll_back_unregistered_migration_without_stored_down_sql() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let old_index = MigrationIndex::new(vec![Box::new(SqlMigration::new())]);
+    old_index.run(&transaction).unwrap();
+
+    let new_index = MigrationIndex::new(vec![]);
+    assert!(new_index.rollback(&transaction).is_err());
+}
+
+#[test]
+fn plans_outstanding_migrations_without_applying_them() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    MigrationIndex::new(vec![Box::new(GoodMigration1::new())]).run(&transaction).unwrap();
+    let migration_index = MigrationIndex::builder()
+        .migration(GoodMigration1::new())
+        .migration(sql_migration::SqlMigration::new(
+            MigrationVersion::new(36),
+            "36_create_planned_data",
+            "CREATE TABLE planned_data (id INT);",
+            "DROP TABLE planned_data;"
+        ))
+        .build()
+        .unwrap();
+
+    let plan = migration_index.plan(&transaction).unwrap();
+    assert_eq!(
+        plan.iter().map(|migration| migration.to_string()).collect::<Vec<_>>(),
+        vec!["36_create_planned_data".to_owned()]
+    );
+    assert_eq!(plan[0].up_sql(), Some("CREATE TABLE planned_data (id INT);".to_owned()));
+    assert!(!testing::table_exists(&transaction, "planned_data").unwrap());
+    assert_eq!(migration_index.history(&transaction).unwrap().len(), 1);
+}
+
+#[test]
+fn refuses_to_run_when_applied_migration_is_not_registered() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let newer_index = MigrationIndex::new(
+        vec![Box::new(GoodMigration1::new()), Box::new(GoodMigration2::new())]
+    );
+    newer_index.run(&transaction).unwrap();
+
+    // an older release doesn't know about the second migration
+    let older_index = MigrationIndex::new(
+        vec![Box::new(GoodMigration1::new()), Box::new(SqlMigration::new())]
+    );
+    match older_index.run(&transaction) {
+        Err(trek::error::Error::UnknownAppliedVersion { ref migration_name, .. }) => {
+            assert_eq!(migration_name, "GoodMigration2");
+        },
+        result => panic!("Expected an UnknownAppliedVersion error, got {:?}", result),
+    }
+    assert!(!testing::table_exists(&transaction, "sql_data").unwrap());
+}
+
+#[test]
+fn verify_reports_every_drifted_migration() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let migration_index = MigrationIndex::new(
+        vec![
+            Box::new(ChecksummedMigration::new("original")),
+            Box::new(GoodMigration1::new()),
+            Box::new(GoodMigration2::new()),
+        ]
+    );
+    migration_index.run(&transaction).unwrap();
+    assert!(migration_index.verify(&transaction).unwrap().is_ok());
+
+    // an older release doesn't know about the last migration
+    let older_index = MigrationIndex::new(
+        vec![Box::new(ChecksummedMigration::new("original")), Box::new(GoodMigration1::new())]
+    );
+    assert_eq!(older_index.verify(&transaction).unwrap().unknown(), ["GoodMigration2".to_owned()]);
+
+    // the last migration was deleted, and the first modified
+    let drifted_index = MigrationIndex::new(
+        vec![
+            Box::new(ChecksummedMigration::new("modified")),
+            Box::new(GoodMigration1::new()),
+            Box::new(SqlMigration::new()),
+        ]
+    );
+    let report = drifted_index.verify(&transaction).unwrap();
+    assert!(!report.is_ok());
+    assert_eq!(report.dirty(), None);
+    assert_eq!(report.modified(), ["ChecksummedMigration".to_owned()]);
+    assert_eq!(report.missing(), ["GoodMigration2".to_owned()]);
+    assert!(report.unknown().is_empty());
+    assert_eq!(report.pending(), ["SqlMigration".to_owned()]);
+    assert!(!testing::table_exists(&transaction, "sql_data").unwrap());
+}
+
+#[test]
+fn renaming_applied_migration_does_not_rerun_it() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let migration_index = MigrationIndex::new(vec![Box::new(GoodMigration1::new())]);
+    migration_index.run(&transaction).unwrap();
+
+    // applied state is keyed by version, so the renamed migration counts as applied
+    let renamed_index = MigrationIndex::new(
+        vec![Box::new(GoodMigration1::with_name("RenamedMigration1"))]
+    );
+    renamed_index.run(&transaction).unwrap();
+    assert_eq!(renamed_index.history(&transaction).unwrap().len(), 1);
+    assert_eq!(
+        renamed_index.schema_version(&transaction).unwrap().unwrap(),
+        "RenamedMigration1"
+    );
+    renamed_index.rollback(&transaction).unwrap();
+    assert!(renamed_index.schema_version(&transaction).unwrap().is_none());
+}
+
+#[test]
+fn ignores_version_table_outside_current_schema() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let migration_index = MigrationIndex::new(vec![Box::new(GoodMigration1::new())]);
+    migration_index.run(&transaction).unwrap();
+
+    // the table created in the original schema is still on the search_path, but isn't in the
+    // current schema
+    transaction.batch_execute(
+        "CREATE SCHEMA other_application;
+        SELECT set_config(
+            'search_path', 'other_application, ' || quote_ident(current_schema()), true
+        );"
+    ).unwrap();
+    assert!(migration_index.schema_version(&transaction).unwrap().is_none());
+    migration_index.run(&transaction).unwrap();
+    assert_eq!(
+        migration_index.schema_version(&transaction).unwrap().unwrap(),
+        "GoodMigration1"
+    );
+    let prepared_statement = transaction.prepare(
+            "SELECT table_schema FROM information_schema.tables WHERE table_name=$1;"
+        ).unwrap();
+    let result = prepared_statement.query(&[&"trek_schema_history"]).unwrap();
+    assert_eq!(result.len(), 2);
+}
+
+#[test]
+fn refuses_to_run_while_a_migration_is_dirty() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let migration_index = MigrationIndex::new(
+        vec![
+            Box::new(GoodMigration1::new()),
+            Box::new(PartiallyFailingMigration::new()),
+        ]
+    );
+    assert!(migration_index.run(&transaction).is_err());
+
+    let history = migration_index.history(&transaction).unwrap();
+    assert_eq!(history.len(), 2);
+    assert!(!history[0].is_dirty());
+    assert!(history[1].is_dirty());
+    let error = migration_index.run(&transaction).unwrap_err();
+    assert!(error.hint().unwrap().contains("force_clean()"));
+    assert!(error.to_string().contains("Hint: "));
+    assert!(migration_index.rollback(&transaction).is_err());
+
+    // the operator finishes the migration by hand
+    migration_index.force_clean(&transaction, MigrationVersion::new(6)).unwrap();
+    assert!(!migration_index.history(&transaction).unwrap()[1].is_dirty());
+    migration_index.run(&transaction).unwrap();
+    assert_eq!(
+        migration_index.schema_version(&transaction).unwrap().unwrap(),
+        "PartiallyFailingMigration"
+    );
+}
+
+#[test]
+fn force_clean_can_record_dirty_migration_as_not_applied() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let migration_index = MigrationIndex::new(
+        vec![
+            Box::new(GoodMigration1::new()),
+            Box::new(PartiallyFailingMigration::new()),
+        ]
     );
     assert!(migration_index.run(&transaction).is_err());
+
+    // the operator reverts the migration's changes by hand
+    transaction.batch_execute("DROP TABLE half_done;").unwrap();
+    migration_index.force_clean(&transaction, MigrationVersion::new(1)).unwrap();
+    assert_eq!(migration_index.history(&transaction).unwrap().len(), 1);
+    assert_eq!(
+        migration_index.schema_version(&transaction).unwrap().unwrap(),
+        "GoodMigration1"
+    );
 }
 
 #[test]
-fn fails_gracefully_on_migration_rollback_error() {
+fn can_prune_history_before_a_version() {
     let connection = new_test_connection();
     let transaction = connection.transaction().unwrap();
     let migration_index = MigrationIndex::new(
         vec![
             Box::new(GoodMigration1::new()),
-            Box::new(GoodMigrationUpBadMigrationDown::new()),
+            Box::new(GoodMigration2::new()),
+            Box::new(SqlMigration::new()),
         ]
     );
     migration_index.run(&transaction).unwrap();
+
+    let pruned = migration_index.prune_history(
+        &transaction, PruneCutoff::Version(MigrationVersion::new(5))
+    ).unwrap();
+    assert_eq!(pruned.len(), 1);
+    assert_eq!(pruned[0].name(), "GoodMigration1");
+
+    let history = migration_index.history(&transaction).unwrap();
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[0].name(), "GoodMigration2");
+    assert!(history[0].is_baseline());
+    assert_eq!(history[1].name(), "SqlMigration");
+    assert!(!history[1].is_baseline());
+
+    // the pruned migration still counts as applied
+    migration_index.run(&transaction).unwrap();
+    assert_eq!(migration_index.history(&transaction).unwrap().len(), 2);
+
+    // the head can be rolled back, but not the baseline
+    migration_index.rollback(&transaction).unwrap();
     assert!(migration_index.rollback(&transaction).is_err());
 }
+
+#[test]
+fn can_roll_back_last_batch_of_migrations() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let first_index = MigrationIndex::new(vec![Box::new(GoodMigration1::new())]);
+    first_index.run(&transaction).unwrap();
+    let second_index = MigrationIndex::new(
+        vec![
+            Box::new(GoodMigration1::new()),
+            Box::new(GoodMigration2::new()),
+            Box::new(SqlMigration::new()),
+        ]
+    );
+    second_index.run(&transaction).unwrap();
+
+    let history = second_index.history(&transaction).unwrap();
+    assert_eq!(history[0].batch(), Some(1));
+    assert_eq!(history[1].batch(), Some(2));
+    assert_eq!(history[2].batch(), Some(2));
+
+    second_index.rollback_last_batch(&transaction).unwrap();
+    assert_eq!(
+        second_index.schema_version(&transaction).unwrap().unwrap(),
+        "GoodMigration1"
+    );
+    second_index.rollback_last_batch(&transaction).unwrap();
+    assert_eq!(second_index.schema_version(&transaction).unwrap(), None);
+}
+
+#[test]
+fn can_run_and_roll_back_to_a_version() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let migration_index = MigrationIndex::new(
+        vec![
+            Box::new(GoodMigration1::new()),
+            Box::new(GoodMigration2::new()),
+            Box::new(SqlMigration::new()),
+        ]
+    );
+
+    migration_index.run_to(&transaction, MigrationVersion::new(2)).unwrap();
+    assert_eq!(
+        migration_index.schema_version(&transaction).unwrap().unwrap(),
+        "GoodMigration2"
+    );
+    assert!(!testing::table_exists(&transaction, "sql_data").unwrap());
+    assert!(migration_index.run_to(&transaction, MigrationVersion::new(3)).is_err());
+
+    migration_index.run(&transaction).unwrap();
+    migration_index.rollback_to(&transaction, MigrationVersion::new(1)).unwrap();
+    assert_eq!(
+        migration_index.schema_version(&transaction).unwrap().unwrap(),
+        "GoodMigration1"
+    );
+    assert!(!testing::table_exists(&transaction, "sql_data").unwrap());
+    assert_eq!(migration_index.history(&transaction).unwrap().len(), 1);
+}
+
+#[test]
+#[cfg(feature = "serde")]
+fn can_export_and_import_history() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let migration_index = MigrationIndex::new(
+        vec![
+            Box::new(GoodMigration1::new()),
+            Box::new(GoodMigration2::new()),
+        ]
+    );
+    migration_index.run(&transaction).unwrap();
+    let exported = migration_index.export_history(&transaction).unwrap();
+    let document = serde_json::from_str::<serde_json::Value>(&exported).unwrap();
+    assert_eq!(document["format_version"], 1);
+    assert_eq!(document["migrations"][1]["name"], "GoodMigration2");
+
+    migration_index.rollback(&transaction).unwrap();
+    migration_index.rollback(&transaction).unwrap();
+    assert_eq!(migration_index.import_history(&transaction, &exported).unwrap(), 2);
+    assert_eq!(
+        migration_index.schema_version(&transaction).unwrap().unwrap(),
+        "GoodMigration2"
+    );
+    let history = migration_index.history(&transaction).unwrap();
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[0].name(), "GoodMigration1");
+    assert_eq!(history[0].batch(), Some(1));
+
+    // already recorded migrations are skipped
+    assert_eq!(migration_index.import_history(&transaction, &exported).unwrap(), 0);
+    assert!(migration_index.import_history(&transaction, "{\"migrations\": []}").is_err());
+    let invalid_timestamp = exported.replacen(
+        &history[0].applied_at().to_rfc3339(), "yesterday", 1
+    );
+    assert!(migration_index.import_history(&transaction, &invalid_timestamp).is_err());
+}
+
+#[test]
+fn runs_non_transactional_migration_outside_of_transaction() {
+    let connection = new_test_connection();
+    let migration_index = MigrationIndex::builder()
+        .migration(NonTransactionalMigration::new())
+        .table_name("non_transactional_schema_version")
+        .build()
+        .unwrap();
+    {
+        let transaction = connection.transaction().unwrap();
+        assert!(migration_index.run(&transaction).is_err());
+    }
+
+    migration_index.run_in_transactions(&connection).unwrap();
+    assert_eq!(
+        migration_index.schema_version(&connection).unwrap().unwrap(),
+        "NonTransactionalMigration"
+    );
+    // the migration was committed, so clean up after it
+    migration_index.rollback(&connection).unwrap();
+    assert_eq!(migration_index.schema_version(&connection).unwrap(), None);
+}
+
+#[test]
+fn runs_non_transactional_migration_outside_of_transaction_up_to_a_version() {
+    let connection = new_test_connection();
+    let migration_index = MigrationIndex::builder()
+        .migration(NonTransactionalMigration::new())
+        .migration(sql_migration::SqlMigration::new(
+            MigrationVersion::new(31),
+            "create_after_concurrent_data",
+            "CREATE TABLE after_concurrent_data (id INT);",
+            "DROP TABLE after_concurrent_data;"
+        ))
+        .table_name("non_transactional_target_schema_version")
+        .build()
+        .unwrap();
+
+    let report = migration_index.run_in_transactions_to(&connection, MigrationVersion::new(8))
+        .unwrap();
+    assert_eq!(report.applied(), ["NonTransactionalMigration".to_owned()]);
+    assert_eq!(
+        migration_index.schema_version(&connection).unwrap().unwrap(),
+        "NonTransactionalMigration"
+    );
+    assert!(!testing::table_exists(&connection, "after_concurrent_data").unwrap());
+    // the migration was committed, so clean up after it
+    migration_index.rollback(&connection).unwrap();
+    assert_eq!(migration_index.schema_version(&connection).unwrap(), None);
+}
+
+#[test]
+fn splits_sql_scripts_into_statements() {
+    let statements = sql_migration::split_statements("
+        INSERT INTO notes VALUES ('a;b', E'it\\'s; escaped', \"odd;column\");
+        /* a /* nested; */ comment; */
+        DO $body$ BEGIN PERFORM 1; END $body$;
+        SELECT $1::int;
+        -- trailing comment;
+    ");
+    assert_eq!(statements, vec![
+        "INSERT INTO notes VALUES ('a;b', E'it\\'s; escaped', \"odd;column\")".to_owned(),
+        "/* a /* nested; */ comment; */\n        DO $body$ BEGIN PERFORM 1; END $body$".to_owned(),
+        "SELECT $1::int".to_owned(),
+    ]);
+}
+
+#[test]
+fn generates_down_sql_for_structural_changes() {
+    let down = sql_migration::generate_down(
+        "CREATE TABLE generated_data (id INT);
+        CREATE UNIQUE INDEX CONCURRENTLY generated_data_id ON app.generated_data (id);
+        ALTER TABLE ONLY generated_data ADD COLUMN note TEXT DEFAULT 'a, b',
+            ADD \"Amount\" NUMERIC(10, 2);
+        ALTER TABLE generated_data ADD CONSTRAINT positive CHECK (id > 0);
+        CREATE INDEX ON generated_data (note);"
+    );
+    assert_eq!(down.statements(), &[
+        "ALTER TABLE generated_data DROP COLUMN \"Amount\", DROP COLUMN note".to_owned(),
+        "DROP INDEX CONCURRENTLY app.generated_data_id".to_owned(),
+        "DROP TABLE generated_data".to_owned(),
+    ]);
+    assert_eq!(down.uninvertible(), &[
+        "ALTER TABLE generated_data ADD CONSTRAINT positive CHECK (id > 0)".to_owned(),
+        "CREATE INDEX ON generated_data (note)".to_owned(),
+    ]);
+    assert!(!down.is_complete());
+}
+
+#[test]
+fn can_roll_back_sql_migration_with_generated_down() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let migration_index = MigrationIndex::builder()
+        .migration(sql_migration::SqlMigration::with_generated_down(
+            MigrationVersion::new(24),
+            "create_generated_data",
+            "CREATE TABLE generated_data (id INT);
+            ALTER TABLE generated_data ADD COLUMN note TEXT;
+            CREATE INDEX generated_data_note ON generated_data (note);"
+        ))
+        .build()
+        .unwrap();
+    migration_index.run(&transaction).unwrap();
+    transaction.execute("INSERT INTO generated_data VALUES (1, 'one');", &[]).unwrap();
+
+    migration_index.rollback(&transaction).unwrap();
+    let result = transaction.query(
+        "SELECT 1 FROM information_schema.tables WHERE table_name = 'generated_data';",
+        &[]
+    ).unwrap();
+    assert_eq!(result.len(), 0);
+}
+
+#[test]
+fn runs_non_transactional_sql_migration_one_statement_at_a_time() {
+    let connection = new_test_connection();
+    let migration_index = MigrationIndex::builder()
+        .migration(
+            sql_migration::SqlMigration::new(
+                MigrationVersion::new(21),
+                "create_concurrent_sql_data",
+                "CREATE TABLE concurrent_sql_data (id INT);
+                CREATE INDEX CONCURRENTLY concurrent_sql_data_id ON concurrent_sql_data (id);",
+                "DROP TABLE concurrent_sql_data;"
+            ).non_transactional()
+        )
+        .table_name("non_transactional_sql_schema_version")
+        .build()
+        .unwrap();
+    migration_index.run_in_transactions(&connection).unwrap();
+    assert_eq!(
+        migration_index.schema_version(&connection).unwrap().unwrap(),
+        "create_concurrent_sql_data"
+    );
+    // the migration was committed, so clean up after it
+    migration_index.rollback(&connection).unwrap();
+    assert_eq!(migration_index.schema_version(&connection).unwrap(), None);
+}
+
+#[test]
+fn can_run_and_roll_back_built_in_sql_migration() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let migration_index = MigrationIndex::builder()
+        .migration(sql_migration::SqlMigration::new(
+            MigrationVersion::new(9),
+            "create_built_in_sql_data",
+            "CREATE TABLE built_in_sql_data (id INT);",
+            "DROP TABLE built_in_sql_data;"
+        ))
+        .build()
+        .unwrap();
+    migration_index.run(&transaction).unwrap();
+    transaction.execute("INSERT INTO built_in_sql_data VALUES (1);", &[]).unwrap();
+    assert_eq!(
+        migration_index.schema_version(&transaction).unwrap().unwrap(),
+        "create_built_in_sql_data"
+    );
+
+    migration_index.rollback(&transaction).unwrap();
+    let result = transaction.query(
+        "SELECT 1 FROM information_schema.tables WHERE table_name = 'built_in_sql_data';",
+        &[]
+    ).unwrap();
+    assert_eq!(result.len(), 0);
+}
+
+#[test]
+fn can_run_and_roll_back_closure_migration() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let migration_index = MigrationIndex::builder()
+        .migration(FnMigration::new(
+            MigrationVersion::new(12),
+            "create_fn_data",
+            |context: &MigrationContext| {
+                let connection = context.connection();
+                try!(connection.execute("CREATE TABLE fn_data (id INT);", &[]));
+                for id in 0..3 {
+                    try!(connection.execute("INSERT INTO fn_data VALUES ($1);", &[&id]));
+                }
+                Ok(())
+            },
+            |context: &MigrationContext| {
+                try!(context.connection().execute("DROP TABLE fn_data;", &[]));
+                Ok(())
+            }
+        ))
+        .build()
+        .unwrap();
+    migration_index.run(&transaction).unwrap();
+    let result = transaction.query("SELECT count(*) FROM fn_data;", &[]).unwrap();
+    let count: i64 = result.get(0).get(0);
+    assert_eq!(count, 3);
+
+    migration_index.rollback(&transaction).unwrap();
+    assert_eq!(migration_index.schema_version(&transaction).unwrap(), None);
+}
+
+#[test]
+fn passes_run_options_to_migrations_through_their_context() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let migration_index = MigrationIndex::builder()
+        .migration(FnMigration::irreversible(
+            MigrationVersion::new(16),
+            "record_environment",
+            |context: &MigrationContext| {
+                let connection = context.connection();
+                try!(connection.execute("CREATE TABLE environment_data (name TEXT);", &[]));
+                try!(connection.execute(
+                    "INSERT INTO environment_data VALUES ($1);",
+                    &[&context.environment()]
+                ));
+                Ok(())
+            }
+        ))
+        .run_options(RunOptions::new().environment("test"))
+        .build()
+        .unwrap();
+    migration_index.run(&transaction).unwrap();
+    let result = transaction.query("SELECT name FROM environment_data;", &[]).unwrap();
+    let environment: Option<String> = result.get(0).get(0);
+    assert_eq!(environment, Some("test".to_owned()));
+}
+
+#[test]
+fn runs_migrations_with_their_timeouts() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let migration_index = MigrationIndex::builder()
+        .migration(FnMigration::irreversible(
+            MigrationVersion::new(22),
+            "record_timeouts",
+            |context: &MigrationContext| {
+                let connection = context.connection();
+                try!(connection.execute(
+                    "CREATE TABLE timeout_data AS SELECT
+                        current_setting('statement_timeout') AS statement_timeout,
+                        current_setting('lock_timeout') AS lock_timeout;",
+                    &[]
+                ));
+                Ok(())
+            }
+        ).with_lock_timeout(Duration::from_secs(2)))
+        .run_options(RunOptions::new().statement_timeout(Duration::from_secs(5)))
+        .build()
+        .unwrap();
+    let current_lock_timeout = || -> String {
+        let result = transaction.query("SELECT current_setting('lock_timeout');", &[]).unwrap();
+        result.get(0).get(0)
+    };
+    let lock_timeout_before = current_lock_timeout();
+    migration_index.run(&transaction).unwrap();
+    let result = transaction.query(
+        "SELECT statement_timeout, lock_timeout FROM timeout_data;", &[]
+    ).unwrap();
+    let statement_timeout: String = result.get(0).get(0);
+    let lock_timeout: String = result.get(0).get(1);
+    assert_eq!(statement_timeout, "5s");
+    assert_eq!(lock_timeout, "2s");
+    // the settings don't outlast the migration
+    assert_eq!(current_lock_timeout(), lock_timeout_before);
+}
+
+#[test]
+fn fails_migrations_that_exceed_their_statement_timeout() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let migration_index = MigrationIndex::builder()
+        .migration(sql_migration::SqlMigration::new(
+            MigrationVersion::new(23),
+            "sleep_too_long",
+            "SELECT pg_sleep(1);",
+            ""
+        ).with_statement_timeout(Duration::from_millis(100)))
+        .build()
+        .unwrap();
+    assert!(migration_index.run(&transaction).is_err());
+}
+
+#[test]
+fn collects_migration_warnings_into_run_report() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let migration_index = MigrationIndex::builder()
+        .migration(GoodMigration1::new())
+        .migration(FnMigration::irreversible(
+            MigrationVersion::new(17),
+            "warn_about_data",
+            |context: &MigrationContext| {
+                context.warn("1 row was skipped");
+                Ok(())
+            }
+        ))
+        .build()
+        .unwrap();
+    let report = migration_index.run(&transaction).unwrap();
+    assert_eq!(report.applied(), &["GoodMigration1".to_owned(), "warn_about_data".to_owned()]);
+    assert!(report.has_warnings());
+    assert_eq!(report.warnings().len(), 1);
+    assert_eq!(report.warnings()[0].migration(), "warn_about_data");
+    assert_eq!(report.warnings()[0].message(), "1 row was skipped");
+
+    let report = migration_index.run(&transaction).unwrap();
+    assert!(report.applied().is_empty());
+    assert!(!report.has_warnings());
+}
+
+#[test]
+fn skips_migrations_that_decide_not_to_run_until_they_do() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let migration_index = MigrationIndex::builder()
+        .migration(ConditionalMigration::new())
+        .build()
+        .unwrap();
+    let report = migration_index.run(&transaction).unwrap();
+    assert!(report.applied().is_empty());
+    assert_eq!(report.skipped(), &["ConditionalMigration".to_owned()]);
+    assert!(migration_index.history(&transaction).unwrap().is_empty());
+    let skipped = migration_index.skipped_migrations(&transaction).unwrap();
+    assert_eq!(skipped.len(), 1);
+    assert_eq!(skipped[0].name(), "ConditionalMigration");
+
+    transaction.batch_execute("CREATE TABLE conditional_flag (enabled BOOLEAN);").unwrap();
+    let report = migration_index.run(&transaction).unwrap();
+    assert_eq!(report.applied(), &["ConditionalMigration".to_owned()]);
+    assert!(migration_index.skipped_migrations(&transaction).unwrap().is_empty());
+    assert_eq!(
+        migration_index.schema_version(&transaction).unwrap().unwrap(),
+        "ConditionalMigration"
+    );
+}
+
+#[test]
+fn runs_environment_gated_migrations_only_in_their_environments() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let seed_index = |environment: &str| {
+        MigrationIndex::builder()
+            .migration(
+                sql_migration::SqlMigration::new(
+                    MigrationVersion::new(19),
+                    "seed_development_data",
+                    "CREATE TABLE development_data (id INT);",
+                    "DROP TABLE development_data;"
+                ).with_environments(&["development"])
+            )
+            .run_options(RunOptions::new().environment(environment))
+            .build()
+            .unwrap()
+    };
+    let report = seed_index("production").run(&transaction).unwrap();
+    assert_eq!(report.skipped(), &["seed_development_data".to_owned()]);
+    assert!(seed_index("production").history(&transaction).unwrap().is_empty());
+
+    let report = seed_index("development").run(&transaction).unwrap();
+    assert_eq!(report.applied(), &["seed_development_data".to_owned()]);
+    transaction.execute("SELECT * FROM development_data;", &[]).unwrap();
+}
+
+#[test]
+fn runs_seeds_separately_from_migrations() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let migration_index = MigrationIndex::builder()
+        .migration(
+            sql_migration::SqlMigration::new(
+                MigrationVersion::new(20),
+                "create_seed_data",
+                "CREATE TABLE seed_data (name TEXT PRIMARY KEY);",
+                "DROP TABLE seed_data;"
+            )
+        )
+        .seed(SqlSeed::new(
+            "reference_data",
+            "INSERT INTO seed_data VALUES ('reference') ON CONFLICT DO NOTHING;"
+        ))
+        .seed(SqlSeed::new(
+            "production_data",
+            "INSERT INTO seed_data VALUES ('production') ON CONFLICT DO NOTHING;"
+        ).with_environments(&["production"]))
+        .run_options(RunOptions::new().environment("test"))
+        .build()
+        .unwrap();
+    migration_index.run(&transaction).unwrap();
+    for _ in 0..2 {
+        let report = migration_index.run_seeds(&transaction).unwrap();
+        assert_eq!(report.applied(), &["reference_data".to_owned()]);
+        assert_eq!(report.skipped(), &["production_data".to_owned()]);
+    }
+    let result = transaction.query("SELECT name FROM seed_data;", &[]).unwrap();
+    assert_eq!(result.len(), 1);
+    let name: String = result.get(0).get(0);
+    assert_eq!(name, "reference");
+
+    let seed_history = migration_index.seed_history(&transaction).unwrap();
+    assert_eq!(seed_history.len(), 1);
+    assert_eq!(seed_history[0].name(), "reference_data");
+    assert_eq!(seed_history[0].environment(), Some("test"));
+    assert_eq!(migration_index.history(&transaction).unwrap().len(), 1);
+}
+
+#[test]
+fn runs_pending_seeds_once_and_named_seeds_again() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    transaction.batch_execute("CREATE TABLE seed_counts (name TEXT PRIMARY KEY, runs INT);")
+        .unwrap();
+    let migration_index = MigrationIndex::builder()
+        .seed(SqlSeed::new(
+            "20150822094521_reference_currencies",
+            "INSERT INTO seed_counts VALUES ('currencies', 1)
+             ON CONFLICT (name) DO UPDATE SET runs = seed_counts.runs + 1;"
+        ))
+        .seed(SqlSeed::new(
+            "20150822094522_demo_users",
+            "INSERT INTO seed_counts VALUES ('users', 1)
+             ON CONFLICT (name) DO UPDATE SET runs = seed_counts.runs + 1;"
+        ))
+        .build()
+        .unwrap();
+    let report = migration_index.run_seed(&transaction, "reference_currencies").unwrap();
+    assert_eq!(report.applied(), &["20150822094521_reference_currencies".to_owned()]);
+    let report = migration_index.run_pending_seeds(&transaction).unwrap();
+    assert_eq!(report.applied(), &["20150822094522_demo_users".to_owned()]);
+    assert!(migration_index.run_pending_seeds(&transaction).unwrap().applied().is_empty());
+    migration_index.run_seed(&transaction, "20150822094521_reference_currencies").unwrap();
+    assert!(migration_index.run_seed(&transaction, "unknown").is_err());
+
+    let result = transaction.query("SELECT runs FROM seed_counts ORDER BY name;", &[]).unwrap();
+    let runs = result.iter().map(|row| row.get(0)).collect::<Vec<i32>>();
+    assert_eq!(runs, vec![2, 1]);
+}
+
+#[test]
+fn can_run_migrations_filtered_by_tag() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let migration_index = MigrationIndex::builder()
+        .migration(
+            sql_migration::SqlMigration::new(
+                MigrationVersion::new(9),
+                "create_built_in_sql_data",
+                "CREATE TABLE built_in_sql_data (id INT);",
+                "DROP TABLE built_in_sql_data;"
+            ).with_tags(&["schema"])
+        )
+        .migration(
+            sql_migration::SqlMigration::new(
+                MigrationVersion::new(13),
+                "backfill_built_in_sql_data",
+                "INSERT INTO built_in_sql_data VALUES (1);",
+                "DELETE FROM built_in_sql_data;"
+            ).with_tags(&["data"])
+        )
+        .build()
+        .unwrap();
+    migration_index.run_tagged(&transaction, &TagFilter::exclude(&["data"])).unwrap();
+    let history = migration_index.history(&transaction).unwrap();
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].name(), "create_built_in_sql_data");
+
+    migration_index.run_tagged(&transaction, &TagFilter::include(&["data"])).unwrap();
+    assert_eq!(
+        migration_index.schema_version(&transaction).unwrap().unwrap(),
+        "backfill_built_in_sql_data"
+    );
+}
+
+#[test]
+fn runs_migrations_after_their_dependencies() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let migration_index = MigrationIndex::builder()
+        .migration(
+            sql_migration::SqlMigration::new(
+                MigrationVersion::new(14),
+                "backfill_dependency_data",
+                "INSERT INTO dependency_data VALUES (1);",
+                "DELETE FROM dependency_data;"
+            ).with_dependencies(&[MigrationVersion::new(15)])
+        )
+        .migration(
+            sql_migration::SqlMigration::new(
+                MigrationVersion::new(15),
+                "create_dependency_data",
+                "CREATE TABLE dependency_data (id INT);",
+                "DROP TABLE dependency_data;"
+            )
+        )
+        .build()
+        .unwrap();
+    migration_index.run(&transaction).unwrap();
+    let result = transaction.query("SELECT count(*) FROM dependency_data;", &[]).unwrap();
+    let count: i64 = result.get(0).get(0);
+    assert_eq!(count, 1);
+
+    // the dependent migration is rolled back first, even though its version is lower
+    migration_index.rollback(&transaction).unwrap();
+    let result = transaction.query("SELECT count(*) FROM dependency_data;", &[]).unwrap();
+    let count: i64 = result.get(0).get(0);
+    assert_eq!(count, 0);
+    migration_index.rollback(&transaction).unwrap();
+    assert_eq!(migration_index.schema_version(&transaction).unwrap(), None);
+}
+
+#[test]
+fn rejects_unknown_and_cyclic_dependencies() {
+    let unknown_dependency = MigrationIndex::builder()
+        .migration(
+            sql_migration::SqlMigration::new(
+                MigrationVersion::new(14),
+                "backfill_dependency_data",
+                "SELECT 1;",
+                "SELECT 1;"
+            ).with_dependencies(&[MigrationVersion::new(99)])
+        )
+        .build();
+    assert!(unknown_dependency.is_err());
+
+    let cyclic_dependencies = MigrationIndex::builder()
+        .migration(
+            sql_migration::SqlMigration::new(
+                MigrationVersion::new(14),
+                "backfill_dependency_data",
+                "SELECT 1;",
+                "SELECT 1;"
+            ).with_dependencies(&[MigrationVersion::new(15)])
+        )
+        .migration(
+            sql_migration::SqlMigration::new(
+                MigrationVersion::new(15),
+                "create_dependency_data",
+                "SELECT 1;",
+                "SELECT 1;"
+            ).with_dependencies(&[MigrationVersion::new(14)])
+        )
+        .build();
+    assert!(cyclic_dependencies.is_err());
+}
+
+#[test]
+fn batched_update_covers_every_row() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    transaction.batch_execute("
+        CREATE TABLE batched_data (id INT PRIMARY KEY, value INT);
+        INSERT INTO batched_data SELECT id, 0 FROM generate_series(1, 25) AS id;
+    ").unwrap();
+    let updated = data::batched_update(
+        &transaction,
+        "batched_data",
+        "id",
+        "UPDATE batched_data SET value = 1 WHERE id > $1::BIGINT AND id <= $2::BIGINT;",
+        &BatchOptions::new().batch_size(10)
+    ).unwrap();
+    assert_eq!(updated, 25);
+    let result = transaction.query(
+        "SELECT count(*) FROM batched_data WHERE value = 1;",
+        &[]
+    ).unwrap();
+    let count: i64 = result.get(0).get(0);
+    assert_eq!(count, 25);
+
+    let deleted = data::batched_update(
+        &transaction,
+        "batched_data",
+        "id",
+        "DELETE FROM batched_data WHERE id > $1::BIGINT AND id <= $2::BIGINT AND id % 2 = 0;",
+        &BatchOptions::new().batch_size(10)
+    ).unwrap();
+    assert_eq!(deleted, 12);
+}
+
+#[test]
+fn reapplies_repeatable_migrations_when_they_change() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let repeatable_index = |sql: &str, applied_count: Rc<Cell<u32>>| {
+        MigrationIndex::builder()
+            .migration(GoodMigration1::new())
+            .repeatable_migration(RepeatableSqlMigration::new("repeatable_view", sql))
+            .progress_observer(move |event: &MigrationEvent| {
+                if let MigrationEvent::RepeatableApplied(..) = *event {
+                    applied_count.set(applied_count.get() + 1);
+                }
+            })
+            .build()
+            .unwrap()
+    };
+    let view_version = || {
+        let result = transaction.query("SELECT version FROM repeatable_view;", &[]).unwrap();
+        let version: i32 = result.get(0).get(0);
+        version
+    };
+    let applied_count = Rc::new(Cell::new(0));
+    let first_index = repeatable_index(
+        "CREATE OR REPLACE VIEW repeatable_view AS SELECT 1 AS version;",
+        applied_count.clone()
+    );
+    first_index.run(&transaction).unwrap();
+    assert_eq!(applied_count.get(), 1);
+    assert_eq!(view_version(), 1);
+
+    // unchanged repeatable migrations aren't applied again
+    first_index.run(&transaction).unwrap();
+    assert_eq!(applied_count.get(), 1);
+
+    let second_index = repeatable_index(
+        "CREATE OR REPLACE VIEW repeatable_view AS SELECT 2 AS version;",
+        applied_count.clone()
+    );
+    second_index.run(&transaction).unwrap();
+    assert_eq!(applied_count.get(), 2);
+    assert_eq!(view_version(), 2);
+}
+
+#[test]
+fn can_load_sql_migrations_from_directory() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let migration_index = MigrationIndex::from_directory(Path::new("tests/sql_migrations"))
+        .unwrap();
+    assert_eq!(migration_index.len(), 2);
+    migration_index.run(&transaction).unwrap();
+    assert_eq!(
+        migration_index.schema_version(&transaction).unwrap().unwrap(),
+        "11_insert_file_data"
+    );
+
+    // the second migration has no down migration
+    assert!(migration_index.rollback(&transaction).unwrap_err().is_irreversible());
+}
+
+#[test]
+fn generates_source_embedding_sql_migrations() {
+    let out_file = env::temp_dir().join("trek_test_embedded_migrations.rs");
+    embed::write_embedded_migrations(Path::new("tests/sql_migrations"), &out_file).unwrap();
+    let mut source = String::new();
+    File::open(&out_file).unwrap().read_to_string(&mut source).unwrap();
+    fs::remove_file(&out_file).unwrap();
+
+    assert!(source.contains("pub fn embedded_migrations()"));
+    assert!(source.contains("SqlMigration::new("));
+    assert!(source.contains("\"10_create_file_data\""));
+    assert!(source.contains("10_create_file_data.down.sql"));
+    assert!(source.contains("SqlMigration::irreversible("));
+    assert!(source.contains("\"11_insert_file_data\""));
+}
+
+#[test]
+fn generates_module_declaring_rust_migrations() {
+    let out_file = env::temp_dir().join("trek_test_migrations.rs");
+    codegen::write_migrations_module(Path::new("examples/migrations"), &out_file).unwrap();
+    let mut source = String::new();
+    File::open(&out_file).unwrap().read_to_string(&mut source).unwrap();
+    fs::remove_file(&out_file).unwrap();
+
+    assert!(source.contains("pub mod migration_20150826001350_create_users_table;"));
+    assert!(source.contains("migration_20150826001350_create_users_table.rs\"]"));
+    assert!(!source.contains("mod.rs"));
+    assert!(source.contains("pub fn all_migrations()"));
+    let users = source.find(
+        "Box::new(self::migration_20150826001350_create_users_table::CreateUsersTable::new())"
+    ).unwrap();
+    let companies = source.find(
+        "migration_20151008562095_create_companies_table::CreateCompaniesTable::new()"
+    ).unwrap();
+    assert!(users < companies);
+}
+
+#[test]
+fn creates_and_finds_migrations_in_date_subdirectories() {
+    let migrations_dir = env::temp_dir().join("trek_test_dated_migrations");
+    fs::create_dir_all(&migrations_dir).unwrap();
+    let users = MigrationGenerator::new(&migrations_dir)
+        .date_subdirectories(true)
+        .create("create_users_table", &Scaffold::Empty)
+        .unwrap();
+    let teams = MigrationGenerator::new(&migrations_dir)
+        .date_subdirectories(true)
+        .template(trek::MigrationTemplate::Sql)
+        .create("create_teams_table", &Scaffold::Empty)
+        .unwrap();
+    let loaded = sql_migration::load_directory(&migrations_dir).unwrap();
+    let out_file = env::temp_dir().join("trek_test_dated_migrations.rs");
+    codegen::write_migrations_module(&migrations_dir, &out_file).unwrap();
+    let mut generated_source = String::new();
+    File::open(&out_file).unwrap().read_to_string(&mut generated_source).unwrap();
+    let mut mod_file = String::new();
+    File::open(migrations_dir.join("mod.rs")).unwrap().read_to_string(&mut mod_file).unwrap();
+    fs::remove_file(&out_file).unwrap();
+    fs::remove_dir_all(&migrations_dir).unwrap();
+
+    let relative_path = users.path().strip_prefix(&migrations_dir).unwrap();
+    let month = UTC::now().format("%m").to_string();
+    assert_eq!(relative_path.components().count(), 3);
+    assert!(relative_path.starts_with(UTC::now().format("%Y").to_string()));
+    assert!(relative_path.parent().unwrap().ends_with(month));
+    assert_eq!(teams.path().parent(), users.path().parent());
+    assert_eq!(
+        mod_file,
+        format!("#[path = {:?}]\npub mod {};\n", relative_path, users.name())
+    );
+    assert_eq!(loaded.len(), 1);
+    assert_eq!(loaded[0].to_string(), teams.name());
+    assert!(generated_source.contains(&format!("pub mod {};", users.name())));
+}
+
+#[test]
+fn generates_tests_alongside_migrations() {
+    let migrations_dir = env::temp_dir().join("trek_test_migration_tests");
+    fs::create_dir_all(&migrations_dir).unwrap();
+    let generated = MigrationGenerator::new(&migrations_dir)
+        .test_skeletons(true)
+        .create("create_tested_data", &Scaffold::create_table("tested_data", vec![
+            Column::new("id", "SERIAL").primary_key(),
+        ]))
+        .unwrap();
+    let mut test_source = String::new();
+    File::open(&generated.paths()[1]).unwrap().read_to_string(&mut test_source).unwrap();
+    let mut mod_file = String::new();
+    File::open(migrations_dir.join("mod.rs")).unwrap().read_to_string(&mut mod_file).unwrap();
+    let out_file = env::temp_dir().join("trek_test_migration_tests.rs");
+    codegen::write_migrations_module(&migrations_dir, &out_file).unwrap();
+    let mut generated_source = String::new();
+    File::open(&out_file).unwrap().read_to_string(&mut generated_source).unwrap();
+    fs::remove_file(&out_file).unwrap();
+    fs::remove_dir_all(&migrations_dir).unwrap();
+
+    let test_module_name = format!("test_{}", generated.name());
+    assert_eq!(generated.test_module_name(), Some(&*test_module_name));
+    assert_eq!(generated.paths()[1], migrations_dir.join(format!("{}.rs", test_module_name)));
+    assert_eq!(
+        mod_file,
+        format!("pub mod {};\n#[cfg(test)]\nmod {};\n", generated.name(), test_module_name)
+    );
+    assert!(test_source.contains(&format!(
+        "use super::{}::CreateTestedData;",
+        generated.name()
+    )));
+    assert!(test_source.contains(
+        "assert!(testing::column_exists(&transaction, \"tested_data\", \"id\").unwrap());"
+    ));
+    assert!(test_source.contains(
+        "assert!(!testing::table_exists(&transaction, \"tested_data\").unwrap());"
+    ));
+    assert!(generated_source.contains("#[cfg(test)]\n#[path = "));
+    assert!(generated_source.contains(&format!("mod {};", test_module_name)));
+}
+
+#[test]
+fn checks_migrations_with_the_test_helpers() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let migration = sql_migration::SqlMigration::new(
+        MigrationVersion::new(30),
+        "30_create_helper_data",
+        "CREATE TABLE helper_data (id SERIAL PRIMARY KEY);
+        CREATE INDEX helper_data_id_index ON helper_data (id);",
+        "DROP TABLE helper_data;"
+    );
+
+    testing::apply(&transaction, &migration).unwrap();
+    assert!(testing::table_exists(&transaction, "helper_data").unwrap());
+    assert!(testing::column_exists(&transaction, "helper_data", "id").unwrap());
+    assert!(!testing::column_exists(&transaction, "helper_data", "name").unwrap());
+    assert!(testing::index_exists(&transaction, "helper_data_id_index").unwrap());
+    assert!(!testing::table_exists(&transaction, "helper_data_id_index").unwrap());
+
+    testing::undo(&transaction, &migration).unwrap();
+    assert!(!testing::table_exists(&transaction, "helper_data").unwrap());
+    assert!(!testing::column_exists(&transaction, "helper_data", "id").unwrap());
+}
+
+#[test]
+fn warns_about_new_migrations_sorting_before_applied_ones() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let migration_index = MigrationIndex::new(vec![Box::new(GoodMigration1::new())]);
+    assert_eq!(migration_index.latest_applied_version(&transaction).unwrap(), None);
+    migration_index.run(&transaction).unwrap();
+    let applied_version = migration_index.latest_applied_version(&transaction).unwrap().unwrap();
+    assert_eq!(applied_version, MigrationVersion::new(1));
+
+    let migrations_dir = env::temp_dir().join("trek_test_out_of_order_migrations");
+    fs::create_dir_all(&migrations_dir).unwrap();
+    let generator = MigrationGenerator::new(&migrations_dir)
+        .template(trek::MigrationTemplate::Sql)
+        .version_strategy(SequentialVersions::new())
+        .latest_applied_version(applied_version);
+    let behind = generator.create("create_users_table", &Scaffold::Empty).unwrap();
+    let ahead = generator.create("create_teams_table", &Scaffold::Empty).unwrap();
+    fs::remove_dir_all(&migrations_dir).unwrap();
+
+    assert_eq!(behind.version(), MigrationVersion::new(1));
+    assert_eq!(behind.warnings().len(), 1);
+    assert!(behind.warnings()[0].contains("the latest version applied to the database"));
+    assert_eq!(ahead.version(), MigrationVersion::new(2));
+    assert!(ahead.warnings().is_empty());
+}
+
+#[test]
+fn records_metadata_in_generated_migrations() {
+    let migrations_dir = env::temp_dir().join("trek_test_migration_metadata");
+    fs::create_dir_all(&migrations_dir).unwrap();
+    let generator = MigrationGenerator::new(&migrations_dir)
+        .author("Jane Doe <jane@example.com>")
+        .ticket("https://example.com/issues/42")
+        .description("Stores user accounts");
+    let rust = generator.create("create_users_table", &Scaffold::Empty).unwrap();
+    let sql = generator.template(trek::MigrationTemplate::Sql)
+        .create("create_teams_table", &Scaffold::Empty)
+        .unwrap();
+    let mut rust_source = String::new();
+    File::open(rust.path()).unwrap().read_to_string(&mut rust_source).unwrap();
+    let loaded = sql_migration::load_directory(&migrations_dir).unwrap();
+    fs::remove_dir_all(&migrations_dir).unwrap();
+
+    assert!(rust_source.contains(
+        "/// Description: Stores user accounts\n\
+        /// Author: Jane Doe <jane@example.com>\n\
+        /// Ticket: https://example.com/issues/42\n\
+        #[derive(Debug)]\n"
+    ));
+    assert!(rust_source.contains(
+        "    fn description(&self) -> Option<String> {\n        \
+        Some(\"Stores user accounts\".to_owned())\n    }\n"
+    ));
+    assert_eq!(loaded.len(), 1);
+    assert_eq!(loaded[0].to_string(), sql.name());
+    assert_eq!(loaded[0].description(), Some("Stores user accounts".to_owned()));
+}
+
+#[test]
+fn generates_expand_contract_migrations() {
+    let migrations_dir = env::temp_dir().join("trek_test_expand_contract");
+    fs::create_dir_all(&migrations_dir).unwrap();
+    let generator = MigrationGenerator::new(&migrations_dir)
+        .template(trek::MigrationTemplate::Sql);
+    let (expand, contract) = generator.create_expand_contract(
+        "users_full_name",
+        &Scaffold::add_column("users", Column::new("full_name", "TEXT")),
+        &Scaffold::sql("ALTER TABLE users DROP COLUMN name;\n", None)
+    ).unwrap();
+    let loaded = sql_migration::load_directory(&migrations_dir).unwrap();
+    fs::remove_dir_all(&migrations_dir).unwrap();
+
+    assert!(expand.name().ends_with("_expand_users_full_name"));
+    assert!(contract.name().ends_with("_contract_users_full_name"));
+    assert!(contract.version() > expand.version());
+    assert_eq!(loaded.len(), 2);
+    assert_eq!(loaded[0].contracts(), None);
+    assert_eq!(loaded[1].contracts(), Some(expand.version()));
+}
+
+#[test]
+fn initializes_a_migrations_directory() {
+    let project_dir = env::temp_dir().join("trek_test_init_migrations");
+    let migrations_dir = project_dir.join("migrations");
+    let created = trek::init_migrations(&migrations_dir).unwrap();
+    let created_again = trek::init_migrations(&migrations_dir).unwrap();
+    let mut mod_file = String::new();
+    File::open(migrations_dir.join("mod.rs")).unwrap().read_to_string(&mut mod_file).unwrap();
+    let mut index_file = String::new();
+    File::open(project_dir.join("migration_index.rs")).unwrap()
+        .read_to_string(&mut index_file)
+        .unwrap();
+    fs::remove_dir_all(&project_dir).unwrap();
+    let sql_dir = project_dir.join("sql_migrations");
+    let sql_created = MigrationGenerator::new(&sql_dir)
+        .template(trek::MigrationTemplate::Sql)
+        .init()
+        .unwrap();
+    let sql_dir_exists = sql_dir.is_dir();
+    fs::remove_dir_all(&project_dir).unwrap();
+
+    assert_eq!(
+        created,
+        vec![migrations_dir.join("mod.rs"), project_dir.join("migration_index.rs")]
+    );
+    assert!(created_again.is_empty());
+    assert_eq!(mod_file, "");
+    assert!(index_file.contains("impl Default for MigrationIndex {"));
+    assert!(index_file.contains("Box::new(migrations::migration_"));
+    assert!(sql_created.is_empty());
+    assert!(sql_dir_exists);
+}
+
+#[test]
+fn creates_migrations_in_a_new_directory() {
+    let migrations_dir = env::temp_dir().join("trek_test_new_migrations_dir").join("migrations");
+    let generated = trek::create_migration(
+        "create_users_table",
+        &migrations_dir,
+        trek::MigrationTemplate::Rust
+    );
+    let mod_file_exists = migrations_dir.join("mod.rs").is_file();
+    fs::remove_dir_all(migrations_dir.parent().unwrap()).unwrap();
+
+    assert!(generated.unwrap().path().starts_with(&migrations_dir));
+    assert!(mod_file_exists);
+}
+
+#[test]
+fn creates_sql_migration_files_that_load_from_their_directory() {
+    let migrations_dir = env::temp_dir().join("trek_test_created_migrations");
+    fs::create_dir_all(&migrations_dir).unwrap();
+    let generated = trek::create_sql_migration("create_users_table", &migrations_dir).unwrap();
+    let migrations = sql_migration::load_directory(&migrations_dir);
+    fs::remove_dir_all(&migrations_dir).unwrap();
+
+    assert_eq!(generated.paths().len(), 2);
+    assert_eq!(
+        generated.path(),
+        migrations_dir.join(format!("{}_create_users_table.up.sql", generated.version()))
+    );
+    assert!(generated.paths()[1].to_str().unwrap().ends_with("_create_users_table.down.sql"));
+    assert_eq!(generated.module_name(), None);
+    assert_eq!(generated.struct_name(), None);
+    let migrations = migrations.unwrap();
+    assert_eq!(migrations.len(), 1);
+    assert_eq!(migrations[0].to_string(), generated.name());
+    assert_eq!(migrations[0].version(), generated.version());
+}
+
+#[test]
+fn declares_created_rust_migrations_in_mod_file() {
+    let migrations_dir = env::temp_dir().join("trek_test_declared_migrations");
+    fs::create_dir_all(&migrations_dir).unwrap();
+    File::create(migrations_dir.join("mod.rs")).unwrap()
+        .write_all(b"pub mod existing_migration;").unwrap();
+    let generated = trek::create_migration(
+        "create_users_table", &migrations_dir, trek::MigrationTemplate::Rust
+    ).unwrap();
+    let mut mod_file = String::new();
+    File::open(migrations_dir.join("mod.rs")).unwrap().read_to_string(&mut mod_file).unwrap();
+    fs::remove_dir_all(&migrations_dir).unwrap();
+
+    let module_name = format!("migration_{}_create_users_table", generated.version());
+    assert_eq!(generated.module_name(), Some(&*module_name));
+    assert_eq!(generated.struct_name(), Some("CreateUsersTable"));
+    assert_eq!(generated.paths(), &[migrations_dir.join(format!("{}.rs", module_name))]);
+    assert_eq!(
+        mod_file,
+        format!("pub mod existing_migration;\npub mod {};\n", module_name)
+    );
+}
+
+#[test]
+fn creates_ready_to_run_scaffolded_migrations() {
+    let migrations_dir = env::temp_dir().join("trek_test_scaffolded_migrations");
+    fs::create_dir_all(&migrations_dir).unwrap();
+    let scaffolds = [
+        Scaffold::create_table("scaffolded_data", vec![
+            Column::new("id", "SERIAL").primary_key(),
+            Column::new("name", "TEXT").not_null().default("''"),
+        ]),
+        Scaffold::add_column("scaffolded_data", Column::new("created_at", "TIMESTAMPTZ")),
+        Scaffold::add_index("scaffolded_data", &["name", "created_at"], true),
+    ];
+    let names = ["create_scaffolded_data", "add_created_at", "index_names"];
+    for (name, scaffold) in names.iter().zip(scaffolds.iter()) {
+        trek::create_scaffolded_migration(
+            name, &migrations_dir, trek::MigrationTemplate::Sql, scaffold
+        ).unwrap();
+    }
+    let rust_migration = trek::create_scaffolded_migration(
+        "create_more_scaffolded_data", &migrations_dir, trek::MigrationTemplate::Rust, &scaffolds[0]
+    ).unwrap();
+    let mut rust_source = String::new();
+    File::open(rust_migration.path()).unwrap().read_to_string(&mut rust_source).unwrap();
+    fs::remove_file(rust_migration.path()).unwrap();
+    let migration_index = MigrationIndex::from_directory(&migrations_dir);
+    fs::remove_dir_all(&migrations_dir).unwrap();
+
+    assert!(rust_source.contains("batch_execute(r#\"\nCREATE TABLE scaffolded_data (\n"));
+    assert!(rust_source.contains("batch_execute(r#\"\nDROP TABLE scaffolded_data;\n\"#)"));
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let migration_index = migration_index.unwrap();
+    assert_eq!(migration_index.len(), 3);
+    migration_index.run(&transaction).unwrap();
+    transaction.execute(
+        "INSERT INTO scaffolded_data (name, created_at) VALUES ('one', now());", &[]
+    ).unwrap();
+    for _ in 0..3 {
+        migration_index.rollback(&transaction).unwrap();
+    }
+    assert_eq!(migration_index.schema_version(&transaction).unwrap(), None);
+}
+
+#[test]
+fn diffs_schema_against_target_sql() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    transaction.batch_execute("CREATE TABLE diff_data (id INT, name TEXT, legacy TEXT);").unwrap();
+    let target_sql = "
+        CREATE TABLE diff_data (id BIGINT NOT NULL, name TEXT DEFAULT 'x', note TEXT);
+        CREATE TABLE diff_new (id SERIAL PRIMARY KEY);";
+
+    let schema_diff = diff::diff_sql(&transaction, target_sql).unwrap();
+    assert_eq!(schema_diff.statements(), &[
+        "CREATE TABLE diff_new (id SERIAL PRIMARY KEY)".to_owned(),
+        "ALTER TABLE diff_data DROP COLUMN legacy".to_owned(),
+        "ALTER TABLE diff_data ALTER COLUMN id TYPE bigint".to_owned(),
+        "ALTER TABLE diff_data ALTER COLUMN id SET NOT NULL".to_owned(),
+        "ALTER TABLE diff_data ALTER COLUMN name SET DEFAULT 'x'::text".to_owned(),
+        "ALTER TABLE diff_data ADD COLUMN note text".to_owned(),
+    ]);
+    assert!(!schema_diff.notes().iter().any(|note| note.contains("diff_")));
+    // the target schema was discarded
+    let result = transaction.query(
+        "SELECT 1 FROM pg_namespace WHERE nspname = 'trek_diff_target';", &[]
+    ).unwrap();
+    assert_eq!(result.len(), 0);
+
+    transaction.batch_execute(&schema_diff.sql()).unwrap();
+    let schema_diff = diff::diff_tables(&transaction, &[
+        Table::new("diff_data", vec![
+            Column::new("id", "BIGINT").not_null(),
+            Column::new("name", "TEXT").default("'x'"),
+            Column::new("note", "TEXT"),
+        ]),
+        Table::new("diff_new", vec![Column::new("id", "SERIAL").primary_key()]),
+    ]).unwrap();
+    assert_eq!(schema_diff.statements().len(), 0);
+}
+
+#[test]
+fn generates_rust_migrations_that_compile_and_run() {
+    let migrations_dir = env::temp_dir().join("trek_test_template_migrations");
+    fs::create_dir_all(&migrations_dir).unwrap();
+    let generated = trek::create_scaffolded_migration(
+        "create_template_data",
+        &migrations_dir,
+        trek::MigrationTemplate::Rust,
+        &Scaffold::create_table("template_data", vec![Column::new("id", "INT")])
+    ).unwrap();
+    let mut source = String::new();
+    File::open(generated.path()).unwrap().read_to_string(&mut source).unwrap();
+    fs::remove_dir_all(&migrations_dir).unwrap();
+
+    // the compiled copy of the template differs only in its version
+    assert_eq!(
+        source.replace(&generated.version().to_string(), "25"),
+        include_str!("types/migration_25_create_template_data.rs")
+    );
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let migration_index = MigrationIndex::new(vec![Box::new(CreateTemplateData::new())]);
+    migration_index.run(&transaction).unwrap();
+    assert_eq!(
+        migration_index.schema_version(&transaction).unwrap().unwrap(),
+        "migration_25_create_template_data"
+    );
+    assert_eq!(CreateTemplateData::new().version(), MigrationVersion::new(25));
+    migration_index.rollback(&transaction).unwrap();
+    assert_eq!(migration_index.schema_version(&transaction).unwrap(), None);
+}
+
+#[test]
+fn generates_reversible_migrations_that_compile_and_roll_back() {
+    let migrations_dir = env::temp_dir().join("trek_test_reversible_migrations");
+    fs::create_dir_all(&migrations_dir).unwrap();
+    let generated = trek::create_scaffolded_migration(
+        "create_reversible_data",
+        &migrations_dir,
+        trek::MigrationTemplate::Reversible,
+        &Scaffold::create_table("reversible_data", vec![
+            Column::new("id", "SERIAL").primary_key(),
+            Column::new("label", "TEXT").not_null(),
+        ])
+    ).unwrap();
+    let mut source = String::new();
+    File::open(generated.path()).unwrap().read_to_string(&mut source).unwrap();
+    fs::remove_dir_all(&migrations_dir).unwrap();
+
+    // the compiled copy of the template differs only in its version
+    assert_eq!(
+        source.replace(&generated.version().to_string(), "26"),
+        include_str!("types/migration_26_create_reversible_data.rs")
+    );
+    assert_eq!(generated.struct_name(), Some("CreateReversibleData"));
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let migration_index = MigrationIndex::new(vec![Box::new(CreateReversibleData::new())]);
+    migration_index.run(&transaction).unwrap();
+    transaction.execute("INSERT INTO reversible_data (label) VALUES ('reversed')", &[]).unwrap();
+    migration_index.rollback(&transaction).unwrap();
+    let result = transaction.query(
+        "SELECT table_name FROM information_schema.tables WHERE table_name=$1;",
+        &[&"reversible_data"]
+    ).unwrap();
+    assert_eq!(result.len(), 0);
+}
+
+#[test]
+fn defines_migrations_with_the_macro() {
+    let migration = DefinedMigration::new();
+    assert_eq!(migration.version(), MigrationVersion::new(27));
+    assert_eq!(migration.to_string(), "migration_27_create_defined_data");
+
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let migration_index = MigrationIndex::new(vec![Box::new(migration)]);
+    migration_index.run(&transaction).unwrap();
+    transaction.execute("INSERT INTO defined_data DEFAULT VALUES", &[]).unwrap();
+    migration_index.rollback(&transaction).unwrap();
+    let result = transaction.query(
+        "SELECT table_name FROM information_schema.tables WHERE table_name=$1;",
+        &[&"defined_data"]
+    ).unwrap();
+    assert_eq!(result.len(), 0);
+}
+
+#[test]
+fn declares_sql_migrations_inline() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let migration_index = MigrationIndex::builder()
+        .migration(migration!(
+            "28_create_inline_data",
+            up: "CREATE TABLE inline_data (id SERIAL PRIMARY KEY);",
+            down: "DROP TABLE inline_data;",
+        ))
+        .migration(migration!("29_fill_inline_data", up: "INSERT INTO inline_data DEFAULT VALUES;"))
+        .build()
+        .unwrap();
+    assert_eq!(
+        migration_index.iter()
+            .map(|migration| migration.version())
+            .collect::<Vec<MigrationVersion>>(),
+        vec![MigrationVersion::new(28), MigrationVersion::new(29)]
+    );
+    migration_index.run(&transaction).unwrap();
+    let result = transaction.query("SELECT COUNT(*) FROM inline_data;", &[]).unwrap();
+    let count: i64 = result.get(0).get(0);
+    assert_eq!(count, 1);
+    assert!(migration_index.rollback(&transaction).unwrap_err().is_irreversible());
+}
+
+#[test]
+fn records_ddl_into_a_migration_scaffold() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    record::start_recording(&transaction).unwrap();
+    assert!(record::is_recording(&transaction).unwrap());
+    assert!(record::start_recording(&transaction).is_err());
+    transaction.execute("CREATE TABLE recorded_data (id SERIAL PRIMARY KEY)", &[]).unwrap();
+    transaction.execute("INSERT INTO recorded_data DEFAULT VALUES", &[]).unwrap();
+    transaction.execute("ALTER TABLE recorded_data ADD COLUMN label TEXT;", &[]).unwrap();
+    let recorded = record::stop_recording(&transaction).unwrap();
+
+    assert!(!record::is_recording(&transaction).unwrap());
+    assert_eq!(recorded.statements(), &[
+        "CREATE TABLE recorded_data (id SERIAL PRIMARY KEY)".to_owned(),
+        "ALTER TABLE recorded_data ADD COLUMN label TEXT".to_owned(),
+    ]);
+    assert_eq!(
+        recorded.scaffold().down_sql().unwrap(),
+        "ALTER TABLE recorded_data DROP COLUMN label;\nDROP TABLE recorded_data;\n"
+    );
+    assert!(record::stop_recording(&transaction).is_err());
+}
+
+#[test]
+fn undoes_reversible_changes_in_reverse_order() {
+    let mut changes = Changes::new();
+    changes
+        .create_table("users", vec![Column::new("id", "SERIAL").primary_key()])
+        .add_column("users", Column::new("email", "TEXT"))
+        .rename_column("users", "email", "address")
+        .rename_table("public.users", "accounts")
+        .remove_column("accounts", Column::new("address", "TEXT"));
+    assert_eq!(
+        changes.down_sql(),
+        "ALTER TABLE accounts ADD COLUMN address TEXT;\n\
+        ALTER TABLE public.accounts RENAME TO users;\n\
+        ALTER TABLE users RENAME COLUMN address TO email;\n\
+        ALTER TABLE users DROP COLUMN email;\n\
+        DROP TABLE users;\n"
+    );
+}
+
+#[test]
+fn prompts_for_a_table_to_create() {
+    let mut input = io::Cursor::new(&b"\
+        \n\
+        prompted_teams\n\
+        id\nSERIAL\ny\n\n\
+        name\nTEXT\n\nmaybe\nn\n'unnamed'\n\
+        \n\
+        name, id\ny\n\
+        \n"[..]);
+    let mut output = vec![];
+    let scaffold = trek::interactive::prompt_create_table(&mut input, &mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+
+    let up_sql = "CREATE TABLE prompted_teams (\n    \
+        id SERIAL PRIMARY KEY,\n    \
+        name TEXT NOT NULL DEFAULT 'unnamed'\n\
+        );\n\
+        CREATE UNIQUE INDEX prompted_teams_name_id_index ON prompted_teams (name, id);\n";
+    let down_sql = "DROP INDEX prompted_teams_name_id_index;\nDROP TABLE prompted_teams;\n";
+    assert_eq!(scaffold, Scaffold::sql(up_sql, Some(down_sql)));
+    assert_eq!(output.matches("Table name: ").count(), 2);
+    assert!(output.contains("Please answer y or n."));
+
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    transaction.batch_execute(up_sql).unwrap();
+    transaction.batch_execute(down_sql).unwrap();
+
+    let mut unfinished_input = io::Cursor::new(&b"prompted_teams\nid\n"[..]);
+    let error = trek::interactive::prompt_create_table(&mut unfinished_input, &mut io::sink())
+        .unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn confirms_destructive_operations_until_answered() {
+    let mut input = io::Cursor::new(&b"maybe\n\n"[..]);
+    let mut output = vec![];
+    let confirmed = trek::interactive::confirm(&mut input, &mut output, "Drop it?", false).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(!confirmed);
+    assert_eq!(output, "Drop it? [y/N] Please answer y or n.\nDrop it? [y/N] ");
+
+    let mut unanswered_input = io::Cursor::new(&b""[..]);
+    let error = trek::interactive::confirm(&mut unanswered_input, &mut io::sink(), "Drop?", false)
+        .unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    assert_eq!(
+        sql_migration::destructive_statements("DELETE FROM users; DROP INDEX users_name_index;"),
+        vec!["DELETE FROM users".to_owned()]
+    );
+}
+
+#[test]
+fn previews_migrations_without_writing_them() {
+    let migrations_dir = env::temp_dir().join("trek_test_previewed_migrations");
+    fs::create_dir_all(&migrations_dir).unwrap();
+    let preview = trek::create_migration_preview(
+        "create_users_table", &migrations_dir, trek::MigrationTemplate::Rust
+    ).unwrap();
+    let files_before_writing = fs::read_dir(&migrations_dir).unwrap().count();
+    let previewed_files = preview.files().iter()
+        .map(|&(path, contents)| (path.to_path_buf(), contents.to_owned()))
+        .collect::<Vec<_>>();
+    let migration = preview.write().unwrap();
+    let mut written_contents = String::new();
+    File::open(migration.path()).unwrap().read_to_string(&mut written_contents).unwrap();
+    fs::remove_dir_all(&migrations_dir).unwrap();
+
+    assert_eq!(files_before_writing, 0);
+    assert_eq!(previewed_files.len(), 1);
+    assert_eq!(previewed_files[0].0, migration.path());
+    assert_eq!(previewed_files[0].1, written_contents);
+    assert_eq!(migration.struct_name(), Some("CreateUsersTable"));
+}
+
+#[test]
+fn creates_migrations_versioned_by_the_given_strategy() {
+    let migrations_dir = env::temp_dir().join("trek_test_sequential_migrations");
+    fs::create_dir_all(&migrations_dir).unwrap();
+    let generator = MigrationGenerator::new(&migrations_dir)
+        .template(trek::MigrationTemplate::Sql)
+        .version_strategy(SequentialVersions::new());
+    let first = generator.create("create_users_table", &Scaffold::Empty).unwrap();
+    let second = generator.create("create_teams_table", &Scaffold::Empty).unwrap();
+    let loaded = sql_migration::load_directory(&migrations_dir).unwrap();
+    fs::remove_dir_all(&migrations_dir).unwrap();
+
+    assert_eq!(first.name(), "0001_create_users_table");
+    assert_eq!(second.name(), "0002_create_teams_table");
+    assert_eq!(
+        loaded.iter().map(|migration| migration.version()).collect::<Vec<_>>(),
+        vec![first.version(), second.version()]
+    );
+    assert_eq!(second.version(), MigrationVersion::new(2));
+}
+
+#[test]
+fn creates_seeds_that_load_from_their_directory() {
+    let seeds_dir = env::temp_dir().join("trek_test_created_seeds");
+    fs::create_dir_all(&seeds_dir).unwrap();
+    let countries = trek::create_seed("countries", &seeds_dir, trek::MigrationTemplate::Sql)
+        .unwrap();
+    File::create(countries.path()).unwrap()
+        .write_all(b"CREATE TABLE IF NOT EXISTS seeded_countries (code TEXT);").unwrap();
+    let demo_users = trek::create_seed("demo_users", &seeds_dir, trek::MigrationTemplate::Rust)
+        .unwrap();
+    let reversible_seed = trek::create_seed(
+        "demo_teams", &seeds_dir, trek::MigrationTemplate::Reversible
+    );
+    let mut seeds = seed::load_directory(&seeds_dir).unwrap();
+    let mut mod_file = String::new();
+    File::open(seeds_dir.join("mod.rs")).unwrap().read_to_string(&mut mod_file).unwrap();
+    fs::remove_dir_all(&seeds_dir).unwrap();
+
+    assert!(countries.version() < demo_users.version());
+    assert_eq!(demo_users.name(), format!("seed_{}_demo_users", demo_users.version()));
+    assert_eq!(demo_users.struct_name(), Some("DemoUsers"));
+    assert_eq!(mod_file, format!("pub mod {};\n", demo_users.name()));
+    assert_eq!(reversible_seed.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(seeds.len(), 1);
+    assert_eq!(seeds[0].to_string(), countries.name());
+
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let migration_index = MigrationIndex::builder().seed(seeds.remove(0)).build().unwrap();
+    migration_index.run_seeds(&transaction).unwrap();
+    transaction.execute("SELECT code FROM seeded_countries", &[]).unwrap();
+}
+
+#[test]
+fn can_create_and_drop_database() {
+    let database_url = test_database_url("trek_test_created_database");
+    database::drop_database(&database_url).unwrap();
+
+    assert!(database::create_database(&database_url).unwrap());
+    assert!(!database::create_database(&database_url).unwrap());
+    Connection::connect(&*database_url, TlsMode::None).unwrap();
+    assert!(database::drop_database(&database_url).unwrap());
+    assert!(!database::drop_database(&database_url).unwrap());
+    assert!(Connection::connect(&*database_url, TlsMode::None).is_err());
+}
+
+#[test]
+fn configures_generator_and_index_from_trek_toml() {
+    let project_dir = env::temp_dir().join("trek_test_configured_project");
+    fs::create_dir_all(project_dir.join("db/migrations")).unwrap();
+    fs::create_dir_all(project_dir.join("src")).unwrap();
+    File::create(project_dir.join("trek.toml")).unwrap().write_all(b"\
+        migrations_dir = \"db/migrations\"
+        seeds_dir = \"db/seeds\"
+        table_name = \"configured_schema_history\"
+        template = \"sql\"
+        structure_file = \"db/structure.sql\"
+
+        [templates]
+        sql = \"migration.sql\"
+
+        [environments.production]
+        database_url = \"postgresql://db.example.com/app\"
+        table_name = \"production_schema_history\"
+        statement_timeout = 5000
+        retry_attempts = 3
+    ").unwrap();
+    File::create(project_dir.join("migration.sql")).unwrap()
+        .write_all(b"-- {{action}} {{migration_name}}\n{{sql}}").unwrap();
+    let config = Config::find(&project_dir.join("src")).unwrap();
+    let migration = config.generator().create(
+        "create_configured_data",
+        &Scaffold::create_table("configured_data", vec![Column::new("id", "INT")])
+    ).unwrap();
+    let mut down_sql = String::new();
+    File::open(&migration.paths()[1]).unwrap().read_to_string(&mut down_sql).unwrap();
+    let misspelt_config = Config::parse("migration_dir = \"db\"", &project_dir);
+    fs::remove_dir_all(&project_dir).unwrap();
+
+    assert_eq!(config.migrations_dir(), project_dir.join("db/migrations").as_path());
+    assert_eq!(config.seeds_dir(), project_dir.join("db/seeds").as_path());
+    assert_eq!(config.structure_file(), Some(project_dir.join("db/structure.sql").as_path()));
+    assert_eq!(migration.path().parent(), Some(config.migrations_dir()));
+    assert_eq!(down_sql, format!("-- undo {}\nDROP TABLE configured_data;\n", migration.name()));
+    assert_eq!(
+        config.environment("production").unwrap().run_options(),
+        RunOptions::new()
+            .environment("production")
+            .statement_timeout(Duration::from_millis(5000))
+            .retry_policy(RetryPolicy::new(3))
+    );
+    assert!(config.index_builder(Some("staging")).is_err());
+    assert!(misspelt_config.is_err());
+    assert_eq!(
+        config.database_url(Some("production")),
+        Some("postgresql://db.example.com/app".to_owned())
+    );
+    let overridden_config = config.clone().with_migrations_dir(Path::new("sql"));
+    assert_eq!(overridden_config.migrations_dir(), Path::new("sql"));
+
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let migration_index = config.index_builder(Some("production")).unwrap()
+        .migration(GoodMigration1::new())
+        .build()
+        .unwrap();
+    migration_index.run(&transaction).unwrap();
+    let result = transaction.query(
+        "SELECT table_name FROM information_schema.tables WHERE table_name=$1;",
+        &[&"production_schema_history"]
+    ).unwrap();
+    assert_eq!(result.len(), 1);
+}
+
+#[test]
+fn reads_the_template_of_new_migrations_from_trek_toml() {
+    let project_dir = Path::new("/project");
+    let unset = Config::parse("", project_dir).unwrap();
+    assert_eq!(unset.template(), trek::MigrationTemplate::Rust);
+    assert_eq!(unset.template_setting(), None);
+    assert_eq!(unset.template_file(trek::MigrationTemplate::Rust), None);
+
+    let configured = Config::parse(
+        "template = \"sql\"
+
+        [templates]
+        rust = \"templates/migration.rs\"",
+        project_dir
+    ).unwrap();
+    assert_eq!(configured.template(), trek::MigrationTemplate::Sql);
+    assert_eq!(configured.template_setting(), Some(trek::MigrationTemplate::Sql));
+    assert_eq!(
+        configured.template_file(trek::MigrationTemplate::Rust),
+        Some(project_dir.join("templates/migration.rs").as_path())
+    );
+    assert_eq!(configured.template_file(trek::MigrationTemplate::Sql), None);
+
+    assert!(Config::parse("template = \"yaml\"", project_dir).is_err());
+    assert!(Config::parse("[templates]\nyaml = \"migration.yaml\"", project_dir).is_err());
+}
+
+#[test]
+fn discovers_migrations_at_cargo_workspace_root_without_trek_toml() {
+    let workspace_dir = env::temp_dir().join("trek_test_cargo_workspace");
+    let member_dir = workspace_dir.join("crates/app");
+    fs::create_dir_all(member_dir.join("src")).unwrap();
+    File::create(workspace_dir.join("Cargo.toml")).unwrap()
+        .write_all(b"[workspace]\nmembers = [\"crates/app\"]\n").unwrap();
+    File::create(member_dir.join("Cargo.toml")).unwrap()
+        .write_all(b"[package]\nname = \"app\"\nversion = \"0.1.0\"\n").unwrap();
+    let workspace_root = config::cargo_workspace_root(&member_dir.join("src"));
+    let discovered_config = Config::discover(&member_dir.join("src")).unwrap();
+    fs::remove_dir_all(&workspace_dir).unwrap();
+
+    assert_eq!(workspace_root, Some(workspace_dir.clone()));
+    assert_eq!(discovered_config.migrations_dir(), workspace_dir.join("migrations").as_path());
+}
+
+#[test]
+fn gives_migrations_created_in_the_same_second_distinct_versions() {
+    let migrations_dir = env::temp_dir().join("trek_test_rapid_migrations");
+    fs::create_dir_all(&migrations_dir).unwrap();
+    let first = trek::create_migration(
+        "create_users_table", &migrations_dir, trek::MigrationTemplate::Rust
+    ).unwrap();
+    let second = trek::create_migration(
+        "create_companies_table", &migrations_dir, trek::MigrationTemplate::Sql
+    ).unwrap();
+    let third = trek::create_migration(
+        "create_teams_table", &migrations_dir, trek::MigrationTemplate::Rust
+    ).unwrap();
+    fs::remove_dir_all(&migrations_dir).unwrap();
+
+    assert!(first.version() < second.version());
+    assert!(second.version() < third.version());
+}
+
+#[test]
+fn refuses_to_create_migrations_with_invalid_names() {
+    let invalid_names = [
+        "", "drop table; --oops", "CreateUsers", "1_create_users", "create__users", "self"
+    ];
+    for name in &invalid_names {
+        assert!(trek::validate_migration_name(name).is_err(), "{:?} was accepted", name);
+    }
+    assert!(trek::validate_migration_name(&"a".repeat(trek::MAX_MIGRATION_NAME_LENGTH + 1))
+        .is_err());
+    assert!(trek::validate_migration_name("create_users_2").is_ok());
+
+    let migrations_dir = env::temp_dir().join("trek_test_invalid_migrations");
+    fs::create_dir_all(&migrations_dir).unwrap();
+    let result = trek::create_migration(
+        "drop table; --oops", &migrations_dir, trek::MigrationTemplate::Rust
+    );
+    let file_count = fs::read_dir(&migrations_dir).unwrap().count();
+    fs::remove_dir_all(&migrations_dir).unwrap();
+    match result {
+        Err(trek::error::Error::Validation(_)) => {},
+        other => panic!("Expected a validation error, got {:?}", other),
+    }
+    assert_eq!(file_count, 0);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serializes_reports_history_and_errors() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let migration_index = MigrationIndex::new(vec![Box::new(GoodMigration1::new())]);
+    let report = serde_json::to_value(&migration_index.run(&transaction).unwrap()).unwrap();
+    assert_eq!(report["applied"][0], "GoodMigration1");
+    let history = serde_json::to_value(&migration_index.history(&transaction).unwrap()).unwrap();
+    assert_eq!(history[0]["version"], 1);
+    assert_eq!(history[0]["name"], "GoodMigration1");
+
+    let failing_index = MigrationIndex::new(
+        vec![Box::new(GoodMigration1::new()), Box::new(BadMigration1::new())]
+    );
+    let error = serde_json::to_value(&failing_index.run(&transaction).unwrap_err()).unwrap();
+    assert_eq!(error["kind"], "migration");
+    assert_eq!(error["migration_name"], "BadMigration1");
+    assert_eq!(error["phase"], "Up");
+    assert_eq!(error["sqlstate"], "42601");
+}
+
+#[test]
+fn retries_idempotent_migrations_after_transient_errors() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    // sequences aren't rolled back, so they count attempts across rolled back savepoints
+    transaction.batch_execute(
+        "CREATE SEQUENCE unretried_attempts; CREATE SEQUENCE retried_attempts;"
+    ).unwrap();
+    let migration = |sequence: &str| sql_migration::SqlMigration::irreversible(
+        MigrationVersion::new(35),
+        "create_retried_data",
+        &format!(
+            "DO $$
+            BEGIN
+                IF nextval('{}') = 1 THEN
+                    RAISE EXCEPTION 'conflict' USING ERRCODE = 'serialization_failure';
+                END IF;
+            END
+            $$;
+            CREATE TABLE retried_data (id INT);",
+            sequence
+        )
+    );
+    let options = RunOptions::new()
+        .retry_policy(RetryPolicy::new(3).initial_backoff(Duration::from_millis(1)));
+
+    let savepoint = transaction.transaction().unwrap();
+    let error = MigrationIndex::builder()
+        .migration(migration("unretried_attempts"))
+        .run_options(options.clone())
+        .build()
+        .unwrap()
+        .run(&savepoint)
+        .unwrap_err();
+    assert!(error.is_retryable());
+    drop(savepoint);
+
+    let migration_index = MigrationIndex::builder()
+        .migration(migration("retried_attempts").as_idempotent())
+        .run_options(options)
+        .build()
+        .unwrap();
+    migration_index.run(&transaction).unwrap();
+    transaction.execute("SELECT id FROM retried_data;", &[]).unwrap();
+    let attempts: i64 = transaction.query("SELECT last_value FROM retried_attempts;", &[])
+        .unwrap().get(0).get(0);
+    assert_eq!(attempts, 2);
+}
+
+#[test]
+fn sends_errors_between_threads() {
+    let error = trek::error::Error::from_message("Migration history has diverged".to_owned());
+    let boxed: Box<StdError + Send + Sync> = thread::spawn(move || error.into()).join().unwrap();
+    assert_eq!(boxed.to_string(), "Migration history has diverged");
+}
+
+#[test]
+fn fails_gracefully_on_migration_run_error() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let migration_index = MigrationIndex::new(
+        vec![Box::new(BadMigration1::new())]
+    );
+    let error = migration_index.run(&transaction).unwrap_err();
+    assert_eq!(error.migration_name(), Some("BadMigration1"));
+    assert_eq!(error.phase(), Some(MigrationPhase::Up));
+    assert!(error.db_error().is_some());
+    assert_eq!(error.source().unwrap().to_string(), error.db_error().unwrap().to_string());
+}
+
+#[test]
+fn adds_context_to_errors() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let migration_index = MigrationIndex::new(
+        vec![Box::new(BadMigration1::new())]
+    );
+    let error = migration_index.run(&transaction)
+        .context("Error deploying the inventory service")
+        .unwrap_err();
+    assert_eq!(error.message(), "Error deploying the inventory service");
+    assert_eq!(error.migration_name(), Some("BadMigration1"));
+    assert_eq!(error.phase(), Some(MigrationPhase::Up));
+    assert!(error.db_error().is_some());
+    assert!(
+        error.to_string()
+            .starts_with("Error deploying the inventory service. The specific error is: ")
+    );
+    let io_error = File::open("/nonexistent/migrations/up.sql")
+        .with_context(|| format!("Error reading {}", "up.sql"))
+        .unwrap_err();
+    assert_eq!(io_error.message(), "Error reading up.sql");
+    assert!(io_error.source().unwrap().is::<io::Error>());
+}
+
+#[test]
+fn names_the_failing_statement_and_sqlstate_in_migration_errors() {
+    let connection = new_test_connection();
+    let failing_index = |up_sql: &str| MigrationIndex::builder()
+        .migration(sql_migration::SqlMigration::irreversible(
+            MigrationVersion::new(34),
+            "create_failing_statement_data",
+            up_sql
+        ))
+        .build()
+        .unwrap();
+
+    let transaction = connection.transaction().unwrap();
+    let error = failing_index(
+        "CREATE TABLE failing_statement_data (id INT);
+        SELEKT * FROM failing_statement_data;"
+    ).run(&transaction).unwrap_err();
+    assert_eq!(error.statement(), Some("SELEKT * FROM failing_statement_data"));
+    assert_eq!(error.sqlstate(), Some("42601"));
+    drop(transaction);
+
+    let transaction = connection.transaction().unwrap();
+    let up_sql = "CREATE TABLE failing_statement_data (id INT PRIMARY KEY);
+        INSERT INTO failing_statement_data VALUES (1), (1);";
+    let error = failing_index(up_sql).run(&transaction).unwrap_err();
+    assert_eq!(error.statement(), Some(up_sql));
+    assert_eq!(error.sqlstate(), Some("23505"));
+    assert_eq!(error.constraint(), Some("failing_statement_data_pkey"));
+    assert!(error.to_string().contains("SQLSTATE 23505"));
+}
+
+#[test]
+fn fails_gracefully_on_migration_rollback_error() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let migration_index = MigrationIndex::new(
+        vec![
+            Box::new(GoodMigration1::new()),
+            Box::new(GoodMigrationUpBadMigrationDown::new()),
+        ]
+    );
+    migration_index.run(&transaction).unwrap();
+    let error = migration_index.rollback(&transaction).unwrap_err();
+    assert_eq!(error.migration_name(), Some("GoodMigrationUpBadMigrationDown"));
+    assert_eq!(error.phase(), Some(MigrationPhase::Down));
+}
+
+#[test]
+fn refuses_to_roll_back_irreversible_migration() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let migration_index = MigrationIndex::new(
+        vec![
+            Box::new(GoodMigration1::new()),
+            Box::new(IrreversibleMigration::new()),
+        ]
+    );
+    migration_index.run(&transaction).unwrap();
+
+    let error = migration_index.rollback(&transaction).unwrap_err();
+    assert!(error.is_irreversible());
+    assert_eq!(
+        migration_index.schema_version(&transaction).unwrap().unwrap(),
+        "IrreversibleMigration"
+    );
+}
+
+#[test]
+fn fails_with_a_variant_for_each_kind_of_failure() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let migration_index = MigrationIndex::new(
+        vec![
+            Box::new(GoodMigration1::new()),
+            Box::new(IrreversibleMigration::new()),
+        ]
+    );
+
+    match diff::diff_sql(&transaction, "CREATE TABLE broken_data (id INT") {
+        Err(trek::error::Error::Db { ref cause, .. }) => {
+            assert_eq!(cause.code().map(|code| code.code()), Some("42601"));
+        },
+        result => panic!("expected a database error, got {:?}", result),
+    }
+    match MigrationIndex::new(vec![Box::new(BadMigration1::new())]).run(&transaction) {
+        Err(trek::error::Error::Migration { ref migration_name, phase, .. }) => {
+            assert_eq!(migration_name.as_ref().map(|name| &**name), Some("BadMigration1"));
+            assert_eq!(phase, MigrationPhase::Up);
+        },
+        result => panic!("expected a migration error, got {:?}", result),
+    }
+    drop(transaction);
+
+    let transaction = connection.transaction().unwrap();
+    let error: trek::error::Error = Config::load(Path::new("/nonexistent/trek.toml"))
+        .unwrap_err()
+        .into();
+    match error {
+        trek::error::Error::Io { ref cause, .. } => {
+            assert_eq!(cause.kind(), io::ErrorKind::NotFound);
+        },
+        error => panic!("expected an IO error, got {:?}", error),
+    }
+    match migration_index.run_to(&transaction, MigrationVersion::new(3)) {
+        Err(trek::error::Error::Validation(ref error)) => {
+            assert!(error.to_string().contains("version 3"));
+        },
+        result => panic!("expected a validation error, got {:?}", result),
+    }
+    migration_index.run(&transaction).unwrap();
+    match migration_index.rollback(&transaction) {
+        Err(trek::error::Error::Irreversible { ref cause, .. }) => {
+            assert_eq!(cause.migration_name(), "IrreversibleMigration");
+        },
+        result => panic!("expected an irreversible migration error, got {:?}", result),
+    }
+    match migration_index.rollback(&transaction).context("Error rolling back the release") {
+        Err(trek::error::Error::Context { ref cause, .. }) => {
+            assert!(cause.is_irreversible());
+        },
+        result => panic!("expected an error with context, got {:?}", result),
+    }
+}
+
+#[test]
+fn can_mark_migration_applied() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let migration_index = MigrationIndex::new(
+        vec![
+            Box::new(GoodMigration1::new()),
+            Box::new(GoodMigration2::new()),
+        ]
+    );
+    migration_index.mark_applied(&transaction, MigrationVersion::new(1)).unwrap();
+
+    // check that the migration's SQL wasn't run
+    let prepared_statement = transaction.prepare(
+            "SELECT table_name FROM information_schema.tables WHERE table_name='data';"
+        ).unwrap();
+    let result = prepared_statement.query(&[]).unwrap();
+    assert_eq!(result.len(), 0);
+
+    // check schema version is correct
+    let schema_version = migration_index.schema_version(&transaction).unwrap();
+    assert!(schema_version.is_some());
+    assert_eq!(
+        schema_version.unwrap(),
+        "GoodMigration1"
+    );
+}
+
+#[test]
+fn marks_migrations_applied_by_version_whatever_their_names() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let original_index = MigrationIndex::builder()
+        .migration(GoodMigration1::new())
+        .build()
+        .unwrap();
+    original_index.mark_applied(&transaction, MigrationVersion::new(1)).unwrap();
+
+    let renamed_index = MigrationIndex::builder()
+        .migration(GoodMigration1::with_name("RenamedMigration1"))
+        .build()
+        .unwrap();
+    renamed_index.mark_applied(&transaction, MigrationVersion::new(1)).unwrap();
+    renamed_index.run(&transaction).unwrap();
+    assert_eq!(renamed_index.history(&transaction).unwrap().len(), 1);
+}
+
+#[test]
+fn marking_unknown_migration_applied_fails() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let migration_index = MigrationIndex::new(vec![Box::new(GoodMigration1::new())]);
+    match migration_index.mark_applied(&transaction, MigrationVersion::new(99)) {
+        Err(trek::error::Error::Validation(_)) => {},
+        other => panic!("Expected a validation error, got {:?}", other),
+    }
+    assert!(migration_index.schema_version(&transaction).unwrap().is_none());
+}
+
+#[test]
+fn can_baseline_existing_database() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let migration_index = MigrationIndex::new(
+        vec![
+            Box::new(GoodMigration1::new()),
+            Box::new(GoodMigration2::new()),
+        ]
+    );
+    // simulate a database that already has GoodMigration1's schema
+    transaction.execute(
+        "CREATE TABLE data (good_migration_1_ran boolean NOT NULL DEFAULT false);",
+        &[]
+    ).unwrap();
+    transaction.execute("INSERT INTO data (good_migration_1_ran) values (true);", &[]).unwrap();
+
+    migration_index.baseline(&transaction, MigrationVersion::new(1)).unwrap();
+    migration_index.run(&transaction).unwrap();
+
+    // check that only the migration after the baseline ran
+    let prepared_statement = transaction.prepare("SELECT good_migration_2_ran FROM data;")
+        .unwrap();
+    let result = prepared_statement.query(&[]).unwrap();
+    assert_eq!(result.len(), 1);
+    let migration_ran: bool = result.get(0).get(0);
+    assert!(migration_ran);
+
+    let schema_version = migration_index.schema_version(&transaction).unwrap();
+    assert_eq!(
+        schema_version.unwrap(),
+        "GoodMigration2"
+    );
+}
+
+#[test]
+fn baselining_fails_at_unknown_migration_or_tracked_database() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let migration_index = MigrationIndex::new(
+        vec![
+            Box::new(GoodMigration1::new()),
+            Box::new(GoodMigration2::new()),
+        ]
+    );
+    match migration_index.baseline(&transaction, MigrationVersion::new(99)) {
+        Err(trek::error::Error::Validation(_)) => {},
+        other => panic!("Expected a validation error, got {:?}", other),
+    }
+    assert!(migration_index.schema_version(&transaction).unwrap().is_none());
+
+    migration_index.baseline(&transaction, MigrationVersion::new(1)).unwrap();
+    match migration_index.baseline(&transaction, MigrationVersion::new(2)) {
+        Err(trek::error::Error::Validation(_)) => {},
+        other => panic!("Expected a validation error, got {:?}", other),
+    }
+    assert_eq!(migration_index.schema_version(&transaction).unwrap().unwrap(), "GoodMigration1");
+}
+
+#[test]
+fn runs_hooks_around_each_applied_and_rolled_back_migration() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    // the audit table isn't committed, so the hooks can only write to it on the same connection
+    transaction.batch_execute("CREATE TABLE hook_audit (id SERIAL PRIMARY KEY, event TEXT);")
+        .unwrap();
+    let audit = |event: &'static str| {
+        move |connection: &postgres::GenericConnection, migration: &Migration| {
+            connection.execute(
+                "INSERT INTO hook_audit (event) VALUES ($1);",
+                &[&format!("{} {}", event, migration)]
+            ).map(|_| ())
+        }
+    };
+    let migration_index = MigrationIndex::builder()
+        .migration(GoodMigration1::new())
+        .migration(GoodMigration2::new())
+        .before_each(audit("before"))
+        .after_each(audit("after"))
+        .build()
+        .unwrap();
+    migration_index.run(&transaction).unwrap();
+    migration_index.rollback(&transaction).unwrap();
+
+    let result = transaction.query("SELECT event FROM hook_audit ORDER BY id;", &[]).unwrap();
+    let events = result.iter().map(|row| row.get(0)).collect::<Vec<String>>();
+    assert_eq!(
+        events,
+        vec![
+            "before GoodMigration1",
+            "after GoodMigration1",
+            "before GoodMigration2",
+            "after GoodMigration2",
+            "before GoodMigration2",
+            "after GoodMigration2",
+        ]
+    );
+}
+
+#[test]
+fn failing_before_each_hook_stops_the_migration() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let migration_index = MigrationIndex::builder()
+        .migration(GoodMigration1::new())
+        .before_each(|_connection, _migration| {
+            Err(io::Error::new(io::ErrorKind::Other, "not allowed to migrate").into())
+        })
+        .build()
+        .unwrap();
+    let error = migration_index.run(&transaction).unwrap_err();
+    assert_eq!(error.phase(), Some(MigrationPhase::Hook));
+    assert_eq!(error.migration_name(), Some("GoodMigration1"));
+
+    let result = transaction.query(
+        "SELECT table_name FROM information_schema.tables WHERE table_name = 'data';",
+        &[]
+    ).unwrap();
+    assert!(result.is_empty());
+    assert!(migration_index.schema_version(&transaction).unwrap().is_none());
+}
+
+thread_local!(static LOGGED_MESSAGES: RefCell<Vec<String>> = RefCell::new(vec![]));
+
+/// A logger recording the messages logged on each thread, so a test can see what it logged.
+struct ThreadLogger;
+
+impl log::Log for ThreadLogger {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        LOGGED_MESSAGES.with(|messages| messages.borrow_mut().push(record.args().to_string()));
+    }
+
+    fn flush(&self) {}
+}
+
+static THREAD_LOGGER: ThreadLogger = ThreadLogger;
+
+/// Records the messages logged from now on, for the current thread in `LOGGED_MESSAGES`.
+fn log_to_thread() {
+    // only this function sets a logger, so it's either unset or already this one
+    let _ = log::set_logger(&THREAD_LOGGER);
+    log::set_max_level(log::LevelFilter::Debug);
+}
+
+#[test]
+fn reports_progress_to_the_observer_instead_of_the_log() {
+    log_to_thread();
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let events = Rc::new(RefCell::new(vec![]));
+    let observed_events = events.clone();
+    let migration_index = MigrationIndex::builder()
+        .migration(GoodMigration1::new())
+        .migration(GoodMigration2::new())
+        .progress_observer(move |event: &MigrationEvent| {
+            observed_events.borrow_mut().push(event.to_string());
+        })
+        .build()
+        .unwrap();
+    migration_index.run(&transaction).unwrap();
+    migration_index.rollback(&transaction).unwrap();
+
+    let events = events.borrow();
+    assert_eq!(events.len(), 6);
+    assert_eq!(events[0], "Running migration GoodMigration1");
+    assert!(events[1].starts_with("Ran migration GoodMigration1 in "));
+    assert_eq!(events[2], "Running migration GoodMigration2");
+    assert!(events[3].starts_with("Ran migration GoodMigration2 in "));
+    assert_eq!(events[4], "Rolling back migration GoodMigration2");
+    assert!(events[5].starts_with("Rolled back migration GoodMigration2 in "));
+    assert!(events[5].ends_with(", database is now at version GoodMigration1"));
+    // the default observer, which would have logged the same messages, was replaced
+    let logged = LOGGED_MESSAGES.with(|messages| messages.borrow().clone());
+    assert!(events.iter().all(|event| !logged.contains(event)));
+
+    // while an index with the default observer logs them
+    drop(transaction);
+    let logging_transaction = connection.transaction().unwrap();
+    let logging_index = MigrationIndex::new(vec![Box::new(GoodMigration1::with_name("Logged"))]);
+    logging_index.run(&logging_transaction).unwrap();
+    let logged = LOGGED_MESSAGES.with(|messages| messages.borrow().clone());
+    assert!(logged.contains(&"Running migration Logged".to_owned()));
+}
+
+#[test]
+fn logs_the_sql_migrations_execute() {
+    log_to_thread();
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let old_index = MigrationIndex::builder()
+        .migration(sql_migration::SqlMigration::new(
+            MigrationVersion::new(32),
+            "create_logged_data",
+            "CREATE TABLE logged_data (id INT);",
+            "DROP TABLE logged_data;"
+        ))
+        .store_down_sql(true)
+        .build()
+        .unwrap();
+    old_index.run(&transaction).unwrap();
+    // rolled back with the down SQL recorded when it was applied
+    MigrationIndex::new(vec![]).rollback(&transaction).unwrap();
+
+    let logged = LOGGED_MESSAGES.with(|messages| messages.borrow().clone());
+    assert!(logged.contains(&"Executing SQL: CREATE TABLE logged_data (id INT);".to_owned()));
+    assert!(logged.contains(&"Executing SQL: DROP TABLE logged_data;".to_owned()));
+}
+
+#[test]
+fn records_squashed_baselines_as_applied() {
+    let squashed_index = MigrationIndex::new(
+        vec![
+            Box::new(GoodMigration1::new()),
+            Box::new(GoodMigration2::new()),
+        ]
+    );
+    // the old migrations' files have been deleted, leaving the baseline squashing them
+    let baseline_index = MigrationIndex::builder()
+        .migration(sql_migration::SqlMigration::irreversible(
+            MigrationVersion::new(31),
+            "31_squashed_baseline",
+            "-- Squashes: 2\nCREATE TABLE squashed_data (id INT);"
+        ))
+        .build()
+        .unwrap();
+    assert_eq!(
+        baseline_index.iter().next().unwrap().squashes(),
+        Some(MigrationVersion::new(2))
+    );
+    let connection = new_test_connection();
+
+    {
+        // an existing database has the baseline recorded without it being run
+        let transaction = connection.transaction().unwrap();
+        squashed_index.run(&transaction).unwrap();
+        let report = baseline_index.run(&transaction).unwrap();
+        assert!(report.applied().is_empty());
+        assert!(!testing::table_exists(&transaction, "squashed_data").unwrap());
+        assert_eq!(
+            baseline_index.schema_version(&transaction).unwrap().unwrap(),
+            "31_squashed_baseline"
+        );
+    }
+
+    // a fresh database runs it
+    let transaction = connection.transaction().unwrap();
+    baseline_index.run(&transaction).unwrap();
+    assert!(testing::table_exists(&transaction, "squashed_data").unwrap());
+}
+
+#[test]
+fn defers_contract_migrations_until_a_later_run() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let expand = sql_migration::SqlMigration::new(
+        MigrationVersion::new(32),
+        "32_expand_contract_data",
+        "CREATE TABLE contract_data (name TEXT, full_name TEXT);",
+        "DROP TABLE contract_data;"
+    );
+    let contract = sql_migration::SqlMigration::new(
+        MigrationVersion::new(33),
+        "33_contract_contract_data",
+        "-- Contracts: 32\nALTER TABLE contract_data DROP COLUMN name;",
+        "ALTER TABLE contract_data ADD COLUMN name TEXT;"
+    );
+    assert_eq!(contract.contracts(), Some(MigrationVersion::new(32)));
+    let migration_index = MigrationIndex::builder()
+        .migration(expand.clone())
+        .migration(contract.clone())
+        .build()
+        .unwrap();
+
+    let report = migration_index.run(&transaction).unwrap();
+    assert_eq!(report.applied(), &["32_expand_contract_data".to_owned()]);
+    assert_eq!(report.deferred(), &["33_contract_contract_data".to_owned()]);
+    assert!(testing::column_exists(&transaction, "contract_data", "name").unwrap());
+
+    // with a delay, the next run still has to wait
+    let delayed_index = MigrationIndex::builder()
+        .migration(expand)
+        .migration(contract)
+        .run_options(RunOptions::new().contract_delay(Duration::from_secs(3600)))
+        .build()
+        .unwrap();
+    let report = delayed_index.run(&transaction).unwrap();
+    assert!(report.applied().is_empty());
+    assert_eq!(report.deferred(), &["33_contract_contract_data".to_owned()]);
+
+    let report = migration_index.run(&transaction).unwrap();
+    assert_eq!(report.applied(), &["33_contract_contract_data".to_owned()]);
+    assert!(report.deferred().is_empty());
+    assert!(!testing::column_exists(&transaction, "contract_data", "name").unwrap());
+}
+
+#[test]
+fn can_use_custom_version_table_name() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let migration_index = MigrationIndex::builder()
+        .migration(GoodMigration1::new())
+        .table_name("custom_schema_version")
+        .build()
+        .unwrap();
+    migration_index.run(&transaction).unwrap();
+
+    // check that the default table wasn't used
+    let prepared_statement = transaction.prepare(
+            "SELECT table_name FROM information_schema.tables WHERE table_name=$1;"
+        ).unwrap();
+    let result = prepared_statement.query(&[&"trek_schema_history"]).unwrap();
+    assert_eq!(result.len(), 0);
+    let result = prepared_statement.query(&[&"custom_schema_version"]).unwrap();
+    assert_eq!(result.len(), 1);
+
+    assert_eq!(
+        migration_index.schema_version(&transaction).unwrap().unwrap(),
+        "GoodMigration1"
+    );
+}
+
+#[test]
+fn creates_schema_for_schema_qualified_version_table() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let migration_index = MigrationIndex::builder()
+        .migration(GoodMigration1::new())
+        .table_name("trek.schema_history")
+        .build()
+        .unwrap();
+    migration_index.run(&transaction).unwrap();
+
+    let result = transaction.query(
+        "SELECT table_schema::text FROM information_schema.tables WHERE table_name=$1;",
+        &[&"schema_history"]
+    ).unwrap();
+    assert_eq!(result.len(), 1);
+    let table_schema: String = result.get(0).get(0);
+    assert_eq!(table_schema, "trek");
+
+    assert_eq!(
+        migration_index.schema_version(&transaction).unwrap().unwrap(),
+        "GoodMigration1"
+    );
+    migration_index.rollback(&transaction).unwrap();
+    assert_eq!(migration_index.schema_version(&transaction).unwrap(), None);
+}
+
+#[test]
+fn migration_groups_track_versions_independently() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let core_index = MigrationIndex::new(
+        vec![Box::new(GoodMigration1::new())]
+    );
+    let plugin_index = MigrationIndex::builder()
+        .migration(GoodMigrationUpBadMigrationDown::new())
+        .group("plugin")
+        .build()
+        .unwrap();
+    core_index.run(&transaction).unwrap();
+    plugin_index.run(&transaction).unwrap();
+
+    assert_eq!(
+        core_index.schema_version(&transaction).unwrap().unwrap(),
+        "GoodMigration1"
+    );
+    assert_eq!(
+        plugin_index.schema_version(&transaction).unwrap().unwrap(),
+        "GoodMigrationUpBadMigrationDown"
+    );
+
+    // rolling back one group leaves the other untouched
+    core_index.rollback(&transaction).unwrap();
+    assert!(core_index.schema_version(&transaction).unwrap().is_none());
+    assert_eq!(
+        plugin_index.schema_version(&transaction).unwrap().unwrap(),
+        "GoodMigrationUpBadMigrationDown"
+    );
+}
+
+#[test]
+fn can_migrate_multiple_schemas_independently() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    transaction.batch_execute(
+        "CREATE SCHEMA trek_tenant_a;
+        CREATE SCHEMA trek_tenant_b;
+        CREATE TABLE trek_tenant_b.data (conflicting_column INT);"
+    ).unwrap();
+    let migration_index = MigrationIndex::new(
+        vec![Box::new(GoodMigration1::new())]
+    );
+    let report = migration_index.run_for_schemas(
+        &transaction, &["trek_tenant_a", "trek_tenant_b"]
+    ).unwrap();
+
+    // the conflicting table in the second schema makes only that schema fail
+    assert!(!report.is_success());
+    assert_eq!(report.successes(), vec!["trek_tenant_a"]);
+    assert_eq!(report.failures().len(), 1);
+    assert_eq!(report.failures()[0].0, "trek_tenant_b");
+
+    let prepared_statement = transaction.prepare(
+            "SELECT good_migration_1_ran FROM trek_tenant_a.data;"
+        ).unwrap();
+    let result = prepared_statement.query(&[]).unwrap();
+    assert_eq!(result.len(), 1);
+}
+
+#[test]
+fn builder_rejects_conflicting_table_options() {
+    let result = MigrationIndex::builder()
+        .migration(GoodMigration1::new())
+        .table_name("custom_schema_version")
+        .group("plugin")
+        .build();
+    assert!(result.is_err());
+
+    let result = MigrationIndex::builder()
+        .migration(GoodMigration1::new())
+        .table_name("")
+        .build();
+    assert!(result.is_err());
+
+    let result = MigrationIndex::builder()
+        .migration(GoodMigration1::new())
+        .group("plugin")
+        .namespace("billing")
+        .build();
+    assert!(result.is_err());
+
+    let result = MigrationIndex::builder()
+        .migration(GoodMigration1::new())
+        .table_schema("admin")
+        .table_name("trek.schema_history")
+        .build();
+    assert!(result.is_err());
+}
+
+#[test]
+fn namespaces_track_versions_in_separate_tables() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let billing_index = MigrationIndex::builder()
+        .migration(GoodMigration1::new())
+        .namespace("billing")
+        .build()
+        .unwrap();
+    billing_index.run(&transaction).unwrap();
+
+    let prepared_statement = transaction.prepare(
+            "SELECT table_name FROM information_schema.tables WHERE table_name=$1;"
+        ).unwrap();
+    let result = prepared_statement.query(&[&"trek_history_billing"]).unwrap();
+    assert_eq!(result.len(), 1);
+    let result = prepared_statement.query(&[&"trek_schema_history"]).unwrap();
+    assert_eq!(result.len(), 0);
+
+    let default_index = MigrationIndex::new(vec![Box::new(GoodMigration1::new())]);
+    assert!(default_index.schema_version(&transaction).unwrap().is_none());
+    assert_eq!(
+        billing_index.schema_version(&transaction).unwrap().unwrap(),
+        "GoodMigration1"
+    );
+}
+
+#[test]
+fn can_list_registered_migrations() {
+    let migration_index = MigrationIndex::new(
+        vec![
+            Box::new(GoodMigration1::new()),
+            Box::new(GoodMigration2::new()),
+        ]
+    );
+    assert_eq!(migration_index.len(), 2);
+    let names = migration_index.iter()
+        .map(|migration| migration.to_string())
+        .collect::<Vec<String>>();
+    assert_eq!(names, vec!["GoodMigration1", "GoodMigration2"]);
+    assert_eq!(migration_index.get("GoodMigration2").unwrap().to_string(), "GoodMigration2");
+    assert!(migration_index.get("MissingMigration").is_none());
+}
+
+#[test]
+fn refuses_to_run_when_applied_migration_was_modified() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let original_index = MigrationIndex::new(
+        vec![Box::new(ChecksummedMigration::new("original"))]
+    );
+    original_index.run(&transaction).unwrap();
+    // re-running with the same contents is fine
+    original_index.run(&transaction).unwrap();
+
+    let modified_index = MigrationIndex::new(
+        vec![
+            Box::new(ChecksummedMigration::new("modified")),
+            Box::new(GoodMigration1::new()),
+        ]
+    );
+    match modified_index.run(&transaction) {
+        Err(error @ trek::error::Error::ModifiedMigration { .. }) => {
+            assert_eq!(error.migration_name(), Some("ChecksummedMigration"));
+            assert!(error.is_drift());
+        },
+        result => panic!("Expected a ModifiedMigration error, got {:?}", result),
+    }
+    assert_eq!(
+        modified_index.schema_version(&transaction).unwrap().unwrap(),
+        "ChecksummedMigration"
+    );
+}
+
+#[test]
+fn records_checksums_in_history_table() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let migration_index = MigrationIndex::new(
+        vec![
+            Box::new(ChecksummedMigration::new("original")),
+            Box::new(GoodMigration1::new()),
+        ]
+    );
+    migration_index.run(&transaction).unwrap();
+
+    let prepared_statement = transaction.prepare(
+            "SELECT name, checksum FROM trek_schema_history ORDER BY version;"
+        ).unwrap();
+    let result = prepared_statement.query(&[]).unwrap();
+    assert_eq!(result.len(), 2);
+    let checksum: Option<String> = result.get(0).get(1);
+    assert_eq!(checksum, Some("original".to_owned()));
+    // migrations without a checksum leave the column empty
+    let checksum: Option<String> = result.get(1).get(1);
+    assert_eq!(checksum, None);
+}
+
+#[test]
+fn history_lists_migrations_in_order_applied() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let first_index = MigrationIndex::new(
+        vec![Box::new(GoodMigration1::new())]
+    );
+    first_index.run(&transaction).unwrap();
+    // a migration with an earlier version registered after later ones have been applied
+    let second_index = MigrationIndex::new(
+        vec![
+            Box::new(ChecksummedMigration::new("original")),
+            Box::new(GoodMigration1::new()),
+        ]
+    );
+    second_index.run(&transaction).unwrap();
+
+    let history = second_index.history(&transaction).unwrap();
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[0].name(), "GoodMigration1");
+    assert_eq!(history[0].checksum(), None);
+    assert_eq!(history[1].name(), "ChecksummedMigration");
+    assert_eq!(history[1].checksum(), Some("original"));
+}
+
+#[test]
+fn rejects_duplicate_migration_names() {
+    let migration_index = MigrationIndex::new(
+        vec![
+            Box::new(GoodMigration1::new()),
+            Box::new(GoodMigration1::new()),
+        ]
+    );
+    assert!(migration_index.validate().is_err());
+
+    let result = MigrationIndex::builder()
+        .migration(GoodMigration1::new())
+        .migration(GoodMigration1::new())
+        .build();
+    assert!(result.is_err());
+}
+
+#[test]
+fn orders_migrations_by_version() {
+    let connection = new_test_connection();
+    let transaction = connection.transaction().unwrap();
+    let migration_index = MigrationIndex::new(
+        vec![
+            Box::new(GoodMigration2::new()),
+            Box::new(GoodMigration1::new()),
+        ]
+    );
+    let names = migration_index.iter()
+        .map(|migration| migration.to_string())
+        .collect::<Vec<String>>();
+    assert_eq!(names, vec!["GoodMigration1", "GoodMigration2"]);
+
+    // GoodMigration2 depends on GoodMigration1, so this only succeeds if they ran in order
+    migration_index.run(&transaction).unwrap();
+    assert_eq!(
+        migration_index.schema_version(&transaction).unwrap().unwrap(),
+        "GoodMigration2"
+    );
+}
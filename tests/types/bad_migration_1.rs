@@ -1,6 +1,8 @@
 use std::fmt::{self, Display};
-use postgres::{self, GenericConnection};
+use postgres;
 use trek::migration::Migration;
+use trek::migration_context::MigrationContext;
+use trek::migration_version::MigrationVersion;
 
 // this migration is expected to fail when run
 #[derive(Debug)]
@@ -15,11 +17,16 @@ impl BadMigration1 {
     }
 }
 impl Migration for BadMigration1 {
-    fn up(&self, transaction: &GenericConnection) -> postgres::Result<()> {
+    fn version(&self) -> MigrationVersion {
+        MigrationVersion::new(4)
+    }
+    fn up(&self, context: &MigrationContext) -> postgres::Result<()> {
+        let transaction = context.connection();
         try!(transaction.execute("rargle blargle", &[]));
         Ok(())
     }
-    fn down(&self, transaction: &GenericConnection) -> postgres::Result<()> {
+    fn down(&self, context: &MigrationContext) -> postgres::Result<()> {
+        let transaction = context.connection();
         try!(transaction.execute("rargle blargle", &[]));
         Ok(())
     }
@@ -0,0 +1,43 @@
+use std::fmt::{self, Display};
+use postgres;
+use trek::migration::Migration;
+use trek::migration_context::MigrationContext;
+use trek::migration_version::MigrationVersion;
+
+// this migration reports whatever checksum it was constructed with, to simulate edits
+#[derive(Debug)]
+pub struct ChecksummedMigration {
+    name: String,
+    checksum: String,
+}
+impl ChecksummedMigration {
+    pub fn new(checksum: &str) -> Self {
+        ChecksummedMigration {
+            name: "ChecksummedMigration".to_owned(),
+            checksum: checksum.to_owned(),
+        }
+    }
+}
+impl Migration for ChecksummedMigration {
+    fn version(&self) -> MigrationVersion {
+        MigrationVersion::new(0)
+    }
+    fn up(&self, context: &MigrationContext) -> postgres::Result<()> {
+        let transaction = context.connection();
+        try!(transaction.execute("CREATE TABLE checksummed_data (id INT);", &[]));
+        Ok(())
+    }
+    fn down(&self, context: &MigrationContext) -> postgres::Result<()> {
+        let transaction = context.connection();
+        try!(transaction.execute("DROP TABLE checksummed_data;", &[]));
+        Ok(())
+    }
+    fn checksum(&self) -> Option<String> {
+        Some(self.checksum.clone())
+    }
+}
+impl Display for ChecksummedMigration {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{}", self.name)
+    }
+}
@@ -0,0 +1,45 @@
+use std::fmt::{self, Display};
+use postgres;
+use trek::migration::Migration;
+use trek::migration_context::MigrationContext;
+use trek::migration_version::MigrationVersion;
+
+// this migration only runs once the conditional_flag table exists
+#[derive(Debug)]
+pub struct ConditionalMigration {
+    name: String
+}
+impl ConditionalMigration {
+    pub fn new() -> Self {
+        ConditionalMigration {
+            name: "ConditionalMigration".to_owned(),
+        }
+    }
+}
+impl Migration for ConditionalMigration {
+    fn version(&self) -> MigrationVersion {
+        MigrationVersion::new(18)
+    }
+    fn should_run(&self, context: &MigrationContext) -> postgres::Result<bool> {
+        let result = try!(context.connection().query(
+            "SELECT to_regclass('conditional_flag') IS NOT NULL;",
+            &[]
+        ));
+        Ok(result.get(0).get(0))
+    }
+    fn up(&self, context: &MigrationContext) -> postgres::Result<()> {
+        let transaction = context.connection();
+        try!(transaction.execute("INSERT INTO conditional_flag VALUES (true);", &[]));
+        Ok(())
+    }
+    fn down(&self, context: &MigrationContext) -> postgres::Result<()> {
+        let transaction = context.connection();
+        try!(transaction.execute("DELETE FROM conditional_flag;", &[]));
+        Ok(())
+    }
+}
+impl Display for ConditionalMigration {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{}", self.name)
+    }
+}
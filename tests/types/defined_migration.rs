@@ -0,0 +1,16 @@
+define_migration! {
+    pub struct DefinedMigration = "migration_27_create_defined_data";
+
+    fn up(context) {
+        try!(context.connection().execute(
+            "CREATE TABLE defined_data (id SERIAL PRIMARY KEY);",
+            &[]
+        ));
+        Ok(())
+    }
+
+    fn down(context) {
+        try!(context.connection().execute("DROP TABLE defined_data;", &[]));
+        Ok(())
+    }
+}
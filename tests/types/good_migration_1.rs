@@ -1,6 +1,8 @@
 use std::fmt::{self, Display};
-use postgres::{self, GenericConnection};
+use postgres;
 use trek::migration::Migration;
+use trek::migration_context::MigrationContext;
+use trek::migration_version::MigrationVersion;
 
 #[derive(Debug)]
 pub struct GoodMigration1 {
@@ -12,9 +14,19 @@ impl GoodMigration1 {
             name: "GoodMigration1".to_owned(),
         }
     }
+    // the same migration under a different name, to simulate renaming it
+    pub fn with_name(name: &str) -> Self {
+        GoodMigration1 {
+            name: name.to_owned(),
+        }
+    }
 }
 impl Migration for GoodMigration1 {
-    fn up(&self, transaction: &GenericConnection) -> postgres::Result<()> {
+    fn version(&self) -> MigrationVersion {
+        MigrationVersion::new(1)
+    }
+    fn up(&self, context: &MigrationContext) -> postgres::Result<()> {
+        let transaction = context.connection();
         try!(transaction.execute(
             "CREATE TABLE data (
                 good_migration_1_ran boolean NOT NULL DEFAULT false
@@ -24,7 +36,8 @@ impl Migration for GoodMigration1 {
         try!(transaction.execute("INSERT INTO data (good_migration_1_ran) values (true);", &[]));
         Ok(())
     }
-    fn down(&self, transaction: &GenericConnection) -> postgres::Result<()> {
+    fn down(&self, context: &MigrationContext) -> postgres::Result<()> {
+        let transaction = context.connection();
         try!(transaction.execute("DROP TABLE data;", &[]));
         Ok(())
     }
@@ -1,6 +1,8 @@
 use std::fmt::{self, Display};
-use postgres::{self, GenericConnection};
+use postgres;
 use trek::migration::Migration;
+use trek::migration_context::MigrationContext;
+use trek::migration_version::MigrationVersion;
 
 // this migration depends on GoodMigration1 having been run
 #[derive(Debug)]
@@ -15,7 +17,11 @@ impl GoodMigration2 {
     }
 }
 impl Migration for GoodMigration2 {
-    fn up(&self, transaction: &GenericConnection) -> postgres::Result<()> {
+    fn version(&self) -> MigrationVersion {
+        MigrationVersion::new(2)
+    }
+    fn up(&self, context: &MigrationContext) -> postgres::Result<()> {
+        let transaction = context.connection();
         try!(transaction.execute(
             "ALTER TABLE data ADD COLUMN good_migration_2_ran boolean NOT NULL DEFAULT false;",
             &[]
@@ -23,7 +29,8 @@ impl Migration for GoodMigration2 {
         try!(transaction.execute("UPDATE data SET good_migration_2_ran = true;", &[]));
         Ok(())
     }
-    fn down(&self, transaction: &GenericConnection) -> postgres::Result<()> {
+    fn down(&self, context: &MigrationContext) -> postgres::Result<()> {
+        let transaction = context.connection();
         try!(transaction.execute(
             "ALTER TABLE data DROP COLUMN good_migration_2_ran;",
             &[]
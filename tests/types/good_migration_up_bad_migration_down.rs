@@ -1,6 +1,8 @@
 use std::fmt::{self, Display};
-use postgres::{self, GenericConnection};
+use postgres;
 use trek::migration::Migration;
+use trek::migration_context::MigrationContext;
+use trek::migration_version::MigrationVersion;
 
 // this migration has a valid up() but its down() will fail
 #[derive(Debug)]
@@ -15,7 +17,11 @@ impl GoodMigrationUpBadMigrationDown {
     }
 }
 impl Migration for GoodMigrationUpBadMigrationDown {
-    fn up(&self, transaction: &GenericConnection) -> postgres::Result<()> {
+    fn version(&self) -> MigrationVersion {
+        MigrationVersion::new(3)
+    }
+    fn up(&self, context: &MigrationContext) -> postgres::Result<()> {
+        let transaction = context.connection();
         try!(transaction.execute(
             "CREATE TABLE independent_data (
                 good_up_bad_down_migration_ran boolean NOT NULL DEFAULT FALSE
@@ -28,7 +34,8 @@ impl Migration for GoodMigrationUpBadMigrationDown {
         ));
         Ok(())
     }
-    fn down(&self, transaction: &GenericConnection) -> postgres::Result<()> {
+    fn down(&self, context: &MigrationContext) -> postgres::Result<()> {
+        let transaction = context.connection();
         try!(transaction.execute("rargle blargle", &[]));
         Ok(())
     }
@@ -0,0 +1,33 @@
+use std::fmt::{self, Display};
+use postgres;
+use trek::migration::Migration;
+use trek::migration_context::MigrationContext;
+use trek::migration_version::MigrationVersion;
+
+// this migration depends on GoodMigration1 having been run and has no down() method
+#[derive(Debug)]
+pub struct IrreversibleMigration {
+    name: String
+}
+impl IrreversibleMigration {
+    pub fn new() -> Self {
+        IrreversibleMigration {
+            name: "IrreversibleMigration".to_owned(),
+        }
+    }
+}
+impl Migration for IrreversibleMigration {
+    fn version(&self) -> MigrationVersion {
+        MigrationVersion::new(7)
+    }
+    fn up(&self, context: &MigrationContext) -> postgres::Result<()> {
+        let transaction = context.connection();
+        try!(transaction.execute("DELETE FROM data;", &[]));
+        Ok(())
+    }
+}
+impl Display for IrreversibleMigration {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{}", self.name)
+    }
+}
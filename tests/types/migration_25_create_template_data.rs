@@ -0,0 +1,48 @@
+use std::fmt::{self, Display};
+use postgres;
+use trek::migration::Migration;
+use trek::migration_context::MigrationContext;
+use trek::migration_version::MigrationVersion;
+
+#[derive(Debug)]
+pub struct CreateTemplateData {
+    name: String,
+    version: MigrationVersion,
+}
+impl CreateTemplateData {
+    pub fn new() -> Self {
+        let name = "migration_25_create_template_data";
+        CreateTemplateData {
+            name: name.to_owned(),
+            // the version is the number after the name's "migration_" prefix
+            version: MigrationVersion::from_name(&name["migration_".len()..])
+                .expect("Migration names start with their version"),
+        }
+    }
+}
+impl Migration for CreateTemplateData {
+    fn version(&self) -> MigrationVersion {
+        self.version
+    }
+
+    fn up(&self, context: &MigrationContext) -> postgres::Result<()> {
+        try!(context.connection().batch_execute(r#"
+CREATE TABLE template_data (
+    id INT
+);
+"#));
+        Ok(())
+    }
+
+    fn down(&self, context: &MigrationContext) -> postgres::Result<()> {
+        try!(context.connection().batch_execute(r#"
+DROP TABLE template_data;
+"#));
+        Ok(())
+    }
+}
+impl Display for CreateTemplateData {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{}", self.name)
+    }
+}
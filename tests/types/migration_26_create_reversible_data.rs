@@ -0,0 +1,38 @@
+use std::fmt::{self, Display};
+use trek::migration_version::MigrationVersion;
+use trek::reversible_migration::{Changes, ReversibleMigration};
+use trek::scaffold::Column;
+
+#[derive(Debug)]
+pub struct CreateReversibleData {
+    name: String,
+    version: MigrationVersion,
+}
+impl CreateReversibleData {
+    pub fn new() -> Self {
+        let name = "migration_26_create_reversible_data";
+        CreateReversibleData {
+            name: name.to_owned(),
+            // the version is the number after the name's "migration_" prefix
+            version: MigrationVersion::from_name(&name["migration_".len()..])
+                .expect("Migration names start with their version"),
+        }
+    }
+}
+impl ReversibleMigration for CreateReversibleData {
+    fn version(&self) -> MigrationVersion {
+        self.version
+    }
+
+    fn change(&self, changes: &mut Changes) {
+        changes.create_table("reversible_data", vec![
+            Column::new("id", "SERIAL").primary_key(),
+            Column::new("label", "TEXT").not_null(),
+        ]);
+    }
+}
+impl Display for CreateReversibleData {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{}", self.name)
+    }
+}
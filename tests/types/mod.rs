@@ -2,3 +2,12 @@ pub mod good_migration_1;
 pub mod good_migration_2;
 pub mod good_migration_up_bad_migration_down;
 pub mod bad_migration_1;
+pub mod checksummed_migration;
+pub mod defined_migration;
+pub mod sql_migration;
+pub mod partially_failing_migration;
+pub mod irreversible_migration;
+pub mod non_transactional_migration;
+pub mod conditional_migration;
+pub mod migration_25_create_template_data;
+pub mod migration_26_create_reversible_data;
@@ -0,0 +1,45 @@
+use std::fmt::{self, Display};
+use postgres;
+use trek::migration::Migration;
+use trek::migration_context::MigrationContext;
+use trek::migration_version::MigrationVersion;
+
+// this migration can't run inside a transaction
+#[derive(Debug)]
+pub struct NonTransactionalMigration {
+    name: String
+}
+impl NonTransactionalMigration {
+    pub fn new() -> Self {
+        NonTransactionalMigration {
+            name: "NonTransactionalMigration".to_owned(),
+        }
+    }
+}
+impl Migration for NonTransactionalMigration {
+    fn version(&self) -> MigrationVersion {
+        MigrationVersion::new(8)
+    }
+    fn up(&self, context: &MigrationContext) -> postgres::Result<()> {
+        let transaction = context.connection();
+        try!(transaction.execute("CREATE TABLE concurrent_data (id INT);", &[]));
+        try!(transaction.execute(
+            "CREATE INDEX CONCURRENTLY concurrent_data_id ON concurrent_data (id);",
+            &[]
+        ));
+        Ok(())
+    }
+    fn down(&self, context: &MigrationContext) -> postgres::Result<()> {
+        let transaction = context.connection();
+        try!(transaction.execute("DROP TABLE concurrent_data;", &[]));
+        Ok(())
+    }
+    fn transactional(&self) -> bool {
+        false
+    }
+}
+impl Display for NonTransactionalMigration {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{}", self.name)
+    }
+}
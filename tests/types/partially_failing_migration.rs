@@ -0,0 +1,40 @@
+use std::fmt::{self, Display};
+use std::io;
+use postgres;
+use trek::migration::Migration;
+use trek::migration_context::MigrationContext;
+use trek::migration_version::MigrationVersion;
+
+// this migration makes a change and then fails without aborting the transaction, like a
+// migration failing partway outside of a transaction
+#[derive(Debug)]
+pub struct PartiallyFailingMigration {
+    name: String
+}
+impl PartiallyFailingMigration {
+    pub fn new() -> Self {
+        PartiallyFailingMigration {
+            name: "PartiallyFailingMigration".to_owned(),
+        }
+    }
+}
+impl Migration for PartiallyFailingMigration {
+    fn version(&self) -> MigrationVersion {
+        MigrationVersion::new(6)
+    }
+    fn up(&self, context: &MigrationContext) -> postgres::Result<()> {
+        let transaction = context.connection();
+        try!(transaction.execute("CREATE TABLE half_done (id INT);", &[]));
+        Err(io::Error::new(io::ErrorKind::Other, "failed partway").into())
+    }
+    fn down(&self, context: &MigrationContext) -> postgres::Result<()> {
+        let transaction = context.connection();
+        try!(transaction.execute("DROP TABLE half_done;", &[]));
+        Ok(())
+    }
+}
+impl Display for PartiallyFailingMigration {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{}", self.name)
+    }
+}
@@ -0,0 +1,42 @@
+use std::fmt::{self, Display};
+use postgres;
+use trek::migration::Migration;
+use trek::migration_context::MigrationContext;
+use trek::migration_version::MigrationVersion;
+
+const UP_SQL: &'static str = "CREATE TABLE sql_data (id INT);";
+const DOWN_SQL: &'static str = "DROP TABLE sql_data;";
+
+// this migration provides its down SQL so it can be rolled back without being registered
+#[derive(Debug)]
+pub struct SqlMigration {
+    name: String
+}
+impl SqlMigration {
+    pub fn new() -> Self {
+        SqlMigration {
+            name: "SqlMigration".to_owned(),
+        }
+    }
+}
+impl Migration for SqlMigration {
+    fn version(&self) -> MigrationVersion {
+        MigrationVersion::new(5)
+    }
+    fn up(&self, context: &MigrationContext) -> postgres::Result<()> {
+        let transaction = context.connection();
+        transaction.batch_execute(UP_SQL)
+    }
+    fn down(&self, context: &MigrationContext) -> postgres::Result<()> {
+        let transaction = context.connection();
+        transaction.batch_execute(DOWN_SQL)
+    }
+    fn down_sql(&self) -> Option<String> {
+        Some(DOWN_SQL.to_owned())
+    }
+}
+impl Display for SqlMigration {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{}", self.name)
+    }
+}